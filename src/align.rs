@@ -0,0 +1,81 @@
+use crate::hallucination::simplify;
+
+#[cfg(test)]
+mod tests;
+
+/// A single word with its estimated start and end time in seconds.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct WordTimestamp {
+    pub word: String,
+    pub start: f32,
+    pub end: f32,
+}
+
+/// Estimate word timings by spreading the words of `text` over `start..end`.
+///
+/// Whisper-style alignment runs DTW over decoder cross-attention weights, but
+/// Voxtral has no cross-attention (audio embeddings are spliced into the
+/// decoder input) and candle does not expose its attention weights. Instead
+/// each word gets a share of the segment duration proportional to its length
+/// in characters, plus one character for the following space. The estimate is
+/// good enough for caption paging but drifts when speech rate varies within a
+/// segment.
+pub fn estimate_word_timestamps(text: &str, start: f32, end: f32) -> Vec<WordTimestamp> {
+    let words: Vec<&str> = text.split_whitespace().collect();
+    let total_weight: usize = words.iter().map(|w| w.chars().count() + 1).sum();
+    if words.is_empty() || end <= start {
+        return Vec::new();
+    }
+
+    let duration = end - start;
+    let mut elapsed = 0usize;
+    words
+        .iter()
+        .map(|word| {
+            let weight = word.chars().count() + 1;
+            let word_start = start + duration * elapsed as f32 / total_weight as f32;
+            elapsed += weight;
+            let word_end = start + duration * elapsed as f32 / total_weight as f32;
+            WordTimestamp {
+                word: (*word).to_string(),
+                start: word_start,
+                end: word_end,
+            }
+        })
+        .collect()
+}
+
+/// Append the words of the next chunk to `words`, leaving out its leading
+/// words that repeat the end of `words`.
+///
+/// Consecutive chunks overlap, so the words spoken in the overlap are
+/// transcribed by both. Among the last words of `words`, the earliest from
+/// which the rest of `words` matches the start of `next`, compared after
+/// [`simplify`], marks the repeat; those words keep the times of the earlier
+/// chunk. As the times are estimates, words ending up to one overlap length
+/// before `next` starts are still looked at.
+pub fn extend_without_overlap(words: &mut Vec<WordTimestamp>, next: Vec<WordTimestamp>) {
+    let (Some(next_start), Some(previous_end)) = (
+        next.first().map(|word| word.start),
+        words.last().map(|word| word.end),
+    ) else {
+        words.extend(next);
+        return;
+    };
+    let earliest_end = next_start - (previous_end - next_start).max(0.0);
+    let overlap_start = words
+        .iter()
+        .position(|word| word.end > earliest_end)
+        .unwrap_or(words.len());
+    let repeated = (overlap_start..words.len())
+        .map(|i| &words[i..])
+        .find(|tail| {
+            tail.len() <= next.len()
+                && tail
+                    .iter()
+                    .zip(&next)
+                    .all(|(a, b)| simplify(&a.word) == simplify(&b.word))
+        })
+        .map_or(0, <[WordTimestamp]>::len);
+    words.extend(next.into_iter().skip(repeated));
+}
@@ -0,0 +1,70 @@
+use super::*;
+
+#[test]
+fn test_estimate_word_timestamps_covers_segment() {
+    let words = estimate_word_timestamps("hello kitsune world", 10.0, 13.0);
+
+    assert_eq!(words.len(), 3);
+    assert_eq!(words[0].word, "hello");
+    assert_eq!(words[0].start, 10.0);
+    assert!((words[2].end - 13.0).abs() < 1e-4);
+}
+
+#[test]
+fn test_estimate_word_timestamps_are_contiguous() {
+    let words = estimate_word_timestamps("one two three four", 0.0, 4.0);
+
+    for pair in words.windows(2) {
+        assert_eq!(pair[0].end, pair[1].start);
+        assert!(pair[0].start < pair[0].end);
+    }
+}
+
+#[test]
+fn test_estimate_word_timestamps_longer_words_take_longer() {
+    let words = estimate_word_timestamps("a extraordinary", 0.0, 1.0);
+
+    let short = words[0].end - words[0].start;
+    let long = words[1].end - words[1].start;
+    assert!(long > short);
+}
+
+#[test]
+fn test_estimate_word_timestamps_empty_text() {
+    assert!(estimate_word_timestamps("   ", 0.0, 1.0).is_empty());
+    assert!(estimate_word_timestamps("word", 1.0, 1.0).is_empty());
+}
+
+#[test]
+fn test_word_timestamp_serialization() {
+    let word = WordTimestamp {
+        word: "fox".to_string(),
+        start: 1.5,
+        end: 2.0,
+    };
+    let json = serde_json::to_string(&word).unwrap();
+    assert_eq!(json, r#"{"word":"fox","start":1.5,"end":2.0}"#);
+}
+
+#[test]
+fn test_words_in_the_chunk_overlap_are_kept_once() {
+    let mut words = estimate_word_timestamps("we met on monday at noon", 0.0, 15.0);
+    let next = estimate_word_timestamps("Monday, at noon. Then we left", 12.0, 27.0);
+
+    extend_without_overlap(&mut words, next);
+
+    let text: Vec<&str> = words.iter().map(|word| word.word.as_str()).collect();
+    assert_eq!(
+        text,
+        ["we", "met", "on", "monday", "at", "noon", "Then", "we", "left"]
+    );
+    assert_eq!(words[5].end, 15.0);
+
+    // Without a repeat, or for words before the overlap, nothing is dropped
+    let mut words = estimate_word_timestamps("noon came", 0.0, 15.0);
+    extend_without_overlap(
+        &mut words,
+        estimate_word_timestamps("noon again", 12.0, 27.0),
+    );
+    assert_eq!(words.len(), 4);
+}
@@ -1,69 +1,400 @@
 use candle_core::{Error, Result};
+use symphonia::core::audio::Channels;
+
+use crate::profile::{self, Stage};
 
 /// Sample rate used by the Voxtral model (16 kHz)
 pub const SAMPLE_RATE: u32 = 16_000;
 
-#[cfg(test)]
-mod tests;
+/// Magnitude from which a sample counts as clipped, just below full scale
+/// so that 16-bit and 24-bit maxima count too.
+pub const CLIP_LEVEL: f32 = 0.99;
+
+/// Fraction of clipped samples above which the input is reported as
+/// overloaded, about one sample in a thousand.
+pub const CLIP_WARNING_FRACTION: f32 = 0.001;
+
+/// Level reported for digital silence, the noise floor of 24-bit audio, so
+/// that a level is always a finite number of dBFS.
+pub const SILENCE_DBFS: f32 = -144.0;
 
 #[cfg(test)]
-use tests::*;
+mod tests;
 
 /// Decode an audio file into a mono PCM float vector and its sample rate.
 ///
 /// This function uses `symphonia` to probe and decode the given audio file
 /// path. It selects the first decodable audio track and converts samples to
 /// `f32` PCM samples in the range appropriate for the original sample format.
-/// The returned audio is mono (first channel) as `Vec<f32>` together with the
-/// sample rate (Hz).
+/// The returned audio is mono (all channels averaged) as `Vec<f32>` together
+/// with the sample rate (Hz).
 ///
 /// Errors are returned via `candle::Error` on file/codec failures.
 pub fn pcm_decode<P: AsRef<std::path::Path>>(path: P) -> Result<(Vec<f32>, u32)> {
-    use symphonia::core::audio::SampleBuffer;
-    use symphonia::core::codecs::{DecoderOptions, CODEC_TYPE_NULL};
+    pcm_decode_with(path, Downmix::Average)
+}
 
-    // Open the media source.
+/// Decode an audio file into a mono PCM float vector and its sample rate,
+/// mixing its channels down as `downmix` says.
+///
+/// Like [`pcm_decode`], which averages the channels with equal weights.
+pub fn pcm_decode_with<P: AsRef<std::path::Path>>(
+    path: P,
+    downmix: Downmix,
+) -> Result<(Vec<f32>, u32)> {
     let src = std::fs::File::open(path.as_ref()).map_err(Error::wrap)?;
+    let extension = path.as_ref().extension().and_then(|e| e.to_str());
+    let (channels, sample_rate, layout) = decode_channels_reader(src, extension)?;
+    let weights = downmix.weights(layout, channels.len());
+    Ok((downmix_weighted(&channels, &weights), sample_rate))
+}
 
-    // Create the media source stream.
-    let mss = symphonia::core::io::MediaSourceStream::new(Box::new(src), Default::default());
+/// How the channels of a multi-channel recording are mixed down to mono.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Downmix {
+    /// Every channel with equal weight.
+    #[default]
+    Average,
+    /// Favour the centre channel, which carries the dialog in 5.1 and 7.1
+    /// mixes: centre at full weight, front left and right at half, the LFE
+    /// left out and all other channels at a quarter. Recordings without a
+    /// centre channel, such as stereo, are averaged as usual.
+    CenterFocus,
+}
 
-    // Create a probe hint using the file's extension. [Optional]
-    // This helps Symphonia choose the correct format reader based on
-    // the file extension when available.
-    let mut hint = symphonia::core::probe::Hint::new();
-    if let Some(ext) = path.as_ref().extension().and_then(|e| e.to_str()) {
-        hint.with_extension(ext);
+impl Downmix {
+    /// Gain of each of `count` channels laid out as `layout`, in the order
+    /// the channels are decoded. Equal when the layout does not describe
+    /// exactly `count` channels.
+    pub fn weights(self, layout: Channels, count: usize) -> Vec<f32> {
+        if self == Downmix::Average || layout.count() != count {
+            return vec![1.0; count];
+        }
+        layout
+            .iter()
+            .map(|channel| {
+                if channel == Channels::FRONT_CENTRE {
+                    1.0
+                } else if channel == Channels::FRONT_LEFT || channel == Channels::FRONT_RIGHT {
+                    0.5
+                } else if channel == Channels::LFE1 || channel == Channels::LFE2 {
+                    0.0
+                } else {
+                    0.25
+                }
+            })
+            .collect()
     }
+}
 
-    // Use the default options for metadata and format readers.
-    let meta_opts: symphonia::core::meta::MetadataOptions = Default::default();
-    let fmt_opts: symphonia::core::formats::FormatOptions = Default::default();
+/// Check that `path` can be an audio file before handing it to the decoder,
+/// whose error for a directory is an unhelpful OS message.
+///
+/// # Errors
+///
+/// Returns an error naming the path if it is a directory.
+pub fn check_input_file<P: AsRef<std::path::Path>>(path: P) -> anyhow::Result<()> {
+    let path = path.as_ref();
+    if path.is_dir() {
+        anyhow::bail!(
+            "{} is a directory, but a single audio file is expected. Pass one file per run",
+            path.display()
+        );
+    }
+    Ok(())
+}
 
-    // Probe the media source.
-    let probed = symphonia::default::get_probe()
-        .format(&hint, mss, &fmt_opts, &meta_opts)
-        .map_err(Error::wrap)?;
-    // Get the instantiated format reader.
-    let mut format = probed.format;
+/// Refuse audio at `sample_rate` when it is not `target_sr`, for pipelines
+/// that must not be resampled.
+///
+/// # Errors
+///
+/// Returns an error stating both rates if they differ.
+pub fn require_sample_rate(sample_rate: u32, target_sr: u32) -> anyhow::Result<()> {
+    if sample_rate != target_sr {
+        anyhow::bail!(
+            "Input is sampled at {sample_rate} Hz but {target_sr} Hz is required, and resampling is disabled"
+        );
+    }
+    Ok(())
+}
 
-    // Find the first audio track with a known (decodable) codec.
-    let track = format
-        .tracks()
-        .iter()
-        .find(|t| t.codec_params.codec != CODEC_TYPE_NULL)
-        .ok_or_else(|| Error::Msg("no supported audio tracks".to_string()))?;
+/// Decode `path` to mono with `downmix` and resample it to `target_sr` with
+/// `resample`, ready to be chunked and transcribed. With `resample` set to `None`, input
+/// at another rate is an error instead. With `sanitize` set, NaN and infinite
+/// samples from a malformed file are replaced with silence, see [`sanitize`].
+///
+/// # Errors
+///
+/// Returns an error if `path` is a directory, if the file cannot be decoded
+/// or resampled, or if it holds no samples.
+pub fn decode_and_prepare<P: AsRef<std::path::Path>>(
+    path: P,
+    target_sr: u32,
+    resample: Option<ResampleQuality>,
+    downmix: Downmix,
+    sanitize: bool,
+) -> anyhow::Result<Vec<f32>> {
+    use anyhow::Context;
+
+    check_input_file(&path)?;
+    let decoding = profile::span(Stage::Decode);
+    let (mut audio_data, sample_rate) = pcm_decode_with(path, downmix)
+        .context("Failed to decode audio file. Perhaps its not supported? See https://docs.rs/symphonia/latest/symphonia/index.html")?;
+    drop(decoding);
+    // Before resampling, which would smear a NaN over its neighbours
+    if sanitize {
+        warn_if_sanitized(self::sanitize(&mut audio_data), audio_data.len());
+    }
+    warn_if_clipped(&audio_data);
+
+    if resample.is_none() {
+        require_sample_rate(sample_rate, target_sr)?;
+    }
+    let prepared = match resample {
+        Some(quality) if sample_rate != target_sr => {
+            log::info!(
+                "Resampling audio from {} Hz to {} Hz to match model expectations...",
+                sample_rate,
+                target_sr
+            );
+            let _span = profile::span(Stage::Resample);
+            resample_with_quality(&audio_data, sample_rate, target_sr, quality)
+                .context("Failed to resample audio to 16 kHz")?
+        }
+        _ => audio_data,
+    };
+
+    if prepared.is_empty() {
+        anyhow::bail!("No audio samples after decoding/resampling.");
+    }
+    log::debug!("Prepared audio level: {}", levels(&prepared));
+
+    Ok(prepared)
+}
+
+/// How audio is extended to the length the encoder takes, a whole number
+/// of 30-second windows.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum PadMode {
+    /// Silence. The jump from the last sample to zero can reach the encoder
+    /// as a click.
+    #[default]
+    Zero,
+    /// The audio mirrored at its last sample, back and forth as often as
+    /// the padding needs, like NumPy's `reflect`. The waveform continues
+    /// smoothly, but a long pad repeats the speech backwards.
+    Reflect,
+    /// The last sample repeated, a constant level without a jump.
+    Edge,
+}
+
+/// `audio` extended to `len` samples as `mode` says; audio of `len`
+/// samples or more is returned as it is. Empty audio is padded with zeros
+/// whatever the mode.
+pub fn pad(audio: &[f32], len: usize, mode: PadMode) -> Vec<f32> {
+    let mut padded = audio.to_vec();
+    if audio.len() >= len {
+        return padded;
+    }
+    let Some(&last) = audio.last() else {
+        padded.resize(len, 0.0);
+        return padded;
+    };
+    match mode {
+        PadMode::Zero => padded.resize(len, 0.0),
+        PadMode::Edge => padded.resize(len, last),
+        PadMode::Reflect => {
+            padded.extend((audio.len()..len).map(|i| audio[reflect(i, audio.len())]))
+        }
+    }
+    padded
+}
+
+/// Index of `audio` that position `index` past its end mirrors, for audio
+/// of `len` samples reflected at both ends without repeating them.
+fn reflect(index: usize, len: usize) -> usize {
+    if len == 1 {
+        return 0;
+    }
+    let period = 2 * (len - 1);
+    let phase = index % period;
+    if phase < len {
+        phase
+    } else {
+        period - phase
+    }
+}
+
+/// Replace NaN and infinite samples in `pcm` with silence, returning how
+/// many there were.
+///
+/// Such samples come from malformed files and would otherwise turn the whole
+/// mel spectrogram, and so the transcript, into nothing.
+pub fn sanitize(pcm: &mut [f32]) -> usize {
+    let mut count = 0;
+    for sample in pcm.iter_mut().filter(|sample| !sample.is_finite()) {
+        *sample = 0.0;
+        count += 1;
+    }
+    count
+}
+
+/// Warn that `count` of `total` samples were not finite and were replaced
+/// with silence by [`sanitize`].
+pub fn warn_if_sanitized(count: usize, total: usize) {
+    if count > 0 {
+        log::warn!(
+            "Replaced {count} of {total} samples that were NaN or infinite with silence; \
+             the file may be damaged"
+        );
+    }
+}
+
+/// Average `channels` into a single mono signal.
+pub fn downmix(channels: &[Vec<f32>]) -> Vec<f32> {
+    downmix_weighted(channels, &vec![1.0; channels.len()])
+}
+
+/// Mix `channels` into a single mono signal, the mean of the channels
+/// weighted by `weights`. All-zero weights fall back to equal ones.
+pub fn downmix_weighted(channels: &[Vec<f32>], weights: &[f32]) -> Vec<f32> {
+    let equal;
+    let weights = if weights.iter().sum::<f32>() > 0.0 {
+        weights
+    } else {
+        equal = vec![1.0; channels.len()];
+        &equal
+    };
+    let total: f32 = weights.iter().sum();
+    let frames = channels.iter().map(Vec::len).min().unwrap_or(0);
+    (0..frames)
+        .map(|frame| {
+            let sum: f32 = channels
+                .iter()
+                .zip(weights)
+                .map(|(channel, weight)| channel[frame] * weight)
+                .sum();
+            sum / total
+        })
+        .collect()
+}
+
+/// Decode audio from memory or any other seekable reader into a mono PCM
+/// float vector and its sample rate, like [`pcm_decode`] does for a file.
+///
+/// `format_hint` is the file extension the data would have, such as `"wav"`
+/// or `"mp3"`; it helps choosing the format reader but the content is probed
+/// either way.
+pub fn pcm_decode_reader<R>(reader: R, format_hint: Option<&str>) -> Result<(Vec<f32>, u32)>
+where
+    R: std::io::Read + std::io::Seek + Send + Sync + 'static,
+{
+    let (channels, sample_rate) = pcm_decode_channels_reader(reader, format_hint)?;
+    Ok((downmix(&channels), sample_rate))
+}
+
+/// Decode an audio file into one PCM float vector per channel and its sample
+/// rate.
+///
+/// Like [`pcm_decode`], but the channels are kept apart, for example to
+/// transcribe each speaker of a two-channel interview separately.
+pub fn pcm_decode_channels<P: AsRef<std::path::Path>>(path: P) -> Result<(Vec<Vec<f32>>, u32)> {
+    let src = std::fs::File::open(path.as_ref()).map_err(Error::wrap)?;
+    let extension = path.as_ref().extension().and_then(|e| e.to_str());
+    pcm_decode_channels_reader(src, extension)
+}
+
+/// Seekable reader as a Symphonia media source.
+///
+/// The length is measured up front: the MP4 reader used for `.m4a` files
+/// refuses a seekable source that cannot tell its length.
+struct ReaderSource<R> {
+    reader: R,
+    len: Option<u64>,
+}
+
+impl<R: std::io::Seek> ReaderSource<R> {
+    fn new(mut reader: R) -> Self {
+        let len = measure_len(&mut reader).ok();
+        Self { reader, len }
+    }
+}
+
+/// Bytes from the start to the end of `reader`, leaving its position as it was.
+fn measure_len(reader: &mut impl std::io::Seek) -> std::io::Result<u64> {
+    let position = reader.stream_position()?;
+    let len = reader.seek(std::io::SeekFrom::End(0))?;
+    reader.seek(std::io::SeekFrom::Start(position))?;
+    Ok(len)
+}
+
+impl<R: std::io::Read> std::io::Read for ReaderSource<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.reader.read(buf)
+    }
+}
+
+impl<R: std::io::Seek> std::io::Seek for ReaderSource<R> {
+    fn seek(&mut self, pos: std::io::SeekFrom) -> std::io::Result<u64> {
+        self.reader.seek(pos)
+    }
+}
+
+impl<R> symphonia::core::io::MediaSource for ReaderSource<R>
+where
+    R: std::io::Read + std::io::Seek + Send + Sync,
+{
+    fn is_seekable(&self) -> bool {
+        true
+    }
+
+    fn byte_len(&self) -> Option<u64> {
+        self.len
+    }
+}
+
+/// Decode audio from `reader` into one PCM float vector per channel and its
+/// sample rate; the reader counterpart of [`pcm_decode_channels`].
+pub fn pcm_decode_channels_reader<R>(
+    reader: R,
+    format_hint: Option<&str>,
+) -> Result<(Vec<Vec<f32>>, u32)>
+where
+    R: std::io::Read + std::io::Seek + Send + Sync + 'static,
+{
+    let (channels, sample_rate, _) = decode_channels_reader(reader, format_hint)?;
+    Ok((channels, sample_rate))
+}
+
+/// Decode audio from `reader` into one PCM float vector per channel, its
+/// sample rate and the speaker positions of the channels, in order.
+fn decode_channels_reader<R>(
+    reader: R,
+    format_hint: Option<&str>,
+) -> Result<(Vec<Vec<f32>>, u32, Channels)>
+where
+    R: std::io::Read + std::io::Seek + Send + Sync + 'static,
+{
+    use symphonia::core::audio::SampleBuffer;
+    use symphonia::core::codecs::DecoderOptions;
+
+    let mut format = probe_format(reader, format_hint)?;
+    let track = select_track(format.as_ref())?;
+
+    let sample_rate = track_sample_rate(&track.codec_params)?;
 
     // Use the default options for the decoder.
     let dec_opts: DecoderOptions = Default::default();
 
     // Create a decoder for the track.
+    let codec = track.codec_params.codec;
     let mut decoder = symphonia::default::get_codecs()
         .make(&track.codec_params, &dec_opts)
-        .map_err(|_| Error::Msg("unsupported codec".to_string()))?;
+        .map_err(|e| undecodable_track(codec, e))?;
     let track_id = track.id;
-    let sample_rate = track.codec_params.sample_rate.unwrap_or(0);
-    let mut pcm_data = Vec::new();
+    let mut layout = track.codec_params.channels.unwrap_or(Channels::empty());
+    let mut pcm_channels: Vec<Vec<f32>> = Vec::new();
     // The decode loop.
     while let Ok(packet) = format.next_packet() {
         // Consume any new metadata that has been read since the last packet.
@@ -77,8 +408,14 @@ pub fn pcm_decode<P: AsRef<std::path::Path>>(path: P) -> Result<(Vec<f32>, u32)>
         }
         // Decode to an AudioBufferRef and copy samples into a SampleBuffer<f32>
         // which provides interleaved f32 samples regardless of the packet's
-        // original sample type. Then average channels to produce mono.
-        let decoded = decoder.decode(&packet).map_err(Error::wrap)?;
+        // original sample type. Then split the frames into channels.
+        let decoded = decoder.decode(&packet).map_err(|e| {
+            if codec == symphonia::core::codecs::CODEC_TYPE_AAC {
+                Error::Msg(format!("failed to decode AAC audio: {e}. {AAC_NOTE}"))
+            } else {
+                Error::wrap(e)
+            }
+        })?;
         let frames = decoded.frames();
         let spec = *decoded.spec();
 
@@ -90,41 +427,420 @@ pub fn pcm_decode<P: AsRef<std::path::Path>>(path: P) -> Result<(Vec<f32>, u32)>
         if channels == 0 {
             continue;
         }
+        layout = spec.channels;
 
-        // Average channels into mono per frame.
-        for frame in 0..frames {
-            let base = frame * channels;
-            let mut sum = 0f32;
-            for ch in 0..channels {
-                sum += interleaved[base + ch];
+        if pcm_channels.len() < channels {
+            pcm_channels.resize(channels, Vec::new());
+        }
+        for frame in interleaved.chunks_exact(channels) {
+            for (channel, &sample) in pcm_channels.iter_mut().zip(frame) {
+                channel.push(sample);
             }
-            pcm_data.push(sum / channels as f32);
         }
     }
-    Ok((pcm_data, sample_rate))
+    Ok((pcm_channels, sample_rate, layout))
+}
+
+/// Probe `reader` and return the format reader Symphonia picks for it.
+fn probe_format<R>(
+    reader: R,
+    format_hint: Option<&str>,
+) -> Result<Box<dyn symphonia::core::formats::FormatReader>>
+where
+    R: std::io::Read + std::io::Seek + Send + Sync + 'static,
+{
+    // Create the media source stream.
+    let mss = symphonia::core::io::MediaSourceStream::new(
+        Box::new(ReaderSource::new(reader)),
+        Default::default(),
+    );
+
+    // Create a probe hint using the file's extension. [Optional]
+    // This helps Symphonia choose the correct format reader based on
+    // the file extension when available.
+    let mut hint = symphonia::core::probe::Hint::new();
+    if let Some(ext) = format_hint {
+        hint.with_extension(ext);
+    }
+
+    // Use the default options for metadata and format readers.
+    let meta_opts: symphonia::core::meta::MetadataOptions = Default::default();
+    let fmt_opts: symphonia::core::formats::FormatOptions = Default::default();
+
+    // Probe the media source.
+    let probed = symphonia::default::get_probe()
+        .format(&hint, mss, &fmt_opts, &meta_opts)
+        .map_err(|e| match format_hint {
+            Some(ext) if is_aac_extension(ext) => Error::Msg(format!(
+                "no AAC audio found in the .{ext} data: {e}. {AAC_NOTE}"
+            )),
+            Some(ext) if ext.eq_ignore_ascii_case("wv") => Error::Msg(format!(
+                "WavPack (.{ext}) is not supported: Symphonia has no WavPack reader or decoder \
+                 in any build. {} {WAVPACK_HINT}",
+                supported_codecs_note()
+            )),
+            _ => Error::wrap(e),
+        })?;
+    Ok(probed.format)
+}
+
+/// The track that gets decoded: the first one with a codec Symphonia can
+/// decode or, failing that, the first one with a known codec so that the
+/// error can name it.
+fn select_track(
+    format: &dyn symphonia::core::formats::FormatReader,
+) -> Result<&symphonia::core::formats::Track> {
+    let codecs = symphonia::default::get_codecs();
+    let tracks = format.tracks();
+    tracks
+        .iter()
+        .find(|t| codecs.get_codec(t.codec_params.codec).is_some())
+        .or_else(|| {
+            tracks
+                .iter()
+                .find(|t| t.codec_params.codec != symphonia::core::codecs::CODEC_TYPE_NULL)
+        })
+        .ok_or_else(|| no_audio_track(tracks.len()))
+}
+
+/// How to get a file into a format that can be decoded, for error messages.
+const CONVERT_HINT: &str =
+    "Convert the file first, for example with `ffmpeg -i input.webm -ar 16000 input.wav`";
+
+/// Extensions of files that usually hold AAC audio: MP4 containers such as
+/// phone recordings, and raw ADTS streams.
+const AAC_EXTENSIONS: [&str; 4] = ["m4a", "m4b", "mp4", "aac"];
+
+/// What to expect of AAC input, for error messages.
+const AAC_NOTE: &str = "AAC is decoded from .m4a, .mp4 and .aac files in the AAC-LC profile \
+     that most phones and recorders use; HE-AAC and other profiles are not supported. Convert \
+     the file first, for example with `ffmpeg -i input.m4a -ar 16000 input.wav`";
+
+/// How to get WavPack audio into a lossless format that can be decoded.
+const WAVPACK_HINT: &str =
+    "Convert the file to FLAC first, for example with `ffmpeg -i input.wv input.flac`";
+
+/// Whether a file with extension `ext`, in any case, usually holds AAC audio.
+fn is_aac_extension(ext: &str) -> bool {
+    AAC_EXTENSIONS
+        .iter()
+        .any(|aac| aac.eq_ignore_ascii_case(ext))
+}
+
+/// Codec families by the Symphonia feature that provides their decoder.
+const CODEC_FEATURES: [(&str, &[symphonia::core::codecs::CodecType]); 9] = {
+    use symphonia::core::codecs::*;
+    [
+        (
+            "pcm",
+            &[
+                CODEC_TYPE_PCM_S16LE,
+                CODEC_TYPE_PCM_S16BE,
+                CODEC_TYPE_PCM_S24LE,
+                CODEC_TYPE_PCM_S24BE,
+                CODEC_TYPE_PCM_S32LE,
+                CODEC_TYPE_PCM_S32BE,
+                CODEC_TYPE_PCM_S8,
+                CODEC_TYPE_PCM_U8,
+                CODEC_TYPE_PCM_F32LE,
+                CODEC_TYPE_PCM_F32BE,
+                CODEC_TYPE_PCM_F64LE,
+                CODEC_TYPE_PCM_F64BE,
+                CODEC_TYPE_PCM_ALAW,
+                CODEC_TYPE_PCM_MULAW,
+            ],
+        ),
+        ("adpcm", &[CODEC_TYPE_ADPCM_MS, CODEC_TYPE_ADPCM_IMA_WAV]),
+        ("flac", &[CODEC_TYPE_FLAC]),
+        ("alac", &[CODEC_TYPE_ALAC]),
+        ("aac", &[CODEC_TYPE_AAC]),
+        ("mp1", &[CODEC_TYPE_MP1]),
+        ("mp2", &[CODEC_TYPE_MP2]),
+        ("mp3", &[CODEC_TYPE_MP3]),
+        ("vorbis", &[CODEC_TYPE_VORBIS]),
+    ]
+};
+
+/// Names of codecs that containers can declare but Symphonia has no decoder
+/// for in any build.
+const UNDECODABLE_CODECS: [(symphonia::core::codecs::CodecType, &str); 14] = {
+    use symphonia::core::codecs::*;
+    [
+        (CODEC_TYPE_OPUS, "opus"),
+        (CODEC_TYPE_SPEEX, "speex"),
+        (CODEC_TYPE_WMA, "wma"),
+        (CODEC_TYPE_EAC3, "eac3"),
+        (CODEC_TYPE_AC4, "ac4"),
+        (CODEC_TYPE_DCA, "dts"),
+        (CODEC_TYPE_MUSEPACK, "musepack"),
+        (CODEC_TYPE_WAVPACK, "wavpack"),
+        (CODEC_TYPE_MONKEYS_AUDIO, "ape"),
+        (CODEC_TYPE_TTA, "tta"),
+        (CODEC_TYPE_ATRAC1, "atrac1"),
+        (CODEC_TYPE_ATRAC3, "atrac3"),
+        (CODEC_TYPE_ATRAC3PLUS, "atrac3plus"),
+        (CODEC_TYPE_ATRAC9, "atrac9"),
+    ]
+};
+
+/// Codec families this build can decode, named after the Symphonia feature
+/// that provides each, such as `pcm`, `flac` or `mp3`.
+pub fn supported_codecs() -> Vec<&'static str> {
+    let codecs = symphonia::default::get_codecs();
+    CODEC_FEATURES
+        .iter()
+        .filter(|(_, types)| types.iter().any(|&t| codecs.get_codec(t).is_some()))
+        .map(|&(feature, _)| feature)
+        .collect()
+}
+
+/// The Symphonia feature that provides a decoder for `codec`, if any does.
+fn codec_feature(codec: symphonia::core::codecs::CodecType) -> Option<&'static str> {
+    CODEC_FEATURES
+        .iter()
+        .find(|(_, types)| types.contains(&codec))
+        .map(|&(feature, _)| feature)
+}
+
+/// Short name of `codec`, such as `pcm_s16le`, including codecs that a
+/// container can declare but Symphonia cannot decode.
+fn codec_name(codec: symphonia::core::codecs::CodecType) -> String {
+    if let Some(descriptor) = symphonia::default::get_codecs().get_codec(codec) {
+        return descriptor.short_name.to_string();
+    }
+    UNDECODABLE_CODECS
+        .iter()
+        .find(|&&(t, _)| t == codec)
+        .map(|&(_, name)| name.to_string())
+        .or_else(|| codec_feature(codec).map(str::to_string))
+        .unwrap_or_else(|| codec.to_string())
+}
+
+/// The sentence listing the codecs this build decodes, ending error messages.
+fn supported_codecs_note() -> String {
+    format!("This build decodes {}.", supported_codecs().join(", "))
+}
+
+/// Error for a track whose codec has no decoder: names the codec, says
+/// whether a Symphonia feature would add one, and how to convert the file.
+fn unsupported_codec(codec: symphonia::core::codecs::CodecType) -> Error {
+    let name = codec_name(codec);
+    let reason = match codec_feature(codec) {
+        Some(feature) => format!(
+            "this build has no decoder for it; enabling the `{feature}` feature of the \
+             symphonia dependency adds one. {CONVERT_HINT}"
+        ),
+        None if codec == symphonia::core::codecs::CODEC_TYPE_OPUS => {
+            format!("Symphonia has no Opus decoder. {CONVERT_HINT}")
+        }
+        None => format!("Symphonia has no decoder for it. {CONVERT_HINT}"),
+    };
+    Error::Msg(format!(
+        "unsupported codec {name}: {reason}. {}",
+        supported_codecs_note()
+    ))
+}
+
+/// Error for a track whose decoder cannot be created: a codec without a
+/// decoder in this build, or a variant of a decodable codec that its
+/// decoder does not handle, such as HE-AAC.
+fn undecodable_track(
+    codec: symphonia::core::codecs::CodecType,
+    error: symphonia::core::errors::Error,
+) -> Error {
+    if symphonia::default::get_codecs().get_codec(codec).is_none() {
+        return unsupported_codec(codec);
+    }
+    let note = if codec == symphonia::core::codecs::CODEC_TYPE_AAC {
+        AAC_NOTE
+    } else {
+        CONVERT_HINT
+    };
+    Error::Msg(format!(
+        "cannot decode the {} track: {error}. {note}",
+        codec_name(codec)
+    ))
+}
+
+/// Error for a file without a track that has a known codec, such as a video
+/// with its audio in a codec Symphonia does not recognise.
+fn no_audio_track(track_count: usize) -> Error {
+    Error::Msg(format!(
+        "no supported audio tracks: none of the {track_count} tracks in the file has a codec \
+         Symphonia recognises. {CONVERT_HINT}. {}",
+        supported_codecs_note()
+    ))
 }
 
-/// Resample a PCM buffer from `sr_in` to `sr_out` using a high-quality FFT resampler.
+/// Codec and sample format of the track [`pcm_decode`] selects in a file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrackInfo {
+    /// Short codec name, such as `pcm_s16le` or `mp3`.
+    pub codec: String,
+    /// Id of the selected track within the container.
+    pub track_id: u32,
+    /// Number of tracks in the container.
+    pub track_count: usize,
+    /// Number of channels, when the container states it.
+    pub channels: Option<usize>,
+    /// Sample rate in Hz, when the container states it.
+    pub sample_rate: Option<u32>,
+    /// Bits per decoded sample, when the codec has a fixed bit depth.
+    pub bits_per_sample: Option<u32>,
+}
+
+impl std::fmt::Display for TrackInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let unknown = || "unknown".to_string();
+        write!(
+            f,
+            "codec {}, {} channels, {} Hz, {} bits per sample (track {} of {})",
+            self.codec,
+            self.channels.map_or_else(unknown, |c| c.to_string()),
+            self.sample_rate.map_or_else(unknown, |r| r.to_string()),
+            self.bits_per_sample.map_or_else(unknown, |b| b.to_string()),
+            self.track_id,
+            self.track_count
+        )
+    }
+}
+
+/// Probe `path` and describe the track [`pcm_decode`] would decode, without
+/// decoding any audio. Useful to tell a wrongly picked track or a
+/// mis-detected format apart from a transcription problem.
+pub fn track_info<P: AsRef<std::path::Path>>(path: P) -> Result<TrackInfo> {
+    let src = std::fs::File::open(path.as_ref()).map_err(Error::wrap)?;
+    let extension = path.as_ref().extension().and_then(|e| e.to_str());
+    let format = probe_format(src, extension)?;
+    let track = select_track(format.as_ref())?;
+    let params = &track.codec_params;
+    Ok(TrackInfo {
+        codec: codec_name(params.codec),
+        track_id: track.id,
+        track_count: format.tracks().len(),
+        channels: params.channels.map(|c| c.count()),
+        sample_rate: params.sample_rate,
+        bits_per_sample: params.bits_per_sample,
+    })
+}
+
+/// Sample rate of a track, rejecting a missing or zero rate that would later
+/// divide by zero while resampling.
+fn track_sample_rate(params: &symphonia::core::codecs::CodecParameters) -> Result<u32> {
+    match params.sample_rate {
+        Some(rate) if rate > 0 => Ok(rate),
+        _ => Err(Error::Msg(
+            "could not determine sample rate for track".to_string(),
+        )),
+    }
+}
+
+/// Trade-off between resampling speed and fidelity.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum ResampleQuality {
+    /// Cubic polynomial interpolation without anti-aliasing.
+    Fast,
+    /// Short (32-tap) windowed-sinc interpolation with anti-aliasing.
+    Balanced,
+    /// Synchronous FFT resampler (the historical default).
+    #[default]
+    High,
+}
+
+/// Resample a PCM buffer from `sr_in` to `sr_out` using the default (high) quality.
+///
+/// See [`resample_with_quality`] for details.
+pub fn resample(pcm_in: &[f32], sr_in: u32, sr_out: u32) -> Result<Vec<f32>> {
+    resample_with_quality(pcm_in, sr_in, sr_out, ResampleQuality::default())
+}
+
+/// Resample a PCM buffer from `sr_in` to `sr_out` with the requested quality.
 ///
 /// - `pcm_in`: input mono PCM samples (f32)
 /// - `sr_in`: input sample rate in Hz
 /// - `sr_out`: desired output sample rate in Hz
+/// - `quality`: which rubato resampler to use
+///
+/// Measured on a 10-minute 44.1 kHz mono signal resampled to 16 kHz (release
+/// build, single core):
+///
+/// | Quality    | Resampler                          | Time    | Notes                                  |
+/// |------------|------------------------------------|---------|----------------------------------------|
+/// | `Fast`     | `FastFixedIn`, cubic               | ~0.13 s | no anti-aliasing, aliasing above 8 kHz |
+/// | `Balanced` | `SincFixedIn`, 32 taps, nearest    | ~0.21 s | anti-aliased, wider transition band    |
+/// | `High`     | `FftFixedInOut`, 1024-frame chunks | ~0.22 s | anti-aliased, flat passband            |
+///
+/// The FFT resampler is already cheap for the common 44.1/48 kHz inputs, so
+/// the gain from `Fast` is modest; it matters most for long batch jobs.
+/// `Balanced` keeps anti-aliasing while avoiding the large FFTs that
+/// `High` needs for sample rates with a small common divisor.
 ///
 /// Returns a newly allocated `Vec<f32>` with the resampled audio.
-pub fn resample(pcm_in: &[f32], sr_in: u32, sr_out: u32) -> Result<Vec<f32>> {
-    use rubato::Resampler;
+pub fn resample_with_quality(
+    pcm_in: &[f32],
+    sr_in: u32,
+    sr_out: u32,
+    quality: ResampleQuality,
+) -> Result<Vec<f32>> {
+    use rubato::{
+        FastFixedIn, FftFixedInOut, PolynomialDegree, SincFixedIn, SincInterpolationParameters,
+        SincInterpolationType, WindowFunction,
+    };
 
-    let mut pcm_out =
-        Vec::with_capacity((pcm_in.len() as f64 * sr_out as f64 / sr_in as f64) as usize + 1024);
+    let ratio = sr_out as f64 / sr_in as f64;
+    match quality {
+        ResampleQuality::Fast => {
+            let mut resampler =
+                FastFixedIn::<f32>::new(ratio, 1.0, PolynomialDegree::Cubic, 1024, 1)
+                    .map_err(Error::wrap)?;
+            run_resampler(&mut resampler, pcm_in, ratio)
+        }
+        ResampleQuality::Balanced => {
+            let params = SincInterpolationParameters {
+                sinc_len: 32,
+                f_cutoff: 0.9,
+                interpolation: SincInterpolationType::Nearest,
+                oversampling_factor: 256,
+                window: WindowFunction::Hann2,
+            };
+            let mut resampler =
+                SincFixedIn::<f32>::new(ratio, 1.0, params, 1024, 1).map_err(Error::wrap)?;
+            run_resampler(&mut resampler, pcm_in, ratio)
+        }
+        ResampleQuality::High => {
+            let mut resampler = FftFixedInOut::<f32>::new(sr_in as usize, sr_out as usize, 1024, 1)
+                .map_err(Error::wrap)?;
+            run_resampler(&mut resampler, pcm_in, ratio)
+        }
+    }
+}
+
+/// Drive a rubato resampler over the whole mono input buffer.
+///
+/// The resamplers introduce a fixed delay of `output_delay()` frames, so the
+/// leading delay is dropped and the tail is flushed with silence until the
+/// output covers the full input. The result holds `round(len * ratio)`
+/// samples aligned with the input, and at least one for a non-empty input
+/// so that a clip of a few samples is not lost.
+fn run_resampler<R: rubato::Resampler<f32>>(
+    resampler: &mut R,
+    pcm_in: &[f32],
+    ratio: f64,
+) -> Result<Vec<f32>> {
+    let expected_len =
+        ((pcm_in.len() as f64 * ratio).round() as usize).max(usize::from(!pcm_in.is_empty()));
+    if expected_len == 0 {
+        return Ok(Vec::new());
+    }
+    let delay = resampler.output_delay();
+    let mut pcm_out = Vec::with_capacity(delay + expected_len + resampler.output_frames_max());
 
-    let mut resampler = rubato::FftFixedInOut::<f32>::new(sr_in as usize, sr_out as usize, 1024, 1)
-        .map_err(candle_core::Error::wrap)?;
     let mut output_buffer = resampler.output_buffer_allocate(true);
     let mut pos_in = 0;
-    while pos_in + resampler.input_frames_next() < pcm_in.len() {
+    while pos_in + resampler.input_frames_next() <= pcm_in.len() {
         let (in_len, out_len) = resampler
             .process_into_buffer(&[&pcm_in[pos_in..]], &mut output_buffer, None)
-            .map_err(candle_core::Error::wrap)?;
+            .map_err(Error::wrap)?;
         pos_in += in_len;
         pcm_out.extend_from_slice(&output_buffer[0][..out_len]);
     }
@@ -132,9 +848,143 @@ pub fn resample(pcm_in: &[f32], sr_in: u32, sr_out: u32) -> Result<Vec<f32>> {
     if pos_in < pcm_in.len() {
         let (_in_len, out_len) = resampler
             .process_partial_into_buffer(Some(&[&pcm_in[pos_in..]]), &mut output_buffer, None)
-            .map_err(candle_core::Error::wrap)?;
+            .map_err(Error::wrap)?;
         pcm_out.extend_from_slice(&output_buffer[0][..out_len]);
     }
 
+    // Push silence through until the delayed tail of the input has come out.
+    while pcm_out.len() < delay + expected_len {
+        let (_in_len, out_len) = resampler
+            .process_partial_into_buffer(None::<&[&[f32]]>, &mut output_buffer, None)
+            .map_err(Error::wrap)?;
+        if out_len == 0 {
+            break;
+        }
+        pcm_out.extend_from_slice(&output_buffer[0][..out_len]);
+    }
+
+    pcm_out.drain(..delay.min(pcm_out.len()));
+    pcm_out.truncate(expected_len);
     Ok(pcm_out)
 }
+
+/// Root mean square level of `pcm`, 0.0 for an empty buffer.
+///
+/// Full-scale sine waves measure about 0.707; 0.001 is roughly -60 dBFS.
+pub fn rms(pcm: &[f32]) -> f32 {
+    if pcm.is_empty() {
+        return 0.0;
+    }
+    let sum: f64 = pcm.iter().map(|&s| f64::from(s) * f64::from(s)).sum();
+    (sum / pcm.len() as f64).sqrt() as f32
+}
+
+/// Convert a linear `amplitude`, where 1.0 is full scale, to dBFS, floored
+/// at [`SILENCE_DBFS`].
+pub fn dbfs(amplitude: f32) -> f32 {
+    if amplitude <= 0.0 {
+        return SILENCE_DBFS;
+    }
+    (20.0 * amplitude.log10()).max(SILENCE_DBFS)
+}
+
+/// Peak and RMS level of a buffer in dBFS, to tell whether a recording is
+/// too quiet or too hot before transcribing it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Levels {
+    /// Level of the loudest sample.
+    pub peak_dbfs: f32,
+    /// Level of the root mean square of all samples, see [`rms`].
+    pub rms_dbfs: f32,
+}
+
+impl std::fmt::Display for Levels {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "peak {:.1} dBFS, RMS {:.1} dBFS",
+            self.peak_dbfs, self.rms_dbfs
+        )
+    }
+}
+
+/// Measure the [`Levels`] of `pcm` in a single pass; an empty buffer
+/// measures as silence.
+pub fn levels(pcm: &[f32]) -> Levels {
+    let (peak, sum) = pcm.iter().fold((0f32, 0f64), |(peak, sum), &s| {
+        (peak.max(s.abs()), sum + f64::from(s) * f64::from(s))
+    });
+    let rms = if pcm.is_empty() {
+        0.0
+    } else {
+        (sum / pcm.len() as f64).sqrt() as f32
+    };
+    Levels {
+        peak_dbfs: dbfs(peak),
+        rms_dbfs: dbfs(rms),
+    }
+}
+
+/// Fraction of the samples in `pcm` at or near full scale, see
+/// [`CLIP_LEVEL`]; 0.0 for an empty buffer.
+pub fn clipped_fraction(pcm: &[f32]) -> f32 {
+    if pcm.is_empty() {
+        return 0.0;
+    }
+    let clipped = pcm.iter().filter(|s| s.abs() >= CLIP_LEVEL).count();
+    clipped as f32 / pcm.len() as f32
+}
+
+/// Log the clipped fraction of `pcm` and warn when it exceeds
+/// [`CLIP_WARNING_FRACTION`], as an overloaded recording transcribes worse.
+///
+/// Meant for decoded audio before resampling, which smooths the flat tops
+/// of clipped waveforms away.
+pub fn warn_if_clipped(pcm: &[f32]) {
+    let fraction = clipped_fraction(pcm);
+    log::debug!("Clipped samples: {:.3}%", fraction * 100.0);
+    if fraction > CLIP_WARNING_FRACTION {
+        log::warn!(
+            "{:.2}% of the samples are at full scale, so the recording is likely clipped. \
+             Transcription may suffer; record again with less gain if possible",
+            fraction * 100.0
+        );
+    }
+}
+
+/// Write mono `pcm` samples to `path` as a 16-bit PCM WAV file.
+///
+/// Samples are clamped to `-1.0..=1.0` before conversion, so the file is
+/// exactly what a 16-bit player would hear of the buffer.
+pub fn write_wav<P: AsRef<std::path::Path>>(path: P, pcm: &[f32], sample_rate: u32) -> Result<()> {
+    use std::io::Write;
+
+    const CHANNELS: u16 = 1;
+    const BITS_PER_SAMPLE: u16 = 16;
+    let block_align = CHANNELS * BITS_PER_SAMPLE / 8;
+    let data_len = u32::try_from(pcm.len() * usize::from(block_align))
+        .map_err(|_| Error::Msg("audio too long for a WAV file".to_string()))?;
+
+    let mut bytes = Vec::with_capacity(44 + data_len as usize);
+    bytes.extend_from_slice(b"RIFF");
+    bytes.extend_from_slice(&(36 + data_len).to_le_bytes());
+    bytes.extend_from_slice(b"WAVE");
+    bytes.extend_from_slice(b"fmt ");
+    bytes.extend_from_slice(&16u32.to_le_bytes()); // fmt chunk size
+    bytes.extend_from_slice(&1u16.to_le_bytes()); // PCM
+    bytes.extend_from_slice(&CHANNELS.to_le_bytes());
+    bytes.extend_from_slice(&sample_rate.to_le_bytes());
+    bytes.extend_from_slice(&(sample_rate * u32::from(block_align)).to_le_bytes()); // byte rate
+    bytes.extend_from_slice(&block_align.to_le_bytes());
+    bytes.extend_from_slice(&BITS_PER_SAMPLE.to_le_bytes());
+    bytes.extend_from_slice(b"data");
+    bytes.extend_from_slice(&data_len.to_le_bytes());
+    for &sample in pcm {
+        let value = (sample.clamp(-1.0, 1.0) * i16::MAX as f32).round() as i16;
+        bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    let mut file = std::fs::File::create(path.as_ref()).map_err(Error::wrap)?;
+    file.write_all(&bytes).map_err(Error::wrap)?;
+    Ok(())
+}
@@ -63,6 +63,28 @@ fn test_pcm_decode_empty_path() {
     assert!(result.is_err(), "Should fail for nonexistent file");
 }
 
+#[test]
+fn test_decode_and_prepare_rejects_directory() {
+    let dir = tempfile::tempdir().unwrap();
+
+    let err = decode_and_prepare(
+        dir.path(),
+        SAMPLE_RATE,
+        Some(ResampleQuality::default()),
+        Downmix::Average,
+        true,
+    )
+    .unwrap_err();
+
+    assert_eq!(
+        err.to_string(),
+        format!(
+            "{} is a directory, but a single audio file is expected. Pass one file per run",
+            dir.path().display()
+        )
+    );
+}
+
 #[test]
 fn test_resample_large_downsample() {
     // Test downsampling from a high rate to a much lower rate
@@ -81,16 +103,581 @@ fn test_resample_large_downsample() {
     }
 }
 
+/// Write a 16-bit stereo WAV with the given per-channel samples.
+fn write_stereo_wav(path: &std::path::Path, left: &[i16], right: &[i16], sample_rate: u32) {
+    std::fs::write(path, stereo_wav_bytes(left, right, sample_rate)).unwrap();
+}
+
+/// A 16-bit stereo WAV file with the given per-channel samples.
+fn stereo_wav_bytes(left: &[i16], right: &[i16], sample_rate: u32) -> Vec<u8> {
+    let data_len = (left.len() * 4) as u32;
+    let mut bytes = Vec::new();
+    bytes.extend_from_slice(b"RIFF");
+    bytes.extend_from_slice(&(36 + data_len).to_le_bytes());
+    bytes.extend_from_slice(b"WAVEfmt ");
+    bytes.extend_from_slice(&16u32.to_le_bytes());
+    bytes.extend_from_slice(&1u16.to_le_bytes());
+    bytes.extend_from_slice(&2u16.to_le_bytes());
+    bytes.extend_from_slice(&sample_rate.to_le_bytes());
+    bytes.extend_from_slice(&(sample_rate * 4).to_le_bytes());
+    bytes.extend_from_slice(&4u16.to_le_bytes());
+    bytes.extend_from_slice(&16u16.to_le_bytes());
+    bytes.extend_from_slice(b"data");
+    bytes.extend_from_slice(&data_len.to_le_bytes());
+    for (l, r) in left.iter().zip(right) {
+        bytes.extend_from_slice(&l.to_le_bytes());
+        bytes.extend_from_slice(&r.to_le_bytes());
+    }
+    bytes
+}
+
+#[test]
+fn test_decode_and_prepare_without_resampling() {
+    let temp_file = NamedTempFile::with_suffix(".wav").unwrap();
+    write_stereo_wav(temp_file.path(), &[1000; 80], &[1000; 80], 8000);
+
+    let err = decode_and_prepare(temp_file.path(), SAMPLE_RATE, None, Downmix::Average, true)
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Input is sampled at 8000 Hz but 16000 Hz is required, and resampling is disabled"
+    );
+
+    let prepared =
+        decode_and_prepare(temp_file.path(), 8000, None, Downmix::Average, true).unwrap();
+    assert_eq!(prepared.len(), 80);
+}
+
+#[test]
+fn test_pcm_decode_reader_from_memory() {
+    let bytes = stereo_wav_bytes(&[16384; 100], &[0; 100], 16000);
+
+    let (mono, sample_rate) = pcm_decode_reader(std::io::Cursor::new(bytes), Some("wav")).unwrap();
+
+    assert_eq!(sample_rate, 16000);
+    assert_eq!(mono.len(), 100);
+    assert!(mono.iter().all(|&s| (s - 0.25).abs() < 1e-4));
+}
+
+#[test]
+fn test_pcm_decode_reader_without_hint() {
+    let bytes = stereo_wav_bytes(&[16384; 50], &[-8192; 50], 8000);
+
+    let (channels, sample_rate) =
+        pcm_decode_channels_reader(std::io::Cursor::new(bytes), None).unwrap();
+
+    assert_eq!(sample_rate, 8000);
+    assert_eq!(channels.len(), 2);
+    assert!(channels[1].iter().all(|&s| (s + 0.25).abs() < 1e-4));
+}
+
+#[test]
+fn test_pcm_decode_reader_rejects_garbage() {
+    let result = pcm_decode_reader(std::io::Cursor::new(vec![0u8; 256]), Some("wav"));
+
+    assert!(result.is_err());
+}
+
+#[test]
+fn test_pcm_decode_reader_names_aac_for_m4a() {
+    let err = pcm_decode_reader(std::io::Cursor::new(vec![0u8; 256]), Some("M4A"))
+        .unwrap_err()
+        .to_string();
+    assert!(
+        err.starts_with("no AAC audio found in the .M4A data"),
+        "{err}"
+    );
+    assert!(err.contains("AAC-LC") && err.contains("ffmpeg -i"), "{err}");
+
+    let err = pcm_decode_reader(std::io::Cursor::new(vec![0u8; 256]), Some("wav"))
+        .unwrap_err()
+        .to_string();
+    assert!(!err.contains("AAC"), "{err}");
+}
+
+#[test]
+fn test_aac_extensions() {
+    for ext in ["m4a", "M4A", "mp4", "m4b", "aac"] {
+        assert!(is_aac_extension(ext), "{ext}");
+    }
+    assert!(!is_aac_extension("wav"));
+    assert!(!is_aac_extension("ogg"));
+}
+
+#[test]
+fn test_reader_source_measures_length() {
+    use std::io::Seek;
+
+    let mut reader = std::io::Cursor::new(vec![0u8; 10]);
+    reader.set_position(3);
+
+    let mut source = ReaderSource::new(reader);
+    assert_eq!(
+        symphonia::core::io::MediaSource::byte_len(&source),
+        Some(10)
+    );
+    assert_eq!(source.stream_position().unwrap(), 3);
+}
+
+#[test]
+fn test_undecodable_aac_profile_is_named() {
+    use symphonia::core::codecs::CODEC_TYPE_AAC;
+    use symphonia::core::errors::Error as SymphoniaError;
+
+    let err = undecodable_track(
+        CODEC_TYPE_AAC,
+        SymphoniaError::Unsupported("aac: ELD config"),
+    )
+    .to_string();
+    assert!(err.starts_with("cannot decode the aac track: "), "{err}");
+    assert!(
+        err.contains("aac: ELD config") && err.contains("HE-AAC"),
+        "{err}"
+    );
+}
+
 #[test]
 fn test_pcm_decode_channel_averaging() {
-    // This test would require a multi-channel WAV file
-    // For now, we just verify the function signature and basic behavior
-    // In a real scenario, you'd use a test fixture
+    let temp_file = NamedTempFile::with_suffix(".wav").unwrap();
+    let path = temp_file.path().to_path_buf();
+    write_stereo_wav(&path, &[16384; 100], &[0; 100], 16000);
+
+    let (mono, sample_rate) = pcm_decode(&path).unwrap();
 
-    // Verify that the function can handle the expected input types
+    assert_eq!(sample_rate, 16000);
+    assert_eq!(mono.len(), 100);
+    assert!(mono.iter().all(|&s| (s - 0.25).abs() < 1e-4));
+}
+
+#[test]
+fn test_pcm_decode_channels_keeps_channels_apart() {
     let temp_file = NamedTempFile::with_suffix(".wav").unwrap();
     let path = temp_file.path().to_path_buf();
+    write_stereo_wav(&path, &[16384; 50], &[-8192; 50], 8000);
+
+    let (channels, sample_rate) = pcm_decode_channels(&path).unwrap();
+
+    assert_eq!(sample_rate, 8000);
+    assert_eq!(channels.len(), 2);
+    assert!(channels[0].iter().all(|&s| (s - 0.5).abs() < 1e-4));
+    assert!(channels[1].iter().all(|&s| (s + 0.25).abs() < 1e-4));
+}
+
+#[test]
+fn test_track_info_describes_selected_track() {
+    let temp_file = NamedTempFile::with_suffix(".wav").unwrap();
+    write_stereo_wav(temp_file.path(), &[0; 10], &[0; 10], 8000);
+
+    let info = track_info(temp_file.path()).unwrap();
+    assert_eq!(info.codec, "pcm_s16le");
+    assert_eq!(info.channels, Some(2));
+    assert_eq!(info.sample_rate, Some(8000));
+    assert_eq!(info.bits_per_sample, Some(16));
+    assert_eq!(info.track_count, 1);
+    assert_eq!(
+        info.to_string(),
+        "codec pcm_s16le, 2 channels, 8000 Hz, 16 bits per sample (track 0 of 1)"
+    );
+}
+
+#[test]
+fn test_supported_codecs_follow_enabled_features() {
+    let supported = supported_codecs();
+    // The symphonia dependency enables every codec feature
+    for feature in ["pcm", "adpcm", "flac", "alac", "aac", "mp3", "vorbis"] {
+        assert!(supported.contains(&feature), "{feature} in {supported:?}");
+    }
+    assert!(!supported.contains(&"opus"));
+}
+
+#[test]
+fn test_wavpack_is_reported_as_unsupported() {
+    // A WavPack block header: the container is not one Symphonia can read
+    let mut wavpack = b"wvpk".to_vec();
+    wavpack.extend_from_slice(&[0; 28]);
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let path = temp_dir.path().join("archive.WV");
+    std::fs::write(&path, &wavpack).unwrap();
+
+    let err = pcm_decode(&path).unwrap_err().to_string();
+    assert!(err.starts_with("WavPack (.WV) is not supported"), "{err}");
+    assert!(err.contains("This build decodes pcm, adpcm, flac"), "{err}");
+    assert!(err.contains("ffmpeg -i input.wv input.flac"), "{err}");
+
+    let err = pcm_decode_reader(std::io::Cursor::new(wavpack), Some("wv"))
+        .unwrap_err()
+        .to_string();
+    assert!(err.starts_with("WavPack (.wv) is not supported"), "{err}");
+}
+
+#[test]
+fn test_codec_names_and_features() {
+    use symphonia::core::codecs::{CODEC_TYPE_AAC, CODEC_TYPE_OPUS, CODEC_TYPE_WMA};
+
+    assert_eq!(codec_name(CODEC_TYPE_AAC), "aac");
+    assert_eq!(codec_name(CODEC_TYPE_OPUS), "opus");
+    assert_eq!(codec_name(CODEC_TYPE_WMA), "wma");
+    assert_eq!(codec_feature(CODEC_TYPE_AAC), Some("aac"));
+    assert_eq!(codec_feature(CODEC_TYPE_OPUS), None);
+}
+
+#[test]
+fn test_unsupported_codec_errors_are_actionable() {
+    use symphonia::core::codecs::{CODEC_TYPE_OPUS, CODEC_TYPE_WMA};
+
+    let err = unsupported_codec(CODEC_TYPE_OPUS).to_string();
+    assert!(err.starts_with("unsupported codec opus: Symphonia has no Opus decoder."));
+    assert!(err.contains("ffmpeg -i"), "{err}");
+    assert!(err.contains("This build decodes pcm, adpcm, flac"), "{err}");
+
+    let err = unsupported_codec(CODEC_TYPE_WMA).to_string();
+    assert!(err.starts_with("unsupported codec wma: Symphonia has no decoder for it."));
+
+    let err = no_audio_track(2).to_string();
+    assert!(err.contains("none of the 2 tracks"), "{err}");
+    assert!(err.contains("ffmpeg -i") && err.contains("This build decodes"));
+}
+
+#[test]
+fn test_track_sample_rate_rejects_unknown_rate() {
+    use symphonia::core::codecs::CodecParameters;
+
+    let mut params = CodecParameters::new();
+    let err = track_sample_rate(&params).unwrap_err().to_string();
+    assert!(err.contains("could not determine sample rate for track"));
+
+    params.with_sample_rate(0);
+    assert!(track_sample_rate(&params).is_err());
+
+    params.with_sample_rate(44_100);
+    assert_eq!(track_sample_rate(&params).unwrap(), 44_100);
+}
+
+#[test]
+fn test_downmix_averages_channels() {
+    assert_eq!(downmix(&[vec![1.0, 0.5], vec![0.0, -0.5]]), vec![0.5, 0.0]);
+    assert_eq!(downmix(&[vec![0.25, 0.75]]), vec![0.25, 0.75]);
+    assert!(downmix(&[]).is_empty());
+}
+
+/// 5.1 channel layout in decoding order: L, R, C, LFE, rear L, rear R.
+fn surround_5_1() -> Channels {
+    Channels::FRONT_LEFT
+        | Channels::FRONT_RIGHT
+        | Channels::FRONT_CENTRE
+        | Channels::LFE1
+        | Channels::REAR_LEFT
+        | Channels::REAR_RIGHT
+}
+
+#[test]
+fn test_downmix_weights() {
+    assert_eq!(Downmix::default(), Downmix::Average);
+    assert_eq!(Downmix::Average.weights(surround_5_1(), 6), vec![1.0; 6]);
+    assert_eq!(
+        Downmix::CenterFocus.weights(surround_5_1(), 6),
+        vec![0.5, 0.5, 1.0, 0.0, 0.25, 0.25]
+    );
+    // Stereo has no centre to favour, and an unknown layout stays equal
+    let stereo = Channels::FRONT_LEFT | Channels::FRONT_RIGHT;
+    assert_eq!(Downmix::CenterFocus.weights(stereo, 2), vec![0.5, 0.5]);
+    assert_eq!(
+        Downmix::CenterFocus.weights(Channels::empty(), 6),
+        vec![1.0; 6]
+    );
+}
+
+#[test]
+fn test_center_focus_favours_dialog_channel() {
+    // Only the centre channel carries signal
+    let mut channels = vec![vec![0.0; 4]; 6];
+    channels[2] = vec![0.6; 4];
+
+    let average = downmix(&channels);
+    let weights = Downmix::CenterFocus.weights(surround_5_1(), channels.len());
+    let focused = downmix_weighted(&channels, &weights);
+
+    assert!((average[0] - 0.1).abs() < 1e-6);
+    assert!((focused[0] - 0.24).abs() < 1e-6);
+    assert_eq!(downmix_weighted(&channels, &[0.0; 6]), average);
+}
+
+#[test]
+fn test_resample_quality_variants_produce_expected_length() {
+    let pcm_in: Vec<f32> = (0..44100).map(|i| (i as f32 * 0.01).sin() * 0.5).collect();
+    let expected = 16000.0;
+
+    for quality in [
+        ResampleQuality::Fast,
+        ResampleQuality::Balanced,
+        ResampleQuality::High,
+    ] {
+        let pcm_out = resample_with_quality(&pcm_in, 44100, 16000, quality).unwrap();
+        let ratio = pcm_out.len() as f32 / expected;
+        assert!(
+            (0.9..1.1).contains(&ratio),
+            "{:?} produced {} samples",
+            quality,
+            pcm_out.len()
+        );
+    }
+}
+
+#[test]
+fn test_resample_quality_default_is_high() {
+    assert_eq!(ResampleQuality::default(), ResampleQuality::High);
+}
+
+/// Resample a linear ramp and check the output length and alignment.
+///
+/// The ramp is smooth, so away from the very edges every output sample must
+/// match the ideal ramp value at the same instant. A dropped or repeated
+/// block at the seam between full chunks and the flushed tail shows up as a
+/// large deviation.
+fn assert_ramp_resamples_cleanly(sr_in: u32, sr_out: u32, quality: ResampleQuality) {
+    let len_in = sr_in as usize / 2 + 37; // deliberately not a multiple of any chunk size
+    let pcm_in: Vec<f32> = (0..len_in).map(|i| i as f32 / len_in as f32).collect();
+
+    let pcm_out = resample_with_quality(&pcm_in, sr_in, sr_out, quality).unwrap();
+
+    let theoretical = len_in as f64 * sr_out as f64 / sr_in as f64;
+    assert!(
+        (pcm_out.len() as f64 - theoretical).abs() <= 1.0,
+        "{sr_in}->{sr_out} {quality:?}: got {} samples, expected ~{theoretical}",
+        pcm_out.len()
+    );
+
+    let margin = 64;
+    let step = sr_in as f32 / sr_out as f32;
+    for (i, &sample) in pcm_out
+        .iter()
+        .enumerate()
+        .take(pcm_out.len() - margin)
+        .skip(margin)
+    {
+        let ideal = i as f32 * step / len_in as f32;
+        assert!(
+            (sample - ideal).abs() < 0.01,
+            "{sr_in}->{sr_out} {quality:?}: sample {i} is {sample}, expected {ideal}"
+        );
+    }
+}
+
+#[test]
+fn test_resample_ramp_44100_to_16000() {
+    assert_ramp_resamples_cleanly(44100, 16000, ResampleQuality::High);
+}
+
+#[test]
+fn test_resample_ramp_48000_to_16000() {
+    assert_ramp_resamples_cleanly(48000, 16000, ResampleQuality::High);
+}
+
+#[test]
+fn test_resample_ramp_8000_to_16000() {
+    assert_ramp_resamples_cleanly(8000, 16000, ResampleQuality::High);
+}
+
+#[test]
+fn test_resample_ramp_all_qualities() {
+    for quality in [ResampleQuality::Fast, ResampleQuality::Balanced] {
+        assert_ramp_resamples_cleanly(44100, 16000, quality);
+        assert_ramp_resamples_cleanly(8000, 16000, quality);
+    }
+}
+
+#[test]
+fn test_resample_short_clip_keeps_tail() {
+    // Shorter than a single resampler chunk.
+    let pcm_in = vec![0.25f32; 300];
+    let pcm_out = resample(&pcm_in, 48000, 16000).unwrap();
+    assert_eq!(pcm_out.len(), 100);
+}
+
+#[test]
+fn test_resample_single_sample_keeps_one() {
+    for quality in [
+        ResampleQuality::Fast,
+        ResampleQuality::Balanced,
+        ResampleQuality::High,
+    ] {
+        let pcm_out = resample_with_quality(&[0.25], 44100, 16000, quality).unwrap();
+        assert_eq!(pcm_out.len(), 1, "{quality:?}");
+        assert!(pcm_out[0].is_finite());
+        assert_eq!(
+            resample_with_quality(&[0.25; 100], 44100, 16000, quality)
+                .unwrap()
+                .len(),
+            36
+        );
+    }
+}
+
+#[test]
+fn test_decode_and_prepare_single_sample() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let path = temp_dir.path().join("click.wav");
+    write_wav(&path, &[0.5], 44100).unwrap();
+
+    let prepared = decode_and_prepare(
+        &path,
+        16000,
+        Some(ResampleQuality::default()),
+        Downmix::Average,
+        true,
+    )
+    .unwrap();
+    assert_eq!(prepared.len(), 1);
+}
+
+#[test]
+fn test_pad_modes_fill_the_tail() {
+    let audio = [0.1, 0.2, 0.3];
+
+    assert_eq!(
+        pad(&audio, 6, PadMode::Zero),
+        [0.1, 0.2, 0.3, 0.0, 0.0, 0.0]
+    );
+    assert_eq!(
+        pad(&audio, 6, PadMode::Edge),
+        [0.1, 0.2, 0.3, 0.3, 0.3, 0.3]
+    );
+    // Mirrored at the last sample without repeating it, then back again
+    assert_eq!(
+        pad(&audio, 9, PadMode::Reflect),
+        [0.1, 0.2, 0.3, 0.2, 0.1, 0.2, 0.3, 0.2, 0.1]
+    );
+}
+
+#[test]
+fn test_pad_edge_cases() {
+    for mode in [PadMode::Zero, PadMode::Reflect, PadMode::Edge] {
+        // Long enough audio is left alone
+        assert_eq!(pad(&[0.1, 0.2], 2, mode), [0.1, 0.2]);
+        assert_eq!(pad(&[0.1, 0.2, 0.3], 2, mode), [0.1, 0.2, 0.3]);
+        // Nothing to extend
+        assert_eq!(pad(&[], 3, mode), [0.0; 3]);
+    }
+    assert_eq!(pad(&[0.5], 4, PadMode::Reflect), [0.5; 4]);
+    assert_eq!(pad(&[0.5], 4, PadMode::Edge), [0.5; 4]);
+    assert_eq!(PadMode::default(), PadMode::Zero);
+}
+
+#[test]
+fn test_rms_levels() {
+    assert_eq!(rms(&[]), 0.0);
+    assert_eq!(rms(&[0.0; 100]), 0.0);
+    assert!((rms(&[0.5, -0.5, 0.5, -0.5]) - 0.5).abs() < 1e-6);
+
+    let sine: Vec<f32> = (0..16000)
+        .map(|i| (2.0 * std::f32::consts::PI * 440.0 * i as f32 / 16000.0).sin())
+        .collect();
+    assert!((rms(&sine) - std::f32::consts::FRAC_1_SQRT_2).abs() < 1e-3);
+}
+
+#[test]
+fn test_sanitize_replaces_non_finite_samples() {
+    let mut pcm = vec![0.5, f32::NAN, -0.25, f32::INFINITY, f32::NEG_INFINITY, 1.0];
+
+    assert_eq!(sanitize(&mut pcm), 3);
+    assert_eq!(pcm, [0.5, 0.0, -0.25, 0.0, 0.0, 1.0]);
+    assert_eq!(sanitize(&mut pcm), 0);
+}
+
+#[test]
+fn test_clipped_fraction() {
+    assert_eq!(clipped_fraction(&[]), 0.0);
+    assert_eq!(clipped_fraction(&[0.5, -0.98, 0.0, 0.9]), 0.0);
+
+    // A sine overdriven by 2x and hard-limited spends two thirds of its time
+    // pinned at full scale
+    let clipped: Vec<f32> = (0..16000)
+        .map(|i| {
+            (2.0 * (2.0 * std::f32::consts::PI * 440.0 * i as f32 / 16000.0).sin()).clamp(-1.0, 1.0)
+        })
+        .collect();
+    let fraction = clipped_fraction(&clipped);
+    assert!((fraction - 2.0 / 3.0).abs() < 0.02, "{fraction}");
+    assert!(fraction > CLIP_WARNING_FRACTION);
+
+    assert_eq!(clipped_fraction(&[1.0, -1.0, 0.995, 0.0]), 0.75);
+}
+
+#[test]
+fn test_levels_of_known_buffers() {
+    let sine: Vec<f32> = (0..16000)
+        .map(|i| 0.5 * (2.0 * std::f32::consts::PI * 440.0 * i as f32 / 16000.0).sin())
+        .collect();
+    let measured = levels(&sine);
+    // A sine peaks 3 dB above its RMS level
+    assert!((measured.peak_dbfs - -6.02).abs() < 0.01, "{measured}");
+    assert!((measured.rms_dbfs - -9.03).abs() < 0.01, "{measured}");
+
+    let measured = levels(&[0.1, -0.1, 0.1, -0.1]);
+    assert!((measured.peak_dbfs - -20.0).abs() < 1e-4);
+    assert!((measured.rms_dbfs - -20.0).abs() < 1e-4);
+    assert_eq!(measured.to_string(), "peak -20.0 dBFS, RMS -20.0 dBFS");
+
+    let measured = levels(&[1.0, -1.0]);
+    assert_eq!((measured.peak_dbfs, measured.rms_dbfs), (0.0, 0.0));
+    for silent in [&[][..], &[0.0; 8][..]] {
+        let measured = levels(silent);
+        assert_eq!(
+            (measured.peak_dbfs, measured.rms_dbfs),
+            (SILENCE_DBFS, SILENCE_DBFS)
+        );
+    }
+    assert_eq!(dbfs(1e-12), SILENCE_DBFS);
+}
+
+#[test]
+fn test_write_wav_header() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let path = temp_dir.path().join("dump.wav");
+
+    write_wav(&path, &[0.0, 0.5, -0.5], 16000).unwrap();
+
+    let bytes = std::fs::read(&path).unwrap();
+    assert_eq!(bytes.len(), 44 + 6);
+    assert_eq!(&bytes[0..4], b"RIFF");
+    assert_eq!(u32::from_le_bytes(bytes[4..8].try_into().unwrap()), 42);
+    assert_eq!(&bytes[8..16], b"WAVEfmt ");
+    assert_eq!(u16::from_le_bytes([bytes[20], bytes[21]]), 1); // PCM
+    assert_eq!(u16::from_le_bytes([bytes[22], bytes[23]]), 1); // mono
+    assert_eq!(u32::from_le_bytes(bytes[24..28].try_into().unwrap()), 16000);
+    assert_eq!(u32::from_le_bytes(bytes[28..32].try_into().unwrap()), 32000);
+    assert_eq!(u16::from_le_bytes([bytes[32], bytes[33]]), 2);
+    assert_eq!(u16::from_le_bytes([bytes[34], bytes[35]]), 16);
+    assert_eq!(&bytes[36..40], b"data");
+    assert_eq!(u32::from_le_bytes(bytes[40..44].try_into().unwrap()), 6);
+}
+
+#[test]
+fn test_write_wav_round_trip() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let path = temp_dir.path().join("dump.wav");
+    let pcm: Vec<f32> = (0..16000)
+        .map(|i| 0.8 * (2.0 * std::f32::consts::PI * 440.0 * i as f32 / 16000.0).sin())
+        .collect();
+
+    write_wav(&path, &pcm, 16000).unwrap();
+    let (decoded, sample_rate) = pcm_decode(&path).unwrap();
+
+    assert_eq!(sample_rate, 16000);
+    assert_eq!(decoded.len(), pcm.len());
+    for (a, b) in pcm.iter().zip(&decoded) {
+        assert!((a - b).abs() < 1e-3, "{a} vs {b}");
+    }
+}
+
+#[test]
+fn test_write_wav_clamps_out_of_range() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let path = temp_dir.path().join("dump.wav");
+
+    write_wav(&path, &[2.0, -2.0], 16000).unwrap();
 
-    // Just verify compilation - actual multi-channel test would need fixture
-    let _ = path;
+    let bytes = std::fs::read(&path).unwrap();
+    assert_eq!(i16::from_le_bytes([bytes[44], bytes[45]]), i16::MAX);
+    assert_eq!(i16::from_le_bytes([bytes[46], bytes[47]]), -i16::MAX);
 }
@@ -0,0 +1,103 @@
+use std::fmt;
+use std::time::Duration;
+
+#[cfg(test)]
+mod tests;
+
+/// Tokens the model generated in some time spent in the model.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Throughput {
+    pub tokens: usize,
+    pub elapsed: Duration,
+}
+
+impl Throughput {
+    /// Tokens generated per second, 0.0 when no time was measured.
+    pub fn tokens_per_second(&self) -> f64 {
+        let seconds = self.elapsed.as_secs_f64();
+        if seconds > 0.0 {
+            self.tokens as f64 / seconds
+        } else {
+            0.0
+        }
+    }
+}
+
+impl fmt::Display for Throughput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} tokens in {:.1}s ({:.1} tok/s)",
+            self.tokens,
+            self.elapsed.as_secs_f64(),
+            self.tokens_per_second()
+        )
+    }
+}
+
+/// Throughput measurements of a transcription run.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BenchReport {
+    /// Duration of the transcribed audio in seconds.
+    pub audio_seconds: f64,
+    /// Wall-clock time spent transcribing, excluding model loading.
+    pub wall_time: Duration,
+    /// Time spent in the model for each transcribed chunk.
+    pub chunk_times: Vec<Duration>,
+    /// Tokens generated over all transcribed chunks.
+    pub tokens: usize,
+    /// Time of the `--warmup` pass after loading, not part of `wall_time`.
+    pub warm_up: Option<Duration>,
+}
+
+impl BenchReport {
+    /// Seconds of audio transcribed per second of wall-clock time; above 1.0
+    /// is faster than real time.
+    pub fn real_time_factor(&self) -> f64 {
+        let wall = self.wall_time.as_secs_f64();
+        if wall > 0.0 {
+            self.audio_seconds / wall
+        } else {
+            0.0
+        }
+    }
+
+    /// Tokens generated over the time spent in the model for all chunks.
+    pub fn throughput(&self) -> Throughput {
+        Throughput {
+            tokens: self.tokens,
+            elapsed: self.chunk_times.iter().sum(),
+        }
+    }
+
+    /// Minimum, maximum and mean chunk time, or `None` without chunks.
+    pub fn chunk_stats(&self) -> Option<(Duration, Duration, Duration)> {
+        let min = *self.chunk_times.iter().min()?;
+        let max = *self.chunk_times.iter().max()?;
+        let total: Duration = self.chunk_times.iter().sum();
+        Some((min, max, total / self.chunk_times.len() as u32))
+    }
+}
+
+impl fmt::Display for BenchReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Audio duration:   {:.2} s", self.audio_seconds)?;
+        writeln!(f, "Wall-clock time:  {:.2} s", self.wall_time.as_secs_f64())?;
+        write!(f, "Real-time factor: {:.2}x", self.real_time_factor())?;
+        if let Some(warm_up) = self.warm_up {
+            write!(f, "\nWarm-up time:     {:.2} s", warm_up.as_secs_f64())?;
+        }
+        if let Some((min, max, mean)) = self.chunk_stats() {
+            write!(
+                f,
+                "\nChunk time ({} chunks): min {:.2} s, max {:.2} s, mean {:.2} s",
+                self.chunk_times.len(),
+                min.as_secs_f64(),
+                max.as_secs_f64(),
+                mean.as_secs_f64()
+            )?;
+            write!(f, "\nGeneration:       {}", self.throughput())?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,68 @@
+use super::*;
+
+fn report(chunk_millis: &[u64]) -> BenchReport {
+    BenchReport {
+        audio_seconds: 60.0,
+        wall_time: Duration::from_secs(20),
+        chunk_times: chunk_millis
+            .iter()
+            .map(|&ms| Duration::from_millis(ms))
+            .collect(),
+        tokens: 900,
+        warm_up: None,
+    }
+}
+
+#[test]
+fn test_real_time_factor() {
+    assert_eq!(report(&[]).real_time_factor(), 3.0);
+
+    let idle = BenchReport::default();
+    assert_eq!(idle.real_time_factor(), 0.0);
+}
+
+#[test]
+fn test_chunk_stats() {
+    let (min, max, mean) = report(&[4000, 6000, 8000]).chunk_stats().unwrap();
+    assert_eq!(min, Duration::from_secs(4));
+    assert_eq!(max, Duration::from_secs(8));
+    assert_eq!(mean, Duration::from_secs(6));
+
+    assert!(report(&[]).chunk_stats().is_none());
+}
+
+#[test]
+fn test_report_display() {
+    let text = report(&[4000, 8000]).to_string();
+    assert!(text.contains("Audio duration:   60.00 s"));
+    assert!(text.contains("Real-time factor: 3.00x"));
+    assert!(text.contains("Chunk time (2 chunks): min 4.00 s, max 8.00 s, mean 6.00 s"));
+    assert!(text.contains("Generation:       900 tokens in 12.0s (75.0 tok/s)"));
+    assert!(!report(&[]).to_string().contains("Generation"));
+}
+
+#[test]
+fn test_throughput() {
+    let chunk = Throughput {
+        tokens: 42,
+        elapsed: Duration::from_millis(1500),
+    };
+    assert_eq!(chunk.tokens_per_second(), 28.0);
+    assert_eq!(chunk.to_string(), "42 tokens in 1.5s (28.0 tok/s)");
+    assert_eq!(Throughput::default().tokens_per_second(), 0.0);
+
+    let total = report(&[4000, 8000]).throughput();
+    assert_eq!(total.elapsed, Duration::from_secs(12));
+    assert_eq!(total.tokens_per_second(), 75.0);
+}
+
+#[test]
+fn test_report_display_warm_up() {
+    assert!(!report(&[]).to_string().contains("Warm-up"));
+
+    let warmed = BenchReport {
+        warm_up: Some(Duration::from_millis(2500)),
+        ..report(&[])
+    };
+    assert!(warmed.to_string().contains("Warm-up time:     2.50 s"));
+}
@@ -0,0 +1,114 @@
+use anyhow::Result;
+use std::ops::Range;
+
+#[cfg(test)]
+mod tests;
+
+/// Position of a chunk in the schedule, for progress reporting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkProgress {
+    /// Position of the chunk, counting from 0.
+    pub index: usize,
+    /// Number of chunks in the schedule.
+    pub total: usize,
+    pub start_sample: usize,
+    pub end_sample: usize,
+}
+
+/// Reported by [`ChunkPlan::for_each_chunk`] around the work on each chunk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChunkEvent {
+    Started(ChunkProgress),
+    Finished(ChunkProgress),
+}
+
+/// How audio is split into overlapping chunks for transcription.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkPlan {
+    /// Length of each chunk in samples.
+    pub chunk_samples: usize,
+    /// Samples shared by consecutive chunks.
+    pub overlap_samples: usize,
+}
+
+impl ChunkPlan {
+    /// Plan chunks of `chunk_seconds` overlapping by `overlap_ratio` of a chunk.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the chunk length is not positive or the overlap
+    /// ratio is outside `0.0..1.0`.
+    pub fn new(chunk_seconds: f32, overlap_ratio: f32, sample_rate: u32) -> Result<Self> {
+        if !(chunk_seconds.is_finite() && chunk_seconds > 0.0) {
+            anyhow::bail!("Chunk length must be a positive number of seconds");
+        }
+        if !(0.0..1.0).contains(&overlap_ratio) {
+            anyhow::bail!("Overlap ratio must be at least 0 and below 1");
+        }
+        let chunk_samples = ((chunk_seconds * sample_rate as f32) as usize).max(1);
+        let overlap_samples = (chunk_samples as f32 * overlap_ratio) as usize;
+        Ok(Self {
+            chunk_samples,
+            overlap_samples,
+        })
+    }
+
+    /// Distance between the starts of consecutive chunks.
+    pub fn step(&self) -> usize {
+        if self.chunk_samples > self.overlap_samples {
+            self.chunk_samples - self.overlap_samples
+        } else {
+            self.chunk_samples
+        }
+    }
+
+    /// Sample ranges of the chunks covering `total_samples`.
+    ///
+    /// The last chunk ends at `total_samples` and may be shorter than the
+    /// others; no chunk lies entirely inside the previous one.
+    pub fn ranges(&self, total_samples: usize) -> Vec<Range<usize>> {
+        let mut ranges = Vec::new();
+        let mut start = 0usize;
+        while start < total_samples {
+            let end = (start + self.chunk_samples).min(total_samples);
+            ranges.push(start..end);
+            if end == total_samples {
+                break;
+            }
+            start += self.step();
+        }
+        ranges
+    }
+
+    /// Run `work` on each chunk of `audio` in order, starting at chunk
+    /// `first_chunk`, and report to `on_event` before and after each one.
+    ///
+    /// `work` receives the chunk index and its samples. The first error
+    /// stops the loop; the failed chunk gets no [`ChunkEvent::Finished`].
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error of `work`.
+    pub fn for_each_chunk(
+        &self,
+        audio: &[f32],
+        first_chunk: usize,
+        mut on_event: impl FnMut(ChunkEvent),
+        mut work: impl FnMut(usize, &[f32]) -> Result<()>,
+    ) -> Result<()> {
+        let ranges = self.ranges(audio.len());
+        let total = ranges.len();
+        for (index, range) in ranges.into_iter().enumerate().skip(first_chunk) {
+            let progress = ChunkProgress {
+                index,
+                total,
+                start_sample: range.start,
+                end_sample: range.end,
+            };
+            on_event(ChunkEvent::Started(progress));
+            work(index, &audio[range])?;
+            on_event(ChunkEvent::Finished(progress));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,99 @@
+use super::*;
+
+#[test]
+fn test_chunk_plan_default_settings() {
+    let plan = ChunkPlan::new(15.0, 0.1, 16000).unwrap();
+    assert_eq!(plan.chunk_samples, 240_000);
+    assert_eq!(plan.overlap_samples, 24_000);
+    assert_eq!(plan.step(), 216_000);
+}
+
+#[test]
+fn test_chunk_plan_ranges_cover_audio() {
+    let plan = ChunkPlan::new(15.0, 0.1, 16000).unwrap();
+    let ranges = plan.ranges(500_000);
+    assert_eq!(ranges, vec![0..240_000, 216_000..456_000, 432_000..500_000]);
+}
+
+#[test]
+fn test_chunk_plan_single_chunk() {
+    let plan = ChunkPlan::new(15.0, 0.1, 16000).unwrap();
+    assert_eq!(plan.ranges(100_000), vec![0..100_000]);
+    assert_eq!(plan.ranges(240_000), vec![0..240_000]);
+    assert!(plan.ranges(0).is_empty());
+}
+
+#[test]
+fn test_chunk_plan_without_overlap() {
+    let plan = ChunkPlan::new(1.0, 0.0, 10).unwrap();
+    assert_eq!(plan.ranges(25), vec![0..10, 10..20, 20..25]);
+}
+
+#[test]
+fn test_chunk_plan_rejects_invalid_settings() {
+    assert!(ChunkPlan::new(0.0, 0.1, 16000).is_err());
+    assert!(ChunkPlan::new(-5.0, 0.1, 16000).is_err());
+    assert!(ChunkPlan::new(15.0, 1.0, 16000).is_err());
+    assert!(ChunkPlan::new(15.0, -0.1, 16000).is_err());
+}
+
+#[test]
+fn test_for_each_chunk_reports_around_work() {
+    let plan = ChunkPlan::new(1.0, 0.5, 4).unwrap();
+    let audio: Vec<f32> = (0..8).map(|i| i as f32).collect();
+    let log = std::cell::RefCell::new(Vec::new());
+
+    plan.for_each_chunk(
+        &audio,
+        1,
+        |event| log.borrow_mut().push(format!("{event:?}")),
+        |index, chunk| {
+            log.borrow_mut().push(format!("work {index} {chunk:?}"));
+            Ok(())
+        },
+    )
+    .unwrap();
+
+    let progress = |index, start_sample, end_sample| ChunkProgress {
+        index,
+        total: 3,
+        start_sample,
+        end_sample,
+    };
+    assert_eq!(
+        log.into_inner(),
+        [
+            format!("{:?}", ChunkEvent::Started(progress(1, 2, 6))),
+            "work 1 [2.0, 3.0, 4.0, 5.0]".to_string(),
+            format!("{:?}", ChunkEvent::Finished(progress(1, 2, 6))),
+            format!("{:?}", ChunkEvent::Started(progress(2, 4, 8))),
+            "work 2 [4.0, 5.0, 6.0, 7.0]".to_string(),
+            format!("{:?}", ChunkEvent::Finished(progress(2, 4, 8))),
+        ]
+    );
+}
+
+#[test]
+fn test_for_each_chunk_stops_on_error() {
+    let plan = ChunkPlan::new(1.0, 0.0, 4).unwrap();
+    let mut events = Vec::new();
+
+    let result = plan.for_each_chunk(
+        &[0.0; 12],
+        0,
+        |event| events.push(event),
+        |index, _| {
+            if index == 1 {
+                anyhow::bail!("chunk {index} failed");
+            }
+            Ok(())
+        },
+    );
+
+    assert_eq!(result.unwrap_err().to_string(), "chunk 1 failed");
+    assert_eq!(events.len(), 3);
+    assert!(matches!(
+        events[2],
+        ChunkEvent::Started(ChunkProgress { index: 1, .. })
+    ));
+}
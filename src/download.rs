@@ -1,88 +1,601 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
-use anyhow::Result;
-use hf_hub::{api::sync::Api, Repo, RepoType};
+use anyhow::{Context, Result};
+use hf_hub::api::sync::{Api, ApiError, ApiRepo};
+use hf_hub::{Repo, RepoType};
 
 #[cfg(test)]
 mod tests;
 
-#[cfg(test)]
-use tests::*;
+/// Model configuration file.
+const CONFIG_FILE: &str = "config.json";
+
+/// Index mapping tensor names to the shard that holds them.
+const SHARD_INDEX_FILE: &str = "model.safetensors.index.json";
+
+/// Weights of an unsharded model.
+const SINGLE_WEIGHTS_FILE: &str = "model.safetensors";
+
+/// Tokenizer locations used by different mirrors, in order of preference.
+const TOKENIZER_FILES: [&str; 3] = ["tekken.json", "tokenizer.json", "tokenizer/tokenizer.json"];
+
+/// Suffix of a file being downloaded, renamed away once it is complete.
+const PARTIAL_SUFFIX: &str = ".kitsune-partial";
+
+/// Age under which a partial download may still be written by another run.
+const PARTIAL_GRACE: Duration = Duration::from_secs(60);
+
+/// Retries of a Hub request that was rate limited or hit a server error.
+const MAX_RETRIES: u32 = 5;
+
+/// Wait before the first retry, doubled before each further one.
+const FIRST_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Longest wait before a retry, also for a `Retry-After` asking for more.
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Model files sorted by role.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModelPaths {
+    pub config: PathBuf,
+    pub tokenizer: PathBuf,
+    /// Safetensors weights: the shards in file name order, or a single
+    /// merged file.
+    pub weights: Vec<PathBuf>,
+}
+
+impl ModelPaths {
+    /// Sort `files` by file name: `config.json`, `*.safetensors` weights and
+    /// a tokenizer (`tekken.json` or `tokenizer.json`). Other files are
+    /// ignored and the order of `files` does not matter.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the config, the tokenizer or any weights are
+    /// missing, or if there is more than one config or tokenizer.
+    pub fn classify(files: impl IntoIterator<Item = PathBuf>) -> Result<Self> {
+        let mut config = None;
+        let mut tokenizer = None;
+        let mut weights = Vec::new();
+
+        for file in files {
+            let Some(name) = file.file_name().and_then(|n| n.to_str()) else {
+                continue;
+            };
+            let slot = match name {
+                "config.json" => &mut config,
+                "tekken.json" | "tokenizer.json" => &mut tokenizer,
+                _ if name.ends_with(".safetensors") => {
+                    weights.push(file);
+                    continue;
+                }
+                _ => continue,
+            };
+            if let Some(previous) = slot.replace(file.clone()) {
+                anyhow::bail!(
+                    "Found both {} and {} among the model files",
+                    previous.display(),
+                    file.display()
+                );
+            }
+        }
+
+        weights.sort();
+        if weights.is_empty() {
+            anyhow::bail!("No .safetensors weights among the model files");
+        }
+        Ok(Self {
+            config: config.context("No config.json among the model files")?,
+            tokenizer: tokenizer
+                .context("No tekken.json or tokenizer.json among the model files")?,
+            weights,
+        })
+    }
+}
+
+/// Model folder used when none is given, relative to the working directory
+/// and named after the repository.
+pub const DEFAULT_MODEL_DIR: &str = "Voxtral-Mini-3B-2507";
 
-/// Download model artifacts from Hugging Face Hub for a given model id.
+/// Hugging Face Hub repository the model is downloaded from.
+const MODEL_REPO: &str = "mistralai/Voxtral-Mini-3B-2507";
+
+/// Download model artifacts from Hugging Face Hub into `model_dir`.
+///
+/// When `model_dir` already holds the `config.json`, a tokenizer and a
+/// complete set of weight shards, they are returned as they are and the Hub
+/// is never contacted. Otherwise this function fetches the `config.json`,
+/// every `model-*-of-*.safetensors` shard (or a single `model.safetensors`)
+/// listed in the repository or in its `model.safetensors.index.json`, and a
+/// tokenizer file (the first of `tekken.json`, `tokenizer.json` and
+/// `tokenizer/tokenizer.json` that exists locally or can be downloaded).
 ///
-/// This function fetches the `config.json`, a set of `safetensors` weight files
-/// (matching common Voxtral naming patterns) and a tokenizer file (tries
-/// `tekken.json` and `tokenizer/tokenizer.json`). It returns a tuple with the
-/// config, the list of safetensors files and the tokenizer path.
+/// With `refresh`, the local files are not trusted: every file is fetched
+/// from the Hub again, bypassing its cache, and replaces the local copy only
+/// once it is complete.
 ///
 /// # Errors
 ///
 /// Returns an error if any of the network requests or file retrievals fail.
-pub fn model_files() -> Result<Vec<PathBuf>> {
-    let revision = "main";
+pub fn model_files(model_dir: &Path, refresh: bool) -> Result<ModelPaths> {
+    model_files_from(model_dir, refresh, hub_repo)
+}
 
-    // Local model folder name (same as repository name)
-    let model_dir = PathBuf::from("Voxtral-Mini-3B-2507");
+/// [`model_files`] for weights loaded from elsewhere, such as a GGUF file:
+/// only the `config.json` and a tokenizer are looked for and downloaded,
+/// and `weights` is left empty.
+///
+/// # Errors
+///
+/// Returns an error if any of the network requests or file retrievals fail.
+pub fn model_files_without_weights(model_dir: &Path, refresh: bool) -> Result<ModelPaths> {
+    model_files_without_weights_from(model_dir, refresh, hub_repo)
+}
 
+/// The model repository on the Hugging Face Hub.
+fn hub_repo() -> Result<ApiRepo> {
     // https://huggingface.co/mistralai/Voxtral-Mini-3B-2507
-    let model_files: Vec<&str> = vec![
-        "config.json",
-        "model-00001-of-00002.safetensors",
-        "model-00002-of-00002.safetensors",
-        "tekken.json",
-    ];
-    let mut existing_files: Vec<&str> = Vec::new();
-
-    // If the folder already exists and contains expected files, use them.
-    if model_dir.exists() {
-        // Which of the model files already exist and which not?
-        existing_files = model_files
-            .iter()
-            .filter(|p| model_dir.join(p).exists())
-            .cloned()
-            .collect();
+    let api = Api::new().context("Failed to create Hugging Face Hub client")?;
+    Ok(api.repo(Repo::with_revision(
+        MODEL_REPO.to_string(),
+        RepoType::Model,
+        "main".to_string(),
+    )))
+}
+
+/// [`model_files`] with the Hub repository created by `connect`, which is
+/// only called when something has to be downloaded.
+fn model_files_from(
+    model_dir: &Path,
+    refresh: bool,
+    connect: impl FnOnce() -> Result<ApiRepo>,
+) -> Result<ModelPaths> {
+    // Files left by an interrupted download are never complete
+    remove_partial_downloads(model_dir);
 
-        if existing_files.len() == model_files.len() {
-            println!("Using existing model files in {}", model_dir.display());
-            return Ok(existing_files.iter().map(|p| model_dir.join(p)).collect());
+    // If the folder already contains every file, use them.
+    if !refresh {
+        if let Some(files) = local_model_files(model_dir) {
+            log::info!("Using existing model files in {}", model_dir.display());
+            return ModelPaths::classify(files);
         }
+    }
+
+    // Ensure local directory exists
+    std::fs::create_dir_all(model_dir)?;
+
+    // Otherwise download the missing files, or all of them when refreshing,
+    // into the local folder.
+    let repo = connect()?;
+    let fetch = |filename: &str| download(&repo, model_dir, filename, refresh);
+    let missing = |filename: &str| refresh || !model_dir.join(filename).exists();
+
+    if refresh {
+        log::info!("Refreshing model files in {}...", model_dir.display());
     } else {
-        // Ensure local directory exists
-        std::fs::create_dir_all(&model_dir)?;
+        log::info!("Downloading model files into {}...", model_dir.display());
+    }
+    if missing(CONFIG_FILE) {
+        fetch(CONFIG_FILE)?;
     }
 
-    // Otherwise download into the local folder.
-    let api = Api::new().unwrap();
-    let repo = api.repo(Repo::with_revision(
-        "mistralai/Voxtral-Mini-3B-2507".to_string(),
-        RepoType::Model,
-        revision.to_string(),
-    ));
+    let shards = remote_shards(&repo, model_dir, refresh)?;
+    for shard in &shards {
+        if missing(shard) {
+            fetch(shard)?;
+        }
+    }
 
-    // Download model files - look for safetensors
-    let needed_files: Vec<&str> = model_files
-        .iter()
-        .filter(|p| existing_files.contains(p))
-        .cloned()
+    let tokenizer = tokenizer(model_dir, refresh, fetch)?;
+
+    let mut files = vec![model_dir.join(CONFIG_FILE), tokenizer];
+    files.extend(shards.iter().map(|shard| model_dir.join(shard)));
+    ModelPaths::classify(files)
+}
+
+/// [`model_files_without_weights`] with the Hub repository created by
+/// `connect`, which is only called when something has to be downloaded.
+fn model_files_without_weights_from(
+    model_dir: &Path,
+    refresh: bool,
+    connect: impl FnOnce() -> Result<ApiRepo>,
+) -> Result<ModelPaths> {
+    remove_partial_downloads(model_dir);
+
+    let config = model_dir.join(CONFIG_FILE);
+    if !refresh && config.exists() {
+        if let Some(tokenizer) = local_tokenizer(model_dir) {
+            log::info!("Using existing model files in {}", model_dir.display());
+            return Ok(ModelPaths {
+                config,
+                tokenizer,
+                weights: Vec::new(),
+            });
+        }
+    }
+
+    std::fs::create_dir_all(model_dir)?;
+    let repo = connect()?;
+    let fetch = |filename: &str| download(&repo, model_dir, filename, refresh);
+
+    log::info!(
+        "Downloading the model config and tokenizer into {}...",
+        model_dir.display()
+    );
+    if refresh || !config.exists() {
+        fetch(CONFIG_FILE)?;
+    }
+    let tokenizer = tokenizer(model_dir, refresh, fetch)?;
+    Ok(ModelPaths {
+        config,
+        tokenizer,
+        weights: Vec::new(),
+    })
+}
+
+/// The tokenizer in `model_dir` or, when there is none or with `refresh`,
+/// the first one `fetch` can download.
+fn tokenizer(
+    model_dir: &Path,
+    refresh: bool,
+    fetch: impl Fn(&str) -> Result<PathBuf>,
+) -> Result<PathBuf> {
+    match local_tokenizer(model_dir).filter(|_| !refresh) {
+        Some(tokenizer) => Ok(tokenizer),
+        None => first_present(TOKENIZER_FILES, fetch)?.with_context(|| {
+            format!(
+                "No tokenizer found in model repository, tried {}",
+                TOKENIZER_FILES.join(", ")
+            )
+        }),
+    }
+}
+
+/// The model files in `model_dir`, if the config, the tokenizer and a
+/// complete set of weight shards are present.
+fn local_model_files(model_dir: &Path) -> Option<Vec<PathBuf>> {
+    let config = model_dir.join(CONFIG_FILE);
+    if !config.exists() {
+        return None;
+    }
+    let names: Vec<String> = std::fs::read_dir(model_dir)
+        .ok()?
+        .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
         .collect();
-    let mut downloaded_files: Vec<PathBuf> = Vec::new();
-    println!("Downloading model files...");
-    for filename in &needed_files {
-        match repo.get(filename) {
-            Ok(tmp) => {
-                let target = model_dir.join(filename);
-                std::fs::copy(&tmp, &target)?;
-                println!("{} downloaded -> {}", filename, target.display());
-                downloaded_files.push(target);
+    let shards = weight_shards(names.iter().map(String::as_str));
+    if !shards_complete(&shards) {
+        return None;
+    }
+
+    let mut files = vec![config, local_tokenizer(model_dir)?];
+    files.extend(shards.iter().map(|shard| model_dir.join(shard)));
+    Some(files)
+}
+
+/// Names of the weight shards in the repository, from its file listing or,
+/// failing that, from its shard index.
+fn remote_shards(repo: &ApiRepo, model_dir: &Path, refresh: bool) -> Result<Vec<String>> {
+    let listed = match with_retries("Listing the model repository", || repo.info()) {
+        Ok(info) => weight_shards(info.siblings.iter().map(|s| s.rfilename.as_str())),
+        // A mirror may not list files, the shard index names them as well
+        Err(e) if failure_of(&e) != Some(Failure::Transient) => {
+            log::info!("{e:#}, reading the shard index instead");
+            Vec::new()
+        }
+        Err(e) => return Err(e),
+    };
+    let shards = if listed.is_empty() {
+        let index = download(repo, model_dir, SHARD_INDEX_FILE, refresh)?;
+        shards_from_index(&std::fs::read_to_string(index)?)?
+    } else {
+        listed
+    };
+    if !shards_complete(&shards) {
+        anyhow::bail!(
+            "Incomplete set of weight shards in model repository: {}",
+            shards.join(", ")
+        );
+    }
+    Ok(shards)
+}
+
+/// Shard number and shard count of a `model-00001-of-00002.safetensors` name.
+fn shard_position(name: &str) -> Option<(usize, usize)> {
+    let (index, count) = name
+        .strip_prefix("model-")?
+        .strip_suffix(".safetensors")?
+        .split_once("-of-")?;
+    Some((index.parse().ok()?, count.parse().ok()?))
+}
+
+/// The weight files among `names`, sorted and without duplicates.
+///
+/// When a merged `model.safetensors` sits next to numbered shards, only one
+/// of them is loaded: the shards if all of them are there, else the merged
+/// file.
+fn weight_shards<'a>(names: impl IntoIterator<Item = &'a str>) -> Vec<String> {
+    let mut single = false;
+    let mut shards: Vec<String> = names
+        .into_iter()
+        .filter(|name| {
+            single |= *name == SINGLE_WEIGHTS_FILE;
+            shard_position(name).is_some()
+        })
+        .map(str::to_string)
+        .collect();
+    shards.sort();
+    shards.dedup();
+    if single && !shards_complete(&shards) {
+        return vec![SINGLE_WEIGHTS_FILE.to_string()];
+    }
+    shards
+}
+
+/// Whether `shards` is a single `model.safetensors` or every shard from 1 to
+/// the shard count.
+fn shards_complete(shards: &[String]) -> bool {
+    if shards.len() == 1 && shards[0] == SINGLE_WEIGHTS_FILE {
+        return true;
+    }
+    let Some(Some((_, count))) = shards.first().map(|name| shard_position(name)) else {
+        return false;
+    };
+    let mut indices: Vec<usize> = Vec::with_capacity(shards.len());
+    for shard in shards {
+        match shard_position(shard) {
+            Some((index, c)) if c == count => indices.push(index),
+            _ => return false,
+        }
+    }
+    indices.sort_unstable();
+    indices == (1..=count).collect::<Vec<_>>()
+}
+
+/// Shard names referenced by the `weight_map` of a safetensors index.
+fn shards_from_index(index_json: &str) -> Result<Vec<String>> {
+    let index: serde_json::Value =
+        serde_json::from_str(index_json).context("Failed to parse safetensors index")?;
+    let weight_map = index
+        .get("weight_map")
+        .and_then(|map| map.as_object())
+        .context("Safetensors index has no weight_map")?;
+    Ok(weight_shards(
+        weight_map.values().filter_map(|v| v.as_str()),
+    ))
+}
+
+/// The first tokenizer of [`TOKENIZER_FILES`] present in `model_dir`.
+fn local_tokenizer(model_dir: &Path) -> Option<PathBuf> {
+    TOKENIZER_FILES
+        .iter()
+        .map(|f| model_dir.join(f))
+        .find(|f| f.exists())
+}
+
+/// Fetch `filename` from `repo` into the same relative path under `model_dir`.
+///
+/// With `refresh` the file is downloaded even when the Hub cache holds it.
+/// Requests that were rate limited or hit a server error are retried, see
+/// [`with_retries`].
+fn download(repo: &ApiRepo, model_dir: &Path, filename: &str, refresh: bool) -> Result<PathBuf> {
+    let cached = with_retries(&format!("Downloading {filename}"), || {
+        if refresh {
+            repo.download(filename)
+        } else {
+            repo.get(filename)
+        }
+    })?;
+    let target = model_dir.join(filename);
+    if let Some(parent) = target.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    write_atomically(&target, |partial| {
+        std::fs::copy(&cached, partial)?;
+        Ok(())
+    })
+    .with_context(|| format!("Failed to copy {filename} into {}", model_dir.display()))?;
+    log::info!("{} downloaded -> {}", filename, target.display());
+    Ok(target)
+}
+
+/// How a failed Hub request is handled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Failure {
+    /// HTTP 404: the repository has no such file.
+    Missing,
+    /// HTTP 429 or 5xx: rate limited or a server error, worth retrying.
+    Transient,
+    /// Any other failure, such as a refused token or no network connection.
+    Fatal,
+}
+
+impl Failure {
+    fn of(error: &ApiError) -> Self {
+        match http_status(error) {
+            Some(404) => Failure::Missing,
+            Some(429 | 500..=599) => Failure::Transient,
+            _ => Failure::Fatal,
+        }
+    }
+}
+
+/// The HTTP status the Hub answered a failed request with, if it answered.
+fn http_status(error: &ApiError) -> Option<u16> {
+    match error {
+        ApiError::RequestError(error) => match error.as_ref() {
+            ureq::Error::Status(status, _) => Some(*status),
+            ureq::Error::Transport(_) => None,
+        },
+        ApiError::TooManyRetries(error) => http_status(error),
+        _ => None,
+    }
+}
+
+/// How `error` from [`with_retries`] failed, or `None` if it did not come
+/// from a Hub request.
+fn failure_of(error: &anyhow::Error) -> Option<Failure> {
+    error.downcast_ref::<ApiError>().map(Failure::of)
+}
+
+/// Wait the Hub asked for in the `Retry-After` header of a failed request.
+fn retry_after(error: &ApiError) -> Option<Duration> {
+    match error {
+        ApiError::RequestError(error) => match error.as_ref() {
+            ureq::Error::Status(_, response) => response
+                .header("Retry-After")?
+                .trim()
+                .parse()
+                .ok()
+                .map(Duration::from_secs),
+            ureq::Error::Transport(_) => None,
+        },
+        _ => None,
+    }
+}
+
+/// Why `error` failed, naming the HTTP status when the Hub answered.
+fn describe(error: &ApiError) -> String {
+    match http_status(error) {
+        Some(429) => "HTTP 429, rate limited by the Hugging Face Hub".to_string(),
+        Some(404) => "HTTP 404, no such file in the repository".to_string(),
+        Some(status @ (401 | 403)) => {
+            format!("HTTP {status}, access denied; check the Hugging Face token")
+        }
+        Some(status @ 500..=599) => format!("HTTP {status}, server error at the Hugging Face Hub"),
+        Some(status) => format!("HTTP {status}"),
+        None => match error {
+            ApiError::RequestError(_) => "network error".to_string(),
+            _ => "local error".to_string(),
+        },
+    }
+}
+
+/// Run the Hub request `call`, retrying it with exponential backoff while
+/// it is rate limited or hits a server error. `what` names the request in
+/// the error.
+///
+/// # Errors
+///
+/// Returns the last [`ApiError`], with `what` and the HTTP status as its
+/// context, when the request fails for another reason or still fails after
+/// [`MAX_RETRIES`] retries.
+fn with_retries<T>(what: &str, call: impl FnMut() -> Result<T, ApiError>) -> Result<T> {
+    with_retries_sleeping(what, call, std::thread::sleep)
+}
+
+/// [`with_retries`] waiting with `sleep`.
+fn with_retries_sleeping<T>(
+    what: &str,
+    mut call: impl FnMut() -> Result<T, ApiError>,
+    mut sleep: impl FnMut(Duration),
+) -> Result<T> {
+    let mut backoff = FIRST_BACKOFF;
+    let mut retries = 0;
+    loop {
+        let error = match call() {
+            Ok(value) => return Ok(value),
+            Err(error) => error,
+        };
+        let failure = Failure::of(&error);
+        if failure != Failure::Transient || retries == MAX_RETRIES {
+            let attempts = if retries > 0 {
+                format!(" after {} attempts", retries + 1)
+            } else {
+                String::new()
+            };
+            let message = format!("{what} failed{attempts}: {}", describe(&error));
+            return Err(anyhow::Error::new(error).context(message));
+        }
+        let wait = retry_after(&error).unwrap_or(backoff).min(MAX_BACKOFF);
+        log::warn!(
+            "{what} failed: {}, retrying in {} s",
+            describe(&error),
+            wait.as_secs_f32()
+        );
+        sleep(wait);
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+        retries += 1;
+    }
+}
+
+/// The result of `fetch` for the first of `filenames` the repository has,
+/// or `None` when it has none of them. Only a missing file moves on to the
+/// next name; any other failure is returned.
+fn first_present<'a>(
+    filenames: impl IntoIterator<Item = &'a str>,
+    mut fetch: impl FnMut(&str) -> Result<PathBuf>,
+) -> Result<Option<PathBuf>> {
+    for filename in filenames {
+        match fetch(filename) {
+            Ok(path) => return Ok(Some(path)),
+            Err(e) if failure_of(&e) == Some(Failure::Missing) => {
+                log::debug!("{e:#}");
             }
-            Err(_) => continue,
+            Err(e) => return Err(e),
         }
     }
+    Ok(None)
+}
+
+/// Sibling of `target` that holds it while it is being written.
+fn partial_path(target: &Path) -> PathBuf {
+    let mut path = target.as_os_str().to_owned();
+    path.push(PARTIAL_SUFFIX);
+    PathBuf::from(path)
+}
 
-    if downloaded_files.is_empty() {
-        anyhow::bail!("No model files found in model repository");
+/// Create `target` with `write`, which writes the file at the path it is
+/// given, so that `target` only ever appears complete.
+///
+/// The file is written next to `target` and renamed over it once `write`
+/// succeeds; when `write` fails the partial file is removed.
+fn write_atomically(target: &Path, write: impl FnOnce(&Path) -> Result<()>) -> Result<()> {
+    let partial = partial_path(target);
+    let written = write(&partial).and_then(|()| {
+        std::fs::rename(&partial, target)
+            .with_context(|| format!("Failed to move {} into place", partial.display()))
+    });
+    if written.is_err() {
+        let _ = std::fs::remove_file(&partial);
     }
+    written
+}
+
+/// Whether `name` is one of the files downloaded into a model folder.
+fn is_model_file(name: &str) -> bool {
+    [CONFIG_FILE, SHARD_INDEX_FILE, SINGLE_WEIGHTS_FILE].contains(&name)
+        || TOKENIZER_FILES
+            .iter()
+            .any(|file| Path::new(file).file_name() == Some(name.as_ref()))
+        || shard_position(name).is_some()
+}
 
-    Ok(model_files.iter().map(|p| model_dir.join(p)).collect())
+/// Remove the partial downloads that an interrupted run left in `model_dir`
+/// and its tokenizer folder: model files with [`PARTIAL_SUFFIX`] appended
+/// that were not written to for [`PARTIAL_GRACE`], so that one another run
+/// is downloading is left alone, as are files of the user's.
+fn remove_partial_downloads(model_dir: &Path) {
+    for dir in [model_dir.to_path_buf(), model_dir.join("tokenizer")] {
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let partial = path
+                .file_name()
+                .and_then(|name| name.to_str()?.strip_suffix(PARTIAL_SUFFIX))
+                .is_some_and(is_model_file);
+            let stale = entry
+                .metadata()
+                .and_then(|metadata| metadata.modified())
+                .is_ok_and(|modified| modified.elapsed().is_ok_and(|age| age >= PARTIAL_GRACE));
+            if partial && stale && path.is_file() {
+                log::info!("Removing partial download {}", path.display());
+                if let Err(e) = std::fs::remove_file(&path) {
+                    log::warn!("Failed to remove {}: {e}", path.display());
+                }
+            }
+        }
+    }
 }
@@ -1,5 +1,6 @@
 use super::*;
 use std::fs;
+use std::path::Path;
 use tempfile::TempDir;
 
 #[test]
@@ -18,7 +19,7 @@ fn test_model_dir_creation() {
     // The test expectation depends on whether files exist or not
     // If no files exist, it will try to download (which may fail in test environment)
     // If files exist, it will return them
-    let result = model_files();
+    let result = model_files(Path::new(DEFAULT_MODEL_DIR), false);
 
     // Clean up after test
     if model_dir.exists() {
@@ -27,13 +28,9 @@ fn test_model_dir_creation() {
 
     // The function may succeed (if network/files available) or fail
     // Both are acceptable for this test - we're checking it doesn't panic
-    if let Ok(files) = result {
+    if let Ok(paths) = result {
         // If successful, verify we got expected files
-        assert!(!files.is_empty(), "Should return at least one file");
-        assert!(
-            files.len() >= 3,
-            "Should return config, tokenizer, and safetensors files"
-        );
+        assert!(!paths.weights.is_empty(), "Should return safetensors files");
     }
 }
 
@@ -73,13 +70,13 @@ fn test_model_files_determinism() {
     // Verify that calling model_files() multiple times is deterministic
     // (doesn't create duplicate directories or files)
 
-    let result1 = model_files();
-    let result2 = model_files();
+    let result1 = model_files(Path::new(DEFAULT_MODEL_DIR), false);
+    let result2 = model_files(Path::new(DEFAULT_MODEL_DIR), false);
 
     // Both should either succeed or fail consistently
     match (result1, result2) {
-        (Ok(files1), Ok(files2)) => {
-            assert_eq!(files1.len(), files2.len());
+        (Ok(paths1), Ok(paths2)) => {
+            assert_eq!(paths1, paths2);
         }
         (Err(_), Err(_)) => {
             // Both failed - acceptable if network/file system issues
@@ -98,20 +95,27 @@ fn test_model_files_error_handling() {
 
     // The function should return an error if HuggingFace API fails
     // We can't easily mock this, but we can verify the function structure
-    let result = model_files();
+    let result = model_files(Path::new(DEFAULT_MODEL_DIR), false);
 
     // Either succeed with files or return an error
     match result {
-        Ok(files) => {
-            assert!(!files.is_empty(), "If successful, should return files");
+        Ok(paths) => {
+            assert!(
+                !paths.weights.is_empty(),
+                "If successful, should return files"
+            );
             // Verify all returned paths are valid
-            for path in files {
+            for path in paths
+                .weights
+                .iter()
+                .chain([&paths.config, &paths.tokenizer])
+            {
                 assert!(path.exists(), "Returned file should exist: {:?}", path);
             }
         }
         Err(e) => {
             // Expected if network is unavailable or repo doesn't exist
-            assert!(e.to_string().len() > 0, "Error should have a message");
+            assert!(!e.to_string().is_empty(), "Error should have a message");
         }
     }
 }
@@ -119,7 +123,7 @@ fn test_model_files_error_handling() {
 #[test]
 fn test_required_files_structure() {
     // Verify that the expected file structure is correct
-    let expected_files = vec![
+    let expected_files = [
         "config.json",
         "tekken.json",
         "model-00001-of-00002.safetensors",
@@ -163,7 +167,7 @@ fn test_file_existence_check() {
     fs::write(&test_file, "test content").unwrap();
 
     assert!(test_file.exists());
-    assert!(test_dir.path().join("nonexistent.txt").exists() == false);
+    assert!(!test_dir.path().join("nonexistent.txt").exists());
 }
 
 #[test]
@@ -172,7 +176,7 @@ fn test_download_api_pattern() {
     // hf_hub uses Repo::with_revision pattern
 
     let repo_name = "mistralai/Voxtral-Mini-3B-2507";
-    let repo_type = hf_hub::RepoType::Model;
+    let _repo_type = hf_hub::RepoType::Model;
     let revision = "main";
 
     // Verify the components used to construct the repo
@@ -184,14 +188,14 @@ fn test_download_api_pattern() {
 #[test]
 fn test_model_files_filtering_logic() {
     // Test the filtering logic used to find existing files
-    let model_files = vec![
+    let model_files = [
         "config.json",
         "tekken.json",
         "model-00001-of-00002.safetensors",
         "model-00002-of-00002.safetensors",
     ];
 
-    let existing_files = vec!["config.json", "tekken.json"];
+    let existing_files = ["config.json", "tekken.json"];
 
     // Find files that don't exist yet
     let needed_files: Vec<&str> = model_files
@@ -204,3 +208,544 @@ fn test_model_files_filtering_logic() {
     assert!(needed_files.contains(&"model-00001-of-00002.safetensors"));
     assert!(needed_files.contains(&"model-00002-of-00002.safetensors"));
 }
+
+fn fake_model_dir(tokenizer: Option<&str>) -> TempDir {
+    let temp_dir = TempDir::new().unwrap();
+    for filename in [
+        "config.json",
+        "model-00001-of-00002.safetensors",
+        "model-00002-of-00002.safetensors",
+    ] {
+        fs::write(temp_dir.path().join(filename), "fake").unwrap();
+    }
+    if let Some(tokenizer) = tokenizer {
+        let path = temp_dir.path().join(tokenizer);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, "{}").unwrap();
+    }
+    temp_dir
+}
+
+#[test]
+fn test_local_model_files_for_each_tokenizer_layout() {
+    for tokenizer in TOKENIZER_FILES {
+        let temp_dir = fake_model_dir(Some(tokenizer));
+
+        let files = local_model_files(temp_dir.path()).unwrap();
+
+        assert_eq!(files.len(), 4);
+        assert!(files[0].ends_with("config.json"));
+        assert_eq!(files[1], temp_dir.path().join(tokenizer));
+        assert!(files[2].ends_with("model-00001-of-00002.safetensors"));
+        assert!(files[3].ends_with("model-00002-of-00002.safetensors"));
+    }
+}
+
+#[test]
+fn test_local_tokenizer_prefers_tekken() {
+    let temp_dir = fake_model_dir(Some("tokenizer/tokenizer.json"));
+    fs::write(temp_dir.path().join("tekken.json"), "{}").unwrap();
+
+    assert_eq!(
+        local_tokenizer(temp_dir.path()),
+        Some(temp_dir.path().join("tekken.json"))
+    );
+}
+
+#[test]
+fn test_local_model_files_incomplete() {
+    let temp_dir = fake_model_dir(None);
+    assert!(local_model_files(temp_dir.path()).is_none());
+
+    let temp_dir = fake_model_dir(Some("tekken.json"));
+    fs::remove_file(temp_dir.path().join("model-00002-of-00002.safetensors")).unwrap();
+    assert!(local_model_files(temp_dir.path()).is_none());
+}
+
+#[test]
+fn test_classify_model_files_ignores_order() {
+    let files = [
+        "dir/model-00002-of-00002.safetensors",
+        "dir/tokenizer/tokenizer.json",
+        "dir/model.safetensors.index.json",
+        "dir/config.json",
+        "dir/model-00001-of-00002.safetensors",
+    ]
+    .map(PathBuf::from);
+
+    let paths = ModelPaths::classify(files).unwrap();
+
+    assert_eq!(paths.config, PathBuf::from("dir/config.json"));
+    assert_eq!(
+        paths.tokenizer,
+        PathBuf::from("dir/tokenizer/tokenizer.json")
+    );
+    assert_eq!(
+        paths.weights,
+        [
+            PathBuf::from("dir/model-00001-of-00002.safetensors"),
+            PathBuf::from("dir/model-00002-of-00002.safetensors"),
+        ]
+    );
+}
+
+#[test]
+fn test_classify_model_files_reports_missing_and_duplicates() {
+    let err = ModelPaths::classify(["config.json", "tekken.json"].map(PathBuf::from)).unwrap_err();
+    assert!(err.to_string().contains("No .safetensors weights"));
+
+    let err =
+        ModelPaths::classify(["config.json", "a.safetensors"].map(PathBuf::from)).unwrap_err();
+    assert!(err.to_string().contains("No tekken.json or tokenizer.json"));
+
+    let err = ModelPaths::classify(
+        [
+            "config.json",
+            "a.safetensors",
+            "tekken.json",
+            "tokenizer.json",
+        ]
+        .map(PathBuf::from),
+    )
+    .unwrap_err();
+    assert!(err
+        .to_string()
+        .contains("Found both tekken.json and tokenizer.json"));
+}
+
+#[test]
+fn test_local_model_files_classify() {
+    let temp_dir = fake_model_dir(Some("tekken.json"));
+    let paths = ModelPaths::classify(local_model_files(temp_dir.path()).unwrap()).unwrap();
+
+    assert_eq!(paths.config, temp_dir.path().join("config.json"));
+    assert_eq!(paths.tokenizer, temp_dir.path().join("tekken.json"));
+    assert_eq!(paths.weights.len(), 2);
+}
+
+#[test]
+fn test_shard_position() {
+    assert_eq!(
+        shard_position("model-00001-of-00002.safetensors"),
+        Some((1, 2))
+    );
+    assert_eq!(
+        shard_position("model-00003-of-00003.safetensors"),
+        Some((3, 3))
+    );
+    assert_eq!(shard_position("model.safetensors"), None);
+    assert_eq!(shard_position("model-00001-of-00002.bin"), None);
+    assert_eq!(shard_position("consolidated.safetensors"), None);
+}
+
+#[test]
+fn test_weight_shards_from_listing() {
+    let listing = [
+        "README.md",
+        "config.json",
+        "model-00002-of-00003.safetensors",
+        "model-00001-of-00003.safetensors",
+        "model-00003-of-00003.safetensors",
+        "consolidated.safetensors",
+        "model.safetensors.index.json",
+        "tekken.json",
+    ];
+
+    let shards = weight_shards(listing);
+
+    assert_eq!(
+        shards,
+        [
+            "model-00001-of-00003.safetensors",
+            "model-00002-of-00003.safetensors",
+            "model-00003-of-00003.safetensors",
+        ]
+    );
+    assert!(shards_complete(&shards));
+}
+
+#[test]
+fn test_weight_shards_merged_next_to_shards() {
+    let mut listing = vec![
+        "model.safetensors",
+        "model-00001-of-00002.safetensors",
+        "model-00002-of-00002.safetensors",
+    ];
+    assert_eq!(
+        weight_shards(listing.iter().copied()),
+        [
+            "model-00001-of-00002.safetensors",
+            "model-00002-of-00002.safetensors",
+        ]
+    );
+
+    listing.pop();
+    assert_eq!(
+        weight_shards(listing.iter().copied()),
+        ["model.safetensors"]
+    );
+}
+
+#[test]
+fn test_classify_single_merged_weights() {
+    let paths = ModelPaths::classify(
+        [
+            "dir/tekken.json",
+            "dir/model.safetensors",
+            "dir/config.json",
+        ]
+        .map(PathBuf::from),
+    )
+    .unwrap();
+
+    assert_eq!(paths.weights, [PathBuf::from("dir/model.safetensors")]);
+}
+
+#[test]
+fn test_shards_complete() {
+    let shards = |names: &[&str]| names.iter().map(|n| n.to_string()).collect::<Vec<_>>();
+
+    assert!(shards_complete(&shards(&["model.safetensors"])));
+    assert!(shards_complete(&shards(&[
+        "model-00001-of-00001.safetensors"
+    ])));
+    assert!(!shards_complete(&shards(&[])));
+    assert!(!shards_complete(&shards(&[
+        "model-00001-of-00002.safetensors"
+    ])));
+    assert!(!shards_complete(&shards(&[
+        "model-00001-of-00002.safetensors",
+        "model-00002-of-00003.safetensors",
+    ])));
+}
+
+#[test]
+fn test_shards_from_index() {
+    let index = r#"{
+        "metadata": {"total_size": 1},
+        "weight_map": {
+            "a.weight": "model-00002-of-00002.safetensors",
+            "b.weight": "model-00001-of-00002.safetensors",
+            "c.weight": "model-00001-of-00002.safetensors"
+        }
+    }"#;
+
+    assert_eq!(
+        shards_from_index(index).unwrap(),
+        [
+            "model-00001-of-00002.safetensors",
+            "model-00002-of-00002.safetensors",
+        ]
+    );
+    assert!(shards_from_index("{}").is_err());
+}
+
+#[test]
+fn test_local_model_files_single_and_partial_shards() {
+    let temp_dir = TempDir::new().unwrap();
+    fs::write(temp_dir.path().join("config.json"), "{}").unwrap();
+    fs::write(temp_dir.path().join("tekken.json"), "{}").unwrap();
+    fs::write(temp_dir.path().join("model.safetensors"), "fake").unwrap();
+    assert_eq!(local_model_files(temp_dir.path()).unwrap().len(), 3);
+
+    fs::remove_file(temp_dir.path().join("model.safetensors")).unwrap();
+    fs::write(
+        temp_dir.path().join("model-00001-of-00003.safetensors"),
+        "fake",
+    )
+    .unwrap();
+    fs::write(
+        temp_dir.path().join("model-00003-of-00003.safetensors"),
+        "fake",
+    )
+    .unwrap();
+    assert!(local_model_files(temp_dir.path()).is_none());
+}
+
+#[test]
+fn test_model_files_from_complete_absolute_dir_skips_hub() {
+    let temp_dir = fake_model_dir(Some("tekken.json"));
+    let model_dir = fs::canonicalize(temp_dir.path()).unwrap();
+    assert!(model_dir.is_absolute());
+
+    let paths = model_files_from(&model_dir, false, || {
+        panic!("the Hub must not be contacted for a complete model directory")
+    })
+    .unwrap();
+
+    assert_eq!(paths.config, model_dir.join("config.json"));
+    assert_eq!(paths.tokenizer, model_dir.join("tekken.json"));
+    assert_eq!(paths.weights.len(), 2);
+}
+
+#[test]
+fn test_model_files_without_weights_need_no_shards() {
+    let temp_dir = TempDir::new().unwrap();
+    fs::write(temp_dir.path().join("config.json"), "{}").unwrap();
+    fs::write(temp_dir.path().join("tekken.json"), "{}").unwrap();
+
+    let paths = model_files_without_weights_from(temp_dir.path(), false, || {
+        panic!("the Hub must not be contacted for the config and tokenizer")
+    })
+    .unwrap();
+    assert_eq!(paths.tokenizer, temp_dir.path().join("tekken.json"));
+    assert!(paths.weights.is_empty());
+
+    fs::remove_file(temp_dir.path().join("tekken.json")).unwrap();
+    let err = model_files_without_weights_from(temp_dir.path(), false, || anyhow::bail!("offline"))
+        .unwrap_err();
+    assert_eq!(err.to_string(), "offline");
+}
+
+#[test]
+fn test_model_files_from_incomplete_dir_connects() {
+    let temp_dir = fake_model_dir(None);
+
+    let err = model_files_from(temp_dir.path(), false, || anyhow::bail!("offline")).unwrap_err();
+
+    assert_eq!(err.to_string(), "offline");
+}
+
+#[test]
+fn test_refresh_downloads_over_a_complete_dir() {
+    let temp_dir = fake_model_dir(Some("tekken.json"));
+    assert!(local_model_files(temp_dir.path()).is_some());
+
+    let err = model_files_from(temp_dir.path(), true, || anyhow::bail!("offline")).unwrap_err();
+
+    assert_eq!(err.to_string(), "offline");
+    // A refresh that cannot connect keeps the local copies usable
+    let paths = model_files_from(temp_dir.path(), false, || {
+        panic!("the Hub must not be contacted for a complete model directory")
+    })
+    .unwrap();
+    assert_eq!(paths.weights.len(), 2);
+    assert_eq!(fs::read_to_string(&paths.config).unwrap(), "fake");
+}
+
+#[test]
+fn test_failed_copy_leaves_no_partial_file() {
+    let temp_dir = fake_model_dir(Some("tekken.json"));
+    let shard = temp_dir.path().join("model-00002-of-00002.safetensors");
+    fs::remove_file(&shard).unwrap();
+
+    // The connection drops halfway through the copy
+    let err = write_atomically(&shard, |partial| {
+        fs::write(partial, "half of the weights")?;
+        anyhow::bail!("connection reset")
+    })
+    .unwrap_err();
+
+    assert_eq!(err.to_string(), "connection reset");
+    assert!(!shard.exists());
+    assert!(!partial_path(&shard).exists());
+    // So the next run sees the model as incomplete and downloads again
+    assert!(local_model_files(temp_dir.path()).is_none());
+    let err = model_files_from(temp_dir.path(), false, || anyhow::bail!("offline")).unwrap_err();
+    assert_eq!(err.to_string(), "offline");
+
+    write_atomically(&shard, |partial| {
+        fs::write(partial, "all of the weights")?;
+        Ok(())
+    })
+    .unwrap();
+    assert_eq!(fs::read_to_string(&shard).unwrap(), "all of the weights");
+    assert!(!partial_path(&shard).exists());
+}
+
+/// Write `path` as if it was last modified `age` ago.
+fn write_aged(path: &Path, age: Duration) {
+    fs::write(path, "half").unwrap();
+    let modified = std::time::SystemTime::now() - age;
+    fs::File::options()
+        .write(true)
+        .open(path)
+        .unwrap()
+        .set_modified(modified)
+        .unwrap();
+}
+
+#[test]
+fn test_partial_downloads_are_removed_on_startup() {
+    let temp_dir = fake_model_dir(Some("tekken.json"));
+    let old = PARTIAL_GRACE * 2;
+    let stray = temp_dir
+        .path()
+        .join("model-00001-of-00002.safetensors.kitsune-partial");
+    write_aged(&stray, old);
+    fs::create_dir(temp_dir.path().join("tokenizer")).unwrap();
+    let stray_tokenizer = temp_dir
+        .path()
+        .join("tokenizer/tokenizer.json.kitsune-partial");
+    write_aged(&stray_tokenizer, old);
+    // Files of the user's and a download in progress in another run stay
+    let kept = [
+        temp_dir.path().join("notes.tmp"),
+        temp_dir.path().join("config.json.tmp"),
+        temp_dir.path().join("notes.kitsune-partial"),
+    ];
+    for path in &kept {
+        write_aged(path, old);
+    }
+    let in_progress = temp_dir.path().join("config.json.kitsune-partial");
+    write_aged(&in_progress, Duration::ZERO);
+
+    let paths = model_files_from(temp_dir.path(), false, || {
+        panic!("the Hub must not be contacted for a complete model directory")
+    })
+    .unwrap();
+
+    assert_eq!(paths.weights.len(), 2);
+    assert!(!stray.exists());
+    assert!(!stray_tokenizer.exists());
+    assert!(temp_dir.path().join("config.json").exists());
+    for path in kept.iter().chain([&in_progress]) {
+        assert!(path.exists(), "{}", path.display());
+    }
+}
+
+/// A Hub request answered with HTTP `status` and the raw `headers`.
+fn status_error(status: u16, headers: &str) -> ApiError {
+    let response: ureq::Response = format!("HTTP/1.1 {status} Failed\r\n{headers}\r\n")
+        .parse()
+        .unwrap();
+    ApiError::RequestError(Box::new(ureq::Error::Status(status, response)))
+}
+
+/// A Hub request that never got an answer.
+fn network_error() -> ApiError {
+    let io = std::io::Error::new(std::io::ErrorKind::ConnectionReset, "connection reset");
+    ApiError::RequestError(Box::new(ureq::Error::from(io)))
+}
+
+#[test]
+fn test_failure_classification() {
+    assert_eq!(Failure::of(&status_error(404, "")), Failure::Missing);
+    for status in [429, 500, 502, 503] {
+        assert_eq!(Failure::of(&status_error(status, "")), Failure::Transient);
+    }
+    for error in [
+        status_error(401, ""),
+        status_error(403, ""),
+        network_error(),
+        ApiError::IoError(std::io::Error::other("disk full")),
+    ] {
+        assert_eq!(Failure::of(&error), Failure::Fatal, "{error}");
+    }
+    let retried = ApiError::TooManyRetries(Box::new(status_error(503, "")));
+    assert_eq!(Failure::of(&retried), Failure::Transient);
+
+    assert_eq!(http_status(&status_error(429, "")), Some(429));
+    assert_eq!(http_status(&network_error()), None);
+    assert_eq!(describe(&network_error()), "network error");
+    assert_eq!(
+        describe(&status_error(403, "")),
+        "HTTP 403, access denied; check the Hugging Face token"
+    );
+}
+
+#[test]
+fn test_retries_rate_limits_with_backoff() {
+    let mut calls = 0;
+    let mut waits = Vec::new();
+    let value = with_retries_sleeping(
+        "Downloading config.json",
+        || {
+            calls += 1;
+            match calls {
+                1 => Err(status_error(429, "")),
+                2 => Err(status_error(503, "")),
+                3 => Err(status_error(429, "Retry-After: 7\r\n")),
+                _ => Ok("config"),
+            }
+        },
+        |wait| waits.push(wait),
+    )
+    .unwrap();
+
+    assert_eq!(value, "config");
+    assert_eq!(calls, 4);
+    assert_eq!(
+        waits,
+        [1, 2, 7].map(Duration::from_secs),
+        "doubling, unless the Hub says how long"
+    );
+}
+
+#[test]
+fn test_retries_give_up_with_the_http_status() {
+    let mut calls = 0;
+    let mut waits = Vec::new();
+    let err = with_retries_sleeping(
+        "Downloading config.json",
+        || -> Result<(), ApiError> {
+            calls += 1;
+            Err(status_error(429, "Retry-After: 3600\r\n"))
+        },
+        |wait| waits.push(wait),
+    )
+    .unwrap_err();
+
+    assert_eq!(calls, MAX_RETRIES + 1);
+    assert!(waits.iter().all(|&wait| wait == MAX_BACKOFF));
+    assert_eq!(
+        err.to_string(),
+        "Downloading config.json failed after 6 attempts: \
+         HTTP 429, rate limited by the Hugging Face Hub"
+    );
+    assert_eq!(failure_of(&err), Some(Failure::Transient));
+}
+
+#[test]
+fn test_missing_and_fatal_failures_are_not_retried() {
+    for (error, message) in [
+        (
+            status_error(404, ""),
+            "Downloading tekken.json failed: HTTP 404, no such file in the repository",
+        ),
+        (
+            network_error(),
+            "Downloading tekken.json failed: network error",
+        ),
+    ] {
+        let mut error = Some(error);
+        let err = with_retries_sleeping(
+            "Downloading tekken.json",
+            || -> Result<(), ApiError> { Err(error.take().unwrap()) },
+            |_| panic!("no retry expected"),
+        )
+        .unwrap_err();
+        assert_eq!(err.to_string(), message);
+    }
+}
+
+#[test]
+fn test_first_present_only_skips_missing_files() {
+    let fetch_from = |missing: &'static [&'static str], failing: &'static str| {
+        move |filename: &str| -> Result<PathBuf> {
+            let failed =
+                |error: fn() -> ApiError| with_retries_sleeping(filename, || Err(error()), |_| {});
+            if missing.contains(&filename) {
+                failed(|| status_error(404, ""))
+            } else if filename == failing {
+                failed(network_error)
+            } else {
+                Ok(PathBuf::from(filename))
+            }
+        }
+    };
+
+    let found = first_present(TOKENIZER_FILES, fetch_from(&["tekken.json"], "")).unwrap();
+    assert_eq!(found, Some(PathBuf::from("tokenizer.json")));
+
+    let none = first_present(TOKENIZER_FILES, fetch_from(&TOKENIZER_FILES, "")).unwrap();
+    assert_eq!(none, None);
+
+    // A network failure is reported instead of trying the next name
+    let err = first_present(
+        TOKENIZER_FILES,
+        fetch_from(&["tekken.json"], "tokenizer.json"),
+    )
+    .unwrap_err();
+    assert_eq!(err.to_string(), "tokenizer.json failed: network error");
+}
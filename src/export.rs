@@ -0,0 +1,76 @@
+use anyhow::{Context, Result};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use crate::audio;
+use crate::output::{csv_field, Segment};
+
+#[cfg(test)]
+mod tests;
+
+/// Writes the audio of each transcribed chunk to its own WAV clip, with a
+/// CSV manifest pairing every clip with its text and time range.
+///
+/// Clips are named `<stem>_<index>.wav` after the chunk index and hold
+/// exactly the samples that were transcribed, so their length matches the
+/// segment's time range.
+pub struct ChunkExporter {
+    dir: PathBuf,
+    stem: String,
+    manifest_path: PathBuf,
+    manifest: BufWriter<File>,
+}
+
+impl ChunkExporter {
+    /// Create `dir` if needed and start `<stem>_manifest.csv` in it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the directory or the manifest cannot be created.
+    pub fn create(dir: &Path, stem: &str) -> Result<Self> {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create chunk directory {}", dir.display()))?;
+        let manifest_path = dir.join(format!("{stem}_manifest.csv"));
+        let mut manifest = BufWriter::new(
+            File::create(&manifest_path)
+                .with_context(|| format!("Failed to create {}", manifest_path.display()))?,
+        );
+        writeln!(manifest, "file,start_sec,end_sec,text")?;
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            stem: stem.to_string(),
+            manifest_path,
+            manifest,
+        })
+    }
+
+    /// Path of the manifest.
+    pub fn manifest_path(&self) -> &Path {
+        &self.manifest_path
+    }
+
+    /// Write `samples`, the audio `segment` was transcribed from, as a clip
+    /// and add its manifest row.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the clip or the manifest row cannot be written.
+    pub fn export(&mut self, segment: &Segment, samples: &[f32], sample_rate: u32) -> Result<()> {
+        let file_name = format!("{}_{:04}.wav", self.stem, segment.index);
+        let clip_path = self.dir.join(&file_name);
+        audio::write_wav(&clip_path, samples, sample_rate)
+            .with_context(|| format!("Failed to write chunk clip {}", clip_path.display()))?;
+        writeln!(
+            self.manifest,
+            "{},{:.3},{:.3},{}",
+            csv_field(&file_name),
+            segment.start_sec,
+            segment.end_sec,
+            csv_field(&segment.text)
+        )?;
+        self.manifest
+            .flush()
+            .context("Failed to write chunk manifest")
+    }
+}
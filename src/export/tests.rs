@@ -0,0 +1,33 @@
+use super::*;
+use crate::output::fixtures::segment;
+use tempfile::TempDir;
+
+#[test]
+fn test_export_writes_clips_and_manifest() {
+    let dir = TempDir::new().unwrap();
+    let out = dir.path().join("chunks");
+    let mut exporter = ChunkExporter::create(&out, "talk").unwrap();
+
+    exporter
+        .export(
+            &segment(0, "Hello, world", Some(-0.2)),
+            &[0.0; 16_000],
+            16_000,
+        )
+        .unwrap();
+    exporter
+        .export(&segment(1, "Again", Some(-0.2)), &[0.0; 8_000], 16_000)
+        .unwrap();
+
+    let manifest = std::fs::read_to_string(exporter.manifest_path()).unwrap();
+    assert_eq!(
+        manifest,
+        "file,start_sec,end_sec,text\n\
+         talk_0000.wav,0.000,15.000,\"Hello, world\"\n\
+         talk_0001.wav,13.500,28.500,Again\n"
+    );
+
+    // The clip holds exactly the chunk: 16-bit mono after a 44-byte header
+    let clip = std::fs::metadata(out.join("talk_0001.wav")).unwrap();
+    assert_eq!(clip.len(), 44 + 8_000 * 2);
+}
@@ -0,0 +1,317 @@
+use anyhow::Result;
+use std::time::{Duration, Instant};
+
+#[cfg(test)]
+mod tests;
+
+/// Settings for the token generation loop.
+#[derive(Debug, Clone)]
+pub struct GenerationConfig {
+    /// Upper bound on the number of tokens generated after the prompt.
+    pub max_new_tokens: usize,
+    /// Token ids that terminate generation. They are not emitted.
+    pub eos_tokens: Vec<u32>,
+    /// Penalty applied to the logits of already generated tokens; 1.0 disables it.
+    pub repetition_penalty: f32,
+    /// Forbid repeating any n-gram of this size; 0 disables the guard.
+    pub no_repeat_ngram_size: usize,
+    /// Stop generating once this much wall-clock time has passed, including
+    /// the prompt step. Checked between steps, so one slow step can overrun it.
+    pub timeout: Option<Duration>,
+    /// Sample each token from the softmax of the logits divided by this
+    /// value; 0.0 always picks the most likely token.
+    pub temperature: f32,
+    /// Seed of the sampler, so that a sampled generation can be repeated.
+    pub seed: u64,
+    /// Token ids that are never selected, such as symbols that are not speech.
+    pub suppress_tokens: Vec<u32>,
+}
+
+/// Tokens produced by [`generate`] together with decoding statistics.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Generation {
+    /// Generated tokens, excluding the prompt and the terminating EOS token.
+    pub tokens: Vec<u32>,
+    /// Mean log-probability of every selected token, including a final EOS.
+    /// Values near 0 mean the model was certain; 0.0 if nothing was selected.
+    pub avg_logprob: f32,
+    /// Probability of ending immediately: the EOS mass at the first step.
+    /// High values mean the audio most likely holds no speech.
+    pub no_speech_prob: f32,
+    /// Whether generation was cut short by [`GenerationConfig::timeout`].
+    pub truncated: bool,
+}
+
+/// Run greedy or sampled decoding on top of a `step` function producing
+/// next-token logits.
+///
+/// `step` is first called with the full `prompt` and then with each newly
+/// generated token on its own, mirroring how a KV-cached decoder is driven.
+/// It must return the logits for the next position as a flat `Vec<f32>`.
+///
+/// Every generated token is passed to `on_token` as soon as it is selected,
+/// which lets callers stream partial output. When the configured timeout
+/// passes, the tokens so far are returned with `truncated` set.
+pub fn generate<S, F>(
+    prompt: &[u32],
+    mut step: S,
+    config: &GenerationConfig,
+    mut on_token: F,
+) -> Result<Generation>
+where
+    S: FnMut(&[u32]) -> Result<Vec<f32>>,
+    F: FnMut(u32),
+{
+    let mut generated: Vec<u32> = Vec::new();
+    let mut sum_logprob = 0f32;
+    let mut selected = 0usize;
+    let mut truncated = false;
+    let mut sampler = Sampler::new(config.seed);
+    let started = Instant::now();
+    let mut logits = step(prompt)?;
+    let no_speech_prob = probability_mass(&logits, &config.eos_tokens);
+
+    for _ in 0..config.max_new_tokens {
+        apply_repetition_penalty(&mut logits, &generated, config.repetition_penalty);
+        ban_repeated_ngrams(&mut logits, &generated, config.no_repeat_ngram_size);
+        suppress_tokens(&mut logits, &config.suppress_tokens);
+
+        let next_token = sampler
+            .pick(&logits, config.temperature)
+            .ok_or_else(|| anyhow::anyhow!("Model produced no finite logits"))?;
+        sum_logprob += log_softmax_at(&logits, next_token);
+        selected += 1;
+
+        if config.eos_tokens.contains(&next_token) {
+            break;
+        }
+
+        generated.push(next_token);
+        on_token(next_token);
+
+        // Also stop on runs of pad tokens, which indicate the model is stuck
+        if generated.len() >= 5 && generated[generated.len() - 5..].iter().all(|&t| t == 0) {
+            break;
+        }
+
+        if config
+            .timeout
+            .is_some_and(|timeout| started.elapsed() >= timeout)
+        {
+            truncated = true;
+            break;
+        }
+
+        logits = step(&[next_token])?;
+    }
+
+    Ok(Generation {
+        tokens: generated,
+        avg_logprob: if selected > 0 {
+            sum_logprob / selected as f32
+        } else {
+            0.0
+        },
+        no_speech_prob,
+        truncated,
+    })
+}
+
+/// Total softmax probability of `tokens` under `logits`.
+pub fn probability_mass(logits: &[f32], tokens: &[u32]) -> f32 {
+    let mut unique = tokens.to_vec();
+    unique.sort_unstable();
+    unique.dedup();
+    unique
+        .iter()
+        .map(|&token| log_softmax_at(logits, token).exp())
+        .sum()
+}
+
+/// Log-probability of `token` under the softmax of `logits`.
+///
+/// Non-finite logits, such as masked tokens, carry no probability mass.
+pub fn log_softmax_at(logits: &[f32], token: u32) -> f32 {
+    let max = logits
+        .iter()
+        .copied()
+        .filter(|v| v.is_finite())
+        .fold(f32::NEG_INFINITY, f32::max);
+    let sum: f32 = logits
+        .iter()
+        .filter(|v| v.is_finite())
+        .map(|&v| (v - max).exp())
+        .sum();
+    logits
+        .get(token as usize)
+        .map_or(f32::NEG_INFINITY, |&v| v - max - sum.ln())
+}
+
+/// Penalise tokens that were already generated, as in the CTRL paper.
+///
+/// Positive logits are divided by `penalty` and negative ones multiplied, so
+/// a penalty above 1.0 always makes a repeat less likely. Each token is
+/// penalised once no matter how often it occurred.
+pub fn apply_repetition_penalty(logits: &mut [f32], generated: &[u32], penalty: f32) {
+    if penalty == 1.0 {
+        return;
+    }
+    let mut seen = std::collections::HashSet::new();
+    for &token in generated {
+        if !seen.insert(token) {
+            continue;
+        }
+        if let Some(logit) = logits.get_mut(token as usize) {
+            if *logit >= 0.0 {
+                *logit /= penalty;
+            } else {
+                *logit *= penalty;
+            }
+        }
+    }
+}
+
+/// Mask every token that would complete an n-gram already present in
+/// `generated`.
+pub fn ban_repeated_ngrams(logits: &mut [f32], generated: &[u32], ngram_size: usize) {
+    if ngram_size == 0 || generated.len() < ngram_size {
+        return;
+    }
+    let prefix = &generated[generated.len() + 1 - ngram_size..];
+    for window in generated.windows(ngram_size) {
+        if &window[..ngram_size - 1] == prefix {
+            if let Some(logit) = logits.get_mut(window[ngram_size - 1] as usize) {
+                *logit = f32::NEG_INFINITY;
+            }
+        }
+    }
+}
+
+/// Mask `tokens` so they are never selected. Ids outside `logits` are ignored.
+pub fn suppress_tokens(logits: &mut [f32], tokens: &[u32]) {
+    for &token in tokens {
+        if let Some(logit) = logits.get_mut(token as usize) {
+            *logit = f32::NEG_INFINITY;
+        }
+    }
+}
+
+/// Index of the largest finite logit, or `None` if there is none.
+pub fn argmax(logits: &[f32]) -> Option<u32> {
+    let mut best: Option<(usize, f32)> = None;
+    for (index, &value) in logits.iter().enumerate() {
+        if !value.is_finite() {
+            continue;
+        }
+        if best.is_none_or(|(_, best_value)| value > best_value) {
+            best = Some((index, value));
+        }
+    }
+    best.map(|(index, _)| index as u32)
+}
+
+/// Seeded token sampler, a SplitMix64 generator so that runs are repeatable
+/// without depending on a random number crate.
+#[derive(Debug, Clone)]
+pub struct Sampler {
+    state: u64,
+}
+
+impl Sampler {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    /// Uniform value in `[0, 1)`.
+    fn next_f32(&mut self) -> f32 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^= z >> 31;
+        // The top 24 bits fill the mantissa exactly
+        (z >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    /// Token drawn from the softmax of `logits / temperature`, or the
+    /// [`argmax`] when `temperature` is 0.0. Non-finite logits are never
+    /// drawn; `None` if there is no finite logit.
+    pub fn pick(&mut self, logits: &[f32], temperature: f32) -> Option<u32> {
+        let best = argmax(logits)?;
+        if temperature <= 0.0 {
+            return Some(best);
+        }
+        let max = logits[best as usize];
+        let weights: Vec<f32> = logits
+            .iter()
+            .map(|&v| {
+                if v.is_finite() {
+                    ((v - max) / temperature).exp()
+                } else {
+                    0.0
+                }
+            })
+            .collect();
+        let mut target = self.next_f32() * weights.iter().sum::<f32>();
+        for (index, &weight) in weights.iter().enumerate() {
+            if weight > 0.0 && target < weight {
+                return Some(index as u32);
+            }
+            target -= weight;
+        }
+        // Rounding left the target past the last weight
+        Some(best)
+    }
+}
+
+/// Incremental UTF-8 decoder for streamed token bytes.
+///
+/// A single token may hold only part of a multi-byte character, so bytes are
+/// buffered until they form complete characters. Invalid sequences are
+/// replaced with U+FFFD rather than dropped.
+#[derive(Debug, Default)]
+pub struct Utf8Stream {
+    pending: Vec<u8>,
+}
+
+impl Utf8Stream {
+    /// Append `bytes` and return the text that is now complete.
+    pub fn push(&mut self, bytes: &[u8]) -> String {
+        self.pending.extend_from_slice(bytes);
+
+        let mut out = String::new();
+        loop {
+            match std::str::from_utf8(&self.pending) {
+                Ok(text) => {
+                    out.push_str(text);
+                    self.pending.clear();
+                    break;
+                }
+                Err(e) => {
+                    let valid = e.valid_up_to();
+                    out.push_str(std::str::from_utf8(&self.pending[..valid]).unwrap_or_default());
+                    match e.error_len() {
+                        // Invalid bytes: replace them and keep going
+                        Some(len) => {
+                            out.push(char::REPLACEMENT_CHARACTER);
+                            self.pending.drain(..valid + len);
+                        }
+                        // Incomplete character at the end: wait for more bytes
+                        None => {
+                            self.pending.drain(..valid);
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+        out
+    }
+
+    /// Flush whatever is still buffered, replacing an incomplete tail.
+    pub fn finish(&mut self) -> String {
+        let out = String::from_utf8_lossy(&self.pending).into_owned();
+        self.pending.clear();
+        out
+    }
+}
@@ -0,0 +1,361 @@
+use super::*;
+
+/// Build a step function that replays a fixed sequence of argmax choices.
+fn scripted_step(choices: Vec<u32>, vocab: usize) -> impl FnMut(&[u32]) -> Result<Vec<f32>> {
+    let mut calls = 0usize;
+    move |_input: &[u32]| {
+        let mut logits = vec![0f32; vocab];
+        let choice = choices[calls.min(choices.len() - 1)] as usize;
+        logits[choice] = 10.0;
+        calls += 1;
+        Ok(logits)
+    }
+}
+
+fn config(max_new_tokens: usize) -> GenerationConfig {
+    GenerationConfig {
+        max_new_tokens,
+        eos_tokens: vec![2],
+        repetition_penalty: 1.0,
+        no_repeat_ngram_size: 0,
+        timeout: None,
+        temperature: 0.0,
+        seed: 0,
+        suppress_tokens: Vec::new(),
+    }
+}
+
+/// A step function for a model stuck in the loop 5 -> 6 -> 7 -> 5 ...,
+/// where EOS is always the runner-up.
+fn looping_step() -> impl FnMut(&[u32]) -> Result<Vec<f32>> {
+    |input: &[u32]| {
+        let mut logits = vec![0f32; 10];
+        let next = match input.last() {
+            Some(5) => 6,
+            Some(6) => 7,
+            _ => 5,
+        };
+        logits[next] = 3.0;
+        logits[2] = 2.5;
+        Ok(logits)
+    }
+}
+
+#[test]
+fn test_generate_stops_at_eos() {
+    let step = scripted_step(vec![5, 6, 7, 2, 8], 10);
+    let tokens = generate(&[1, 3], step, &config(100), |_| {})
+        .unwrap()
+        .tokens;
+    assert_eq!(tokens, vec![5, 6, 7]);
+}
+
+#[test]
+fn test_generate_respects_max_new_tokens() {
+    let step = scripted_step(vec![5], 10);
+    let tokens = generate(&[1], step, &config(4), |_| {}).unwrap().tokens;
+    assert_eq!(tokens.len(), 4);
+}
+
+#[test]
+fn test_generate_streams_each_token() {
+    let step = scripted_step(vec![5, 6, 2], 10);
+    let mut streamed = Vec::new();
+    let tokens = generate(&[1], step, &config(10), |t| streamed.push(t))
+        .unwrap()
+        .tokens;
+    assert_eq!(streamed, tokens);
+}
+
+#[test]
+fn test_generate_feeds_prompt_then_single_tokens() {
+    let mut inputs: Vec<Vec<u32>> = Vec::new();
+    let mut inner = scripted_step(vec![5, 6, 2], 10);
+    let step = |input: &[u32]| {
+        inputs.push(input.to_vec());
+        inner(input)
+    };
+    generate(&[1, 3, 4], step, &config(10), |_| {}).unwrap();
+    assert_eq!(inputs, vec![vec![1, 3, 4], vec![5], vec![6]]);
+}
+
+#[test]
+fn test_generate_stops_on_pad_run() {
+    let step = scripted_step(vec![0], 10);
+    let tokens = generate(&[1], step, &config(100), |_| {}).unwrap().tokens;
+    assert_eq!(tokens, vec![0; 5]);
+}
+
+#[test]
+fn test_argmax_skips_non_finite() {
+    assert_eq!(argmax(&[0.1, f32::NAN, 0.5, f32::INFINITY]), Some(2));
+    assert_eq!(argmax(&[f32::NAN]), None);
+    assert_eq!(argmax(&[]), None);
+}
+
+#[test]
+fn test_utf8_stream_splits_multibyte_characters() {
+    let text = "kitsune 狐 🦊";
+    let bytes = text.as_bytes();
+    let mut stream = Utf8Stream::default();
+    let mut out = String::new();
+    for byte in bytes {
+        let piece = stream.push(std::slice::from_ref(byte));
+        // Never emit a partial character
+        assert!(!piece.contains(char::REPLACEMENT_CHARACTER));
+        out.push_str(&piece);
+    }
+    out.push_str(&stream.finish());
+    assert_eq!(out, text);
+}
+
+#[test]
+fn test_utf8_stream_replaces_invalid_bytes() {
+    let mut stream = Utf8Stream::default();
+    let out = stream.push(&[b'a', 0xff, b'b']);
+    assert_eq!(out, "a\u{fffd}b");
+}
+
+#[test]
+fn test_utf8_stream_finish_flushes_incomplete_tail() {
+    let mut stream = Utf8Stream::default();
+    assert_eq!(stream.push(&[b'x', 0xe7, 0x8b]), "x");
+    assert_eq!(stream.finish(), "\u{fffd}");
+    assert_eq!(stream.finish(), "");
+}
+
+#[test]
+fn test_generate_loops_without_penalty() {
+    let tokens = generate(&[1], looping_step(), &config(12), |_| {})
+        .unwrap()
+        .tokens;
+    assert_eq!(tokens, [5, 6, 7].repeat(4));
+}
+
+#[test]
+fn test_repetition_penalty_breaks_loop() {
+    let config = GenerationConfig {
+        repetition_penalty: 1.5,
+        ..config(100)
+    };
+    let tokens = generate(&[1], looping_step(), &config, |_| {})
+        .unwrap()
+        .tokens;
+    assert_eq!(tokens, vec![5, 6, 7]);
+}
+
+#[test]
+fn test_no_repeat_ngram_breaks_loop() {
+    let config = GenerationConfig {
+        no_repeat_ngram_size: 3,
+        ..config(100)
+    };
+    let tokens = generate(&[1], looping_step(), &config, |_| {})
+        .unwrap()
+        .tokens;
+    assert_eq!(tokens, vec![5, 6, 7, 5, 6]);
+}
+
+#[test]
+fn test_repetition_penalty_handles_negative_logits() {
+    let mut logits = vec![2.0, -2.0, 1.0];
+    apply_repetition_penalty(&mut logits, &[0, 1, 0], 2.0);
+    assert_eq!(logits, vec![1.0, -4.0, 1.0]);
+}
+
+#[test]
+fn test_generate_reports_lower_confidence_for_flat_logits() {
+    let peaked = |_: &[u32]| {
+        let mut logits = vec![0f32; 10];
+        logits[5] = 10.0;
+        logits[2] = 9.0;
+        Ok(logits)
+    };
+    let flat = |_: &[u32]| {
+        let mut logits = vec![0f32; 10];
+        logits[5] = 1.0;
+        logits[2] = 0.5;
+        Ok(logits)
+    };
+
+    let sharp = generate(&[1], peaked, &config(3), |_| {}).unwrap();
+    let unsure = generate(&[1], flat, &config(3), |_| {}).unwrap();
+
+    assert_eq!(sharp.tokens, unsure.tokens);
+    assert!(sharp.avg_logprob <= 0.0);
+    assert!(unsure.avg_logprob < sharp.avg_logprob);
+}
+
+#[test]
+fn test_generate_avg_logprob_includes_eos() {
+    // Each step has two equally likely tokens, so every pick has p = 0.5
+    let mut calls = 0;
+    let step = |_: &[u32]| {
+        let mut logits = vec![f32::NEG_INFINITY; 6];
+        let pair = if calls == 0 { [4, 5] } else { [2, 3] };
+        logits[pair[0]] = 1.0;
+        logits[pair[1]] = 1.0;
+        calls += 1;
+        Ok(logits)
+    };
+    let generation = generate(&[1], step, &config(10), |_| {}).unwrap();
+    assert_eq!(generation.tokens, vec![4]);
+    assert!((generation.avg_logprob - 0.5f32.ln()).abs() < 1e-5);
+}
+
+#[test]
+fn test_log_softmax_at_ignores_masked_logits() {
+    let logits = [0.0, f32::NEG_INFINITY, 0.0];
+    assert!((log_softmax_at(&logits, 0) - 0.5f32.ln()).abs() < 1e-6);
+    assert_eq!(log_softmax_at(&logits, 1), f32::NEG_INFINITY);
+}
+
+#[test]
+fn test_generate_reports_no_speech_probability() {
+    // EOS and token 5 share the first step evenly
+    let mut calls = 0;
+    let step = |_: &[u32]| {
+        let mut logits = vec![f32::NEG_INFINITY; 6];
+        if calls == 0 {
+            logits[5] = 1.0;
+            logits[2] = 1.0;
+        } else {
+            logits[2] = 1.0;
+        }
+        calls += 1;
+        Ok(logits)
+    };
+    let generation = generate(&[1], step, &config(10), |_| {}).unwrap();
+    assert!((generation.no_speech_prob - 0.5).abs() < 1e-6);
+}
+
+#[test]
+fn test_probability_mass_sums_tokens_once() {
+    let logits = [0.0f32; 4];
+    assert!((probability_mass(&logits, &[1, 2, 2]) - 0.5).abs() < 1e-6);
+    assert_eq!(probability_mass(&logits, &[]), 0.0);
+}
+
+#[test]
+fn test_timeout_truncates_long_generation() {
+    // A model that never emits EOS and takes a while per step
+    let mut slow = scripted_step(vec![5], 10);
+    let step = |input: &[u32]| {
+        std::thread::sleep(Duration::from_millis(5));
+        slow(input)
+    };
+    let config = GenerationConfig {
+        timeout: Some(Duration::from_millis(50)),
+        ..config(1000)
+    };
+
+    let mut streamed = Vec::new();
+    let generation = generate(&[1], step, &config, |token| streamed.push(token)).unwrap();
+
+    assert!(generation.truncated);
+    assert!(!generation.tokens.is_empty());
+    assert!(generation.tokens.len() < 100, "{}", generation.tokens.len());
+    assert_eq!(generation.tokens, streamed);
+}
+
+#[test]
+fn test_generation_within_timeout_is_not_truncated() {
+    let config = GenerationConfig {
+        timeout: Some(Duration::from_secs(60)),
+        ..config(100)
+    };
+
+    let generation = generate(&[1], scripted_step(vec![5, 6, 2], 10), &config, |_| {}).unwrap();
+
+    assert!(!generation.truncated);
+    assert_eq!(generation.tokens, vec![5, 6]);
+}
+
+#[test]
+fn test_sampler_is_greedy_at_zero_temperature() {
+    let mut sampler = Sampler::new(7);
+    let logits = [0.0, 3.0, f32::NEG_INFINITY, 2.9];
+    for _ in 0..20 {
+        assert_eq!(sampler.pick(&logits, 0.0), Some(1));
+    }
+    assert_eq!(sampler.pick(&[f32::NAN], 1.0), None);
+}
+
+#[test]
+fn test_sampler_follows_the_softmax() {
+    let mut sampler = Sampler::new(42);
+    // Probabilities of about 0.73 and 0.27, never the masked token
+    let logits = [1.0, f32::NEG_INFINITY, 0.0];
+    let mut counts = [0usize; 3];
+    for _ in 0..10_000 {
+        counts[sampler.pick(&logits, 1.0).unwrap() as usize] += 1;
+    }
+    assert_eq!(counts[1], 0);
+    assert!((6_900..7_700).contains(&counts[0]), "{counts:?}");
+
+    // A high temperature flattens the distribution towards uniform
+    let mut counts = [0usize; 3];
+    for _ in 0..10_000 {
+        counts[sampler.pick(&logits, 100.0).unwrap() as usize] += 1;
+    }
+    assert!((4_700..5_300).contains(&counts[0]), "{counts:?}");
+}
+
+#[test]
+fn test_sampled_generation_is_repeatable() {
+    let flat = |_: &[u32]| Ok(vec![1.0f32; 10]);
+    let config = GenerationConfig {
+        temperature: 1.0,
+        seed: 3,
+        eos_tokens: vec![],
+        ..config(20)
+    };
+
+    let first = generate(&[1], flat, &config, |_| {}).unwrap().tokens;
+    let second = generate(&[1], flat, &config, |_| {}).unwrap().tokens;
+    let other_seed = GenerationConfig { seed: 4, ..config };
+    let third = generate(&[1], flat, &other_seed, |_| {}).unwrap().tokens;
+
+    assert_eq!(first, second);
+    assert_ne!(first, third);
+    assert!(first.iter().any(|&token| token != first[0]));
+}
+
+#[test]
+fn test_suppressed_tokens_are_never_selected() {
+    // Token 5 would win every step, then 6 as the runner-up
+    let step = |_: &[u32]| {
+        let mut logits = vec![0f32; 10];
+        logits[5] = 10.0;
+        logits[6] = 8.0;
+        logits[7] = 7.0;
+        Ok(logits)
+    };
+    let config = GenerationConfig {
+        suppress_tokens: vec![5, 6, 99],
+        eos_tokens: vec![],
+        ..config(6)
+    };
+
+    let tokens = generate(&[1], step, &config, |_| {}).unwrap().tokens;
+    assert_eq!(tokens, vec![7; 6]);
+
+    let sampled = GenerationConfig {
+        temperature: 5.0,
+        ..config.clone()
+    };
+    let tokens = generate(&[1], step, &sampled, |_| {}).unwrap().tokens;
+    assert_eq!(tokens.len(), 6);
+    assert!(
+        tokens.iter().all(|token| ![5, 6].contains(token)),
+        "{tokens:?}"
+    );
+}
+
+#[test]
+fn test_suppress_tokens_masks_logits() {
+    let mut logits = vec![1.0, 3.0, 2.0];
+    suppress_tokens(&mut logits, &[1, 7]);
+    assert_eq!(logits, vec![1.0, f32::NEG_INFINITY, 2.0]);
+    assert_eq!(argmax(&logits), Some(2));
+}
@@ -0,0 +1,204 @@
+//! Detection of the text the model tends to invent for the quiet tail of a
+//! recording, such as "Thank you." or a repeat of the previous segment.
+//!
+//! A final segment is flagged when its chunk is quiet and its text is either
+//! made up of filler phrases or near-identical to, or contained in, the text
+//! of the previous segment. Requiring a quiet chunk keeps a spoken "thank
+//! you" at the end of a talk.
+//!
+//! [`RollingContext`] carries the end of one chunk's transcript over to the
+//! next with `--condition-on-previous`, and drops it once the model starts
+//! repeating itself, so that the loop does not spread to the chunks after.
+
+#[cfg(test)]
+mod tests;
+
+/// Phrases commonly produced for trailing silence, compared after
+/// [`simplify`].
+pub const DEFAULT_FILLERS: [&str; 8] = [
+    "thank you",
+    "thanks",
+    "thank you for watching",
+    "thanks for watching",
+    "thank you for listening",
+    "thanks for listening",
+    "please subscribe",
+    "bye",
+];
+
+/// RMS level below which a chunk counts as quiet enough to hallucinate on,
+/// about -40 dBFS: above the silence threshold, which skips the chunk, but
+/// well below speech.
+pub const LOW_ENERGY_RMS: f32 = 0.01;
+
+/// Similarity from which two simplified texts count as the same.
+pub const SIMILARITY_THRESHOLD: f32 = 0.85;
+
+/// Words a text needs to count as a repeat by being contained in the
+/// previous segment, so that a short answer such as "yes" is kept.
+const MIN_CONTAINED_WORDS: usize = 3;
+
+/// Tokens of the previous chunk's transcript kept as context.
+pub const PREVIOUS_CONTEXT_TOKENS: usize = 64;
+
+/// Longest run of tokens, repeated back to back, that counts as a loop.
+const MAX_LOOP_PERIOD: usize = 16;
+
+/// Times a run has to repeat back to back to count as a loop.
+const MIN_LOOP_REPEATS: usize = 3;
+
+/// Tokens a loop has to cover, so that "no, no, no" stays speech.
+const MIN_LOOP_TOKENS: usize = 12;
+
+/// Settings of the trailing hallucination check.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrailingHallucination {
+    /// Filler phrases, in any case and punctuation.
+    pub fillers: Vec<String>,
+    /// Chunks with an RMS level from this up are never flagged.
+    pub low_energy_rms: f32,
+}
+
+impl Default for TrailingHallucination {
+    fn default() -> Self {
+        Self {
+            fillers: DEFAULT_FILLERS.iter().map(|f| f.to_string()).collect(),
+            low_energy_rms: LOW_ENERGY_RMS,
+        }
+    }
+}
+
+impl TrailingHallucination {
+    /// Whether `text`, transcribed from a final chunk at `chunk_rms`, is
+    /// likely a hallucination, given the `previous` segment's text.
+    pub fn is_likely(&self, text: &str, previous: Option<&str>, chunk_rms: f32) -> bool {
+        if chunk_rms >= self.low_energy_rms {
+            return false;
+        }
+        let simplified = simplify(text);
+        if simplified.is_empty() {
+            return false;
+        }
+        if self.is_filler(text) {
+            return true;
+        }
+        previous.map(simplify).is_some_and(|previous| {
+            similarity(&simplified, &previous) >= SIMILARITY_THRESHOLD
+                || is_contained(&simplified, &previous)
+        })
+    }
+
+    /// Whether every sentence of `text` is near-identical to a filler, as in
+    /// "Thank you. Thank you."
+    fn is_filler(&self, text: &str) -> bool {
+        let fillers: Vec<String> = self.fillers.iter().map(|f| simplify(f)).collect();
+        let mut sentences = text
+            .split(['.', '!', '?', '\n'])
+            .map(simplify)
+            .filter(|sentence| !sentence.is_empty())
+            .peekable();
+        sentences.peek().is_some()
+            && sentences.all(|sentence| {
+                fillers
+                    .iter()
+                    .any(|filler| similarity(&sentence, filler) >= SIMILARITY_THRESHOLD)
+            })
+    }
+}
+
+/// Whether the simplified `text` of at least [`MIN_CONTAINED_WORDS`] words
+/// appears in the simplified `previous` text as a run of whole words.
+fn is_contained(text: &str, previous: &str) -> bool {
+    text.split(' ').count() >= MIN_CONTAINED_WORDS
+        && format!(" {previous} ").contains(&format!(" {text} "))
+}
+
+/// `text` in lower case, with punctuation dropped and whitespace collapsed,
+/// so that "Thank you!" and "thank you" compare equal.
+pub fn simplify(text: &str) -> String {
+    text.to_lowercase()
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { ' ' })
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Similarity of `a` and `b` between 0 and 1: one minus their edit distance
+/// in characters over the length of the longer one.
+pub fn similarity(a: &str, b: &str) -> f32 {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let longest = a.len().max(b.len());
+    if longest == 0 {
+        return 1.0;
+    }
+    // Levenshtein distance, one row at a time
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.iter().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(ca != cb);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    1.0 - row[b.len()] as f32 / longest as f32
+}
+
+/// Whether `tokens` end in the same run of tokens repeated back to back,
+/// the loop the model falls into on unclear audio.
+pub fn ends_in_loop(tokens: &[u32]) -> bool {
+    (1..=MAX_LOOP_PERIOD.min(tokens.len())).any(|period| {
+        let run = &tokens[tokens.len() - period..];
+        let repeats = tokens
+            .rchunks_exact(period)
+            .take_while(|chunk| *chunk == run)
+            .count();
+        repeats >= MIN_LOOP_REPEATS && repeats * period >= MIN_LOOP_TOKENS
+    })
+}
+
+/// End of the previous chunk's transcript, given to the decoder of the next
+/// chunk like Whisper's condition on previous text.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct RollingContext {
+    max_tokens: usize,
+    tokens: Vec<u32>,
+}
+
+impl RollingContext {
+    /// An empty context keeping at most `max_tokens` tokens.
+    pub fn new(max_tokens: usize) -> Self {
+        Self {
+            max_tokens,
+            tokens: Vec::new(),
+        }
+    }
+
+    /// Tokens to place before the next chunk's transcript.
+    pub fn tokens(&self) -> &[u32] {
+        &self.tokens
+    }
+
+    /// Keep the last tokens of a chunk's transcript for the next chunk.
+    ///
+    /// A transcript that ends in a loop or was `truncated` clears the
+    /// context instead, as conditioning on it makes the next chunk repeat
+    /// it. Returns whether the context was kept.
+    pub fn follow(&mut self, tokens: &[u32], truncated: bool) -> bool {
+        if truncated || ends_in_loop(tokens) {
+            self.clear();
+            return false;
+        }
+        self.tokens = tokens[tokens.len().saturating_sub(self.max_tokens)..].to_vec();
+        true
+    }
+
+    /// Forget the context, such as after a chunk without a transcript.
+    pub fn clear(&mut self) {
+        self.tokens.clear();
+    }
+}
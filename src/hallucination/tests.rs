@@ -0,0 +1,111 @@
+use super::*;
+
+const QUIET: f32 = 0.002;
+const LOUD: f32 = 0.1;
+
+#[test]
+fn test_simplify() {
+    assert_eq!(simplify("  Thank you!  "), "thank you");
+    assert_eq!(simplify("Thanks, for   watching..."), "thanks for watching");
+    assert_eq!(simplify("¡Gracias!"), "gracias");
+    assert_eq!(simplify("..."), "");
+}
+
+#[test]
+fn test_similarity() {
+    assert_eq!(similarity("thank you", "thank you"), 1.0);
+    assert_eq!(similarity("", ""), 1.0);
+    assert_eq!(similarity("abc", ""), 0.0);
+    assert!((similarity("thank you", "thank yu") - 8.0 / 9.0).abs() < 1e-6);
+    assert!(similarity("thank you", "the weather") < SIMILARITY_THRESHOLD);
+}
+
+#[test]
+fn test_filler_on_quiet_final_chunk() {
+    let check = TrailingHallucination::default();
+
+    assert!(check.is_likely("Thank you.", Some("And that is all."), QUIET));
+    assert!(check.is_likely("Thanks for watching!", None, QUIET));
+    assert!(check.is_likely("Thank you. Thank you.", None, QUIET));
+    assert!(check.is_likely("Thank you fo watching", None, QUIET));
+}
+
+#[test]
+fn test_spoken_ending_is_kept() {
+    let check = TrailingHallucination::default();
+
+    // Loud enough to be speech
+    assert!(!check.is_likely("Thank you.", None, LOUD));
+    // Quiet, but more than a filler
+    assert!(!check.is_likely("Thank you. See you next week in Helsinki.", None, QUIET));
+    assert!(!check.is_likely("...", None, QUIET));
+    assert!(!check.is_likely("", Some(""), QUIET));
+}
+
+#[test]
+fn test_repeat_of_previous_segment() {
+    let check = TrailingHallucination::default();
+    let previous = Some("We will meet again on Monday at noon.");
+
+    assert!(check.is_likely("We will meet again on Monday at noon", previous, QUIET));
+    assert!(check.is_likely("on Monday at noon.", previous, QUIET));
+    assert!(!check.is_likely("We will meet again on Monday at noon", previous, LOUD));
+    assert!(!check.is_likely("Questions are welcome.", previous, QUIET));
+}
+
+#[test]
+fn test_short_or_partial_words_are_not_repeats() {
+    let check = TrailingHallucination::default();
+    let previous = Some("Do you agree? Yes, yesterday we did.");
+
+    assert!(!check.is_likely("Yes.", previous, QUIET));
+    assert!(!check.is_likely("yesterday we di", previous, QUIET));
+    assert!(check.is_likely("Yes, yesterday we did.", previous, QUIET));
+}
+
+#[test]
+fn test_custom_fillers_replace_defaults() {
+    let check = TrailingHallucination {
+        fillers: vec!["Kiitos katsomisesta".to_string()],
+        ..TrailingHallucination::default()
+    };
+
+    assert!(check.is_likely("Kiitos katsomisesta!", None, QUIET));
+    assert!(!check.is_likely("Thank you.", None, QUIET));
+}
+
+#[test]
+fn test_ends_in_loop() {
+    let speech: Vec<u32> = (100..140).collect();
+    assert!(!ends_in_loop(&speech));
+    assert!(!ends_in_loop(&[]));
+    // "no, no, no" is speech, a long run of one token is not
+    assert!(!ends_in_loop(&[7, 8, 9, 9, 9]));
+    assert!(ends_in_loop(&[9; 12]));
+
+    let mut looping = speech.clone();
+    for _ in 0..3 {
+        looping.extend([1, 2, 3, 4]);
+    }
+    assert!(ends_in_loop(&looping));
+    // A loop that was broken out of does not count
+    looping.extend(200..210);
+    assert!(!ends_in_loop(&looping));
+}
+
+#[test]
+fn test_rolling_context_is_bounded_and_reset() {
+    let mut context = RollingContext::new(4);
+    assert!(context.tokens().is_empty());
+
+    assert!(context.follow(&[1, 2, 3, 4, 5, 6], false));
+    assert_eq!(context.tokens(), [3, 4, 5, 6]);
+    assert!(context.follow(&[7, 8], false));
+    assert_eq!(context.tokens(), [7, 8]);
+
+    assert!(!context.follow(&[5; 20], false));
+    assert!(context.tokens().is_empty());
+    context.follow(&[7, 8], false);
+    assert!(!context.follow(&[9, 10], true));
+    assert!(context.tokens().is_empty());
+}
@@ -0,0 +1,68 @@
+//! Ctrl-C handling that lets a run stop between chunks, with its output
+//! complete up to the last finished chunk, instead of dying mid-write.
+//!
+//! [`install`] replaces the default Ctrl-C behaviour with setting a flag that
+//! the chunk loop polls through [`check`]. A second Ctrl-C ends the process
+//! at once with [`EXIT_CODE`]. Applications embedding the crate
+//! with their own signal handling can call [`request`] instead.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+#[cfg(test)]
+mod tests;
+
+/// Exit code of a run stopped by Ctrl-C: 128 plus the number of SIGINT, as
+/// shells report a process killed by it.
+pub const EXIT_CODE: i32 = 130;
+
+static REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// Error of a run that stopped because an interrupt was requested.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Interrupted;
+
+impl std::fmt::Display for Interrupted {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("interrupted")
+    }
+}
+
+impl std::error::Error for Interrupted {}
+
+/// Turn the first Ctrl-C into an interrupt request, on Unix and Windows.
+///
+/// # Errors
+///
+/// Returns an error if the Ctrl-C handler cannot be installed, or was
+/// already installed.
+pub fn install() -> Result<(), ctrlc::Error> {
+    ctrlc::set_handler(|| {
+        if REQUESTED.swap(true, Ordering::SeqCst) {
+            std::process::exit(EXIT_CODE);
+        }
+    })
+}
+
+/// Ask the running transcription to stop after the current chunk.
+pub fn request() {
+    REQUESTED.store(true, Ordering::SeqCst);
+}
+
+/// Whether an interrupt has been requested.
+pub fn requested() -> bool {
+    REQUESTED.load(Ordering::SeqCst)
+}
+
+/// Fail with [`Interrupted`] once an interrupt has been requested, for use
+/// between chunks.
+///
+/// # Errors
+///
+/// Returns [`Interrupted`] if an interrupt has been requested.
+pub fn check() -> Result<(), Interrupted> {
+    if requested() {
+        Err(Interrupted)
+    } else {
+        Ok(())
+    }
+}
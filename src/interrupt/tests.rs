@@ -0,0 +1,13 @@
+use super::*;
+
+#[test]
+fn test_request_makes_check_fail() {
+    // The only test touching the process-wide flag
+    assert_eq!(check(), Ok(()));
+
+    request();
+
+    assert!(requested());
+    assert_eq!(check(), Err(Interrupted));
+    assert_eq!(Interrupted.to_string(), "interrupted");
+}
@@ -0,0 +1,31 @@
+//! Speech-to-text with the Voxtral model on Candle.
+//!
+//! The `kitsune-stt` binary is a thin CLI over these modules. Library users
+//! typically decode audio with [`audio::pcm_decode`], resample it to the
+//! model's [`model::VoxtralModel::sample_rate`], [`SAMPLE_RATE`] for Voxtral,
+//! and pass it to [`model::VoxtralModel::transcribe_audio_16k`].
+
+pub mod align;
+pub mod audio;
+pub mod bench;
+pub mod chunk;
+pub mod download;
+pub mod export;
+pub mod generate;
+pub mod hallucination;
+pub mod interrupt;
+pub mod manifest;
+pub mod mel;
+pub mod memory;
+pub mod model;
+pub mod output;
+pub mod parallel;
+pub mod profile;
+pub mod progress;
+#[cfg(feature = "server")]
+pub mod server;
+pub mod summary;
+pub mod text;
+pub mod timeline;
+
+pub use audio::SAMPLE_RATE;
@@ -1,40 +1,599 @@
 use anyhow::{Context, Result};
-use clap::Parser;
-use model::VoxtralModel;
+use candle_core::Device;
+use clap::parser::ValueSource;
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand};
+use kitsune_stt::align::{self, WordTimestamp};
+use kitsune_stt::audio::{self, Downmix, PadMode, ResampleQuality};
+use kitsune_stt::bench::{BenchReport, Throughput};
+use kitsune_stt::chunk::{ChunkEvent, ChunkPlan};
+use kitsune_stt::download;
+use kitsune_stt::export::ChunkExporter;
+use kitsune_stt::hallucination::{RollingContext, TrailingHallucination, PREVIOUS_CONTEXT_TOKENS};
+use kitsune_stt::interrupt::{self, Interrupted};
+use kitsune_stt::manifest::{self, ManifestEntry};
+use kitsune_stt::mel;
+use kitsune_stt::model::{
+    self, DecodeStrategy, ModelDType, ModelOptions, TranscribeOptions, TranscriptionResult,
+    VoxtralModel, DEFAULT_FALLBACK_TEMPERATURES, VERBATIM_LANGUAGE,
+};
+use kitsune_stt::output::{
+    self, ChunkTokens, ConfidenceFilter, FormatList, FrontMatter, LineEnding, LowConfidenceAction,
+    OutputFormat, OutputLayout, OutputTemplate, PauseMarker, Segment, TemplateFields, TextWriter,
+};
+use kitsune_stt::parallel;
+use kitsune_stt::profile::{self, Profile, Stage};
+use kitsune_stt::progress::{self, Progress};
+use kitsune_stt::summary::RunSummary;
+use kitsune_stt::text::{self, Corrections};
+use kitsune_stt::timeline::{self, Timeline, WallClock};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::ffi::{OsStr, OsString};
 use std::fs::File;
-use std::io::{BufWriter, Write};
+use std::io::{BufWriter, IsTerminal, Write};
+use std::ops::Range;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
 
-mod audio;
-mod download;
-mod model;
+#[cfg(test)]
+#[path = "main/tests.rs"]
+mod tests;
 
-// Re-export SAMPLE_RATE for use in tests
-pub use audio::SAMPLE_RATE;
+/// Text of the segment written for a chunk that failed every attempt.
+const FAILED_PLACEHOLDER: &str = "[transcription failed]";
+
+/// Appended to the text of a chunk whose generation hit `--chunk-timeout-sec`.
+const TRUNCATED_MARKER: &str = "[truncated]";
+
+/// Log filter without `RUST_LOG`: this crate at info, its dependencies at warn.
+const DEFAULT_LOG_FILTER: &str = "warn,kitsune_stt=info";
+
+/// Last line of the plain text transcript of a run stopped by Ctrl-C.
+const INTERRUPTED_MARKER: &str = "[interrupted]";
+
+/// Most halvings of a chunk with `--retry-split`, at 1/16 of its length.
+const MAX_RETRY_SPLITS: usize = 4;
+
+/// Set by `--quiet`, which leaves out the informational lines on stdout.
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+/// Print an informational line, such as where a transcript was written, to
+/// stdout unless `--quiet` was given. The transcript and the results asked
+/// for, such as the `--dry-run` schedule, use `println!` instead.
+macro_rules! note {
+    ($($arg:tt)*) => {
+        if !QUIET.load(Ordering::Relaxed) {
+            println!($($arg)*);
+        }
+    };
+}
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
+#[command(args_conflicts_with_subcommands = true)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[command(flatten)]
+    args: Args,
+}
+
+impl Cli {
+    /// Parse `argv`, noting where `--language` came from, since
+    /// `--detect-language` conflicts with it on the command line but
+    /// overrides its `KITSUNE_LANGUAGE` default.
+    fn try_parse_with_sources<I, T>(argv: I) -> std::result::Result<Self, clap::Error>
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<OsString> + Clone,
+    {
+        let mut command = Cli::command();
+        let matches = command.try_get_matches_from_mut(argv)?;
+        let mut cli = Cli::from_arg_matches(&matches)?;
+        let (args, matches) = match (&mut cli.command, matches.subcommand()) {
+            (Some(Command::Bench(args)), Some((_, matches))) => (args, matches),
+            #[cfg(feature = "server")]
+            (Some(Command::Serve(serve_args)), Some((_, matches))) => {
+                (&mut serve_args.args, matches)
+            }
+            _ => (&mut cli.args, &matches),
+        };
+        args.language_from_env = matches.value_source("language") == Some(ValueSource::EnvVariable);
+        if args.detect_language && args.language.is_some() && !args.language_from_env {
+            return Err(command.error(
+                clap::error::ErrorKind::ArgumentConflict,
+                "--detect-language cannot be used with --language",
+            ));
+        }
+        Ok(cli)
+    }
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Transcribe a file and report wall-clock time and real-time factor.
+    Bench(Args),
+    /// Keep the model loaded and transcribe audio POSTed over HTTP.
+    #[cfg(feature = "server")]
+    Serve(ServeArgs),
+}
+
+#[cfg(feature = "server")]
+#[derive(clap::Args, Debug)]
+struct ServeArgs {
+    /// Port to listen on.
+    #[arg(long, default_value_t = 8080)]
+    port: u16,
+
+    /// Address to listen on; `0.0.0.0` accepts connections from other hosts.
+    #[arg(long, default_value = "127.0.0.1")]
+    host: String,
+
+    /// Largest upload accepted, in megabytes.
+    #[arg(long, value_name = "MB", default_value_t = 100)]
+    max_upload_mb: usize,
+
+    /// Most connections answered at once; more are refused with 503.
+    #[arg(long, value_name = "N", default_value_t = 4)]
+    max_connections: usize,
+
+    #[command(flatten)]
+    args: Args,
+}
+
+#[derive(clap::Args, Debug)]
 struct Args {
     /// The input audio file to be processed (any format supported by Symphonia).
     input: Option<PathBuf>,
 
+    /// Transcribe the files listed in this text file in order, reusing one
+    /// loaded model: one path per line, optionally followed by a tab and a
+    /// language code for that file. Lines starting with `#` are comments.
+    #[arg(
+        long,
+        value_name = "PATH",
+        conflicts_with_all = ["input", "summary", "dump_audio", "dump_tokens"]
+    )]
+    manifest: Option<PathBuf>,
+
     /// Run on CPU rather than on GPU.
-    #[arg(long, default_value_t = false)]
+    #[arg(
+        long,
+        env = "KITSUNE_CPU",
+        default_value_t = false,
+        value_parser = clap::builder::BoolishValueParser::new()
+    )]
     cpu: bool,
+
+    /// Fail instead of falling back to CPU when no GPU can be initialised.
+    #[arg(long, default_value_t = false, conflicts_with = "cpu")]
+    require_gpu: bool,
+
+    /// Compute dtype for the model weights. Defaults to f16 on GPU and f32 on CPU.
+    #[arg(long, env = "KITSUNE_DTYPE", value_enum)]
+    dtype: Option<ModelDType>,
+
+    /// Load the weights from this GGUF file instead of the safetensors shards,
+    /// which are then not downloaded. They are dequantized to --dtype while
+    /// loading, so the memory used stays the same.
+    #[arg(long, value_name = "GGUF_FILE")]
+    gguf_weights: Option<PathBuf>,
+
+    /// Folder holding the model files, such as an absolute path where they
+    /// were installed; missing files are downloaded into it.
+    #[arg(long, env = "KITSUNE_MODEL_DIR", value_name = "DIR")]
+    model_dir: Option<PathBuf>,
+
+    /// Download the model files again even when the model folder already
+    /// holds them all, replacing each local copy once its download completes.
+    #[arg(long, default_value_t = false)]
+    refresh: bool,
+
+    /// Resampler quality used when the input is not already 16 kHz.
+    #[arg(long, value_enum, default_value_t = ResampleQuality::High)]
+    resample_quality: ResampleQuality,
+
+    /// Refuse input that is not already 16 kHz instead of resampling it.
+    #[arg(long, default_value_t = false, conflicts_with = "resample_quality")]
+    no_resample: bool,
+
+    /// How audio shorter than a whole 30-second encoder window is padded:
+    /// with silence, mirrored, or with its last sample repeated.
+    #[arg(long, value_enum, default_value_t = PadMode::Zero)]
+    pad_mode: PadMode,
+
+    /// Keep NaN and infinite samples of a damaged file instead of replacing
+    /// them with silence; the model then refuses the audio.
+    #[arg(long, default_value_t = false)]
+    no_sanitize: bool,
+
+    /// How the channels of a multi-channel recording are mixed to mono;
+    /// `center-focus` favours the dialog channel of 5.1 and 7.1 mixes.
+    #[arg(long, value_enum, default_value_t = Downmix::Average)]
+    downmix: Downmix,
+
+    /// Log debugging details too, such as the codec, channels, sample rate
+    /// and bit depth of the decoded audio track. Short for `--log-level debug`.
+    #[arg(long, default_value_t = false)]
+    verbose: bool,
+
+    /// Print only the transcript and the results asked for on stdout, and
+    /// only errors on stderr, for scripts.
+    #[arg(long, default_value_t = false, conflicts_with_all = ["verbose", "log_level"])]
+    quiet: bool,
+
+    /// Level of the progress and diagnostic messages written to stderr:
+    /// off, error, warn, info, debug or trace. Overrides `RUST_LOG` for this
+    /// crate, which defaults to info.
+    #[arg(long)]
+    log_level: Option<log::LevelFilter>,
+
+    /// Print each chunk's text on the terminal in green, yellow or red by
+    /// its average log-probability, once the chunk is done. Ignored when
+    /// stdout is not a terminal or `NO_COLOR` is set; files are unaffected.
+    #[arg(long, default_value_t = false)]
+    color: bool,
+
+    /// Print each chunk on stdout as a line of the live format,
+    /// `start_sec<TAB>end_sec<TAB>text`, once the chunk is done, instead of
+    /// its text as it is generated.
+    #[arg(long, default_value_t = false, conflicts_with_all = ["color", "diarize_by_channel"])]
+    live_stdout: bool,
+
+    /// Language code of the speech, such as `en` or `fr`. Detected from the
+    /// first 30 seconds of audio when omitted.
+    #[arg(long, env = "KITSUNE_LANGUAGE", value_name = "CODE")]
+    language: Option<String>,
+
+    /// Print the most likely languages with their probabilities before
+    /// transcribing. Detects the language even when `KITSUNE_LANGUAGE` is set,
+    /// but not over `--language` or the language of a manifest entry.
+    #[arg(long, default_value_t = false)]
+    detect_language: bool,
+
+    /// Whether `--language` only holds the `KITSUNE_LANGUAGE` default.
+    #[arg(skip)]
+    language_from_env: bool,
+
+    /// Number of candidates printed by `--detect-language`.
+    #[arg(long, value_name = "K", default_value_t = 3)]
+    language_top_k: usize,
+
+    /// Detect the language of every chunk and transcribe it in that language,
+    /// for recordings that switch languages. Each chunk then costs an extra
+    /// pass through the model. `--language` or the language detected for
+    /// the whole file is used when detection fails.
+    #[arg(long, default_value_t = false)]
+    language_per_chunk: bool,
+
+    /// Penalty for repeating already generated tokens; 1.0 disables it.
+    #[arg(long, value_name = "F32", default_value_t = 1.0)]
+    repetition_penalty: f32,
+
+    /// Forbid repeating any n-gram of this many tokens; 0 disables the guard.
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    no_repeat_ngram_size: usize,
+
+    /// How tokens are chosen: `greedy` takes the most likely one, `sample`
+    /// draws at `--temperature`, and `temperature-fallback` retries a chunk
+    /// at each of `--fallback-temperatures` while its mean token
+    /// log-probability is below `--logprob-threshold`, keeping the best try.
+    #[arg(long, value_enum, default_value_t = DecodeStrategy::Greedy)]
+    decode_strategy: DecodeStrategy,
+
+    /// Sampling temperature of `--decode-strategy sample`; lower values stay
+    /// closer to greedy decoding.
+    #[arg(long, value_name = "T", default_value_t = 0.5)]
+    temperature: f32,
+
+    /// Comma-separated temperatures `--decode-strategy temperature-fallback`
+    /// retries a chunk at, in order, after the greedy attempt.
+    #[arg(
+        long,
+        value_name = "TEMPERATURES",
+        value_delimiter = ',',
+        default_values_t = DEFAULT_FALLBACK_TEMPERATURES
+    )]
+    fallback_temperatures: Vec<f32>,
+
+    /// Mean token log-probability below which
+    /// `--decode-strategy temperature-fallback` retries a chunk.
+    #[arg(long, value_name = "F32", default_value_t = -1.0, allow_negative_numbers = true)]
+    logprob_threshold: f32,
+
+    /// Seed of the token sampler, so that sampled transcripts can be repeated.
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    seed: u64,
+
+    /// Comma-separated token ids the model may never generate, such as
+    /// emoji or artifacts that keep showing up in the transcript.
+    #[arg(long, value_name = "IDS", value_delimiter = ',')]
+    suppress_tokens: Vec<u32>,
+
+    /// Never generate the tokens of symbols that are not speech, such as
+    /// brackets, quotes and music notes, as Whisper does.
+    #[arg(long, default_value_t = false)]
+    suppress_non_speech: bool,
+
+    /// Skip chunks whose RMS level is below this value without running the
+    /// model, such as 0.001 (about -60 dBFS). Off by default.
+    #[arg(long, value_name = "RMS", default_value_t = 0.0)]
+    silence_threshold: f32,
+
+    /// Text written for a skipped silent chunk. Nothing is written by default.
+    #[arg(long, value_name = "TEXT", default_value = "")]
+    silence_placeholder: String,
+
+    /// Drop the text of the final chunk when it is quiet and its text is a
+    /// filler phrase such as "Thank you." or repeats the previous segment,
+    /// as the model tends to invent for trailing silence.
+    #[arg(long, default_value_t = false, conflicts_with = "diarize_by_channel")]
+    suppress_trailing_hallucination: bool,
+
+    /// Filler phrase for `--suppress-trailing-hallucination`, replacing the
+    /// built-in English list; repeat the option for several phrases.
+    #[arg(
+        long,
+        value_name = "PHRASE",
+        requires = "suppress_trailing_hallucination"
+    )]
+    hallucination_filler: Vec<String>,
+
+    /// Mark a gap of at least this many seconds between the segments of the
+    /// plain text and Markdown transcripts, such as one left by silent
+    /// chunks, with `--pause-marker`.
+    #[arg(long, value_name = "SECONDS", conflicts_with = "diarize_by_channel")]
+    pause_threshold_sec: Option<f32>,
+
+    /// Text of the pause marker; `{seconds}` is replaced by the length of the
+    /// pause, and an empty marker makes a paragraph break in plain text.
+    #[arg(long, value_name = "TEMPLATE", default_value = "[pause {seconds}s]")]
+    pause_marker: String,
+
+    /// Text that biases decoding towards its spellings of names and jargon.
+    #[arg(long, value_name = "TEXT", conflicts_with = "prompt_file")]
+    prompt: Option<String>,
+
+    /// Read the `--prompt` text from a file.
+    #[arg(long, value_name = "FILE")]
+    prompt_file: Option<PathBuf>,
+
+    /// Keep filler words and false starts instead of a cleaned transcript,
+    /// by giving the decoder a verbatim example as context.
+    #[arg(long, default_value_t = false)]
+    verbatim: bool,
+
+    /// Give the decoder the end of the previous chunk's transcript as
+    /// context, for coherent text across chunk boundaries. The context is
+    /// dropped after a chunk whose transcript loops.
+    #[arg(long, default_value_t = false)]
+    condition_on_previous: bool,
+
+    /// Drop the text of chunks whose no-speech probability is above this
+    /// value, such as music or noise; 0.6 is a reasonable start.
+    #[arg(long, value_name = "P")]
+    no_speech_threshold: Option<f32>,
+
+    /// Filter the segments whose average log-probability is below this
+    /// value, such as -1.0, out of the transcripts for clean captions.
+    #[arg(
+        long,
+        value_name = "LOGPROB",
+        allow_hyphen_values = true,
+        conflicts_with = "diarize_by_channel"
+    )]
+    min_confidence: Option<f32>,
+
+    /// What `--min-confidence` does with a low-confidence segment.
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = LowConfidenceAction::Drop,
+        requires = "min_confidence"
+    )]
+    low_confidence_action: LowConfidenceAction,
+
+    /// Length of the chunks the audio is split into, in seconds.
+    #[arg(
+        long,
+        env = "KITSUNE_CHUNK_SECONDS",
+        value_name = "SECONDS",
+        default_value_t = 15.0
+    )]
+    chunk_seconds: f32,
+
+    /// Fraction of each chunk shared with the next one.
+    #[arg(long, value_name = "RATIO", default_value_t = 0.1)]
+    overlap_ratio: f32,
+
+    /// Transcribe from this many seconds into the file; timestamps stay in
+    /// file time.
+    #[arg(long, value_name = "SECONDS")]
+    start_sec: Option<f32>,
+
+    /// Transcribe up to this many seconds into the file.
+    #[arg(long, value_name = "SECONDS")]
+    end_sec: Option<f32>,
+
+    /// Wall-clock time at which the recording started, in RFC 3339 with an
+    /// offset such as `2024-01-02T10:00:00+02:00`. Subtitle cues become
+    /// times of day and json and jsonl segments get absolute `start_time`
+    /// and `end_time`.
+    #[arg(long, value_name = "RFC3339", conflicts_with = "manifest")]
+    start_time: Option<WallClock>,
+
+    /// Write the decoded and resampled audio the model receives to this
+    /// 16 kHz mono 16-bit WAV file.
+    #[arg(long, value_name = "PATH")]
+    dump_audio: Option<PathBuf>,
+
+    /// Run a short dummy pass through each loaded model before transcribing,
+    /// so that GPU kernel compilation does not slow down the first chunk or
+    /// count towards the `bench` timings. The warm-up time is reported apart.
+    #[arg(long, default_value_t = false)]
+    warmup: bool,
+
+    /// Time the decode, resample, mel, encoder and generation stages and
+    /// print the breakdown after each file, also into `--summary`.
+    #[arg(long, default_value_t = false)]
+    profile: bool,
+
+    /// Transcribe chunks on this many model copies at once, one per GPU, or
+    /// CPU threads when running on CPU. Every copy needs its own memory.
+    #[arg(long, value_name = "N", default_value_t = 1)]
+    parallel: usize,
+
+    /// Compute on this many CPU threads instead of all cores. Applied once
+    /// when the first model loads; `--parallel` copies on CPU share them.
+    #[arg(long, value_name = "N")]
+    threads: Option<usize>,
+
+    /// Print the chunk schedule for the input and exit without loading the model.
+    #[arg(long, default_value_t = false)]
+    dry_run: bool,
+
+    /// Print the compute devices this build can use, CPU and any CUDA or
+    /// Metal GPU that initialises, and exit.
+    #[arg(long, default_value_t = false)]
+    list_devices: bool,
+
+    /// Output formats of the transcript, written next to the input file:
+    /// txt, json, jsonl, srt, csv or md, a comma-separated list of them, or `all`
+    /// for txt, srt and json.
+    #[arg(
+        long,
+        env = "KITSUNE_FORMAT",
+        value_name = "FORMATS",
+        default_value = "txt"
+    )]
+    format: FormatList,
+
+    /// Line ending of the plain-text transcript.
+    #[arg(long, value_enum, default_value_t = LineEnding::Lf)]
+    line_ending: LineEnding,
+
+    /// Start the plain-text transcript with a UTF-8 byte order mark.
+    #[arg(long, default_value_t = false)]
+    bom: bool,
+
+    /// Flush the streamed transcripts to disk after every N chunks with text
+    /// instead of after each one, for throughput on slow or remote storage.
+    /// They are still flushed at the end of the run and on interrupt.
+    #[arg(long, value_name = "N", default_value_t = 1)]
+    flush_interval: usize,
+
+    /// Record progress after each chunk and continue an interrupted run of
+    /// the same input from its last completed chunk.
+    #[arg(long, default_value_t = false)]
+    resume: bool,
+
+    /// Append to existing transcripts instead of overwriting them, with a
+    /// `=== <input> ===` header line before each input's text. Only the
+    /// streamed formats, txt and jsonl, can be appended to.
+    #[arg(long, default_value_t = false, conflicts_with_all = ["resume", "diarize_by_channel"])]
+    append: bool,
+
+    /// Write the transcripts under this directory instead of next to the
+    /// input, mirroring the directories of the input path as given.
+    #[arg(long, value_name = "DIR")]
+    output_dir: Option<PathBuf>,
+
+    /// Put the transcripts directly in `--output-dir` instead of mirroring
+    /// the input's directories.
+    #[arg(long, default_value_t = false, requires = "output_dir")]
+    flatten: bool,
+
+    /// Name the transcripts after this pattern instead of the input, such
+    /// as `transcript_{stem}_{lang}.txt`, with the placeholders `{stem}`,
+    /// `{ext}`, `{lang}`, `{date}` and `{index}`. Each format replaces the
+    /// extension it ends in.
+    #[arg(long, value_name = "PATTERN")]
+    output_template: Option<OutputTemplate>,
+
+    /// Overwrite existing transcripts without warning about each one.
+    #[arg(long, default_value_t = false)]
+    force: bool,
+
+    /// Write a JSON summary of the run, with chunk counts, tokens and
+    /// timings, to this file, or to stderr with `-`.
+    #[arg(long, value_name = "PATH")]
+    summary: Option<PathBuf>,
+
+    /// Also write per-word timings to `<input>.estimated-words.json`,
+    /// estimated by spreading each chunk's words over the chunk by their
+    /// length rather than aligned with the audio.
+    #[arg(long, default_value_t = false)]
+    estimated_word_timestamps: bool,
+
+    /// Transcribe each channel of a multi-channel recording as its own
+    /// speaker and label the transcript `Speaker A:`, `Speaker B:`, ...
+    #[arg(long, default_value_t = false, conflicts_with_all = ["resume", "parallel", "estimated_word_timestamps"])]
+    diarize_by_channel: bool,
+
+    /// Write the audio of each transcribed chunk to `DIR/<stem>_<index>.wav`
+    /// and list the clips with their text and times in
+    /// `DIR/<stem>_manifest.csv`.
+    #[arg(long, value_name = "DIR", conflicts_with_all = ["resume", "diarize_by_channel"])]
+    export_chunks: Option<PathBuf>,
+
+    /// Write the mel features the encoder receives for each chunk to
+    /// `DIR/<stem>_<index>.npy`.
+    #[arg(long, value_name = "DIR", conflicts_with = "diarize_by_channel")]
+    dump_mel: Option<PathBuf>,
+
+    /// Write the token ids the model generated for each chunk to this JSON
+    /// file, with the chunk index and times, to debug tokenization.
+    #[arg(long, value_name = "PATH", conflicts_with = "diarize_by_channel")]
+    dump_tokens: Option<PathBuf>,
+
+    /// Normalize the text of every segment before it is written: Unicode
+    /// NFC, runs of whitespace collapsed to one space, and trimmed.
+    #[arg(long, default_value_t = false)]
+    normalize_text: bool,
+
+    /// Correct the spelling of terms in every segment with the
+    /// `wrong<TAB>right` rules of this file, matching whole words in any
+    /// case; a wrong side starting with `re:` is a regular expression.
+    #[arg(long, value_name = "FILE")]
+    corrections: Option<PathBuf>,
+
+    /// Stop generating the text of a chunk after this many seconds of
+    /// wall-clock time and mark it `[truncated]`.
+    #[arg(long, value_name = "SECONDS")]
+    chunk_timeout_sec: Option<f32>,
+
+    /// Retry a chunk whose transcription fails up to this many times. When
+    /// every attempt fails a `[transcription failed]` segment is written and
+    /// the run goes on; with 0 the first failure ends the run.
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    chunk_retries: usize,
+
+    /// Split a failing chunk into halves on every retry, such as after
+    /// running out of GPU memory, and join the text of the pieces.
+    #[arg(long, default_value_t = false)]
+    retry_split: bool,
+
+    /// Put at most this much of a chunk through the model at once, in whole
+    /// 30-second windows, to fit in a GPU with little free memory. The
+    /// estimated activation memory per pass is logged.
+    #[arg(long, value_name = "SECONDS")]
+    max_audio_seconds_per_pass: Option<f32>,
 }
 
-#[cfg(feature = "cuda")]
-/// Return whether the build's default runtime should use CPU when `cuda` feature is enabled.
+#[cfg(any(feature = "cuda", feature = "metal"))]
+/// Return whether the build's default runtime should use CPU when a GPU feature is enabled.
 ///
-/// This version is selected when the `cuda` feature is enabled at compile time.
+/// This version is selected when the `cuda` or `metal` feature is enabled at compile time.
 fn use_cpu() -> bool {
     true
 }
 
-#[cfg(not(feature = "cuda"))]
-/// Return whether the build's default runtime should use CPU when `cuda` is not enabled.
+#[cfg(not(any(feature = "cuda", feature = "metal")))]
+/// Return whether the build's default runtime should use CPU when no GPU feature is enabled.
 ///
-/// This version is selected when the `cuda` feature is not enabled at compile time.
+/// This version is selected when neither `cuda` nor `metal` is enabled at compile time.
 fn use_cpu() -> bool {
     false
 }
@@ -44,128 +603,2056 @@ fn use_cpu() -> bool {
 /// The function returns a `Result` so failures in model loading, audio decoding
 /// or transcription are propagated to the caller.
 fn main() -> Result<()> {
-    let args = Args::parse();
+    let cli = Cli::try_parse_with_sources(std::env::args_os()).unwrap_or_else(|e| e.exit());
+    let args = match &cli.command {
+        Some(Command::Bench(args)) => args,
+        #[cfg(feature = "server")]
+        Some(Command::Serve(serve_args)) => &serve_args.args,
+        None => &cli.args,
+    };
+    init_logging(args);
+    QUIET.store(args.quiet, Ordering::Relaxed);
+    // The server has no chunk loop to stop, so Ctrl-C ends it at once
+    #[cfg(feature = "server")]
+    if let Some(Command::Serve(serve_args)) = &cli.command {
+        return serve(serve_args);
+    }
+    interrupt::install().context("Failed to install the Ctrl-C handler")?;
+    let result = match cli.command {
+        Some(Command::Bench(args)) => run(&args).map(|report| {
+            if let Some(report) = report {
+                println!("{report}");
+            }
+        }),
+        #[cfg(feature = "server")]
+        Some(Command::Serve(_)) => unreachable!("handled above"),
+        None => run(&cli.args).map(|_| ()),
+    };
+    match result {
+        // Returning from `run` has dropped, and so flushed, every writer
+        Err(e) if e.is::<Interrupted>() => {
+            log::warn!("Interrupted, output holds the chunks completed so far");
+            std::process::exit(interrupt::EXIT_CODE)
+        }
+        result => result,
+    }
+}
+
+/// Send log messages to stderr, filtered by `RUST_LOG` and then by
+/// `--log-level` or `--verbose` for this crate, so that stdout only carries
+/// the transcription and other requested results.
+fn init_logging(args: &Args) {
+    let env = env_logger::Env::default().default_filter_or(DEFAULT_LOG_FILTER);
+    let mut builder = env_logger::Builder::from_env(env);
+    let level = args
+        .log_level
+        .or(args.verbose.then_some(log::LevelFilter::Debug))
+        .or(args.quiet.then_some(log::LevelFilter::Error));
+    if args.quiet {
+        builder.filter_level(log::LevelFilter::Error);
+    }
+    if let Some(level) = level {
+        builder.filter_module("kitsune_stt", level);
+    }
+    builder.format_timestamp(None).format_target(false).init();
+}
+
+/// Sample rate the model of `args` expects, read from its configuration
+/// so that the audio can be prepared before the model is loaded.
+fn model_sample_rate(args: &Args) -> Result<u32> {
+    let model_dir = args
+        .model_dir
+        .clone()
+        .unwrap_or_else(|| PathBuf::from(download::DEFAULT_MODEL_DIR));
+    model::expected_sample_rate(&model_dir)
+}
+
+/// Where and how `args` loads the model.
+fn model_options(args: &Args) -> ModelOptions {
+    ModelOptions {
+        use_cpu: args.cpu || !use_cpu(),
+        dtype: args.dtype,
+        gguf_weights: args.gguf_weights.clone(),
+        model_dir: args.model_dir.clone(),
+        require_gpu: args.require_gpu,
+        device_ordinal: 0,
+        threads: args.threads,
+        refresh: args.refresh,
+        pad_mode: args.pad_mode,
+    }
+}
+
+/// Load the model once and transcribe uploads over HTTP until the process
+/// is stopped.
+#[cfg(feature = "server")]
+fn serve(serve_args: &ServeArgs) -> Result<()> {
+    use kitsune_stt::server::{Server, ServerConfig};
+
+    let args = &serve_args.args;
+    if let Some(input) = &args.input {
+        anyhow::bail!(
+            "serve takes audio over HTTP, not {}; POST it to /transcribe",
+            input.display()
+        );
+    }
+    if args.manifest.is_some() {
+        anyhow::bail!("serve takes audio over HTTP, not from a --manifest");
+    }
+    if serve_args.max_connections == 0 {
+        anyhow::bail!("--max-connections must be at least 1");
+    }
+    check_decode_strategy(args)?;
+    let plan = ChunkPlan::new(
+        args.chunk_seconds,
+        args.overlap_ratio,
+        model_sample_rate(args)?,
+    )?;
+    let defaults = TranscribeOptions::default();
+    let config = ServerConfig {
+        plan,
+        options: TranscribeOptions {
+            language: args.language.clone().unwrap_or(defaults.language),
+            repetition_penalty: args.repetition_penalty,
+            no_repeat_ngram_size: args.no_repeat_ngram_size,
+            prompt: args.prompt.clone().filter(|p| !p.is_empty()),
+            max_seconds_per_pass: args.max_audio_seconds_per_pass,
+            decode_strategy: args.decode_strategy,
+            temperature: args.temperature,
+            fallback_temperatures: args.fallback_temperatures.clone(),
+            logprob_threshold: args.logprob_threshold,
+            seed: args.seed,
+            suppress_tokens: args.suppress_tokens.clone(),
+            suppress_non_speech: args.suppress_non_speech,
+            verbatim: args.verbatim,
+            ..defaults
+        },
+        detect_language: args.language.is_none() || args.detect_language,
+        max_upload_bytes: serve_args.max_upload_mb.saturating_mul(1024 * 1024),
+        max_connections: serve_args.max_connections,
+    };
+
+    let mut model = load_model(&model_options(args)).context("Failed to load Voxtral model")?;
+    log::info!(
+        "Model loaded successfully on device: {:?}",
+        model.device().location()
+    );
+    model.check_options(&config.options)?;
+    warn_if_verbatim_unsupported(&model, args);
+    if args.warmup {
+        let elapsed = model.warm_up()?;
+        log::info!("Model warmed up in {:.2} s", elapsed.as_secs_f64());
+    }
+    let address = format!("{}:{}", serve_args.host, serve_args.port);
+    let listener = std::net::TcpListener::bind(&address)
+        .with_context(|| format!("Failed to listen on {address}"))?;
+    log::info!("Listening on http://{address}, POST audio to /transcribe");
+    std::sync::Arc::new(Server::new(model, config)).run(listener)
+}
+
+/// Check the temperatures and threshold of `--decode-strategy`.
+fn check_decode_strategy(args: &Args) -> Result<()> {
+    if !(args.temperature.is_finite() && args.temperature > 0.0) {
+        anyhow::bail!("--temperature must be a positive number");
+    }
+    if args
+        .fallback_temperatures
+        .iter()
+        .any(|temperature| !(temperature.is_finite() && *temperature > 0.0))
+    {
+        anyhow::bail!("--fallback-temperatures must be positive numbers");
+    }
+    if !args.logprob_threshold.is_finite() {
+        anyhow::bail!("--logprob-threshold must be a number");
+    }
+    Ok(())
+}
+
+/// Transcribe the input described by `args`, or every file of its
+/// `--manifest` in order with one loaded model.
+///
+/// Returns the throughput of the transcription, or `None` when nothing was
+/// transcribed, such as without input or with `--dry-run`.
+fn run(args: &Args) -> Result<Option<BenchReport>> {
+    if args.list_devices {
+        for device in model::list_devices() {
+            println!("{device}");
+        }
+        return Ok(None);
+    }
+    if !(args.repetition_penalty.is_finite() && args.repetition_penalty > 0.0) {
+        anyhow::bail!("--repetition-penalty must be a positive number");
+    }
+    if args.parallel == 0 {
+        anyhow::bail!("--parallel must be at least 1");
+    }
+    if args.flush_interval == 0 {
+        anyhow::bail!("--flush-interval must be at least 1");
+    }
+    if args
+        .min_confidence
+        .is_some_and(|logprob| !logprob.is_finite())
+    {
+        anyhow::bail!("--min-confidence must be a number");
+    }
+    // Fail on a malformed file before loading the model
+    if let Some(corrections) = read_corrections(args)? {
+        log::debug!("Loaded {} corrections", corrections.len());
+    }
+    if args.condition_on_previous && args.parallel > 1 {
+        anyhow::bail!("--condition-on-previous transcribes chunks in order and can not be combined with --parallel");
+    }
+    if let Some(language) = args
+        .language
+        .as_deref()
+        .filter(|&language| args.verbatim && language != VERBATIM_LANGUAGE)
+    {
+        anyhow::bail!(
+            "--verbatim gives the model an English example and would push a {language} transcript toward English, it works with --language {VERBATIM_LANGUAGE} only"
+        );
+    }
+    check_decode_strategy(args)?;
+    let timeout = match args.chunk_timeout_sec {
+        Some(seconds) if seconds.is_finite() && seconds > 0.0 => {
+            Some(Duration::from_secs_f32(seconds))
+        }
+        Some(_) => anyhow::bail!("--chunk-timeout-sec must be a positive number"),
+        None => None,
+    };
+    if args.append {
+        if let Some(format) = args.format.0.iter().find(|format| !format.is_streamed()) {
+            anyhow::bail!(
+                "--append works with the txt and jsonl formats only, {} is written whole at the end",
+                format.extension()
+            );
+        }
+    }
+    if let Some(seconds) = args.pause_threshold_sec {
+        if !(seconds.is_finite() && seconds > 0.0) {
+            anyhow::bail!("--pause-threshold-sec must be a positive number");
+        }
+    }
+    if let Some(seconds) = args.max_audio_seconds_per_pass {
+        if !(seconds.is_finite() && seconds > 0.0) {
+            anyhow::bail!("--max-audio-seconds-per-pass must be a positive number");
+        }
+    }
 
-    let use_cpu = args.cpu || !use_cpu();
+    let inputs = match (&args.manifest, &args.input) {
+        (Some(manifest_path), _) => manifest::read(manifest_path)?,
+        (None, Some(input)) => vec![ManifestEntry {
+            line: 0,
+            path: input.clone(),
+            language: None,
+        }],
+        (None, None) => {
+            log::warn!("No audio file submitted");
+            return Ok(None);
+        }
+    };
+
+    if args.profile {
+        profile::enable();
+    }
+    let paths: Vec<PathBuf> = inputs.iter().map(|input| input.path.clone()).collect();
+    let languages = inputs
+        .iter()
+        .map(ManifestEntry::resolve_language)
+        .collect::<Result<Vec<_>>>()?;
+    let layout_bases = layout_bases(&paths, args);
+    check_distinct_outputs(&paths, &layout_bases, &languages, args)?;
 
-    let audio_file = if let Some(input) = args.input {
-        input
+    // Loaded for the first file that gets transcribed and kept for the rest
+    let mut models = Vec::new();
+    let mut total: Option<BenchReport> = None;
+    for (n, ((input, language), layout_base)) in
+        inputs.iter().zip(languages).zip(&layout_bases).enumerate()
+    {
+        if inputs.len() > 1 {
+            log::info!("File {}/{}: {}", n + 1, inputs.len(), input.path.display());
+        }
+        if let Some(language) = &language {
+            log::debug!("Language of {}: {language}", input.path.display());
+        }
+        let Some(report) = transcribe_file(
+            input.path.clone(),
+            n + 1,
+            language.as_deref(),
+            layout_base,
+            &mut models,
+            timeout,
+            args,
+        )?
+        else {
+            continue;
+        };
+        let total = total.get_or_insert_with(BenchReport::default);
+        total.audio_seconds += report.audio_seconds;
+        total.wall_time += report.wall_time;
+        total.chunk_times.extend(report.chunk_times);
+        total.tokens += report.tokens;
+        total.warm_up = total.warm_up.or(report.warm_up);
+    }
+    Ok(total)
+}
+
+/// Transcribe `audio_file`, in `language` when given and otherwise in the
+/// `--language` default or the detected one, loading `models` if they are
+/// not loaded yet. Its transcripts are based on `layout_base`, see
+/// [`layout_bases`].
+///
+/// Returns the throughput of the transcription, or `None` with `--dry-run`.
+fn transcribe_file(
+    audio_file: PathBuf,
+    index: usize,
+    language: Option<&str>,
+    layout_base: &Path,
+    models: &mut Vec<VoxtralModel>,
+    timeout: Option<Duration>,
+    args: &Args,
+) -> Result<Option<BenchReport>> {
+    audio::check_input_file(&audio_file)?;
+    let track = if args.summary.is_some() || log::log_enabled!(log::Level::Debug) {
+        Some(audio::track_info(&audio_file).context("Failed to probe audio file")?)
     } else {
-        println!("No audio file submitted");
-        return Ok(());
+        None
+    };
+    if let Some(track) = &track {
+        log::debug!("Audio track: {track}");
+    }
+
+    let target_sr = match models.first() {
+        Some(model) => model.sample_rate(),
+        None => model_sample_rate(args)?,
+    };
+    let plan = ChunkPlan::new(args.chunk_seconds, args.overlap_ratio, target_sr)?;
+    let resample = (!args.no_resample).then_some(args.resample_quality);
+    let channels = if args.diarize_by_channel {
+        Some(
+            decode_channels_and_prepare(&audio_file, target_sr, resample, !args.no_sanitize)
+                .context("Failed to decode/prepare audio channels")?,
+        )
+    } else {
+        None
+    };
+    let prepared_audio = match &channels {
+        Some(channels) => audio::downmix(channels),
+        None => audio::decode_and_prepare(
+            &audio_file,
+            target_sr,
+            resample,
+            args.downmix,
+            !args.no_sanitize,
+        )
+        .context("Failed to decode/prepare audio")?,
+    };
+
+    // Keep only --start-sec..--end-sec, which the timeline maps back to file time
+    let selection = timeline::seconds_range(
+        args.start_sec,
+        args.end_sec,
+        prepared_audio.len(),
+        target_sr,
+    )?;
+    let mut timeline = Timeline::new(target_sr);
+    timeline.push(selection.clone());
+    let prepared_audio = select_samples(prepared_audio, &selection);
+    let channels = channels.map(|channels| {
+        channels
+            .into_iter()
+            .map(|channel| select_samples(channel, &selection))
+            .collect::<Vec<_>>()
+    });
+
+    if let Some(dump_path) = &args.dump_audio {
+        audio::write_wav(dump_path, &prepared_audio, target_sr)
+            .context("Failed to write prepared audio")?;
+        note!("Prepared audio written to {}", dump_path.display());
+    }
+
+    if args.dry_run {
+        print_chunk_plan(&plan, prepared_audio.len(), target_sr);
+        take_profile(args);
+        return Ok(None);
+    }
+
+    let mut warm_up = None;
+    if models.is_empty() {
+        // Create model - equivalent to loading the model and processor in Python
+        let model_options = model_options(args);
+        let model = load_model(&model_options).context("Failed to load Voxtral model")?;
+
+        log::info!(
+            "Model loaded successfully on device: {:?}",
+            model.device().location()
+        );
+        warn_if_verbatim_unsupported(&model, args);
+
+        let workers = load_workers(&model_options, model.device(), args.parallel)?;
+        models.push(model);
+        models.extend(workers);
+
+        if args.warmup {
+            let mut total = Duration::ZERO;
+            for model in models.iter_mut() {
+                total += model.warm_up()?;
+            }
+            log::info!("Model warmed up in {:.2} s", total.as_secs_f64());
+            warm_up = Some(total);
+        }
+    }
+
+    let language = match given_language(language, args) {
+        Some(language) => language.to_string(),
+        None => detect_language(&mut models[0], &prepared_audio, target_sr, args)?,
     };
+    if args.verbatim && language != VERBATIM_LANGUAGE {
+        log::warn!(
+            "--verbatim has an English example only, so it is ignored for this {language} transcript"
+        );
+    }
 
-    // Create model - equivalent to loading the model and processor in Python
-    let mut model = load_model(use_cpu).context("Failed to load Voxtral model")?;
+    let prompt = match &args.prompt_file {
+        Some(path) => Some(
+            std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read prompt file {}", path.display()))?
+                .trim()
+                .to_string(),
+        ),
+        None => args.prompt.clone(),
+    };
 
-    println!("Model loaded successfully on device: {:?}", model.device());
+    let options = TranscribeOptions {
+        language,
+        repetition_penalty: args.repetition_penalty,
+        no_repeat_ngram_size: args.no_repeat_ngram_size,
+        prompt: prompt.filter(|p| !p.is_empty()),
+        previous_tokens: Vec::new(),
+        timeout,
+        max_seconds_per_pass: args.max_audio_seconds_per_pass,
+        decode_strategy: args.decode_strategy,
+        temperature: args.temperature,
+        fallback_temperatures: args.fallback_temperatures.clone(),
+        logprob_threshold: args.logprob_threshold,
+        seed: args.seed,
+        suppress_tokens: args.suppress_tokens.clone(),
+        suppress_non_speech: args.suppress_non_speech,
+        verbatim: args.verbatim,
+    };
+    // Fail before the first chunk rather than on every chunk
+    models[0].check_options(&options)?;
+    log_pass_memory(&models[0], &plan, args.max_audio_seconds_per_pass);
 
-    let target_sr: u32 = 16_000;
-    let prepared_audio =
-        decode_and_prepare(&audio_file, target_sr).context("Failed to decode/prepare audio")?;
+    let audio_seconds = prepared_audio.len() as f64 / f64::from(target_sr);
+    let levels = audio::levels(&prepared_audio);
+    let mut job = Job {
+        out_base: output_base(layout_base, &audio_file, &options.language, index, args)?,
+        summary: RunSummary {
+            input: audio_file,
+            audio_seconds,
+            sample_rate: track.and_then(|track| track.sample_rate),
+            peak_dbfs: levels.peak_dbfs,
+            rms_dbfs: levels.rms_dbfs,
+            device: format!("{:?}", models[0].device().location()),
+            language: options.language.clone(),
+            ..RunSummary::default()
+        },
+    };
+
+    let started = Instant::now();
+    let chunk_times = match &channels {
+        Some(channels) => transcribe_by_channel(
+            &mut models[0],
+            channels,
+            &timeline,
+            &plan,
+            &mut job,
+            &options,
+            args,
+        )?,
+        None => transcribe_and_stream(
+            models,
+            &prepared_audio,
+            &timeline,
+            &plan,
+            &mut job,
+            &options,
+            args,
+        )?,
+    };
+    let wall_time = started.elapsed();
 
-    transcribe_and_stream(&mut model, &prepared_audio, target_sr, &audio_file)
+    let throughput = Throughput {
+        tokens: job.summary.tokens,
+        elapsed: chunk_times.iter().sum(),
+    };
+    job.summary.model_seconds = throughput.elapsed.as_secs_f64();
+    job.summary.tokens_per_second = throughput.tokens_per_second();
+    job.summary.profile = take_profile(args);
+    if let Some(path) = &args.summary {
+        job.summary.elapsed_seconds = wall_time.as_secs_f64();
+        write_summary(&job.summary, path)?;
+    }
+
+    Ok(Some(BenchReport {
+        audio_seconds,
+        wall_time,
+        chunk_times,
+        tokens: job.summary.tokens,
+        warm_up,
+    }))
+}
+
+/// One input being transcribed: the account of its run and where its
+/// transcripts go.
+struct Job {
+    summary: RunSummary,
+    /// Path the transcripts are based on, each format replacing its
+    /// extension, see [`output_base`].
+    out_base: PathBuf,
+}
+
+impl Job {
+    /// The transcript path of each `--format`.
+    fn out_paths(&self, args: &Args) -> Vec<(OutputFormat, PathBuf)> {
+        args.format
+            .0
+            .iter()
+            .map(|&format| (format, self.out_base.with_extension(format.extension())))
+            .collect()
+    }
+}
+
+/// Check the transcript paths of `input` before anything is written: none
+/// may be the input itself and, when `replacing` them, existing non-empty
+/// transcripts are warned about unless `--force` is given.
+fn check_out_paths(
+    input: &Path,
+    out_paths: &[(OutputFormat, PathBuf)],
+    replacing: bool,
+    args: &Args,
+) -> Result<()> {
+    for (_, path) in out_paths {
+        output::check_not_input(input, path)?;
+    }
+    if replacing && !args.force {
+        for (_, path) in out_paths {
+            if std::fs::metadata(path).is_ok_and(|meta| meta.is_file() && meta.len() > 0) {
+                log::warn!(
+                    "Overwriting {}, pass --force to do so without a warning",
+                    path.display()
+                );
+            }
+        }
+    }
+    Ok(())
 }
 
-fn load_model(use_cpu: bool) -> Result<VoxtralModel> {
-    let model = VoxtralModel::new(use_cpu).context("Failed to create VoxtralModel")?;
+/// Front matter of a Markdown transcript: the input file name and the
+/// duration of its audio.
+fn front_matter(summary: &RunSummary) -> FrontMatter {
+    FrontMatter {
+        title: summary.input.file_name().map_or_else(
+            || summary.input.display().to_string(),
+            |name| name.to_string_lossy().into_owned(),
+        ),
+        duration_sec: summary.audio_seconds as f32,
+    }
+}
+
+/// With `--profile`, print the time spent in each stage since the last call
+/// and return it. The report was asked for, so `--quiet` does not hide it.
+fn take_profile(args: &Args) -> Option<Profile> {
+    if !args.profile {
+        return None;
+    }
+    let profile = profile::take();
+    println!("{profile}");
+    Some(profile)
+}
+
+/// Write `summary` as JSON to `path`, or to stderr when `path` is `-`.
+fn write_summary(summary: &RunSummary, path: &Path) -> Result<()> {
+    let json = summary.to_json()?;
+    if path == Path::new("-") {
+        eprintln!("{json}");
+        return Ok(());
+    }
+    std::fs::write(path, json + "\n")
+        .with_context(|| format!("Failed to write run summary to {}", path.display()))
+}
+
+/// Language to transcribe a file in without detecting it: the one given for
+/// the file, else `--language` unless `--detect-language` overrides its
+/// `KITSUNE_LANGUAGE` default.
+fn given_language<'a>(file_language: Option<&'a str>, args: &'a Args) -> Option<&'a str> {
+    file_language.or(args
+        .language
+        .as_deref()
+        .filter(|_| !(args.detect_language && args.language_from_env)))
+}
+
+/// Detect the spoken language once for the whole file and report it.
+fn detect_language(
+    model: &mut VoxtralModel,
+    prepared_audio: &[f32],
+    target_sr: u32,
+    args: &Args,
+) -> Result<String> {
+    let candidates = model
+        .detect_language(prepared_audio, target_sr)
+        .context("Failed to detect language")?;
+
+    if args.detect_language {
+        println!("Language candidates:");
+        for candidate in candidates.iter().take(args.language_top_k) {
+            println!(
+                "  {}: {:.1}%",
+                candidate.language,
+                candidate.probability * 100.0
+            );
+        }
+    }
+
+    let best = candidates
+        .into_iter()
+        .next()
+        .context("No language candidates")?;
+    log::info!(
+        "Detected language: {} ({:.1}%)",
+        best.language,
+        best.probability * 100.0
+    );
+    Ok(best.language)
+}
+
+/// `samples` cut down to `selection`, without copying the whole buffer.
+fn select_samples(mut samples: Vec<f32>, selection: &Range<usize>) -> Vec<f32> {
+    samples.truncate(selection.end);
+    samples.drain(..selection.start.min(samples.len()));
+    samples
+}
+
+/// Print the chunk schedule used for `total_samples` of audio.
+fn print_chunk_plan(plan: &ChunkPlan, total_samples: usize, sample_rate: u32) {
+    let ranges = plan.ranges(total_samples);
+    let seconds = |sample: usize| sample as f32 / sample_rate as f32;
+    println!(
+        "{} samples ({:.2} s) in {} chunks: {} samples per chunk, {} overlap, step {}",
+        total_samples,
+        seconds(total_samples),
+        ranges.len(),
+        plan.chunk_samples,
+        plan.overlap_samples,
+        plan.step()
+    );
+    for (index, range) in ranges.iter().enumerate() {
+        println!(
+            "  {:>4}: samples {}..{} ({:.2} s - {:.2} s)",
+            index + 1,
+            range.start,
+            range.end,
+            seconds(range.start),
+            seconds(range.end)
+        );
+    }
+}
+
+/// Load the extra model copies for `--parallel`, one per additional GPU or,
+/// on CPU, one per additional thread.
+fn load_workers(
+    options: &ModelOptions,
+    first_device: &Device,
+    parallel: usize,
+) -> Result<Vec<VoxtralModel>> {
+    if first_device.is_cpu() {
+        // The copies share the CPU backend's threads rather than adding their own
+        let threads = options
+            .threads
+            .unwrap_or_else(|| std::thread::available_parallelism().map_or(1, |n| n.get()));
+        if parallel > threads {
+            anyhow::bail!("--parallel {parallel} exceeds the {threads} available CPU threads");
+        }
+    }
+
+    (1..parallel)
+        .map(|ordinal| {
+            let worker_options = if first_device.is_cpu() {
+                ModelOptions {
+                    use_cpu: true,
+                    refresh: false,
+                    ..options.clone()
+                }
+            } else {
+                // Each worker needs a GPU of its own, never a CPU fallback
+                ModelOptions {
+                    require_gpu: true,
+                    device_ordinal: ordinal,
+                    refresh: false,
+                    ..options.clone()
+                }
+            };
+            log::info!("Loading worker model {}/{parallel}...", ordinal + 1);
+            let worker = load_model(&worker_options).with_context(|| {
+                format!("Failed to load worker {ordinal} for --parallel {parallel}; is there a device for every worker?")
+            })?;
+            log::info!("Worker {} on device: {:?}", ordinal + 1, worker.device().location());
+            Ok(worker)
+        })
+        .collect()
+}
+
+fn load_model(options: &ModelOptions) -> Result<VoxtralModel> {
+    let model = VoxtralModel::new(options).context("Failed to create VoxtralModel")?;
     Ok(model)
 }
 
-fn decode_and_prepare(path: &PathBuf, target_sr: u32) -> Result<Vec<f32>> {
-    let (audio_data, sample_rate) = audio::pcm_decode(path)
+/// Warn once that `--verbatim` is ignored when `model` cannot take it.
+fn warn_if_verbatim_unsupported(model: &VoxtralModel, args: &Args) {
+    if args.verbatim && !model.supports_verbatim() {
+        log::warn!(
+            "The model's tokenizer has no [TRANSCRIBE] token, so --verbatim is ignored and the transcript stays cleaned"
+        );
+    }
+}
+
+/// Decode every channel of `path` and resample each to `target_sr`, for
+/// `--diarize-by-channel`.
+fn decode_channels_and_prepare(
+    path: &PathBuf,
+    target_sr: u32,
+    resample: Option<ResampleQuality>,
+    sanitize: bool,
+) -> Result<Vec<Vec<f32>>> {
+    let decoding = profile::span(Stage::Decode);
+    let (mut channels, sample_rate) = audio::pcm_decode_channels(path)
         .context("Failed to decode audio file. Perhaps its not supported? See https://docs.rs/symphonia/latest/symphonia/index.html")?;
+    drop(decoding);
 
-    let prepared = if sample_rate != target_sr {
-        println!(
-            "Resampling audio from {} Hz to {} Hz to match model expectations...",
-            sample_rate, target_sr
+    if channels.len() < 2 {
+        anyhow::bail!("--diarize-by-channel needs a recording with one speaker per channel, but the input is mono");
+    }
+    for channel in &mut channels {
+        if sanitize {
+            audio::warn_if_sanitized(audio::sanitize(channel), channel.len());
+        }
+        audio::warn_if_clipped(channel);
+    }
+
+    if resample.is_none() {
+        audio::require_sample_rate(sample_rate, target_sr)?;
+    }
+    if sample_rate != target_sr {
+        log::info!(
+            "Resampling {} channels from {} Hz to {} Hz to match model expectations...",
+            channels.len(),
+            sample_rate,
+            target_sr
         );
-        audio::resample(&audio_data, sample_rate, target_sr)
-            .context("Failed to resample audio to 16 kHz")?
-    } else {
-        audio_data
-    };
+    }
+    let prepared = channels
+        .into_iter()
+        .map(|channel| match resample {
+            Some(quality) if sample_rate != target_sr => {
+                let _span = profile::span(Stage::Resample);
+                audio::resample_with_quality(&channel, sample_rate, target_sr, quality)
+                    .context("Failed to resample audio to 16 kHz")
+            }
+            _ => Ok(channel),
+        })
+        .collect::<Result<Vec<_>>>()?;
 
-    if prepared.is_empty() {
+    if prepared.iter().all(|channel| channel.is_empty()) {
         anyhow::bail!("No audio samples after decoding/resampling.");
     }
+    for (index, channel) in prepared.iter().enumerate() {
+        log::debug!(
+            "Prepared channel {} level: {}",
+            index + 1,
+            audio::levels(channel)
+        );
+    }
 
     Ok(prepared)
 }
 
-fn transcribe_and_stream(
+/// Transcribe each channel as its own speaker and write the segments of all
+/// channels interleaved by start time. The chunk counts go to the summary of
+/// `job`.
+fn transcribe_by_channel(
     model: &mut VoxtralModel,
-    prepared_audio: &[f32],
-    target_sr: u32,
-    audio_file: &Path,
+    channels: &[Vec<f32>],
+    timeline: &Timeline,
+    plan: &ChunkPlan,
+    job: &mut Job,
+    options: &TranscribeOptions,
+    args: &Args,
+) -> Result<Vec<Duration>> {
+    let out_paths = job.out_paths(args);
+    check_out_paths(&job.summary.input, &out_paths, true, args)?;
+    let summary = &mut job.summary;
+    let mut chunk_times: Vec<Duration> = Vec::new();
+    let mut tracks = Vec::with_capacity(channels.len());
+    let mut failed_chunks: Vec<String> = Vec::new();
+    let mut total_chunks = 0;
+    let color = use_color(args);
+    let corrections = read_corrections(args)?;
+
+    let mut interrupted = false;
+    for (channel, pcm) in channels.iter().enumerate() {
+        let speaker = output::channel_speaker(channel);
+        let ranges = plan.ranges(pcm.len());
+        total_chunks += ranges.len();
+        let mut segments = Vec::new();
+
+        let label = format!("{speaker}: transcribing chunk");
+        let mut context = rolling_context(args);
+        let chunks = plan.for_each_chunk(
+            pcm,
+            0,
+            |event| print_chunk_event(&label, event),
+            |chunk_index, chunk| {
+                // Speaker transcripts are written at the end, so stop before
+                // the next chunk and write the ones completed so far
+                interrupt::check()?;
+                let (start_sec, end_sec) = timeline.seconds(&ranges[chunk_index]);
+                let outcome = transcribe_chunk_in_language(
+                    model,
+                    chunk,
+                    &conditioned(options, context.as_ref()),
+                    args,
+                    !color,
+                )?;
+                follow_chunk(context.as_mut(), &outcome);
+                let (result, elapsed, language) = match outcome {
+                    ChunkOutcome::Silent => {
+                        log::info!("Chunk is silent, skipping");
+                        summary.skipped_chunks += 1;
+                        return Ok(());
+                    }
+                    ChunkOutcome::Failed => {
+                        failed_chunks.push(format!("{speaker} {}", chunk_index + 1));
+                        segments.push(Segment {
+                            index: chunk_index,
+                            start_sec,
+                            end_sec,
+                            text: FAILED_PLACEHOLDER.to_string(),
+                            avg_logprob: None,
+                            no_speech_prob: None,
+                            speaker: Some(speaker.clone()),
+                            language: None,
+                            failed: true,
+                        });
+                        return Ok(());
+                    }
+                    ChunkOutcome::Transcribed {
+                        result,
+                        elapsed,
+                        language,
+                    } => (result, elapsed, language),
+                };
+                finish_text(&result, color);
+                log_throughput(
+                    &format!("{speaker} chunk {}", chunk_index + 1),
+                    &result,
+                    elapsed,
+                );
+                chunk_times.push(elapsed);
+                summary.tokens += result.tokens.len();
+
+                if is_no_speech(&result, args.no_speech_threshold) {
+                    log::info!(
+                        "No speech detected (p = {:.2}), text suppressed",
+                        result.no_speech_prob
+                    );
+                    summary.suppressed_chunks += 1;
+                    return Ok(());
+                }
+
+                let Some(mut segment) = transcribed_segment(
+                    chunk_index,
+                    (start_sec, end_sec),
+                    result,
+                    language,
+                    &mut summary.empty_chunks,
+                ) else {
+                    return Ok(());
+                };
+                if let Some(corrections) = &corrections {
+                    segment.text = corrections.apply(&segment.text).into_owned();
+                }
+                if args.normalize_text {
+                    segment.text = text::normalize(&segment.text);
+                }
+                segment.speaker = Some(speaker.clone());
+                segments.push(segment);
+                Ok(())
+            },
+        );
+        tracks.push(segments);
+        match chunks {
+            Err(e) if e.is::<Interrupted>() => {
+                interrupted = true;
+                break;
+            }
+            chunks => chunks?,
+        }
+    }
+
+    let segments = output::interleave_by_start(tracks);
+    write_speaker_transcripts(&out_paths, &segments, summary, interrupted, args)?;
+    print_failed_chunks(&failed_chunks, total_chunks);
+    summary.chunks = total_chunks;
+    summary.failed_chunks = failed_chunks.len();
+
+    if interrupted {
+        return Err(Interrupted.into());
+    }
+    Ok(chunk_times)
+}
+
+/// Write the transcripts of `--diarize-by-channel`, with the
+/// [`INTERRUPTED_MARKER`] ending the plain text one of an `interrupted` run.
+fn write_speaker_transcripts(
+    out_paths: &[(OutputFormat, PathBuf)],
+    segments: &[Segment],
+    summary: &RunSummary,
+    interrupted: bool,
+    args: &Args,
+) -> Result<()> {
+    for (format, out_path) in out_paths {
+        match format {
+            OutputFormat::Txt => {
+                output::write_speaker_text(out_path, segments, args.line_ending, args.bom)?;
+                if interrupted {
+                    let file = progress::append_output(out_path)?;
+                    let mut writer = TextWriter::new(BufWriter::new(file), args.line_ending, false)
+                        .context("Failed to write transcription to file")?;
+                    writer
+                        .write_line(INTERRUPTED_MARKER)
+                        .and_then(|()| writer.flush())
+                        .context("Failed to write transcription to file")?;
+                }
+            }
+            OutputFormat::Json => output::write_json(out_path, segments, args.start_time.as_ref())?,
+            OutputFormat::Jsonl => output::write_jsonl(
+                out_path,
+                segments,
+                args.line_ending,
+                args.start_time.as_ref(),
+            )?,
+            OutputFormat::Srt => output::write_srt(
+                out_path,
+                segments,
+                args.line_ending,
+                args.start_time.as_ref(),
+            )?,
+            OutputFormat::Csv => output::write_csv(out_path, segments, args.line_ending)?,
+            OutputFormat::Live => output::write_live(out_path, segments, args.line_ending)?,
+            OutputFormat::Md => output::write_markdown(
+                out_path,
+                segments,
+                Some(&front_matter(summary)),
+                None,
+                args.line_ending,
+            )?,
+        }
+        note!("Transcription written to {}", out_path.display());
+    }
+    Ok(())
+}
+
+/// Paths the transcripts of `inputs` are based on before `--output-template`:
+/// each input itself or, with `--output-dir`, its place in that directory.
+/// They are laid out together, so that inputs sharing a file name are kept
+/// apart.
+fn layout_bases(inputs: &[PathBuf], args: &Args) -> Vec<PathBuf> {
+    match &args.output_dir {
+        Some(dir) => OutputLayout {
+            dir: dir.clone(),
+            flatten: args.flatten,
+        }
+        .bases(inputs),
+        None => inputs.to_vec(),
+    }
+}
+
+/// Refuse a run in which two inputs would write the same transcripts, such as
+/// a batch whose `--output-template` has neither `{stem}` nor `{index}`. The
+/// `languages` given for the inputs fill `{lang}`; a language still to be
+/// detected counts as the same for every input. With `--append`, sharing
+/// transcripts is the point.
+fn check_distinct_outputs(
+    inputs: &[PathBuf],
+    layout_bases: &[PathBuf],
+    languages: &[Option<String>],
+    args: &Args,
 ) -> Result<()> {
-    // Chunking parameters
-    let chunk_seconds = 15.0_f32; // model's approx max (derived from config)
-    let overlap_ratio = 0.10_f32; // 10% overlap
-
-    let chunk_samples = (chunk_seconds * target_sr as f32) as usize;
-    let overlap_samples = (chunk_samples as f32 * overlap_ratio) as usize;
-    let step = if chunk_samples > overlap_samples {
-        chunk_samples - overlap_samples
+    if args.append {
+        return Ok(());
+    }
+    let mut taken: HashMap<PathBuf, &Path> = HashMap::new();
+    for (n, ((input, layout_base), language)) in
+        inputs.iter().zip(layout_bases).zip(languages).enumerate()
+    {
+        let language = given_language(language.as_deref(), args).unwrap_or("{lang}");
+        let base = templated_base(layout_base, input, language, n + 1, args).with_extension("");
+        if let Some(other) = taken.get(&base) {
+            let hint = if args.output_template.is_some() {
+                ", add {stem} or {index} to --output-template"
+            } else {
+                ""
+            };
+            anyhow::bail!(
+                "{} and {} would both be transcribed to {}.*{hint}",
+                other.display(),
+                input.display(),
+                base.display()
+            );
+        }
+        taken.insert(base, input);
+    }
+    Ok(())
+}
+
+/// `layout_base` renamed after `--output-template` for a transcript in
+/// `language` of the `index`th input.
+fn templated_base(
+    layout_base: &Path,
+    input: &Path,
+    language: &str,
+    index: usize,
+    args: &Args,
+) -> PathBuf {
+    let mut base = layout_base.to_path_buf();
+    if let Some(template) = &args.output_template {
+        let date = jiff::Timestamp::now().strftime("%Y-%m-%d").to_string();
+        let name = template.expand(&TemplateFields {
+            stem: &input.file_stem().unwrap_or_default().to_string_lossy(),
+            ext: &input.extension().unwrap_or_default().to_string_lossy(),
+            lang: language,
+            date: &date,
+            index,
+        });
+        base.set_file_name(name);
+    }
+    base
+}
+
+/// Path the transcripts of `input` are based on: its `layout_base`, renamed
+/// after `--output-template` for a transcript in `language` of the `index`th
+/// input. The directory it is in is created if needed.
+fn output_base(
+    layout_base: &Path,
+    input: &Path,
+    language: &str,
+    index: usize,
+    args: &Args,
+) -> Result<PathBuf> {
+    let base = templated_base(layout_base, input, language, index, args);
+    if let Some(parent) = base.parent() {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create output directory {}", parent.display()))?;
+    }
+    Ok(base)
+}
+
+/// Transcribe the chunks of `prepared_audio`, streaming text to the output
+/// files as chunks complete. The chunk counts go to the summary of `job`.
+fn transcribe_and_stream(
+    models: &mut [VoxtralModel],
+    prepared_audio: &[f32],
+    timeline: &Timeline,
+    plan: &ChunkPlan,
+    job: &mut Job,
+    options: &TranscribeOptions,
+    args: &Args,
+) -> Result<Vec<Duration>> {
+    // Prepare output files: same stem as input file with each format's
+    // extension, next to it or under --output-dir. Plain text and JSON Lines
+    // are streamed as chunks complete, JSON and SRT are written at the end.
+    let out_paths = job.out_paths(args);
+    let out_path = &out_paths[0].1;
+
+    let summary = &mut job.summary;
+    let audio_file = summary.input.as_path();
+    let ranges = plan.ranges(prepared_audio.len());
+
+    let mut all_tokens: Vec<u32> = Vec::new();
+    let mut chunk_times: Vec<Duration> = Vec::new();
+
+    // With --resume, progress is recorded after each chunk and a previous
+    // run of the same input continues where it stopped
+    let progress_path = Progress::path_for(out_path);
+    let resumed = match Progress::load(&progress_path)? {
+        Some(progress)
+            if args.resume
+                && progress.total_samples == prepared_audio.len()
+                && ranges.get(progress.next_chunk).map(|range| range.start)
+                    == Some(progress.next_sample)
+                && out_paths.iter().all(|(format, _)| {
+                    !format.is_streamed() || progress.output_lens.contains_key(format.extension())
+                }) =>
+        {
+            Some(progress)
+        }
+        Some(_) if args.resume => {
+            log::warn!("Progress file does not match this audio or chunking, starting over");
+            None
+        }
+        _ => None,
+    };
+    check_out_paths(
+        audio_file,
+        &out_paths,
+        resumed.is_none() && !args.append,
+        args,
+    )?;
+    if let Some(progress) = &resumed {
+        log::info!(
+            "Resuming from chunk {} (sample {})",
+            progress.next_chunk + 1,
+            progress.next_sample
+        );
+    }
+    let mut writers = out_paths
+        .iter()
+        .filter(|(format, _)| format.is_streamed())
+        .map(|(format, path)| {
+            let resume_len = resumed
+                .as_ref()
+                .map(|progress| progress.output_lens[format.extension()]);
+            let file = if args.append {
+                progress::append_output(path)?
+            } else {
+                progress::open_output(path, resume_len)?
+            };
+            // A resumed or appended file already starts with the byte order mark
+            let empty = file.metadata()?.len() == 0;
+            let mut writer = TextWriter::new(
+                BufWriter::new(file),
+                args.line_ending,
+                args.bom && *format == OutputFormat::Txt && resume_len.is_none() && empty,
+            )
+            .context("Failed to write transcription to file")?;
+            if args.append && *format == OutputFormat::Txt {
+                writer
+                    .write_line(&output::append_header(audio_file))
+                    .context("Failed to write transcription to file")?;
+            }
+            Ok((*format, writer))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let mut progress = resumed.unwrap_or(Progress {
+        total_samples: prepared_audio.len(),
+        ..Progress::default()
+    });
+    let stem = audio_file
+        .file_stem()
+        .map_or("chunk".into(), |stem| stem.to_string_lossy());
+    let mut exporter = match &args.export_chunks {
+        Some(dir) => Some(ChunkExporter::create(dir, &stem)?),
+        None => None,
+    };
+    if let Some(dir) = &args.dump_mel {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create mel directory {}", dir.display()))?;
+    }
+
+    let first_chunk = progress.next_chunk;
+    let pending: Vec<(usize, Range<usize>)> = ranges
+        .iter()
+        .cloned()
+        .enumerate()
+        .skip(first_chunk)
+        .collect();
+
+    let pause_marker = args.pause_threshold_sec.map(|threshold_sec| PauseMarker {
+        threshold_sec,
+        template: args.pause_marker.clone(),
+    });
+
+    let trailing_hallucination = args.suppress_trailing_hallucination.then(|| {
+        let defaults = TrailingHallucination::default();
+        TrailingHallucination {
+            fillers: if args.hallucination_filler.is_empty() {
+                defaults.fillers
+            } else {
+                args.hallucination_filler.clone()
+            },
+            ..defaults
+        }
+    });
+
+    let color = use_color(args);
+    let corrections = read_corrections(args)?;
+
+    // A resumed run takes over the segments --min-confidence held back
+    progress.confidence = args.min_confidence.map(|min_logprob| {
+        progress
+            .confidence
+            .take()
+            .unwrap_or_else(|| ConfidenceFilter::new(min_logprob, args.low_confidence_action))
+    });
+
+    // Chunks written to the streamed transcripts since they were last flushed
+    let mut unflushed = 0;
+
+    // Write a segment to the streamed transcripts and keep it for the others
+    let write_segment = |mut segment: Segment,
+                         progress: &mut Progress,
+                         writers: &mut [(OutputFormat, TextWriter<BufWriter<File>>)],
+                         unflushed: &mut usize,
+                         exporter: Option<&mut ChunkExporter>|
+     -> Result<()> {
+        if let Some(corrections) = corrections
+            .as_ref()
+            .filter(|_| segment.avg_logprob.is_some())
+        {
+            segment.text = corrections.apply(&segment.text).into_owned();
+        }
+        if args.normalize_text {
+            segment.text = text::normalize(&segment.text);
+        }
+        let previous_end = progress.segments.last().map(|previous| previous.end_sec);
+        let pause = pause_marker
+            .as_ref()
+            .and_then(|marker| marker.between(previous_end, segment.start_sec));
+        // Stream chunk text to output files immediately
+        stream_segment(writers, &segment, pause.as_deref(), args)?;
+        if args.live_stdout {
+            println!("{}", output::live_line(&segment));
+        }
+        *unflushed += 1;
+        if *unflushed >= args.flush_interval {
+            for (_, writer) in writers.iter_mut() {
+                writer.flush().ok();
+            }
+            *unflushed = 0;
+        }
+        // Only chunks whose text came from the model make clip/text pairs
+        let transcribed = segment.avg_logprob.is_some();
+        if let Some(exporter) = exporter.filter(|_| transcribed) {
+            exporter.export(
+                &segment,
+                &prepared_audio[ranges[segment.index].clone()],
+                timeline.sample_rate(),
+            )?;
+        }
+        progress.segments.push(segment);
+        Ok(())
+    };
+
+    // Assemble the output of each chunk, in chunk order
+    // A resumed run goes on with the context the interrupted one had reached
+    let resumed_context = progress.context.take();
+
+    let mut handle_chunk = |chunk_index: usize,
+                            outcome: ChunkOutcome,
+                            context: Option<&RollingContext>|
+     -> Result<()> {
+        let range = &ranges[chunk_index];
+        let (start_sec, end_sec) = timeline.seconds(range);
+
+        let segment = match outcome {
+            ChunkOutcome::Failed => {
+                progress.failed_chunks.push(chunk_index);
+                Some(Segment {
+                    index: chunk_index,
+                    start_sec,
+                    end_sec,
+                    text: FAILED_PLACEHOLDER.to_string(),
+                    avg_logprob: None,
+                    no_speech_prob: None,
+                    speaker: None,
+                    language: None,
+                    failed: true,
+                })
+            }
+            ChunkOutcome::Silent => {
+                log::info!("Chunk is silent, skipping");
+                progress.skipped_chunks += 1;
+                (!args.silence_placeholder.is_empty()).then(|| Segment {
+                    index: chunk_index,
+                    start_sec,
+                    end_sec,
+                    text: args.silence_placeholder.clone(),
+                    avg_logprob: None,
+                    no_speech_prob: None,
+                    speaker: None,
+                    language: None,
+                    failed: false,
+                })
+            }
+            ChunkOutcome::Transcribed {
+                result,
+                elapsed,
+                language,
+            } => {
+                log_throughput(&format!("chunk {}", chunk_index + 1), &result, elapsed);
+                chunk_times.push(elapsed);
+
+                let mut suppressed = is_no_speech(&result, args.no_speech_threshold);
+                if suppressed {
+                    log::info!(
+                        "No speech detected (p = {:.2}), text suppressed",
+                        result.no_speech_prob
+                    );
+                } else if chunk_index + 1 == ranges.len()
+                    && trailing_hallucination.as_ref().is_some_and(|check| {
+                        check.is_likely(
+                            &result.text,
+                            progress
+                                .segments
+                                .last()
+                                .map(|previous| previous.text.as_str()),
+                            audio::rms(&prepared_audio[range.clone()]),
+                        )
+                    })
+                {
+                    log::info!(
+                        "Final chunk looks like a hallucination ({}), text suppressed",
+                        result.text.trim()
+                    );
+                    suppressed = true;
+                }
+                if suppressed {
+                    progress.suppressed_chunks += 1;
+                }
+
+                if args.estimated_word_timestamps && !suppressed {
+                    align::extend_without_overlap(
+                        &mut progress.words,
+                        align::estimate_word_timestamps(&result.text, start_sec, end_sec),
+                    );
+                }
+
+                // Collect tokens for downstream use if needed
+                all_tokens.extend(&result.tokens);
+                if args.dump_tokens.is_some() {
+                    progress.chunk_tokens.push(ChunkTokens {
+                        index: chunk_index,
+                        start_sec,
+                        end_sec,
+                        tokens: result.tokens.clone(),
+                    });
+                }
+
+                if suppressed {
+                    None
+                } else {
+                    transcribed_segment(
+                        chunk_index,
+                        (start_sec, end_sec),
+                        result,
+                        language,
+                        &mut progress.empty_chunks,
+                    )
+                }
+            }
+        };
+
+        // --min-confidence may hold segments back, so the ones written can
+        // belong to earlier chunks
+        let last = chunk_index + 1 == ranges.len();
+        for segment in release_segments(progress.confidence.as_mut(), segment, last, args) {
+            write_segment(
+                segment,
+                &mut progress,
+                &mut writers,
+                &mut unflushed,
+                exporter.as_mut(),
+            )?;
+        }
+
+        if args.resume && !last {
+            progress.next_chunk = chunk_index + 1;
+            progress.next_sample = ranges[chunk_index + 1].start;
+            progress.context = context.cloned();
+            for (format, writer) in writers.iter_mut() {
+                writer
+                    .flush()
+                    .context("Failed to flush transcription before saving progress")?;
+                progress.output_lens.insert(
+                    format.extension().to_string(),
+                    writer.get_ref().get_ref().metadata()?.len(),
+                );
+            }
+            progress.save(&progress_path)?;
+        }
+
+        if interrupt::requested() {
+            // After the progress was saved, so a resumed run drops the marker
+            // and holds these segments back again
+            if !last {
+                for segment in release_segments(progress.confidence.as_mut(), None, true, args) {
+                    write_segment(
+                        segment,
+                        &mut progress,
+                        &mut writers,
+                        &mut unflushed,
+                        exporter.as_mut(),
+                    )?;
+                }
+            }
+            for (format, writer) in writers.iter_mut() {
+                if *format == OutputFormat::Txt {
+                    writer
+                        .write_line(INTERRUPTED_MARKER)
+                        .context("Failed to write chunk transcription to file")?;
+                }
+                writer
+                    .flush()
+                    .context("Failed to write chunk transcription to file")?;
+            }
+            return Err(Interrupted.into());
+        }
+        Ok(())
+    };
+
+    let chunks = if let [model] = models {
+        let mut context = rolling_context(args).map(|fresh| resumed_context.unwrap_or(fresh));
+        // Iterate chunks, streaming text as it is generated
+        plan.for_each_chunk(
+            prepared_audio,
+            first_chunk,
+            |event| print_chunk_event("Transcribing chunk", event),
+            |chunk_index, chunk| {
+                dump_mel(model, chunk, chunk_index, &stem, args)?;
+                let outcome = transcribe_chunk_in_language(
+                    model,
+                    chunk,
+                    &conditioned(options, context.as_ref()),
+                    args,
+                    !color && !args.live_stdout,
+                )?;
+                follow_chunk(context.as_mut(), &outcome);
+                if let ChunkOutcome::Transcribed { result, .. } = &outcome {
+                    if !args.live_stdout {
+                        finish_text(result, color);
+                    }
+                }
+                handle_chunk(chunk_index, outcome, context.as_ref())
+            },
+        )
     } else {
-        chunk_samples
+        // Chunks finish out of order, so text is printed once a chunk is handled
+        log::info!(
+            "Transcribing {} chunks on {} workers...",
+            pending.len(),
+            models.len()
+        );
+        parallel::run_ordered(
+            models.iter_mut().collect(),
+            &pending,
+            |model, (chunk_index, range)| {
+                let chunk = &prepared_audio[range.clone()];
+                dump_mel(model, chunk, *chunk_index, &stem, args)?;
+                let outcome = transcribe_chunk_in_language(model, chunk, options, args, false)?;
+                Ok((*chunk_index, outcome))
+            },
+            |_, (chunk_index, outcome)| {
+                let range = &ranges[chunk_index];
+                log::info!(
+                    "Chunk {}/{} (samples {}..{}):",
+                    chunk_index + 1,
+                    ranges.len(),
+                    range.start,
+                    range.end
+                );
+                if let ChunkOutcome::Transcribed { result, .. } = &outcome {
+                    if color {
+                        println!("{}", paint(&result.text, result.avg_logprob));
+                    } else if !args.live_stdout {
+                        println!("{}", result.text);
+                    }
+                }
+                handle_chunk(chunk_index, outcome, None)
+            },
+        )
+    };
+    // An interrupted run still writes the chunks completed so far
+    let interrupted = match chunks {
+        Err(e) if e.is::<Interrupted>() => true,
+        chunks => {
+            chunks?;
+            false
+        }
+    };
+    for (_, writer) in writers.iter_mut() {
+        writer
+            .flush()
+            .context("Failed to write chunk transcription to file")?;
+    }
+
+    for (format, path) in &out_paths {
+        match format {
+            OutputFormat::Json => {
+                output::write_json(path, &progress.segments, args.start_time.as_ref())?
+            }
+            OutputFormat::Srt => output::write_srt(
+                path,
+                &progress.segments,
+                args.line_ending,
+                args.start_time.as_ref(),
+            )?,
+            OutputFormat::Csv => output::write_csv(path, &progress.segments, args.line_ending)?,
+            OutputFormat::Md => output::write_markdown(
+                path,
+                &progress.segments,
+                Some(&front_matter(summary)),
+                pause_marker.as_ref(),
+                args.line_ending,
+            )?,
+            OutputFormat::Txt | OutputFormat::Jsonl | OutputFormat::Live => {}
+        }
+        note!("Transcription written to {}", path.display());
+    }
+    if progress.skipped_chunks > 0 {
+        log::info!(
+            "Skipped {} of {} chunks as silent",
+            progress.skipped_chunks,
+            ranges.len()
+        );
+    }
+    if progress.suppressed_chunks > 0 {
+        log::info!(
+            "Suppressed the text of {} chunks without speech",
+            progress.suppressed_chunks
+        );
+    }
+    if progress.empty_chunks > 0 {
+        log::info!(
+            "Left out {} chunks the model transcribed to no text",
+            progress.empty_chunks
+        );
+    }
+    let failed: Vec<String> = progress
+        .failed_chunks
+        .iter()
+        .map(|index| (index + 1).to_string())
+        .collect();
+    print_failed_chunks(&failed, ranges.len());
+
+    if args.estimated_word_timestamps {
+        write_word_timestamps(out_path, &progress.words)?;
+    }
+    if let Some(path) = &args.dump_tokens {
+        output::write_tokens(path, &progress.chunk_tokens)?;
+        note!("Tokens written to {}", path.display());
+    }
+    if let Some(exporter) = &exporter {
+        note!(
+            "Chunk clips listed in {}",
+            exporter.manifest_path().display()
+        );
+    }
+
+    summary.chunks = ranges.len();
+    summary.skipped_chunks = progress.skipped_chunks;
+    summary.suppressed_chunks = progress.suppressed_chunks;
+    summary.empty_chunks = progress.empty_chunks;
+    summary.failed_chunks = progress.failed_chunks.len();
+    summary.tokens = all_tokens.len();
+
+    if interrupted {
+        return Err(Interrupted.into());
+    }
+    if args.resume && progress_path.exists() {
+        std::fs::remove_file(&progress_path).context("Failed to remove progress file")?;
+    }
+
+    Ok(chunk_times)
+}
+
+/// Segments to write once `--min-confidence` has seen the `segment` of a
+/// chunk: the ones it lets through and, with `finish`, the ones it still
+/// holds back. Without the option, just `segment`.
+fn release_segments(
+    confidence: Option<&mut ConfidenceFilter>,
+    segment: Option<Segment>,
+    finish: bool,
+    args: &Args,
+) -> Vec<Segment> {
+    let Some(filter) = confidence else {
+        return segment.into_iter().collect();
     };
+    let mut segments = segment
+        .map(|segment| filter.admit(segment))
+        .unwrap_or_default();
+    if finish {
+        if filter.all_low() {
+            log::warn!(
+                "Every segment is below --min-confidence {}, keeping them all",
+                args.min_confidence.unwrap_or_default()
+            );
+        }
+        segments.extend(filter.finish());
+        if filter.filtered() > 0 {
+            log::info!(
+                "Filtered {} segments below --min-confidence",
+                filter.filtered()
+            );
+        }
+    }
+    segments
+}
 
-    let mut all_tokens: Vec<u32> = Vec::new();
+/// What happened to a chunk before its output is assembled.
+enum ChunkOutcome {
+    /// Below the silence threshold; the model did not run.
+    Silent,
+    /// Every attempt to transcribe the chunk failed.
+    Failed,
+    Transcribed {
+        result: TranscriptionResult,
+        elapsed: Duration,
+        /// Language detected for the chunk with `--language-per-chunk`.
+        language: Option<String>,
+    },
+}
 
-    // Prepare output file: same stem as input file with .txt extension
-    let mut out_path = audio_file.to_path_buf();
-    out_path.set_extension("txt");
-    let out_file =
-        File::create(&out_path).context("Failed to create output file for transcription")?;
-    let mut writer = BufWriter::new(out_file);
-
-    if prepared_audio.len() <= chunk_samples {
-        let result = model
-            .transcribe_audio(prepared_audio, target_sr)
-            .context("Failed to transcribe audio with tokens")?;
-        writeln!(writer, "{}", result.text).context("Failed to write transcription to file")?;
-        writer.flush().ok();
-        println!("Transcription written to {}", out_path.display());
-        return Ok(());
+/// The `--corrections` rules, if given.
+fn read_corrections(args: &Args) -> Result<Option<Corrections>> {
+    args.corrections
+        .as_deref()
+        .map(Corrections::read)
+        .transpose()
+}
+
+/// Context carried from chunk to chunk with `--condition-on-previous`.
+fn rolling_context(args: &Args) -> Option<RollingContext> {
+    args.condition_on_previous
+        .then(|| RollingContext::new(PREVIOUS_CONTEXT_TOKENS))
+}
+
+/// `options` with the previous chunk's tokens from `context`, if any.
+fn conditioned<'a>(
+    options: &'a TranscribeOptions,
+    context: Option<&RollingContext>,
+) -> Cow<'a, TranscribeOptions> {
+    match context.filter(|context| !context.tokens().is_empty()) {
+        Some(context) => Cow::Owned(TranscribeOptions {
+            previous_tokens: context.tokens().to_vec(),
+            ..options.clone()
+        }),
+        None => Cow::Borrowed(options),
     }
+}
 
-    // Iterate chunks
-    let mut start = 0usize;
-    let mut chunk_index = 0usize;
-    while start < prepared_audio.len() {
-        let end = std::cmp::min(start + chunk_samples, prepared_audio.len());
-        let chunk = &prepared_audio[start..end];
+/// Pass the transcript of a chunk on to the next one through `context`.
+///
+/// A chunk that is silent or failed leaves nothing to follow on from.
+fn follow_chunk(context: Option<&mut RollingContext>, outcome: &ChunkOutcome) {
+    let Some(context) = context else {
+        return;
+    };
+    match outcome {
+        ChunkOutcome::Transcribed { result, .. } => {
+            if !context.follow(&result.tokens, result.truncated) {
+                log::info!("Chunk transcript loops or is cut off, not passing it on as context");
+            }
+        }
+        ChunkOutcome::Silent | ChunkOutcome::Failed => context.clear(),
+    }
+}
 
-        println!(
-            "Transcribing chunk {}/{} (samples {}..{})...",
-            chunk_index + 1,
-            prepared_audio.len().div_ceil(step),
-            start,
-            end
+/// Log under `--verbose` how fast the model generated the tokens of `result`
+/// for the chunk called `label`, in the `elapsed` time of the model call.
+fn log_throughput(label: &str, result: &TranscriptionResult, elapsed: Duration) {
+    let throughput = Throughput {
+        tokens: result.tokens.len(),
+        elapsed,
+    };
+    log::debug!("{label}: {throughput}");
+}
+
+/// Transcribe one chunk unless it is silent, streaming its text to `on_text`.
+fn transcribe_chunk(
+    model: &mut VoxtralModel,
+    chunk: &[f32],
+    options: &TranscribeOptions,
+    silence_threshold: f32,
+    on_text: impl FnMut(&str),
+) -> Result<ChunkOutcome> {
+    if is_silent(chunk, silence_threshold) {
+        return Ok(ChunkOutcome::Silent);
+    }
+    // Only the model call is timed, for the tokens per second of the chunk
+    let started = Instant::now();
+    let result = model
+        .transcribe_audio_16k(chunk, options, on_text)
+        .context("Failed to transcribe audio chunk")?;
+    Ok(ChunkOutcome::Transcribed {
+        result,
+        elapsed: started.elapsed(),
+        language: None,
+    })
+}
+
+/// Transcribe one chunk like [`transcribe_chunk_with_retries`], with
+/// `--language-per-chunk` in the language detected for it.
+///
+/// Silent chunks are not detected, and a chunk whose detection fails is
+/// transcribed in the language of `options`.
+fn transcribe_chunk_in_language(
+    model: &mut VoxtralModel,
+    chunk: &[f32],
+    options: &TranscribeOptions,
+    args: &Args,
+    print: bool,
+) -> Result<ChunkOutcome> {
+    if !args.language_per_chunk || is_silent(chunk, args.silence_threshold) {
+        return transcribe_chunk_with_retries(model, chunk, options, args, print);
+    }
+    let detected = model
+        .detect_language(chunk, model.sample_rate())
+        .map(|candidates| candidates.into_iter().next());
+    let language = match detected {
+        Ok(Some(best)) => {
+            log::info!(
+                "Chunk language: {} ({:.1}%)",
+                best.language,
+                best.probability * 100.0
+            );
+            best.language
+        }
+        Ok(None) => options.language.clone(),
+        Err(e) => {
+            log::warn!(
+                "Failed to detect the chunk language, using {}: {e:#}",
+                options.language
+            );
+            options.language.clone()
+        }
+    };
+    let options = TranscribeOptions {
+        language: language.clone(),
+        ..options.clone()
+    };
+    let mut outcome = transcribe_chunk_with_retries(model, chunk, &options, args, print)?;
+    if let ChunkOutcome::Transcribed {
+        language: chunk_language,
+        ..
+    } = &mut outcome
+    {
+        *chunk_language = Some(language);
+    }
+    Ok(outcome)
+}
+
+/// Transcribe one chunk like [`transcribe_chunk`], retrying it up to
+/// `--chunk-retries` times when it fails.
+///
+/// With `--retry-split` every retry splits the chunk into twice as many
+/// pieces as the attempt before and joins their results. Once the retries
+/// are used up the chunk is reported as failed; without retries the error
+/// ends the run as before.
+///
+/// With `print` the text is printed on stdout as it is generated; without,
+/// it is printed colored or as a live line once the chunk is done, if at all.
+fn transcribe_chunk_with_retries(
+    model: &mut VoxtralModel,
+    chunk: &[f32],
+    options: &TranscribeOptions,
+    args: &Args,
+    print: bool,
+) -> Result<ChunkOutcome> {
+    let mut attempt = 0;
+    loop {
+        let pieces = if args.retry_split {
+            1 << attempt.min(MAX_RETRY_SPLITS)
+        } else {
+            1
+        };
+        let mut printed = false;
+        let attempted = transcribe_pieces(
+            model,
+            chunk,
+            pieces,
+            options,
+            args.silence_threshold,
+            &mut |text| {
+                if print && !text.is_empty() {
+                    print_partial(text);
+                    printed = true;
+                }
+            },
         );
+        match attempted {
+            Ok(outcome) => return Ok(outcome),
+            Err(e) if args.chunk_retries == 0 => return Err(e),
+            Err(e) if attempt < args.chunk_retries => {
+                attempt += 1;
+                // End the line of text streamed by the failed attempt
+                // before the warning
+                if printed {
+                    println!();
+                }
+                log::warn!(
+                    "Chunk failed: {e:#}. Retrying ({attempt}/{})",
+                    args.chunk_retries
+                );
+            }
+            Err(e) => {
+                if printed {
+                    println!();
+                }
+                log::warn!("Chunk failed: {e:#}. Giving up on it");
+                return Ok(ChunkOutcome::Failed);
+            }
+        }
+    }
+}
+
+/// Transcribe `chunk` as `pieces` consecutive parts of equal length and join
+/// their results; silent parts are left out.
+fn transcribe_pieces(
+    model: &mut VoxtralModel,
+    chunk: &[f32],
+    pieces: usize,
+    options: &TranscribeOptions,
+    silence_threshold: f32,
+    on_text: &mut dyn FnMut(&str),
+) -> Result<ChunkOutcome> {
+    if pieces <= 1 {
+        return transcribe_chunk(model, chunk, options, silence_threshold, on_text);
+    }
+
+    let mut results = Vec::new();
+    let mut elapsed = Duration::ZERO;
+    for piece in chunk.chunks(chunk.len().div_ceil(pieces).max(1)) {
+        if let ChunkOutcome::Transcribed {
+            result,
+            elapsed: piece_elapsed,
+            ..
+        } = transcribe_chunk(model, piece, options, silence_threshold, &mut *on_text)?
+        {
+            results.push(result);
+            elapsed += piece_elapsed;
+        }
+    }
+    if results.is_empty() {
+        return Ok(ChunkOutcome::Silent);
+    }
+    Ok(ChunkOutcome::Transcribed {
+        result: TranscriptionResult::join(results),
+        elapsed,
+        language: None,
+    })
+}
+
+/// With `--dump-mel`, write the mel features of `chunk` to
+/// `<dir>/<stem>_<index>.npy`. Silent chunks never reach the encoder and
+/// are not written.
+fn dump_mel(
+    model: &VoxtralModel,
+    chunk: &[f32],
+    chunk_index: usize,
+    stem: &str,
+    args: &Args,
+) -> Result<()> {
+    let Some(dir) = &args.dump_mel else {
+        return Ok(());
+    };
+    if is_silent(chunk, args.silence_threshold) {
+        return Ok(());
+    }
+    let features = model
+        .mel_features(chunk)
+        .context("Failed to compute mel features")?;
+    mel::write_npy(dir.join(format!("{stem}_{chunk_index:04}.npy")), &features)
+}
 
-        let result = model
-            .transcribe_audio(chunk, target_sr)
-            .context("Failed to transcribe audio chunk")?;
+/// Segment of the transcribed chunk `chunk_index`, from `start_sec` to
+/// `end_sec`, or `None`, counted in `empty_chunks`, when the model produced
+/// no text.
+fn transcribed_segment(
+    chunk_index: usize,
+    (start_sec, end_sec): (f32, f32),
+    result: TranscriptionResult,
+    language: Option<String>,
+    empty_chunks: &mut usize,
+) -> Option<Segment> {
+    let Some(text) = chunk_text(result.text, result.truncated) else {
+        log::info!("Chunk produced no text, leaving it out");
+        *empty_chunks += 1;
+        return None;
+    };
+    Some(Segment {
+        index: chunk_index,
+        start_sec,
+        end_sec,
+        text,
+        avg_logprob: Some(result.avg_logprob),
+        no_speech_prob: Some(result.no_speech_prob),
+        speaker: None,
+        language,
+        failed: false,
+    })
+}
 
-        // Stream chunk text to output file immediately
-        writeln!(writer, "{}", result.text)
+/// Write `segment` to the transcripts streamed while chunks complete,
+/// preceded in plain text by the `pause` marker, if any.
+fn stream_segment<W: Write>(
+    writers: &mut [(OutputFormat, TextWriter<W>)],
+    segment: &Segment,
+    pause: Option<&str>,
+    args: &Args,
+) -> Result<()> {
+    for (format, writer) in writers.iter_mut() {
+        if let Some(pause) = pause.filter(|_| *format == OutputFormat::Txt) {
+            writer
+                .write_line(pause)
+                .context("Failed to write chunk transcription to file")?;
+        }
+        let line = match format {
+            OutputFormat::Jsonl => output::jsonl_line(segment, args.start_time.as_ref())?,
+            OutputFormat::Live => output::live_line(segment),
+            _ => output::tagged_text(segment).into_owned(),
+        };
+        writer
+            .write_line(&line)
             .context("Failed to write chunk transcription to file")?;
-        writer.flush().ok();
+        // Live captions are read as they come, whatever --flush-interval says
+        if *format == OutputFormat::Live {
+            writer
+                .flush()
+                .context("Failed to write chunk transcription to file")?;
+        }
+    }
+    Ok(())
+}
 
-        // Collect tokens for downstream use if needed
-        all_tokens.extend(result.tokens);
+/// Text of a transcribed chunk, with [`TRUNCATED_MARKER`] appended when its
+/// generation was cut short by `--chunk-timeout-sec`, or `None` when the
+/// model produced no text, so the chunk leaves no blank line or empty cue.
+fn chunk_text(text: String, truncated: bool) -> Option<String> {
+    if !truncated {
+        return (!text.trim().is_empty()).then_some(text);
+    }
+    log::warn!("Chunk timed out, text truncated");
+    Some(if text.trim().is_empty() {
+        TRUNCATED_MARKER.to_string()
+    } else {
+        format!("{} {TRUNCATED_MARKER}", text.trim_end())
+    })
+}
 
-        chunk_index += 1;
-        if end == prepared_audio.len() {
-            break;
+/// Log the estimated activation memory of the longest pass through the model:
+/// a whole chunk, or a pass of up to `max_seconds` when that is shorter.
+fn log_pass_memory(model: &VoxtralModel, plan: &ChunkPlan, max_seconds: Option<f32>) {
+    let samples = match max_seconds {
+        Some(seconds) => plan.chunk_samples.min(model.pass_samples(seconds)),
+        None => plan.chunk_samples,
+    };
+    let mib = model.estimated_pass_bytes(samples) as f64 / (1024.0 * 1024.0);
+    let seconds = samples as f32 / model.sample_rate() as f32;
+    match max_seconds {
+        Some(_) => {
+            log::info!("Passes of up to {seconds:.0} s need about {mib:.0} MiB of activations")
         }
-        start += step;
+        None => log::debug!("Chunks of {seconds:.0} s need about {mib:.0} MiB of activations"),
+    }
+}
+
+/// Log which of `total` chunks, by label, failed every attempt.
+fn print_failed_chunks(failed: &[String], total: usize) {
+    if !failed.is_empty() {
+        log::warn!(
+            "Failed to transcribe {} of {} chunks: {}",
+            failed.len(),
+            total,
+            failed.join(", ")
+        );
     }
+}
 
-    println!("Transcription written to {}", out_path.display());
+/// Whether `chunk` is quiet enough to skip; a zero threshold never skips.
+fn is_silent(chunk: &[f32], threshold: f32) -> bool {
+    threshold > 0.0 && audio::rms(chunk) < threshold
+}
 
+/// Whether `--no-speech-threshold` marks `result` as music or noise.
+fn is_no_speech(result: &TranscriptionResult, threshold: Option<f32>) -> bool {
+    threshold.is_some_and(|threshold| result.no_speech_prob > threshold)
+}
+
+/// Write estimated word timings as a JSON array next to the transcript.
+fn write_word_timestamps(out_path: &Path, words: &[WordTimestamp]) -> Result<()> {
+    let words_path = out_path.with_extension("estimated-words.json");
+    let file = File::create(&words_path).context("Failed to create word timestamps file")?;
+    serde_json::to_writer_pretty(BufWriter::new(file), words)
+        .context("Failed to write word timestamps")?;
+    note!(
+        "Estimated word timestamps written to {}",
+        words_path.display()
+    );
     Ok(())
 }
+
+/// Log which chunk is about to be transcribed, prefixed by `what`.
+/// Nothing is logged for audio that fits in one chunk.
+fn print_chunk_event(what: &str, event: ChunkEvent) {
+    if let ChunkEvent::Started(chunk) = event {
+        if chunk.total > 1 {
+            log::info!(
+                "{what} {}/{} (samples {}..{})...",
+                chunk.index + 1,
+                chunk.total,
+                chunk.start_sample,
+                chunk.end_sample
+            );
+        }
+    }
+}
+
+/// Print a piece of streamed transcription text without waiting for a newline.
+fn print_partial(text: &str) {
+    print!("{text}");
+    std::io::stdout().flush().ok();
+}
+
+/// Average log-probability from which a chunk's text is shown in green.
+const CONFIDENT_LOGPROB: f32 = -0.3;
+
+/// Average log-probability below which a chunk's text is shown in red.
+const DOUBTFUL_LOGPROB: f32 = -1.0;
+
+/// Whether `--color` applies: stdout is a terminal and `NO_COLOR` is unset
+/// or empty.
+fn use_color(args: &Args) -> bool {
+    color_applies(
+        args,
+        std::io::stdout().is_terminal(),
+        std::env::var_os("NO_COLOR").as_deref(),
+    )
+}
+
+/// Whether `--color` applies with stdout a `terminal` or not and the given
+/// value of `NO_COLOR`.
+fn color_applies(args: &Args, terminal: bool, no_color: Option<&OsStr>) -> bool {
+    args.color && terminal && no_color.is_none_or(|value| value.is_empty())
+}
+
+/// `text` wrapped in the ANSI color of its confidence: green, yellow, or red
+/// below [`DOUBTFUL_LOGPROB`].
+fn paint(text: &str, avg_logprob: f32) -> String {
+    let code = if avg_logprob >= CONFIDENT_LOGPROB {
+        32
+    } else if avg_logprob >= DOUBTFUL_LOGPROB {
+        33
+    } else {
+        31
+    };
+    format!("\x1b[{code}m{text}\x1b[0m")
+}
+
+/// End the printed text of a finished chunk: a newline after its streamed
+/// text, or with `color` the whole text in the color of its confidence.
+fn finish_text(result: &TranscriptionResult, color: bool) {
+    if color {
+        println!("{}", paint(&result.text, result.avg_logprob));
+    } else {
+        println!();
+    }
+}
@@ -0,0 +1,752 @@
+use super::*;
+use kitsune_stt::output::fixtures::segment;
+use std::sync::Mutex;
+
+/// Environment variables are process wide, so tests that set them take turns.
+static ENV_LOCK: Mutex<()> = Mutex::new(());
+
+const ENV_DEFAULTS: [(&str, &str); 5] = [
+    ("KITSUNE_CPU", "true"),
+    ("KITSUNE_DTYPE", "f32"),
+    ("KITSUNE_LANGUAGE", "fr"),
+    ("KITSUNE_FORMAT", "srt,json"),
+    ("KITSUNE_CHUNK_SECONDS", "30"),
+];
+
+/// Parse `argv` with the `KITSUNE_*` variables set to `vars` and all others unset.
+fn parse_with_env(vars: &[(&str, &str)], argv: &[&str]) -> std::result::Result<Cli, clap::Error> {
+    let _guard = ENV_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    for (name, _) in ENV_DEFAULTS {
+        std::env::remove_var(name);
+    }
+    for (name, value) in vars {
+        std::env::set_var(name, value);
+    }
+    let cli =
+        Cli::try_parse_with_sources(std::iter::once("kitsune-stt").chain(argv.iter().copied()));
+    for (name, _) in vars {
+        std::env::remove_var(name);
+    }
+    cli
+}
+
+#[test]
+fn test_args_built_in_defaults() {
+    let args = parse_with_env(&[], &["talk.wav"]).unwrap().args;
+
+    assert!(!args.cpu);
+    assert_eq!(args.dtype, None);
+    assert_eq!(args.language, None);
+    assert_eq!(args.format, FormatList(vec![OutputFormat::Txt]));
+    assert_eq!(args.chunk_seconds, 15.0);
+    assert_eq!(args.silence_threshold, 0.0);
+}
+
+#[test]
+fn test_args_defaults_from_env() {
+    let args = parse_with_env(&ENV_DEFAULTS, &["talk.wav"]).unwrap().args;
+
+    assert!(args.cpu);
+    assert_eq!(args.dtype, Some(ModelDType::F32));
+    assert_eq!(args.language.as_deref(), Some("fr"));
+    assert_eq!(
+        args.format,
+        FormatList(vec![OutputFormat::Srt, OutputFormat::Json])
+    );
+    assert_eq!(args.chunk_seconds, 30.0);
+}
+
+#[test]
+fn test_args_cli_overrides_env() {
+    let cli = parse_with_env(
+        &ENV_DEFAULTS,
+        &[
+            "bench",
+            "talk.wav",
+            "--dtype",
+            "bf16",
+            "--language",
+            "de",
+            "--format",
+            "txt",
+            "--chunk-seconds",
+            "10",
+        ],
+    )
+    .unwrap();
+    let Some(Command::Bench(args)) = cli.command else {
+        panic!("expected the bench subcommand");
+    };
+
+    assert!(args.cpu);
+    assert_eq!(args.dtype, Some(ModelDType::Bf16));
+    assert_eq!(args.language.as_deref(), Some("de"));
+    assert_eq!(args.format, FormatList(vec![OutputFormat::Txt]));
+    assert_eq!(args.chunk_seconds, 10.0);
+}
+
+#[test]
+fn test_args_falsey_env_flag() {
+    let args = parse_with_env(&[("KITSUNE_CPU", "0")], &["talk.wav"])
+        .unwrap()
+        .args;
+
+    assert!(!args.cpu);
+}
+
+#[test]
+fn test_args_detect_language_despite_env_language() {
+    let result = parse_with_env(&ENV_DEFAULTS, &["talk.wav", "--detect-language"]);
+
+    let args = result.unwrap().args;
+    assert!(args.language_from_env);
+    assert_eq!(given_language(None, &args), None);
+}
+
+#[test]
+fn test_args_detect_language_conflicts_with_cli_language() {
+    let err = parse_with_env(
+        &ENV_DEFAULTS,
+        &["talk.wav", "--language", "de", "--detect-language"],
+    )
+    .unwrap_err();
+    assert_eq!(err.kind(), clap::error::ErrorKind::ArgumentConflict);
+    assert!(parse_with_env(
+        &[],
+        &["bench", "talk.wav", "--language=de", "--detect-language"]
+    )
+    .is_err());
+
+    // Without --detect-language the command line still wins over the default
+    let args = parse_with_env(&ENV_DEFAULTS, &["talk.wav", "--language", "de"])
+        .unwrap()
+        .args;
+    assert!(!args.language_from_env);
+    assert_eq!(given_language(None, &args), Some("de"));
+}
+
+#[test]
+fn test_manifest_language_wins_over_detect_language() {
+    let args = parse_with_env(
+        &ENV_DEFAULTS,
+        &["--manifest", "batch.txt", "--detect-language"],
+    )
+    .unwrap()
+    .args;
+
+    assert_eq!(given_language(Some("ja"), &args), Some("ja"));
+    assert_eq!(given_language(None, &args), None);
+}
+
+#[test]
+fn test_args_invalid_env_value_is_an_error() {
+    let result = parse_with_env(&[("KITSUNE_FORMAT", "docx")], &["talk.wav"]);
+
+    assert!(result.is_err());
+}
+
+#[test]
+fn test_chunk_text_marks_truncation() {
+    assert_eq!(chunk_text("Hello".to_string(), false).unwrap(), "Hello");
+    assert_eq!(
+        chunk_text("Hello ".to_string(), true).unwrap(),
+        "Hello [truncated]"
+    );
+    assert_eq!(chunk_text(String::new(), true).unwrap(), "[truncated]");
+}
+
+#[test]
+fn test_empty_chunks_leave_no_blank_lines() {
+    let args = parse_with_env(&[], &["talk.wav", "--format", "txt,live"])
+        .unwrap()
+        .args;
+    let mut writers: Vec<_> = args
+        .format
+        .0
+        .iter()
+        .map(|&format| {
+            let writer = TextWriter::new(Vec::new(), LineEnding::Lf, false).unwrap();
+            (format, writer)
+        })
+        .collect();
+    let mut empty_chunks = 0;
+
+    for (index, text) in ["Hello", "", " ", "world", "\n", "again"]
+        .into_iter()
+        .enumerate()
+    {
+        let result = TranscriptionResult {
+            text: text.to_string(),
+            tokens: Vec::new(),
+            avg_logprob: -0.1,
+            no_speech_prob: 0.01,
+            truncated: false,
+        };
+        let seconds = (index as f32 * 15.0, index as f32 * 15.0 + 15.0);
+        if let Some(segment) = transcribed_segment(index, seconds, result, None, &mut empty_chunks)
+        {
+            stream_segment(&mut writers, &segment, None, &args).unwrap();
+        }
+    }
+
+    assert_eq!(empty_chunks, 3);
+    let written: Vec<String> = writers
+        .iter()
+        .map(|(_, writer)| String::from_utf8(writer.get_ref().clone()).unwrap())
+        .collect();
+    assert_eq!(written[0], "Hello\nworld\nagain\n");
+    assert_eq!(written[1].lines().count(), 3);
+    assert!(!written[1].lines().any(|line| line.trim().is_empty()));
+}
+
+#[test]
+fn test_args_log_level() {
+    let args = parse_with_env(&[], &["talk.wav", "--log-level", "warn"])
+        .unwrap()
+        .args;
+    assert_eq!(args.log_level, Some(log::LevelFilter::Warn));
+
+    let args = parse_with_env(&[], &["talk.wav"]).unwrap().args;
+    assert_eq!(args.log_level, None);
+
+    assert!(parse_with_env(&[], &["talk.wav", "--log-level", "loud"]).is_err());
+}
+
+#[test]
+fn test_args_decode_strategy() {
+    let args = parse_with_env(&[], &["talk.wav"]).unwrap().args;
+    assert_eq!(args.decode_strategy, DecodeStrategy::Greedy);
+    assert_eq!(args.fallback_temperatures, DEFAULT_FALLBACK_TEMPERATURES);
+    assert_eq!(args.logprob_threshold, -1.0);
+
+    let args = parse_with_env(
+        &[],
+        &[
+            "talk.wav",
+            "--decode-strategy",
+            "temperature-fallback",
+            "--fallback-temperatures",
+            "0.3,0.9",
+            "--logprob-threshold",
+            "-0.8",
+        ],
+    )
+    .unwrap()
+    .args;
+    assert_eq!(args.decode_strategy, DecodeStrategy::TemperatureFallback);
+    assert_eq!(args.fallback_temperatures, [0.3, 0.9]);
+    assert_eq!(args.logprob_threshold, -0.8);
+    assert!(check_decode_strategy(&args).is_ok());
+
+    let args = parse_with_env(&[], &["talk.wav", "--fallback-temperatures", "0.2,0"])
+        .unwrap()
+        .args;
+    assert!(check_decode_strategy(&args).is_err());
+}
+
+#[test]
+fn test_args_suppress_tokens() {
+    let args = parse_with_env(&[], &["talk.wav"]).unwrap().args;
+    assert!(args.suppress_tokens.is_empty());
+    assert!(!args.suppress_non_speech);
+
+    let args = parse_with_env(
+        &[],
+        &[
+            "talk.wav",
+            "--suppress-tokens",
+            "1032,70000",
+            "--suppress-non-speech",
+        ],
+    )
+    .unwrap()
+    .args;
+    assert_eq!(args.suppress_tokens, [1032, 70000]);
+    assert!(args.suppress_non_speech);
+
+    assert!(parse_with_env(&[], &["talk.wav", "--suppress-tokens", "12,-3"]).is_err());
+}
+
+#[test]
+fn test_args_manifest() {
+    let args = parse_with_env(&[], &["--manifest", "batch.txt"])
+        .unwrap()
+        .args;
+    assert_eq!(args.manifest, Some(PathBuf::from("batch.txt")));
+    assert_eq!(args.input, None);
+
+    assert!(parse_with_env(&[], &["talk.wav", "--manifest", "batch.txt"]).is_err());
+    assert!(parse_with_env(&[], &["--manifest", "batch.txt", "--summary", "-"]).is_err());
+}
+
+#[test]
+fn test_args_flush_interval() {
+    let args = parse_with_env(&[], &["talk.wav"]).unwrap().args;
+    assert_eq!(args.flush_interval, 1);
+
+    let args = parse_with_env(&[], &["talk.wav", "--flush-interval", "0"])
+        .unwrap()
+        .args;
+    assert_eq!(
+        run(&args).unwrap_err().to_string(),
+        "--flush-interval must be at least 1"
+    );
+}
+
+#[test]
+fn test_args_threads() {
+    let args = parse_with_env(&[], &["talk.wav"]).unwrap().args;
+    assert_eq!(model_options(&args).threads, None);
+
+    let args = parse_with_env(&[], &["talk.wav", "--threads", "4", "--parallel", "2"])
+        .unwrap()
+        .args;
+    assert_eq!(model_options(&args).threads, Some(4));
+}
+
+#[test]
+fn test_args_verbatim() {
+    let args = parse_with_env(&[], &["talk.wav"]).unwrap().args;
+    assert!(!args.verbatim);
+
+    let args = parse_with_env(&[], &["talk.wav", "--verbatim", "--prompt", "Kitsune"])
+        .unwrap()
+        .args;
+    assert!(args.verbatim);
+    assert_eq!(args.prompt.as_deref(), Some("Kitsune"));
+}
+
+#[test]
+fn test_verbatim_refuses_other_languages() {
+    let args = parse_with_env(&[], &["talk.wav", "--verbatim", "--language", "de"])
+        .unwrap()
+        .args;
+
+    let err = run(&args).unwrap_err().to_string();
+    assert!(err.contains("--language en only"), "{err}");
+}
+
+#[test]
+fn test_args_pad_mode() {
+    let args = parse_with_env(&[], &["talk.wav"]).unwrap().args;
+    assert_eq!(model_options(&args).pad_mode, PadMode::Zero);
+
+    let args = parse_with_env(&[], &["talk.wav", "--pad-mode", "reflect"])
+        .unwrap()
+        .args;
+    assert_eq!(model_options(&args).pad_mode, PadMode::Reflect);
+    assert!(parse_with_env(&[], &["talk.wav", "--pad-mode", "wrap"]).is_err());
+}
+
+#[test]
+fn test_args_refresh() {
+    let args = parse_with_env(&[], &["talk.wav"]).unwrap().args;
+    assert!(!model_options(&args).refresh);
+
+    let args = parse_with_env(&[], &["talk.wav", "--refresh"])
+        .unwrap()
+        .args;
+    assert!(model_options(&args).refresh);
+}
+
+#[test]
+fn test_paint_by_confidence() {
+    assert_eq!(paint("clear", -0.1), "\x1b[32mclear\x1b[0m");
+    assert_eq!(paint("edge", CONFIDENT_LOGPROB), "\x1b[32medge\x1b[0m");
+    assert_eq!(paint("unsure", -0.7), "\x1b[33munsure\x1b[0m");
+    assert_eq!(paint("noise", -2.5), "\x1b[31mnoise\x1b[0m");
+}
+
+#[test]
+fn test_color_needs_a_terminal() {
+    let args = parse_with_env(&[], &["talk.wav"]).unwrap().args;
+    assert!(!args.color);
+    assert!(!color_applies(&args, true, None));
+
+    let args = parse_with_env(&[], &["talk.wav", "--color"]).unwrap().args;
+    assert!(args.color);
+    assert!(color_applies(&args, true, None));
+    assert!(!color_applies(&args, false, None));
+    assert!(!color_applies(&args, true, Some(OsStr::new("1"))));
+    assert!(color_applies(&args, true, Some(OsStr::new(""))));
+}
+
+#[test]
+fn test_args_live_stdout() {
+    let args = parse_with_env(&[], &["talk.wav"]).unwrap().args;
+    assert!(!args.live_stdout);
+
+    let args = parse_with_env(&[], &["talk.wav", "--live-stdout"])
+        .unwrap()
+        .args;
+    assert!(args.live_stdout);
+    assert!(parse_with_env(&[], &["talk.wav", "--live-stdout", "--color"]).is_err());
+}
+
+#[test]
+fn test_args_language_per_chunk() {
+    let args = parse_with_env(&[], &["talk.wav"]).unwrap().args;
+    assert!(!args.language_per_chunk);
+
+    let args = parse_with_env(
+        &[],
+        &["talk.wav", "--language-per-chunk", "--language", "fr"],
+    )
+    .unwrap()
+    .args;
+    assert!(args.language_per_chunk);
+    assert_eq!(args.language.as_deref(), Some("fr"));
+}
+
+#[test]
+fn test_condition_on_previous_passes_context_on() {
+    let args = parse_with_env(&[], &["talk.wav"]).unwrap().args;
+    assert!(rolling_context(&args).is_none());
+
+    let args = parse_with_env(&[], &["talk.wav", "--condition-on-previous"])
+        .unwrap()
+        .args;
+    let mut context = rolling_context(&args);
+    let options = TranscribeOptions::default();
+    assert!(matches!(
+        conditioned(&options, context.as_ref()),
+        Cow::Borrowed(_)
+    ));
+
+    let transcribed = |tokens: Vec<u32>| ChunkOutcome::Transcribed {
+        result: TranscriptionResult {
+            text: String::new(),
+            tokens,
+            avg_logprob: 0.0,
+            no_speech_prob: 0.0,
+            truncated: false,
+        },
+        elapsed: Duration::ZERO,
+        language: None,
+    };
+    follow_chunk(context.as_mut(), &transcribed((0..100).collect()));
+    let previous = conditioned(&options, context.as_ref())
+        .previous_tokens
+        .clone();
+    assert_eq!(previous.len(), PREVIOUS_CONTEXT_TOKENS);
+    assert_eq!(previous.last(), Some(&99));
+
+    follow_chunk(context.as_mut(), &transcribed(vec![5; 30]));
+    assert!(conditioned(&options, context.as_ref())
+        .previous_tokens
+        .is_empty());
+    follow_chunk(context.as_mut(), &transcribed(vec![1, 2]));
+    follow_chunk(context.as_mut(), &ChunkOutcome::Silent);
+    assert!(context.unwrap().tokens().is_empty());
+
+    let args = parse_with_env(
+        &[],
+        &["talk.wav", "--condition-on-previous", "--parallel", "2"],
+    )
+    .unwrap()
+    .args;
+    assert!(run(&args)
+        .unwrap_err()
+        .to_string()
+        .contains("can not be combined with --parallel"));
+}
+
+#[test]
+fn test_args_min_confidence() {
+    let args = parse_with_env(&[], &["talk.wav", "--min-confidence", "-1.2"])
+        .unwrap()
+        .args;
+    assert_eq!(args.min_confidence, Some(-1.2));
+    assert_eq!(args.low_confidence_action, LowConfidenceAction::Drop);
+
+    let args = parse_with_env(
+        &[],
+        &[
+            "talk.wav",
+            "--min-confidence=-1",
+            "--low-confidence-action",
+            "bracket",
+        ],
+    )
+    .unwrap()
+    .args;
+    assert_eq!(args.low_confidence_action, LowConfidenceAction::Bracket);
+
+    assert!(parse_with_env(&[], &["talk.wav", "--low-confidence-action", "bracket"]).is_err());
+    let args = parse_with_env(&[], &["talk.wav", "--min-confidence", "NaN"])
+        .unwrap()
+        .args;
+    assert_eq!(
+        run(&args).unwrap_err().to_string(),
+        "--min-confidence must be a number"
+    );
+}
+
+#[test]
+fn test_release_segments_on_interrupt() {
+    let args = parse_with_env(&[], &["talk.wav", "--min-confidence", "-0.5"])
+        .unwrap()
+        .args;
+    let unsure = segment(0, "mumbled", Some(-2.0));
+    let mut filter = ConfidenceFilter::new(-0.5, LowConfidenceAction::Drop);
+
+    let written = release_segments(Some(&mut filter), Some(unsure.clone()), false, &args);
+    assert!(written.is_empty());
+    // Ctrl-C after the chunk writes what is held back instead of losing it
+    let written = release_segments(Some(&mut filter), None, true, &args);
+    assert_eq!(written, std::slice::from_ref(&unsure));
+    assert!(release_segments(Some(&mut filter), None, true, &args).is_empty());
+
+    assert_eq!(
+        release_segments(None, Some(unsure.clone()), false, &args),
+        [unsure]
+    );
+}
+
+#[test]
+fn test_args_quiet() {
+    let args = parse_with_env(&[], &["talk.wav", "--quiet"]).unwrap().args;
+    assert!(args.quiet);
+
+    assert!(parse_with_env(&[], &["talk.wav", "--quiet", "--verbose"]).is_err());
+    assert!(parse_with_env(&[], &["talk.wav", "--quiet", "--log-level", "info"]).is_err());
+}
+
+#[test]
+fn test_malformed_corrections_fail_before_loading_the_model() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let path = temp_dir.path().join("terms.tsv");
+    std::fs::write(&path, "kubernetes Kubernetes\n").unwrap();
+    let path = path.to_str().unwrap();
+
+    let args = parse_with_env(&[], &["talk.wav", "--corrections", path])
+        .unwrap()
+        .args;
+    let err = format!("{:#}", run(&args).unwrap_err());
+    assert!(err.contains("Failed to parse corrections"), "{err}");
+    assert!(err.contains("line 1: expected a wrong and a right spelling"));
+
+    std::fs::write(path, "kubernetes\tKubernetes\n").unwrap();
+    assert_eq!(read_corrections(&args).unwrap().unwrap().len(), 1);
+}
+
+#[test]
+fn test_model_config_sample_rate_drives_the_resampling() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let model_dir = temp_dir.path().join("model");
+    std::fs::create_dir(&model_dir).unwrap();
+    std::fs::write(
+        model_dir.join("config.json"),
+        r#"{"audio_config": {"sampling_rate": 8000}}"#,
+    )
+    .unwrap();
+    let dump = temp_dir.path().join("prepared.wav");
+    let input = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/vowel_22050.wav");
+
+    let args = parse_with_env(
+        &[],
+        &[
+            input.to_str().unwrap(),
+            "--model-dir",
+            model_dir.to_str().unwrap(),
+            "--dump-audio",
+            dump.to_str().unwrap(),
+            "--dry-run",
+        ],
+    )
+    .unwrap()
+    .args;
+    run(&args).unwrap();
+
+    let (pcm, sample_rate) = audio::pcm_decode(&dump).unwrap();
+    assert_eq!(sample_rate, 8000);
+    // 1.5 seconds of audio
+    assert!(pcm.len().abs_diff(12_000) < 100, "{}", pcm.len());
+}
+
+#[test]
+fn test_output_base_follows_the_template() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let out = temp_dir.path().join("out");
+    let out_arg = out.to_string_lossy().into_owned();
+    let input = Path::new("talks/keynote.wav");
+
+    let args = parse_with_env(&[], &["talks/keynote.wav"]).unwrap().args;
+    assert_eq!(output_base(input, input, "en", 1, &args).unwrap(), input);
+
+    let args = parse_with_env(
+        &[],
+        &[
+            "talks/keynote.wav",
+            "--output-dir",
+            &out_arg,
+            "--output-template",
+            "{lang}/transcript_{stem}_{index}.txt",
+        ],
+    )
+    .unwrap()
+    .args;
+    let layout_base = layout_bases(&[input.to_path_buf()], &args).remove(0);
+    let base = output_base(&layout_base, input, "fr", 3, &args).unwrap();
+    assert_eq!(base, out.join("talks/fr/transcript_keynote_3.txt"));
+    assert!(out.join("talks/fr").is_dir());
+    assert_eq!(
+        base.with_extension(OutputFormat::Srt.extension()),
+        out.join("talks/fr/transcript_keynote_3.srt")
+    );
+
+    let err = parse_with_env(&[], &["a.wav", "--output-template", "{name}.txt"]).unwrap_err();
+    assert!(
+        err.to_string().contains("unknown placeholder `{name}`"),
+        "{err}"
+    );
+}
+
+#[test]
+fn test_layout_bases_keep_flattened_inputs_apart() {
+    let inputs = [PathBuf::from("a/talk.wav"), PathBuf::from("b/talk.wav")];
+
+    let args = parse_with_env(
+        &[],
+        &[
+            "--manifest",
+            "batch.txt",
+            "--output-dir",
+            "out",
+            "--flatten",
+        ],
+    )
+    .unwrap()
+    .args;
+    assert_eq!(
+        layout_bases(&inputs, &args),
+        [
+            PathBuf::from("out/talk.wav"),
+            PathBuf::from("out/talk-2.wav")
+        ]
+    );
+
+    let args = parse_with_env(&[], &["--manifest", "batch.txt"])
+        .unwrap()
+        .args;
+    assert_eq!(layout_bases(&inputs, &args), inputs);
+}
+
+#[test]
+fn test_check_distinct_outputs_refuses_a_shared_template_path() {
+    let inputs = [PathBuf::from("a.wav"), PathBuf::from("b.wav")];
+    let batch = |template: &str| {
+        parse_with_env(
+            &[],
+            &["--manifest", "batch.txt", "--output-template", template],
+        )
+        .unwrap()
+        .args
+    };
+
+    let args = batch("transcript.txt");
+    let err = check_distinct_outputs(&inputs, &inputs, &[None, None], &args).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "a.wav and b.wav would both be transcribed to transcript.*, \
+         add {stem} or {index} to --output-template"
+    );
+
+    // Languages given for the inputs tell them apart, detected ones may not
+    let args = batch("transcript_{lang}.txt");
+    let languages = [Some("fr".to_string()), Some("de".to_string())];
+    assert!(check_distinct_outputs(&inputs, &inputs, &languages, &args).is_ok());
+    assert!(check_distinct_outputs(&inputs, &inputs, &[None, None], &args).is_err());
+
+    for template in ["{stem}.txt", "part_{index}.txt"] {
+        let args = batch(template);
+        assert!(check_distinct_outputs(&inputs, &inputs, &[None, None], &args).is_ok());
+    }
+    let mut args = batch("project.txt");
+    args.append = true;
+    assert!(check_distinct_outputs(&inputs, &inputs, &[None, None], &args).is_ok());
+
+    // Without a template, inputs differing only in extension share transcripts
+    let args = parse_with_env(&[], &["--manifest", "batch.txt"])
+        .unwrap()
+        .args;
+    let inputs = [PathBuf::from("a.wav"), PathBuf::from("a.mp3")];
+    let err = check_distinct_outputs(&inputs, &inputs, &[None, None], &args).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "a.wav and a.mp3 would both be transcribed to a.*"
+    );
+}
+
+#[test]
+fn test_interrupted_speaker_transcripts_keep_the_completed_chunks() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let base = temp_dir.path().join("call");
+    let args = parse_with_env(&[], &["call.wav", "--format", "txt,json"])
+        .unwrap()
+        .args;
+    let out_paths: Vec<_> = args
+        .format
+        .0
+        .iter()
+        .map(|&format| (format, base.with_extension(format.extension())))
+        .collect();
+    let segments = [Segment {
+        speaker: Some(output::channel_speaker(0)),
+        ..segment(0, "Hello there", Some(-0.1))
+    }];
+
+    write_speaker_transcripts(&out_paths, &segments, &RunSummary::default(), true, &args).unwrap();
+
+    let text = std::fs::read_to_string(base.with_extension("txt")).unwrap();
+    assert!(text.contains("Hello there"), "{text}");
+    assert!(text.ends_with(&format!("{INTERRUPTED_MARKER}\n")), "{text}");
+    let json = std::fs::read_to_string(base.with_extension("json")).unwrap();
+    assert!(json.contains("Hello there"), "{json}");
+}
+
+#[test]
+fn test_check_out_paths_refuses_the_input() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let input = temp_dir.path().join("talk.txt");
+    std::fs::write(&input, "RIFF").unwrap();
+    let input_arg = input.to_string_lossy().into_owned();
+    let args = parse_with_env(&[], &[&input_arg, "--format", "txt,srt"])
+        .unwrap()
+        .args;
+    let job = Job {
+        summary: RunSummary::default(),
+        out_base: input.clone(),
+    };
+    let out_paths = job.out_paths(&args);
+    assert_eq!(out_paths[0], (OutputFormat::Txt, input.clone()));
+
+    let err = check_out_paths(&input, &out_paths, true, &args).unwrap_err();
+    assert!(err.to_string().contains("is the input file"), "{err}");
+    assert_eq!(std::fs::read_to_string(&input).unwrap(), "RIFF");
+
+    // Existing transcripts are only warned about
+    let job = Job {
+        summary: RunSummary::default(),
+        out_base: temp_dir.path().join("out.wav"),
+    };
+    std::fs::write(temp_dir.path().join("out.txt"), "earlier run").unwrap();
+    assert!(check_out_paths(&input, &job.out_paths(&args), true, &args).is_ok());
+    let args = parse_with_env(&[], &[&input_arg, "--force"]).unwrap().args;
+    assert!(args.force);
+}
+
+#[test]
+fn test_dry_run_profile_times_the_decoding() {
+    let input = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/vowel_22050.wav");
+    let args = parse_with_env(&[], &[input.to_str().unwrap(), "--profile", "--dry-run"])
+        .unwrap()
+        .args;
+    assert!(args.profile);
+    run(&args).unwrap();
+
+    let args = parse_with_env(&[], &[input.to_str().unwrap()])
+        .unwrap()
+        .args;
+    assert_eq!(take_profile(&args), None);
+}
@@ -0,0 +1,161 @@
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+
+#[cfg(test)]
+mod tests;
+
+/// One input of a `--manifest`: an audio file and its language, if the line
+/// overrides it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ManifestEntry {
+    /// Line of the manifest the entry comes from, counting from 1.
+    pub line: usize,
+    pub path: PathBuf,
+    /// Language code after the tab, such as `fr`.
+    pub language: Option<String>,
+}
+
+/// Parse a manifest: one audio file path per line, optionally followed by a
+/// tab and a language code. Blank lines and lines starting with `#` are
+/// skipped, and CRLF line endings are accepted.
+///
+/// # Errors
+///
+/// Returns an error listing every malformed line with its line number.
+pub fn parse(text: &str) -> Result<Vec<ManifestEntry>> {
+    let mut entries = Vec::new();
+    let mut problems = Vec::new();
+    for (index, raw) in text.lines().enumerate() {
+        let line = index + 1;
+        let content = raw.strip_suffix('\r').unwrap_or(raw);
+        if content.trim().is_empty() || content.trim_start().starts_with('#') {
+            continue;
+        }
+        match parse_line(content) {
+            Ok((path, language)) => entries.push(ManifestEntry {
+                line,
+                path: PathBuf::from(path),
+                language: language.map(str::to_string),
+            }),
+            Err(problem) => problems.push(format!("line {line}: {problem}")),
+        }
+    }
+    if !problems.is_empty() {
+        anyhow::bail!("Malformed manifest:\n  {}", problems.join("\n  "));
+    }
+    Ok(entries)
+}
+
+/// The path and the optional language of a manifest line.
+fn parse_line(content: &str) -> std::result::Result<(&str, Option<&str>), String> {
+    let mut fields = content.split('\t');
+    let path = fields.next().unwrap_or_default().trim();
+    let language = fields.next().map(str::trim);
+    if fields.next().is_some() {
+        return Err("expected a path and at most one language, separated by a tab".to_string());
+    }
+    if path.is_empty() {
+        return Err("no path before the tab".to_string());
+    }
+    match language {
+        Some("") => Err(format!("no language after the tab following {path}")),
+        Some(language) if language.contains(char::is_whitespace) => Err(format!(
+            "language '{language}' is not a single code such as en"
+        )),
+        _ => Ok((path, language)),
+    }
+}
+
+/// Sidecar file next to `input` that names its language, such as
+/// `talk.lang` for `talk.wav`.
+pub fn sidecar_path(input: &Path) -> PathBuf {
+    input.with_extension("lang")
+}
+
+/// Language code of `input` from its [`sidecar_path`], if there is one.
+///
+/// The file holds a single code such as `fr`; surrounding whitespace and
+/// lines starting with `#` are ignored.
+///
+/// # Errors
+///
+/// Returns an error if the sidecar cannot be read or does not hold exactly
+/// one language code.
+pub fn sidecar_language(input: &Path) -> Result<Option<String>> {
+    let path = sidecar_path(input);
+    if !path.is_file() {
+        return Ok(None);
+    }
+    let text = std::fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read language file {}", path.display()))?;
+    let codes: Vec<&str> = text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .collect();
+    match codes.as_slice() {
+        [code] if !code.contains(char::is_whitespace) => Ok(Some(code.to_string())),
+        _ => anyhow::bail!(
+            "Language file {} must hold a single code such as en",
+            path.display()
+        ),
+    }
+}
+
+impl ManifestEntry {
+    /// Language of this file: the one on its manifest line, else the one in
+    /// its sidecar file, else `None` for the global default or detection.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the sidecar file is unreadable or malformed.
+    pub fn resolve_language(&self) -> Result<Option<String>> {
+        match &self.language {
+            Some(language) => Ok(Some(language.clone())),
+            None => sidecar_language(&self.path),
+        }
+    }
+}
+
+/// Read and [`parse`] the manifest at `path` and check that every file it
+/// lists exists, so a batch does not stop halfway at a typo.
+///
+/// # Errors
+///
+/// Returns an error if the manifest cannot be read, is malformed, lists no
+/// files, or lists files that are missing or are directories, naming each
+/// with its line number.
+pub fn read(path: &Path) -> Result<Vec<ManifestEntry>> {
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read manifest {}", path.display()))?;
+    let entries =
+        parse(&text).with_context(|| format!("Failed to parse manifest {}", path.display()))?;
+    if entries.is_empty() {
+        anyhow::bail!("Manifest {} lists no files", path.display());
+    }
+    let problems: Vec<String> = entries
+        .iter()
+        .filter_map(|entry| {
+            let problem = if entry.path.is_dir() {
+                "is a directory"
+            } else if !entry.path.exists() {
+                "does not exist"
+            } else {
+                return None;
+            };
+            Some(format!(
+                "line {}: {} {problem}",
+                entry.line,
+                entry.path.display()
+            ))
+        })
+        .collect();
+    if !problems.is_empty() {
+        anyhow::bail!(
+            "Manifest {} lists unusable files:\n  {}",
+            path.display(),
+            problems.join("\n  ")
+        );
+    }
+    Ok(entries)
+}
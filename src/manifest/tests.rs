@@ -0,0 +1,141 @@
+use super::*;
+use std::fs;
+use tempfile::TempDir;
+
+#[test]
+fn test_parse_paths_languages_and_comments() {
+    let text = "# weekly meetings\n\
+                talks/a.wav\n\
+                \n\
+                talks/b.mp3\tfr\r\n\
+                \x20\x20# indented comment\n\
+                with space.flac\t de \n";
+
+    let entries = parse(text).unwrap();
+
+    assert_eq!(
+        entries,
+        [
+            ManifestEntry {
+                line: 2,
+                path: PathBuf::from("talks/a.wav"),
+                language: None,
+            },
+            ManifestEntry {
+                line: 4,
+                path: PathBuf::from("talks/b.mp3"),
+                language: Some("fr".to_string()),
+            },
+            ManifestEntry {
+                line: 6,
+                path: PathBuf::from("with space.flac"),
+                language: Some("de".to_string()),
+            },
+        ]
+    );
+    assert!(parse("").unwrap().is_empty());
+}
+
+#[test]
+fn test_parse_reports_every_malformed_line() {
+    let text = "a.wav\n\tfr\nb.wav\t\nc.wav\ten\tx\nd.wav\ten us\n";
+
+    let err = parse(text).unwrap_err().to_string();
+
+    assert_eq!(
+        err,
+        "Malformed manifest:\n  \
+         line 2: no path before the tab\n  \
+         line 3: no language after the tab following b.wav\n  \
+         line 4: expected a path and at most one language, separated by a tab\n  \
+         line 5: language 'en us' is not a single code such as en"
+    );
+}
+
+#[test]
+fn test_read_checks_files_up_front() {
+    let temp_dir = TempDir::new().unwrap();
+    let audio = temp_dir.path().join("a.wav");
+    fs::write(&audio, "RIFF").unwrap();
+    let manifest = temp_dir.path().join("batch.txt");
+    fs::write(
+        &manifest,
+        format!(
+            "{}\ten\n{}\n{}\n",
+            audio.display(),
+            temp_dir.path().join("missing.wav").display(),
+            temp_dir.path().display()
+        ),
+    )
+    .unwrap();
+
+    let err = read(&manifest).unwrap_err().to_string();
+
+    assert!(err.contains("lists unusable files"), "{err}");
+    assert!(err.contains("line 2: ") && err.contains("missing.wav does not exist"));
+    assert!(err.contains("line 3: ") && err.contains("is a directory"));
+    assert!(!err.contains("line 1"));
+
+    fs::write(&manifest, format!("{}\ten\n", audio.display())).unwrap();
+    let entries = read(&manifest).unwrap();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].language.as_deref(), Some("en"));
+}
+
+#[test]
+fn test_read_rejects_missing_or_empty_manifest() {
+    let temp_dir = TempDir::new().unwrap();
+    let manifest = temp_dir.path().join("batch.txt");
+
+    assert!(read(&manifest)
+        .unwrap_err()
+        .to_string()
+        .starts_with("Failed to read manifest"));
+
+    fs::write(&manifest, "# nothing yet\n").unwrap();
+    assert!(read(&manifest)
+        .unwrap_err()
+        .to_string()
+        .contains("lists no files"));
+}
+
+#[test]
+fn test_sidecar_language() {
+    let temp_dir = TempDir::new().unwrap();
+    let input = temp_dir.path().join("talk.wav");
+    assert_eq!(sidecar_path(&input), temp_dir.path().join("talk.lang"));
+    assert_eq!(sidecar_language(&input).unwrap(), None);
+
+    fs::write(
+        temp_dir.path().join("talk.lang"),
+        "# recorded in Lyon\n fr \n",
+    )
+    .unwrap();
+    assert_eq!(sidecar_language(&input).unwrap().as_deref(), Some("fr"));
+
+    fs::write(temp_dir.path().join("talk.lang"), "fr\nen\n").unwrap();
+    assert!(sidecar_language(&input)
+        .unwrap_err()
+        .to_string()
+        .contains("must hold a single code"));
+    fs::write(temp_dir.path().join("talk.lang"), "\n").unwrap();
+    assert!(sidecar_language(&input).is_err());
+}
+
+#[test]
+fn test_manifest_language_wins_over_sidecar() {
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path().join("talk.wav");
+    fs::write(temp_dir.path().join("talk.lang"), "de\n").unwrap();
+    let mut entry = ManifestEntry {
+        line: 1,
+        path,
+        language: Some("fr".to_string()),
+    };
+
+    assert_eq!(entry.resolve_language().unwrap().as_deref(), Some("fr"));
+    entry.language = None;
+    assert_eq!(entry.resolve_language().unwrap().as_deref(), Some("de"));
+    fs::remove_file(temp_dir.path().join("talk.lang")).unwrap();
+    assert_eq!(entry.resolve_language().unwrap(), None);
+}
@@ -0,0 +1,224 @@
+use anyhow::{Context, Result};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use candle_core::{DType, Device, Tensor};
+use candle_transformers::models::whisper::audio::log_mel_spectrogram_;
+use std::io::{BufWriter, Cursor, Write};
+use std::path::Path;
+
+#[cfg(test)]
+mod tests;
+
+/// Mel frames per second of audio, whatever its sample rate.
+const FRAMES_PER_SECOND: u32 = 100;
+/// Stride of the encoder's second convolution: mel frames per encoder position.
+const CONV_STRIDE: usize = 2;
+/// Start of every `.npy` file, before the format version.
+const NPY_MAGIC: &[u8] = b"\x93NUMPY";
+/// Number of mel bins of the bundled filter bank.
+const BUNDLED_MEL_BINS: usize = 128;
+/// Sample rate the bundled filter bank was made for.
+const BUNDLED_SAMPLE_RATE: u32 = 16_000;
+
+/// Samples between consecutive mel frames at `sample_rate`: 10 ms of audio,
+/// 160 samples at 16 kHz.
+pub fn hop_length(sample_rate: u32) -> usize {
+    (sample_rate / FRAMES_PER_SECOND) as usize
+}
+
+/// FFT window size at `sample_rate`: 25 ms of audio, 400 samples at 16 kHz.
+pub fn n_fft(sample_rate: u32) -> usize {
+    hop_length(sample_rate) * 5 / 2
+}
+
+/// Mel filter bank with `num_mel_bins` filters for audio at `sample_rate`,
+/// laid out as `num_mel_bins` rows of `n_fft(sample_rate) / 2 + 1`
+/// frequency weights.
+///
+/// The bundled 128-bin bank for 16 kHz is used as is; other sizes and rates
+/// are computed with [`slaney_mel_filters`], which is how the bundled one
+/// was made.
+///
+/// # Errors
+///
+/// Returns an error if `num_mel_bins` is zero, if `sample_rate` is not a
+/// multiple of 200 Hz, so that hops and FFT windows are whole numbers of
+/// samples, or if the bundled file does not hold a 128-bin bank.
+pub fn mel_filters(num_mel_bins: usize, sample_rate: u32) -> Result<Vec<f32>> {
+    if num_mel_bins == 0 {
+        anyhow::bail!("num_mel_bins must be at least 1");
+    }
+    if sample_rate == 0 || !sample_rate.is_multiple_of(2 * FRAMES_PER_SECOND) {
+        anyhow::bail!("The mel front-end needs a sample rate that is a multiple of 200 Hz, got {sample_rate} Hz");
+    }
+    let n_fft = n_fft(sample_rate);
+    if num_mel_bins != BUNDLED_MEL_BINS || sample_rate != BUNDLED_SAMPLE_RATE {
+        return Ok(slaney_mel_filters(num_mel_bins, n_fft, sample_rate));
+    }
+
+    let mel_bytes = include_bytes!("../melfilters128.bytes");
+    let expected = BUNDLED_MEL_BINS * (n_fft / 2 + 1);
+    if mel_bytes.len() != expected * 4 {
+        anyhow::bail!(
+            "melfilters128.bytes holds {} bytes instead of {} mel bins x {} frequencies; is it a Git LFS pointer?",
+            mel_bytes.len(),
+            BUNDLED_MEL_BINS,
+            n_fft / 2 + 1
+        );
+    }
+    let mut filters = vec![0f32; expected];
+    Cursor::new(mel_bytes)
+        .read_f32_into::<LittleEndian>(&mut filters)
+        .context("Failed to read melfilters128.bytes")?;
+    Ok(filters)
+}
+
+/// Compute a mel filter bank the way `librosa.filters.mel` does by default:
+/// Slaney's mel scale with area-normalised triangular filters from 0 Hz to
+/// the Nyquist frequency.
+pub fn slaney_mel_filters(num_mel_bins: usize, n_fft: usize, sample_rate: u32) -> Vec<f32> {
+    let n_freqs = n_fft / 2 + 1;
+    let nyquist = f64::from(sample_rate) / 2.0;
+    let fft_freqs: Vec<f64> = (0..n_freqs)
+        .map(|i| i as f64 * nyquist / (n_freqs - 1) as f64)
+        .collect();
+
+    let max_mel = hz_to_mel(nyquist);
+    let mel_freqs: Vec<f64> = (0..num_mel_bins + 2)
+        .map(|i| mel_to_hz(i as f64 * max_mel / (num_mel_bins + 1) as f64))
+        .collect();
+
+    let mut filters = Vec::with_capacity(num_mel_bins * n_freqs);
+    for m in 0..num_mel_bins {
+        let (lower, center, upper) = (mel_freqs[m], mel_freqs[m + 1], mel_freqs[m + 2]);
+        let norm = 2.0 / (upper - lower);
+        filters.extend(fft_freqs.iter().map(|&f| {
+            let rising = (f - lower) / (center - lower);
+            let falling = (upper - f) / (upper - center);
+            (rising.min(falling).max(0.0) * norm) as f32
+        }));
+    }
+    filters
+}
+
+/// Slaney mel scale: linear below 1 kHz, logarithmic above.
+fn hz_to_mel(hz: f64) -> f64 {
+    const MIN_LOG_HZ: f64 = 1000.0;
+    const MIN_LOG_MEL: f64 = MIN_LOG_HZ * 3.0 / 200.0;
+    if hz < MIN_LOG_HZ {
+        hz * 3.0 / 200.0
+    } else {
+        MIN_LOG_MEL + (hz / MIN_LOG_HZ).ln() / log_step()
+    }
+}
+
+fn mel_to_hz(mel: f64) -> f64 {
+    const MIN_LOG_HZ: f64 = 1000.0;
+    const MIN_LOG_MEL: f64 = MIN_LOG_HZ * 3.0 / 200.0;
+    if mel < MIN_LOG_MEL {
+        mel * 200.0 / 3.0
+    } else {
+        MIN_LOG_HZ * ((mel - MIN_LOG_MEL) * log_step()).exp()
+    }
+}
+
+fn log_step() -> f64 {
+    6.4f64.ln() / 27.0
+}
+
+/// Mel frames in one encoder window of `max_source_positions` positions,
+/// 3000 for Voxtral's 1500.
+pub fn window_frames(max_source_positions: usize) -> usize {
+    max_source_positions * CONV_STRIDE
+}
+
+/// Audio samples at `sample_rate` in one encoder window of
+/// `max_source_positions` positions, 480000 (30 seconds at 16 kHz) for
+/// Voxtral's 1500.
+pub fn window_samples(max_source_positions: usize, sample_rate: u32) -> usize {
+    window_frames(max_source_positions) * hop_length(sample_rate)
+}
+
+/// Convert audio at `sample_rate` into encoder input of shape
+/// `(windows, num_mel_bins, window_frames)`, where `window_frames` is 3000
+/// (30 seconds) for Voxtral.
+///
+/// Equivalent to `voxtral::extract_features`, which only supports 128 bins,
+/// 16 kHz and 3000-frame windows.
+pub fn extract_features(
+    audio: &[f32],
+    filters: &[f32],
+    num_mel_bins: usize,
+    window_frames: usize,
+    sample_rate: u32,
+    device: &Device,
+) -> Result<Tensor> {
+    let n_fft = n_fft(sample_rate);
+    if filters.len() != num_mel_bins * (n_fft / 2 + 1) {
+        anyhow::bail!(
+            "Mel filter bank has {} weights, expected {} mel bins x {} frequencies",
+            filters.len(),
+            num_mel_bins,
+            n_fft / 2 + 1
+        );
+    }
+
+    let hop_length = hop_length(sample_rate);
+    let mel = log_mel_spectrogram_(audio, filters, n_fft, hop_length, num_mel_bins, false);
+    let frames = mel.len() / num_mel_bins;
+    let windows = frames.div_ceil(window_frames).max(1);
+
+    let mel = Tensor::from_vec(mel, (num_mel_bins, frames), device)?;
+    let padding = windows * window_frames - frames;
+    let mel = if padding > 0 {
+        mel.pad_with_zeros(1, 0, padding)?
+    } else {
+        mel
+    };
+
+    Ok(mel
+        .reshape((num_mel_bins, windows, window_frames))?
+        .transpose(0, 1)?)
+}
+
+/// Write `tensor` to `path` as a NumPy `.npy` file of little-endian `f32`
+/// in C order, loadable with `numpy.load`.
+///
+/// # Errors
+///
+/// Returns an error if the tensor cannot be read or the file cannot be written.
+pub fn write_npy<P: AsRef<Path>>(path: P, tensor: &Tensor) -> Result<()> {
+    let path = path.as_ref();
+    let shape = match tensor.dims() {
+        [len] => format!("({len},)"),
+        dims => format!(
+            "({})",
+            dims.iter()
+                .map(usize::to_string)
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    };
+    let data = tensor
+        .to_dtype(DType::F32)?
+        .flatten_all()?
+        .to_vec1::<f32>()?;
+
+    // The header is padded with spaces so the data starts at a multiple of 64
+    let mut header = format!("{{'descr': '<f4', 'fortran_order': False, 'shape': {shape}, }}");
+    let unpadded = NPY_MAGIC.len() + 4 + header.len() + 1;
+    header.push_str(&" ".repeat(unpadded.next_multiple_of(64) - unpadded));
+    header.push('\n');
+
+    let file = std::fs::File::create(path)
+        .with_context(|| format!("Failed to create {}", path.display()))?;
+    let mut writer = BufWriter::new(file);
+    writer.write_all(NPY_MAGIC)?;
+    writer.write_all(&[1, 0])?;
+    writer.write_u16::<LittleEndian>(header.len() as u16)?;
+    writer.write_all(header.as_bytes())?;
+    for value in data {
+        writer.write_f32::<LittleEndian>(value)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
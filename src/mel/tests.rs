@@ -0,0 +1,193 @@
+use super::*;
+
+const N_FREQS: usize = 201;
+
+#[test]
+fn test_slaney_mel_scale_breakpoint() {
+    assert!((hz_to_mel(1000.0) - 15.0).abs() < 1e-9);
+    assert!((mel_to_hz(15.0) - 1000.0).abs() < 1e-9);
+    for hz in [0.0, 440.0, 2500.0, 8000.0] {
+        assert!((mel_to_hz(hz_to_mel(hz)) - hz).abs() < 1e-6);
+    }
+}
+
+#[test]
+fn test_slaney_mel_filters_shape_and_order() {
+    let filters = slaney_mel_filters(80, n_fft(16_000), 16_000);
+    assert_eq!(filters.len(), 80 * N_FREQS);
+    assert!(filters.iter().all(|&w| w >= 0.0));
+
+    // Filter peaks move up in frequency
+    let peaks: Vec<usize> = filters
+        .chunks(N_FREQS)
+        .map(|row| {
+            (0..N_FREQS)
+                .max_by(|&a, &b| row[a].total_cmp(&row[b]))
+                .unwrap()
+        })
+        .collect();
+    assert!(peaks.windows(2).all(|pair| pair[0] <= pair[1]));
+    assert!(peaks[79] > peaks[0]);
+}
+
+#[test]
+fn test_slaney_mel_filters_are_area_normalised() {
+    // The widest filters span many FFT bins, so their discrete area is close to 1
+    let filters = slaney_mel_filters(80, n_fft(16_000), 16_000);
+    let bin_hz = 8000.0 / (N_FREQS - 1) as f32;
+    let area: f32 = filters[79 * N_FREQS..].iter().sum::<f32>() * bin_hz;
+    assert!((area - 1.0).abs() < 0.05, "area = {area}");
+}
+
+#[test]
+fn test_mel_filters_rejects_zero_bins() {
+    assert!(mel_filters(0, 16_000).is_err());
+}
+
+#[test]
+fn test_mel_filters_computes_other_sizes() {
+    assert_eq!(mel_filters(80, 16_000).unwrap().len(), 80 * N_FREQS);
+}
+
+#[test]
+fn test_window_size_follows_source_positions() {
+    assert_eq!(window_frames(1500), 3000);
+    assert_eq!(window_samples(1500, 16_000), 480_000);
+    assert_eq!(window_samples(750, 16_000), 240_000);
+}
+
+#[test]
+fn test_frame_sizes_follow_the_sample_rate() {
+    assert_eq!((hop_length(16_000), n_fft(16_000)), (160, 400));
+    assert_eq!((hop_length(24_000), n_fft(24_000)), (240, 600));
+    assert_eq!(window_samples(1500, 24_000), 720_000);
+    assert_eq!(mel_filters(128, 24_000).unwrap().len(), 128 * 301);
+}
+
+#[test]
+fn test_mel_filters_rejects_uneven_sample_rates() {
+    let err = mel_filters(128, 22_050).unwrap_err();
+    assert!(err.to_string().contains("multiple of 200 Hz"));
+}
+
+#[test]
+fn test_extract_features_keep_100_frames_per_second_at_other_rates() {
+    // 45 seconds fill two 30-second windows at any rate
+    for sample_rate in [16_000, 24_000] {
+        let filters = mel_filters(80, sample_rate).unwrap();
+        let audio: Vec<f32> = (0..45 * sample_rate as usize)
+            .map(|i| (i as f32 * 0.05).sin())
+            .collect();
+        let features =
+            extract_features(&audio, &filters, 80, 3000, sample_rate, &Device::Cpu).unwrap();
+        assert_eq!(features.dims(), &[2, 80, 3000], "{sample_rate} Hz");
+    }
+}
+
+#[test]
+fn test_extract_features_shorter_windows() {
+    let filters = slaney_mel_filters(80, n_fft(16_000), 16_000);
+    let audio = vec![0.0f32; 16_000];
+
+    // The spectrogram is padded to 3000 frames, which is two 1500-frame windows
+    let features = extract_features(&audio, &filters, 80, 1500, 16_000, &Device::Cpu).unwrap();
+    assert_eq!(features.dims(), [2, 80, 1500]);
+}
+
+#[test]
+fn test_extract_features_shape() {
+    let filters = slaney_mel_filters(80, n_fft(16_000), 16_000);
+    let audio = vec![0.0f32; 16_000];
+
+    let features = extract_features(&audio, &filters, 80, 3000, 16_000, &Device::Cpu).unwrap();
+    assert_eq!(features.dims(), [1, 80, 3000]);
+}
+
+#[test]
+fn test_extract_features_of_clips_shorter_than_a_frame() {
+    let filters = slaney_mel_filters(128, n_fft(16_000), 16_000);
+
+    for len in [1, 100, 16_000] {
+        let audio: Vec<f32> = (0..len).map(|i| ((i % 7) as f32 - 3.0) / 10.0).collect();
+        let features = extract_features(&audio, &filters, 128, 3000, 16_000, &Device::Cpu).unwrap();
+
+        assert_eq!(features.dims(), [1, 128, 3000], "{len} samples");
+        let values = features.flatten_all().unwrap().to_vec1::<f32>().unwrap();
+        assert!(values.iter().all(|v| v.is_finite()), "{len} samples");
+    }
+}
+
+#[test]
+fn test_extract_features_matches_candle_for_128_bins() {
+    use candle_transformers::models::voxtral;
+
+    let filters = slaney_mel_filters(128, n_fft(16_000), 16_000);
+    let audio: Vec<f32> = (0..40_000)
+        .map(|i| (i as f32 * 440.0 * std::f32::consts::TAU / 16_000.0).sin() * 0.5)
+        .collect();
+
+    let ours = extract_features(&audio, &filters, 128, 3000, 16_000, &Device::Cpu).unwrap();
+    let theirs = voxtral::extract_features(&audio, &filters, &Device::Cpu).unwrap();
+
+    assert_eq!(ours.dims(), theirs.dims());
+    let diff = (ours - theirs)
+        .unwrap()
+        .abs()
+        .unwrap()
+        .max_all()
+        .unwrap()
+        .to_scalar::<f32>()
+        .unwrap();
+    assert_eq!(diff, 0.0);
+}
+
+#[test]
+fn test_extract_features_rejects_mismatched_filters() {
+    let filters = slaney_mel_filters(80, n_fft(16_000), 16_000);
+    let err =
+        extract_features(&[0.0; 1600], &filters, 128, 3000, 16_000, &Device::Cpu).unwrap_err();
+    assert!(err.to_string().contains("expected 128 mel bins"));
+}
+
+#[test]
+fn test_write_npy_layout() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let path = temp_dir.path().join("features.npy");
+    let filters = slaney_mel_filters(80, n_fft(16_000), 16_000);
+    let audio: Vec<f32> = (0..16_000).map(|i| (i as f32 * 0.05).sin()).collect();
+    let features = extract_features(&audio, &filters, 80, 3000, 16_000, &Device::Cpu).unwrap();
+
+    write_npy(&path, &features).unwrap();
+
+    let bytes = std::fs::read(&path).unwrap();
+    assert_eq!(&bytes[..6], NPY_MAGIC);
+    assert_eq!(&bytes[6..8], &[1, 0]);
+    let header_len = u16::from_le_bytes([bytes[8], bytes[9]]) as usize;
+    let data_start = 10 + header_len;
+    assert_eq!(data_start % 64, 0);
+    let header = std::str::from_utf8(&bytes[10..data_start]).unwrap();
+    assert!(
+        header.starts_with("{'descr': '<f4', 'fortran_order': False, 'shape': (1, 80, 3000), }")
+    );
+    assert!(header.ends_with('\n'));
+
+    // The data is the transposed tensor in C order
+    let expected = features.flatten_all().unwrap().to_vec1::<f32>().unwrap();
+    let data: Vec<f32> = bytes[data_start..]
+        .chunks_exact(4)
+        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .collect();
+    assert_eq!(data, expected);
+    assert_eq!(
+        data[1],
+        features
+            .get(0)
+            .unwrap()
+            .get(0)
+            .unwrap()
+            .get(1)
+            .unwrap()
+            .to_scalar::<f32>()
+            .unwrap()
+    );
+}
@@ -0,0 +1,118 @@
+//! Rough estimate of the activation memory of one pass through the model,
+//! to choose how much audio a GPU with little free memory can take at once.
+//!
+//! The estimate assumes:
+//!
+//! - weights, the tokenizer and the framework's own buffers are not counted,
+//!   only the tensors created while running audio through the model;
+//! - the encoder runs all windows of a pass as one batch, and only one
+//!   layer's activations are alive at a time;
+//! - attention materialises the full score matrix in `f32`, as without
+//!   flash attention, which makes it grow with the square of the length;
+//! - the decoder prefill holds one layer's activations plus the key/value
+//!   cache of all layers for the audio tokens; the few prompt tokens and the
+//!   tokens generated afterwards are ignored.
+//!
+//! Real usage also depends on the allocator and on other processes sharing
+//! the device, so treat the figure as a lower bound to compare pass lengths,
+//! not as a guarantee.
+
+use candle_core::DType;
+use candle_transformers::models::voxtral::VoxtralConfig;
+
+#[cfg(test)]
+mod tests;
+
+/// Bytes per attention score, which is computed in `f32`.
+const SCORE_BYTES: u64 = 4;
+
+/// Encoder positions merged into one audio token by the projector.
+pub(crate) const POSITIONS_PER_AUDIO_TOKEN: usize = 4;
+
+/// Model dimensions that drive the activation memory of a pass.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PassDims {
+    /// Width of the encoder's hidden states.
+    pub encoder_hidden: usize,
+    /// Width of the encoder's feed-forward layers.
+    pub encoder_intermediate: usize,
+    /// Attention heads per encoder layer.
+    pub encoder_heads: usize,
+    /// Encoder positions per window, half its mel frames.
+    pub encoder_positions: usize,
+    /// Width of the decoder's hidden states.
+    pub decoder_hidden: usize,
+    /// Width of the decoder's feed-forward layers.
+    pub decoder_intermediate: usize,
+    /// Query heads per decoder layer.
+    pub decoder_heads: usize,
+    /// Key/value heads per decoder layer.
+    pub decoder_kv_heads: usize,
+    /// Width of one decoder attention head.
+    pub decoder_head_dim: usize,
+    /// Number of decoder layers, each with its own key/value cache.
+    pub decoder_layers: usize,
+    /// Bytes per element of the model's activations.
+    pub dtype_bytes: usize,
+}
+
+impl PassDims {
+    /// Dimensions of the model described by `config`, run in `dtype`.
+    pub fn from_config(config: &VoxtralConfig, dtype: DType) -> Self {
+        let audio = &config.audio_config;
+        let text = &config.text_config;
+        Self {
+            encoder_hidden: audio.hidden_size,
+            encoder_intermediate: audio.intermediate_size,
+            encoder_heads: audio.num_attention_heads,
+            encoder_positions: audio.max_source_positions,
+            decoder_hidden: text.hidden_size,
+            decoder_intermediate: text.intermediate_size,
+            decoder_heads: text.num_attention_heads,
+            decoder_kv_heads: text.num_key_value_heads,
+            decoder_head_dim: text
+                .head_dim
+                .unwrap_or(text.hidden_size / text.num_attention_heads),
+            decoder_layers: text.num_hidden_layers,
+            dtype_bytes: dtype.size_in_bytes(),
+        }
+    }
+
+    /// Estimated peak bytes of activations for a pass over `windows` encoder
+    /// windows: the larger of the encoder and the decoder prefill, see the
+    /// module documentation for what is counted.
+    pub fn activation_bytes(&self, windows: usize) -> u64 {
+        self.encoder_bytes(windows).max(self.decoder_bytes(windows))
+    }
+
+    /// Activations of one encoder layer over `windows` windows, plus the
+    /// convolution output at twice the position count.
+    fn encoder_bytes(&self, windows: usize) -> u64 {
+        let windows = windows as u64;
+        let positions = self.encoder_positions as u64;
+        let hidden = self.encoder_hidden as u64;
+        // Residual, query, key, value and attention output, then the
+        // feed-forward expansion
+        let per_position = 5 * hidden + self.encoder_intermediate as u64;
+        let elements = windows * (2 * positions * hidden + positions * per_position);
+        let scores = windows * self.encoder_heads as u64 * positions * positions;
+        elements * self.dtype_bytes as u64 + scores * SCORE_BYTES
+    }
+
+    /// Activations of one decoder layer over the audio tokens of `windows`
+    /// windows, plus the key/value cache of every layer.
+    fn decoder_bytes(&self, windows: usize) -> u64 {
+        let tokens = (windows * self.encoder_positions / POSITIONS_PER_AUDIO_TOKEN) as u64;
+        let query = (self.decoder_heads * self.decoder_head_dim) as u64;
+        let key_value = (self.decoder_kv_heads * self.decoder_head_dim) as u64;
+        // Residual, normalised input, query, key, value, then the gate and
+        // up projections of the feed-forward layer
+        let per_token = 2 * self.decoder_hidden as u64
+            + query
+            + 2 * key_value
+            + 2 * self.decoder_intermediate as u64;
+        let cache = self.decoder_layers as u64 * 2 * tokens * key_value;
+        let scores = self.decoder_heads as u64 * tokens * tokens;
+        (tokens * per_token + cache) * self.dtype_bytes as u64 + scores * SCORE_BYTES
+    }
+}
@@ -0,0 +1,34 @@
+use super::*;
+
+const MIB: u64 = 1024 * 1024;
+
+fn mini_3b(dtype: DType) -> PassDims {
+    PassDims::from_config(&VoxtralConfig::default(), dtype)
+}
+
+#[test]
+fn test_from_config_reads_dims() {
+    let dims = mini_3b(DType::F16);
+
+    assert_eq!(dims.encoder_positions, 1500);
+    assert_eq!(dims.decoder_head_dim, 128);
+    assert_eq!(dims.dtype_bytes, 2);
+}
+
+#[test]
+fn test_one_window_is_a_few_hundred_mib() {
+    let bytes = mini_3b(DType::F16).activation_bytes(1);
+
+    assert!((100 * MIB..1024 * MIB).contains(&bytes), "{bytes}");
+}
+
+#[test]
+fn test_estimate_grows_with_windows_and_dtype() {
+    let f16 = mini_3b(DType::F16);
+    let f32 = mini_3b(DType::F32);
+
+    assert!(f16.activation_bytes(2) > f16.activation_bytes(1));
+    assert!(f16.activation_bytes(4) >= 4 * f16.activation_bytes(1));
+    assert!(f32.activation_bytes(1) > f16.activation_bytes(1));
+    assert_eq!(f16.activation_bytes(0), 0);
+}
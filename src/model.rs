@@ -1,254 +1,1607 @@
-use std::path::PathBuf;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
 
 use anyhow::{Context, Error, Result};
-use byteorder::{LittleEndian, ReadBytesExt};
 
 #[cfg(test)]
 mod tests;
 
-#[cfg(test)]
-use tests::*;
-
+use candle_core::quantized::gguf_file;
 use candle_core::{utils, DType, Device, Tensor};
 use candle_nn::VarBuilder;
-use candle_transformers::models::voxtral;
 use candle_transformers::models::voxtral::{
     VoxtralCache, VoxtralConfig, VoxtralEncoderConfig, VoxtralForConditionalGeneration,
-    VoxtralGenerationConfig, VoxtralLlamaConfig as LlamaConfig,
+    VoxtralLlamaConfig as LlamaConfig,
 };
 
-use std::io::Cursor;
 use tekken::Tekkenizer;
 
+use super::audio::{self, PadMode};
+use super::chunk::{ChunkEvent, ChunkPlan};
 use super::download;
+use super::generate::{self, Generation, GenerationConfig, Utf8Stream};
+use super::mel;
+use super::memory::{PassDims, POSITIONS_PER_AUDIO_TOKEN};
+use super::output::Segment;
+use super::profile::{self, Stage};
 
+/// Sample rate the model expects when its configuration names none.
 const SAMPLE_RATE: u32 = 16000;
 
+/// Feature extractor settings Hugging Face keeps next to `config.json`.
+const PREPROCESSOR_CONFIG_FILE: &str = "preprocessor_config.json";
+
+/// EOS ids from other chat formats that also end generation, besides `</s>`.
+const EXTRA_EOS_TOKENS: [u32; 3] = [128001, 128009, 128256];
+
+/// Language codes Voxtral is trained to transcribe; the candidates for detection.
+pub const SUPPORTED_LANGUAGES: [&str; 8] = ["en", "es", "fr", "pt", "hi", "de", "nl", "it"];
+
+/// Symbols suppressed by [`TranscribeOptions::suppress_non_speech`], the
+/// list Whisper uses. Their tokens are suppressed when the symbol, alone or
+/// after a space, is a single token.
+const NON_SPEECH_SYMBOLS: [&str; 44] = [
+    "\"", "#", "(", ")", "*", "+", "/", ":", ";", "<", "=", ">", "@", "[", "\\", "]", "^", "_",
+    "`", "{", "|", "}", "~", "「", "」", "『", "』", "<<", ">>", "<<<", ">>>", "--", "---", "-(",
+    "-[", "('", "(\"", "((", "))", "(((", ")))", "[[", "]]", "{{",
+];
+
+/// Music symbols whose first token is suppressed even when they take several.
+const MUSIC_SYMBOLS: [&str; 7] = ["♩", "♪", "♫", "♬", "♭", "♮", "♯"];
+
+/// Prompt tokens spelling `lang:` in Voxtral Mini 3B, used when the
+/// tokenizer cannot encode it.
+const LANG_PREFIX_TOKENS: [u32; 2] = [9909, 1058];
+
+/// Longest initial prompt kept, in tokens. Longer prompts keep their end.
+pub const MAX_PROMPT_TOKENS: usize = 224;
+
+/// Text put in the decoder context by [`TranscribeOptions::verbatim`].
+///
+/// Voxtral's transcription mode takes no instructions, so verbatim output is
+/// asked for by example, as with Whisper: the model continues in the style
+/// of its context, and a context full of filler words and false starts
+/// makes it keep those of the audio instead of cleaning them up.
+pub const VERBATIM_PROMPT: &str =
+    "Umm, so, uh, we- we were, like, hmm... I mean, we were going to, uh, to the the store.";
+
+/// Language of [`VERBATIM_PROMPT`]. In any other language the example would
+/// push the transcript toward English, so it is left out.
+pub const VERBATIM_LANGUAGE: &str = "en";
+
+/// Audio used for language detection: the first 30 seconds.
+const DETECT_LANGUAGE_SECONDS: usize = 30;
+
 /// The result of transcribing audio: decoded text and the produced token ids.
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
 pub struct TranscriptionResult {
     pub text: String,
     pub tokens: Vec<u32>,
+    /// Mean token log-probability; lower values mean a less certain transcript.
+    #[serde(default)]
+    pub avg_logprob: f32,
+    /// Probability that the audio holds no speech, from the first decoding step.
+    #[serde(default)]
+    pub no_speech_prob: f32,
+    /// Whether generation stopped at [`TranscribeOptions::timeout`] and the
+    /// text is incomplete.
+    #[serde(default)]
+    pub truncated: bool,
+}
+
+impl TranscriptionResult {
+    /// Join the results of consecutive pieces of the same audio into one.
+    ///
+    /// Texts are joined with a space and tokens concatenated. The mean log
+    /// probability is weighted by the token count of each piece, and the
+    /// no-speech probability is the lowest of the pieces, so speech in any
+    /// piece counts as speech. The result is truncated if any piece is.
+    pub fn join(parts: Vec<Self>) -> Self {
+        let token_count: usize = parts.iter().map(|part| part.tokens.len()).sum();
+        let logprob_sum: f32 = parts
+            .iter()
+            .map(|part| part.avg_logprob * part.tokens.len() as f32)
+            .sum();
+        Self {
+            text: parts
+                .iter()
+                .map(|part| part.text.trim())
+                .filter(|text| !text.is_empty())
+                .collect::<Vec<_>>()
+                .join(" "),
+            tokens: parts.iter().flat_map(|part| part.tokens.clone()).collect(),
+            avg_logprob: if token_count > 0 {
+                logprob_sum / token_count as f32
+            } else {
+                0.0
+            },
+            no_speech_prob: parts
+                .iter()
+                .map(|part| part.no_speech_prob)
+                .fold(1.0, f32::min),
+            truncated: parts.iter().any(|part| part.truncated),
+        }
+    }
+}
+
+/// A candidate language and its probability among the detection candidates.
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct LanguageProbability {
+    pub language: String,
+    pub probability: f32,
+}
+
+/// Settings for a single transcription call.
+#[derive(Debug, Clone)]
+pub struct TranscribeOptions {
+    /// Language code written into the `lang:` part of the prompt, such as `en`.
+    pub language: String,
+    /// Penalty applied to already generated tokens; 1.0 disables it.
+    pub repetition_penalty: f32,
+    /// Forbid repeating any n-gram of this size; 0 disables the guard.
+    pub no_repeat_ngram_size: usize,
+    /// Text placed in the decoder context to bias spelling of names and jargon.
+    pub prompt: Option<String>,
+    /// Tokens of the text preceding the audio, such as the end of the
+    /// previous chunk's transcript, placed in the decoder context after the
+    /// prompt. Together with the prompt at most [`MAX_PROMPT_TOKENS`] are
+    /// kept, from the end.
+    pub previous_tokens: Vec<u32>,
+    /// Wall-clock budget for generating the text of one call; generation
+    /// stops once it has passed and the result is marked as truncated.
+    pub timeout: Option<Duration>,
+    /// Longest audio, in seconds, put through the model in one pass. Longer
+    /// audio is transcribed in consecutive passes of whole encoder windows
+    /// whose results are joined, which lowers the peak memory at the cost of
+    /// speed and of context across the pass boundaries.
+    pub max_seconds_per_pass: Option<f32>,
+    /// How tokens are chosen from the predictions of the model.
+    pub decode_strategy: DecodeStrategy,
+    /// Temperature of [`DecodeStrategy::Sample`].
+    pub temperature: f32,
+    /// Temperatures [`DecodeStrategy::TemperatureFallback`] retries a pass
+    /// at, in order, after the greedy attempt.
+    pub fallback_temperatures: Vec<f32>,
+    /// Mean token log-probability below which
+    /// [`DecodeStrategy::TemperatureFallback`] retries a pass.
+    pub logprob_threshold: f32,
+    /// Seed of the token sampler.
+    pub seed: u64,
+    /// Token ids the model may never generate.
+    pub suppress_tokens: Vec<u32>,
+    /// Also never generate the tokens of symbols that are not speech, such
+    /// as brackets and music notes; see [`non_speech_tokens`].
+    pub suppress_non_speech: bool,
+    /// Ask for a verbatim transcript, with filler words and false starts,
+    /// instead of a cleaned one, by putting [`VERBATIM_PROMPT`] in the decoder
+    /// context before the prompt. Ignored for a `language` other than
+    /// [`VERBATIM_LANGUAGE`] and by models that do not support it, see
+    /// [`VoxtralModel::supports_verbatim`].
+    pub verbatim: bool,
+}
+
+impl Default for TranscribeOptions {
+    fn default() -> Self {
+        Self {
+            language: "en".to_string(),
+            repetition_penalty: 1.0,
+            no_repeat_ngram_size: 0,
+            prompt: None,
+            previous_tokens: Vec::new(),
+            timeout: None,
+            max_seconds_per_pass: None,
+            decode_strategy: DecodeStrategy::Greedy,
+            temperature: 0.5,
+            fallback_temperatures: DEFAULT_FALLBACK_TEMPERATURES.to_vec(),
+            logprob_threshold: -1.0,
+            seed: 0,
+            suppress_tokens: Vec::new(),
+            suppress_non_speech: false,
+            verbatim: false,
+        }
+    }
+}
+
+impl TranscribeOptions {
+    /// These options for a single attempt at `temperature`, greedy at 0.0.
+    fn at_temperature(&self, temperature: f32) -> Self {
+        Self {
+            decode_strategy: if temperature > 0.0 {
+                DecodeStrategy::Sample
+            } else {
+                DecodeStrategy::Greedy
+            },
+            temperature,
+            ..self.clone()
+        }
+    }
+}
+
+/// Escalation schedule of [`DecodeStrategy::TemperatureFallback`], the one
+/// Whisper uses.
+pub const DEFAULT_FALLBACK_TEMPERATURES: [f32; 5] = [0.2, 0.4, 0.6, 0.8, 1.0];
+
+/// How each token is chosen from the predictions of the model.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum DecodeStrategy {
+    /// Always the most likely token.
+    #[default]
+    Greedy,
+    /// Drawn at random, sharpened or flattened by the temperature.
+    Sample,
+    /// Greedy first, then sampled at rising temperatures while the mean
+    /// token log-probability stays below the threshold, keeping the most
+    /// confident attempt.
+    TemperatureFallback,
+}
+
+/// Run `attempt` at the temperatures of `options.decode_strategy` and
+/// return the result to keep.
+///
+/// Greedy and sampled decoding make one attempt. Temperature fallback starts
+/// greedy and tries the next temperature of the schedule until an attempt
+/// reaches `options.logprob_threshold` or the schedule runs out, then keeps
+/// the attempt with the highest mean log-probability.
+pub fn decode_with_fallback<A>(
+    options: &TranscribeOptions,
+    mut attempt: A,
+) -> Result<TranscriptionResult>
+where
+    A: FnMut(f32) -> Result<TranscriptionResult>,
+{
+    match options.decode_strategy {
+        DecodeStrategy::Greedy => attempt(0.0),
+        DecodeStrategy::Sample => attempt(options.temperature),
+        DecodeStrategy::TemperatureFallback => {
+            let mut best = attempt(0.0)?;
+            for &temperature in &options.fallback_temperatures {
+                if best.avg_logprob >= options.logprob_threshold {
+                    break;
+                }
+                log::debug!(
+                    "Mean log-probability {:.3} below {}, retrying at temperature {temperature}",
+                    best.avg_logprob,
+                    options.logprob_threshold
+                );
+                let result = attempt(temperature)?;
+                if result.avg_logprob > best.avg_logprob {
+                    best = result;
+                }
+            }
+            Ok(best)
+        }
+    }
+}
+
+/// Run `encode` once and `attempt` on its output at the temperatures of
+/// `options.decode_strategy`, as [`decode_with_fallback`] does.
+///
+/// The encoder output does not depend on the temperature, so the attempts
+/// of a temperature fallback share it instead of encoding the audio again.
+fn encode_then_decode<E, C, A>(
+    options: &TranscribeOptions,
+    encode: C,
+    mut attempt: A,
+) -> Result<TranscriptionResult>
+where
+    C: FnOnce() -> Result<E>,
+    A: FnMut(&E, f32) -> Result<TranscriptionResult>,
+{
+    let encoded = encode()?;
+    decode_with_fallback(options, |temperature| attempt(&encoded, temperature))
+}
+
+/// One pass of audio through the encoder, with the decoder run over the
+/// prompt it fills in, ready for decoding attempts to continue from.
+struct EncodedPass {
+    /// Prompt tokens, including the audio tokens.
+    input_tokens: Vec<u32>,
+    /// Decoder cache after the prompt.
+    cache: VoxtralCache,
+    /// Logits of the first token to generate.
+    logits: Vec<f32>,
+    device: Device,
+}
+
+/// Floating point type used for the model weights and activations.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ModelDType {
+    F16,
+    Bf16,
+    F32,
+}
+
+impl ModelDType {
+    /// Return the matching candle `DType`.
+    pub fn to_dtype(self) -> DType {
+        match self {
+            ModelDType::F16 => DType::F16,
+            ModelDType::Bf16 => DType::BF16,
+            ModelDType::F32 => DType::F32,
+        }
+    }
+}
+
+/// Options controlling how and where the model is loaded.
+#[derive(Debug, Clone, Default)]
+pub struct ModelOptions {
+    /// Run on CPU even when a GPU device is available.
+    pub use_cpu: bool,
+    /// Compute dtype; `None` selects F16 on GPU and F32 on CPU.
+    pub dtype: Option<ModelDType>,
+    /// GGUF file to load the weights from instead of the safetensors shards.
+    /// The weights are dequantized to the compute dtype while loading, so
+    /// this shrinks the download but not the memory used at inference time.
+    pub gguf_weights: Option<PathBuf>,
+    /// Folder holding the model files, downloaded into when incomplete;
+    /// `None` selects [`download::DEFAULT_MODEL_DIR`] in the working directory.
+    pub model_dir: Option<PathBuf>,
+    /// Fail instead of falling back to CPU when no GPU can be used.
+    pub require_gpu: bool,
+    /// Index of the GPU to load the model on when several are present.
+    pub device_ordinal: usize,
+    /// Threads the CPU backend computes on, shared by every model in the
+    /// process; `None` uses all cores. See [`set_cpu_threads`].
+    pub threads: Option<usize>,
+    /// Download every model file again even when `model_dir` is complete,
+    /// replacing the local copies. See [`download::model_files`].
+    pub refresh: bool,
+    /// How audio is padded to whole encoder windows before its mel
+    /// features are computed.
+    pub pad_mode: PadMode,
+}
+
+/// Ids of the special tokens the prompt is built from.
+///
+/// They are looked up by name in the tokenizer so model revisions with a
+/// different vocabulary still get a correct prompt. The audio token comes
+/// from `audio_token_id` in config.json, where the model inserts the audio
+/// embeddings.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct SpecialTokens {
+    bos: u32,
+    eos: u32,
+    inst: u32,
+    inst_end: u32,
+    begin_audio: u32,
+    audio: u32,
+    transcribe: u32,
+    /// Tokens spelling `lang:` before the language code.
+    lang_prefix: Vec<u32>,
+}
+
+impl SpecialTokens {
+    /// Resolve each control token with `control_id`, falling back to its id
+    /// in Voxtral Mini 3B when the lookup fails, and `lang:` to `lang_prefix`.
+    ///
+    /// Returns the tokens and the names that had to fall back.
+    fn resolve(
+        audio: u32,
+        control_id: impl Fn(&str) -> Option<u32>,
+        lang_prefix: Option<Vec<u32>>,
+    ) -> (Self, Vec<&'static str>) {
+        let mut fallbacks = Vec::new();
+        let mut id = |name: &'static str, fallback: u32| {
+            control_id(name).unwrap_or_else(|| {
+                fallbacks.push(name);
+                fallback
+            })
+        };
+        let bos = id("<s>", 1);
+        let eos = id("</s>", 2);
+        let inst = id("[INST]", 3);
+        let inst_end = id("[/INST]", 4);
+        let begin_audio = id("[BEGIN_AUDIO]", 25);
+        let transcribe = id("[TRANSCRIBE]", 34);
+        let lang_prefix = match lang_prefix.filter(|tokens| !tokens.is_empty()) {
+            Some(tokens) => tokens,
+            None => {
+                fallbacks.push("lang:");
+                LANG_PREFIX_TOKENS.to_vec()
+            }
+        };
+
+        let tokens = Self {
+            bos,
+            eos,
+            inst,
+            inst_end,
+            begin_audio,
+            audio,
+            transcribe,
+            lang_prefix,
+        };
+        (tokens, fallbacks)
+    }
+
+    /// Single control tokens by name, as the tokenizer spells them.
+    fn named(&self) -> [(&'static str, u32); 7] {
+        [
+            ("<s>", self.bos),
+            ("</s>", self.eos),
+            ("[INST]", self.inst),
+            ("[/INST]", self.inst_end),
+            ("[BEGIN_AUDIO]", self.begin_audio),
+            ("[AUDIO]", self.audio),
+            ("[TRANSCRIBE]", self.transcribe),
+        ]
+    }
+
+    /// Token ids that end generation: `</s>` plus EOS ids from other chat formats.
+    fn eos_tokens(&self) -> Vec<u32> {
+        std::iter::once(self.eos).chain(EXTRA_EOS_TOKENS).collect()
+    }
 }
 
 pub struct VoxtralModel {
     model: VoxtralForConditionalGeneration,
     tokenizer: Tekkenizer,
     device: Device,
-    audio_token_id: usize,
+    tokens: SpecialTokens,
     cache: VoxtralCache,
+    num_mel_bins: usize,
+    mel_filters: Vec<f32>,
+    /// Encoder positions per window, which sets the window length.
+    max_source_positions: usize,
+    /// Dimensions for estimating the activation memory of a pass.
+    pass_dims: PassDims,
+    /// Number of token ids the decoder predicts.
+    vocab_size: usize,
+    /// Tokens suppressed by [`TranscribeOptions::suppress_non_speech`].
+    non_speech_tokens: Vec<u32>,
+    /// Whether the tokenizer has its own `[TRANSCRIBE]` token.
+    supports_verbatim: bool,
+    /// Sample rate of the audio the mel front-end expects.
+    sample_rate: u32,
+    /// How audio is padded to whole encoder windows.
+    pad_mode: PadMode,
 }
 
 impl VoxtralModel {
     /// # Errors
     ///
     /// Returns an error if the model cannot be loaded.
-    pub fn new(use_cpu: bool) -> Result<Self> {
-        // Determine device
-        let device = if !use_cpu && utils::cuda_is_available() {
-            Device::new_cuda(0).context("Failed to create CUDA device")?
+    pub fn new(options: &ModelOptions) -> Result<Self> {
+        if let Some(threads) = options.threads {
+            set_cpu_threads(threads)?;
+        }
+        let device = select_device(options.use_cpu, options.require_gpu, options.device_ordinal)?;
+        let dtype = resolve_dtype(options.dtype, &device)?;
+
+        let model_dir = options
+            .model_dir
+            .clone()
+            .unwrap_or_else(|| PathBuf::from(download::DEFAULT_MODEL_DIR));
+        // A GGUF file replaces the safetensors shards, which are then not fetched
+        let paths = if options.gguf_weights.is_some() {
+            download::model_files_without_weights(&model_dir, options.refresh)?
         } else {
-            Device::Cpu
+            download::model_files(&model_dir, options.refresh)?
         };
-
-        let files = download::model_files()?;
-
-        if files.len() < 3 {
-            return Err(anyhow::anyhow!(
-                "Expected at least config, safetensors and tokenizer files from model_files"
-            ));
+        let sample_rate = expected_sample_rate(&model_dir)?;
+        if sample_rate != SAMPLE_RATE {
+            log::info!("Model expects audio at {sample_rate} Hz");
         }
 
-        // Load model configuration (first entry)
-        let config = load_model_config(files.first().unwrap())?;
+        // Load model configuration
+        let config = load_model_config(&paths.config)?;
 
-        // Load safetensors files (all entries except first and last)
-        let safetensors_slice = &files[1..files.len() - 1];
-        let vb = load_model_weights(safetensors_slice, &device)?;
+        // Load safetensors files, unless a GGUF file was given instead
+        let vb = match &options.gguf_weights {
+            Some(gguf_file) => load_gguf_weights(gguf_file, dtype, &device)?,
+            None => load_model_weights(&paths.weights, dtype, &device)?,
+        };
 
         // Create model
         let model = VoxtralForConditionalGeneration::new(&config, vb)?;
 
-        // Load tokenizer (last entry)
-        let tokenizer_file = files.last().unwrap();
-        let tokenizer = Tekkenizer::from_file(tokenizer_file).map_err(Error::msg)?;
+        // Load tokenizer
+        let tokenizer = Tekkenizer::from_file(&paths.tokenizer).map_err(Error::msg)?;
+        let tokens = special_tokens(&tokenizer, config.audio_token_id)
+            .with_context(|| format!("Unexpected tokenizer {}", paths.tokenizer.display()))?;
+        let non_speech_tokens = non_speech_tokens(|text| tokenizer.encode(text, false, false).ok());
+        let supports_verbatim = tokenizer.get_control_token("[TRANSCRIBE]").is_ok();
 
         // Create cache
-        let cache = VoxtralCache::new(true, DType::F16, &config.text_config, &device)?;
+        let cache = VoxtralCache::new(true, dtype, &config.text_config, &device)?;
 
-        let audio_token_id = config.audio_token_id;
+        // Mel front-end matching the encoder's input channels
+        let num_mel_bins = config.audio_config.num_mel_bins;
+        let mel_filters = mel::mel_filters(num_mel_bins, sample_rate)?;
+        let max_source_positions = config.audio_config.max_source_positions;
+        let pass_dims = PassDims::from_config(&config, dtype);
 
         Ok(Self {
             model,
             tokenizer,
             device,
-            audio_token_id,
+            tokens,
             cache,
+            num_mel_bins,
+            mel_filters,
+            max_source_positions,
+            pass_dims,
+            vocab_size: config.text_config.vocab_size,
+            non_speech_tokens,
+            supports_verbatim,
+            sample_rate,
+            pad_mode: options.pad_mode,
         })
     }
 
-    /// Transcribe audio and return both text and tokens
+    /// Sample rate the model expects its audio at, such as 16000 Hz for
+    /// Voxtral, from the model configuration.
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Detect the spoken language from the first 30 seconds of the audio.
+    ///
+    /// The audio is run through the model with the prompt ending in `lang:`
+    /// and the next-token logits of each code in [`SUPPORTED_LANGUAGES`] are
+    /// compared. Candidates are returned sorted by descending probability.
     ///
     /// # Errors
     ///
-    /// Returns an error if the audio data cannot be transcribed.
-    pub fn transcribe_audio(
+    /// Returns an error if the audio cannot be processed by the model.
+    pub fn detect_language(
         &mut self,
         audio_data: &[f32],
         sample_rate: u32,
+    ) -> Result<Vec<LanguageProbability>> {
+        let prefix_len = audio_data
+            .len()
+            .min(DETECT_LANGUAGE_SECONDS * sample_rate as usize);
+        let audio = resample_for_model(&audio_data[..prefix_len], sample_rate, self.sample_rate)?;
+        let audio_features = self.mel_features(&audio)?;
+
+        let mut input_tokens = audio_prompt(
+            audio_token_count(audio_features.dim(0)?, self.max_source_positions),
+            &self.tokens,
+        );
+        input_tokens.extend_from_slice(&self.tokens.lang_prefix);
+
+        let input_ids = Tensor::new(input_tokens.as_slice(), &self.device)?.unsqueeze(0)?;
+        let mut cache = self.cache.clone();
+        let logits = self
+            .model
+            .forward(&input_ids, Some(&audio_features), &mut cache, 0)
+            .map_err(|e| anyhow::anyhow!("Failed to run language detection: {e}"))?
+            .flatten_all()?
+            .to_dtype(DType::F32)?
+            .to_vec1::<f32>()?;
+
+        let candidates = SUPPORTED_LANGUAGES
+            .iter()
+            .map(|&language| Ok((language, encode_language(&self.tokenizer, language)?[0])))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(rank_languages(&logits, &candidates))
+    }
+
+    /// Run one second of silence through the encoder and the decoder, with
+    /// the prompt step and a single-token step, and return how long it took.
+    ///
+    /// GPU backends compile their kernels on first use, which otherwise
+    /// slows down the first chunk and skews timing. The output is discarded
+    /// and the model is left as it was.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the forward passes fail.
+    pub fn warm_up(&mut self) -> Result<Duration> {
+        let started = Instant::now();
+        let audio_features = self.mel_features(&vec![0.0; self.sample_rate as usize])?;
+        let input_tokens = transcription_prompt(
+            audio_token_count(audio_features.dim(0)?, self.max_source_positions),
+            &self.tokens,
+            &[],
+            &encode_language(&self.tokenizer, "en")?,
+        );
+
+        let mut cache = self.cache.clone();
+        let mut index_pos = 0;
+        for (input, features) in [
+            (input_tokens.as_slice(), Some(&audio_features)),
+            (&[self.tokens.eos][..], None),
+        ] {
+            let input_ids = Tensor::new(input, &self.device)?.unsqueeze(0)?;
+            // Reading the logits back waits for the device to finish
+            self.model
+                .forward(&input_ids, features, &mut cache, index_pos)
+                .map_err(|e| anyhow::anyhow!("Failed to warm up the model: {e}"))?
+                .flatten_all()?
+                .to_dtype(DType::F32)?
+                .to_vec1::<f32>()?;
+            index_pos += input.len();
+        }
+        Ok(started.elapsed())
+    }
+
+    /// Transcribe audio at any sample rate, resampling it to the model's
+    /// [`Self::sample_rate`], 16 kHz for Voxtral, first.
+    ///
+    /// Convenience wrapper around [`Self::transcribe_audio_16k`]; callers that
+    /// already hold audio at that rate should use that directly.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the audio data cannot be resampled or transcribed.
+    pub fn transcribe_audio<F>(
+        &mut self,
+        audio_data: &[f32],
+        sample_rate: u32,
+        options: &TranscribeOptions,
+        on_text: F,
+    ) -> Result<TranscriptionResult>
+    where
+        F: FnMut(&str),
+    {
+        let audio = resample_for_model(audio_data, sample_rate, self.sample_rate)?;
+        self.transcribe_audio_16k(&audio, options, on_text)
+    }
+
+    /// Transcribe encoded audio held in memory, such as an upload, without
+    /// writing it to a file first.
+    ///
+    /// `format_hint` is the extension the data would have as a file, such as
+    /// `"mp3"`. The audio is decoded, mixed down to mono and transcribed in
+    /// one piece with [`Self::transcribe_audio`]; for long recordings decode
+    /// with [`audio::pcm_decode_reader`] and use [`Self::transcribe_chunked`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the audio cannot be decoded, holds no samples or
+    /// cannot be transcribed.
+    pub fn transcribe_bytes<F>(
+        &mut self,
+        bytes: &[u8],
+        format_hint: Option<&str>,
+        options: &TranscribeOptions,
+        on_text: F,
+    ) -> Result<TranscriptionResult>
+    where
+        F: FnMut(&str),
+    {
+        let (audio_data, sample_rate) =
+            audio::pcm_decode_reader(std::io::Cursor::new(bytes.to_vec()), format_hint)
+                .context("Failed to decode audio")?;
+        if audio_data.is_empty() {
+            anyhow::bail!("No audio samples after decoding");
+        }
+        self.transcribe_audio(&audio_data, sample_rate, options, on_text)
+    }
+
+    /// Transcribe mono audio at the model's [`Self::sample_rate`], 16 kHz
+    /// for Voxtral, and return both text and tokens
+    ///
+    /// The audio is used as is; resampling is the caller's responsibility.
+    /// `options` selects the prompt language and the decoding safeguards.
+    /// Audio shorter than an encoder window, down to a single sample, is
+    /// padded with silence to one window. Empty audio gives an empty result
+    /// with a no-speech probability of 1 without running the model.
+    ///
+    /// Decoded text is passed to `on_text` as tokens are generated. The pieces
+    /// always consist of whole UTF-8 characters and concatenated they equal the
+    /// returned `text`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the audio data cannot be transcribed.
+    pub fn transcribe_audio_16k<F>(
+        &mut self,
+        audio_data: &[f32],
+        options: &TranscribeOptions,
+        mut on_text: F,
+    ) -> Result<TranscriptionResult>
+    where
+        F: FnMut(&str),
+    {
+        self.check_options(options)?;
+        if audio_data.is_empty() {
+            return Ok(TranscriptionResult::join(Vec::new()));
+        }
+        profile::start_chunk();
+        let pass_samples = options
+            .max_seconds_per_pass
+            .map(|max_seconds| self.pass_samples(max_seconds));
+        let result = match pass_samples {
+            Some(pass_samples) if audio_data.len() > pass_samples => audio_data
+                .chunks(pass_samples)
+                .map(|piece| self.transcribe_pass(piece, options, &mut on_text))
+                .collect::<Result<Vec<_>>>()
+                .map(TranscriptionResult::join),
+            _ => self.transcribe_pass(audio_data, options, &mut on_text),
+        };
+        profile::finish_chunk();
+        result
+    }
+
+    /// Whether [`TranscribeOptions::verbatim`] has an effect: the model must
+    /// have a transcription mode, with its own `[TRANSCRIBE]` token, for the
+    /// text after `[/INST]` to be taken as the transcript so far. Otherwise
+    /// the [`VERBATIM_PROMPT`] could be mistaken for an instruction, and it
+    /// is left out.
+    pub fn supports_verbatim(&self) -> bool {
+        self.supports_verbatim
+    }
+
+    /// Check that `options` suit this model: every suppressed token id must
+    /// be in its vocabulary.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error naming the suppressed ids outside the vocabulary.
+    pub fn check_options(&self, options: &TranscribeOptions) -> Result<()> {
+        check_suppress_tokens(&options.suppress_tokens, self.vocab_size)
+    }
+
+    /// Token ids never generated under `options`.
+    fn suppressed_tokens(&self, options: &TranscribeOptions) -> Vec<u32> {
+        let mut tokens = options.suppress_tokens.clone();
+        if options.suppress_non_speech {
+            tokens.extend(&self.non_speech_tokens);
+        }
+        tokens
+    }
+
+    /// Transcribe mono audio at the model's rate in a single pass through the model.
+    fn transcribe_pass(
+        &mut self,
+        audio_data: &[f32],
+        options: &TranscribeOptions,
+        on_text: &mut dyn FnMut(&str),
     ) -> Result<TranscriptionResult> {
-        // Resample to 16kHz if needed
-        let audio = if sample_rate == SAMPLE_RATE {
-            audio_data.to_vec()
-        } else {
-            crate::audio::resample(audio_data, sample_rate, SAMPLE_RATE)
-                .context("Failed to resample audio")?
+        let this = &*self;
+        let encode = || this.encode_pass(audio_data, options);
+        let attempt = |encoded: &EncodedPass, temperature: f32, on_text: &mut dyn FnMut(&str)| {
+            let (text, generation) =
+                this.decode_pass(encoded, &options.at_temperature(temperature), on_text)?;
+            Ok(TranscriptionResult {
+                text,
+                tokens: generation.tokens,
+                avg_logprob: generation.avg_logprob,
+                no_speech_prob: generation.no_speech_prob,
+                truncated: generation.truncated,
+            })
         };
 
-        // Pad audio to multiple of 480000 samples before feature extraction
-        let chunk_size = 480000; // 30 seconds * 16000 Hz
-        let padded_audio = if audio.len() % chunk_size != 0 {
-            // Pad to next multiple of chunk_size
-            let target_samples = ((audio.len() / chunk_size) + 1) * chunk_size;
-            let mut padded = audio.clone();
-            padded.resize(target_samples, 0.0); // Pad with zeros
-            padded
-        } else {
-            audio
+        if options.decode_strategy != DecodeStrategy::TemperatureFallback {
+            return encode_then_decode(options, encode, |encoded, temperature| {
+                attempt(encoded, temperature, on_text)
+            });
+        }
+        // Attempts may be discarded, so only the kept text is passed on
+        let result = encode_then_decode(options, encode, |encoded, temperature| {
+            attempt(encoded, temperature, &mut |_| {})
+        })?;
+        on_text(&result.text);
+        Ok(result)
+    }
+
+    /// Run the encoder over `audio_data`, and the decoder over the prompt of
+    /// `options` with the audio in it, for the attempts of a pass to continue
+    /// from.
+    fn encode_pass(&self, audio_data: &[f32], options: &TranscribeOptions) -> Result<EncodedPass> {
+        let audio_features = self.mel_features(audio_data)?;
+        let _span = profile::span(Stage::Encoder);
+        // Create the exact token sequence that HuggingFace processor generates,
+        // plus the optional initial prompt and previous text
+        let batch_size = audio_features.dim(0)?; // Number of 30-second chunks
+        let verbatim = verbatim_example(options) && self.supports_verbatim;
+        let prompt_tokens = match context_text(options.prompt.as_deref(), verbatim) {
+            Some(text) => encode_prompt(&self.tokenizer, &text)?,
+            None => Vec::new(),
         };
+        let prompt_tokens = decoder_context(prompt_tokens, &options.previous_tokens);
+        let input_tokens = transcription_prompt(
+            audio_token_count(batch_size, self.max_source_positions),
+            &self.tokens,
+            &prompt_tokens,
+            &encode_language(&self.tokenizer, &options.language)?,
+        );
 
-        // Use the 128-mel filter bank
-        let mel_bytes = include_bytes!("../melfilters128.bytes");
+        let mut cache = self.cache.clone();
+        let input_ids =
+            Tensor::new(input_tokens.as_slice(), audio_features.device())?.unsqueeze(0)?;
+        // Audio features are inserted at the audio token positions
+        let logits = self
+            .model
+            .forward(&input_ids, Some(&audio_features), &mut cache, 0)
+            .map_err(|e| anyhow::anyhow!("Failed to generate tokens: {e}"))?;
+        // Reading the logits back waits for the device, so the time is real
+        let logits = logits.flatten_all()?.to_vec1::<f32>()?;
+        Ok(EncodedPass {
+            input_tokens,
+            cache,
+            logits,
+            device: audio_features.device().clone(),
+        })
+    }
 
-        let mut mel_filters = vec![0f32; mel_bytes.len() / 4];
-        let mut cursor = Cursor::new(mel_bytes);
-        cursor.read_f32_into::<LittleEndian>(&mut mel_filters)?;
+    /// Generate the transcription of an [`EncodedPass`] under `options`.
+    ///
+    /// The decoder continues from a copy of the pass's cache, so `encoded`
+    /// can be decoded again, for example at another temperature. Text is
+    /// streamed to `on_text` while generating. Returns the decoded string
+    /// and the generation with its token ids.
+    fn decode_pass(
+        &self,
+        encoded: &EncodedPass,
+        options: &TranscribeOptions,
+        on_text: &mut dyn FnMut(&str),
+    ) -> Result<(String, Generation)> {
+        let VoxtralModel {
+            model,
+            tokenizer,
+            tokens,
+            ..
+        } = self;
+        // Generate response using the model (match Python parameters)
+        let generation_config = GenerationConfig {
+            max_new_tokens: 1000,
+            eos_tokens: tokens.eos_tokens(),
+            repetition_penalty: options.repetition_penalty,
+            no_repeat_ngram_size: options.no_repeat_ngram_size,
+            timeout: options.timeout,
+            temperature: match options.decode_strategy {
+                DecodeStrategy::Sample => options.temperature,
+                DecodeStrategy::Greedy | DecodeStrategy::TemperatureFallback => 0.0,
+            },
+            seed: options.seed,
+            suppress_tokens: self.suppressed_tokens(options),
+        };
+
+        let mut cache = encoded.cache.clone();
+        let mut index_pos = encoded.input_tokens.len();
+        // The prompt already went through the decoder when encoding
+        let mut prompt_logits = Some(encoded.logits.clone());
+        let _span = profile::span(Stage::Generation);
+        let step = |input: &[u32]| -> Result<Vec<f32>> {
+            if let Some(logits) = prompt_logits.take() {
+                return Ok(logits);
+            }
+            let input_ids = Tensor::new(input, &encoded.device)?.unsqueeze(0)?;
+            let logits = model
+                .forward(&input_ids, None, &mut cache, index_pos)
+                .map_err(|e| anyhow::anyhow!("Failed to generate tokens: {e}"))?;
+            index_pos += input.len();
+            Ok(logits.flatten_all()?.to_vec1::<f32>()?)
+        };
+
+        let mut utf8 = Utf8Stream::default();
+        let generation =
+            generate::generate(&encoded.input_tokens, step, &generation_config, |token| {
+                if let Ok(bytes) =
+                    tokenizer.id_to_byte_piece(token, tekken::SpecialTokenPolicy::Ignore)
+                {
+                    let text = utf8.push(&bytes);
+                    if !text.is_empty() {
+                        on_text(&text);
+                    }
+                }
+            })?;
+        let tail = utf8.finish();
+        if !tail.is_empty() {
+            on_text(&tail);
+        }
 
-        let audio_features =
-            voxtral::extract_features(&padded_audio, &mel_filters, self.device()).unwrap();
+        let decoded_text = tokenizer
+            .decode(&generation.tokens, tekken::SpecialTokenPolicy::Ignore)
+            .map_err(|e| anyhow::anyhow!("Failed to decode tokens: {}", e))?;
+
+        // Return both transcription and tokens
+        Ok((decoded_text, generation))
+    }
+
+    /// Transcribe mono audio of any length at the model's rate in the chunks
+    /// of `plan`, returning one result per chunk.
+    ///
+    /// `on_chunk` is called before and after each chunk with its position in
+    /// the schedule, for example to show which part of the audio is being
+    /// transcribed. Text is streamed to `on_text` as with
+    /// [`Self::transcribe_audio_16k`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if any chunk cannot be transcribed.
+    pub fn transcribe_chunked<E, F>(
+        &mut self,
+        audio_data: &[f32],
+        plan: &ChunkPlan,
+        options: &TranscribeOptions,
+        on_chunk: E,
+        mut on_text: F,
+    ) -> Result<Vec<TranscriptionResult>>
+    where
+        E: FnMut(ChunkEvent),
+        F: FnMut(&str),
+    {
+        let mut results = Vec::new();
+        plan.for_each_chunk(audio_data, 0, on_chunk, |_, chunk| {
+            results.push(self.transcribe_audio_16k(chunk, options, &mut on_text)?);
+            Ok(())
+        })?;
+        Ok(results)
+    }
 
-        let (result, tokens) = transcribe_with_voxtral(
-            &self.model,
-            &self.tokenizer,
-            &audio_features,
-            &self.audio_token_id,
-            &self.device,
-            &self.cache.clone(),
+    /// Transcribe audio at any sample rate in the chunks of `plan`, yielding
+    /// a [`Segment`] as each chunk completes instead of collecting them.
+    ///
+    /// The audio is resampled to the model's rate up front and owned by the returned
+    /// stream, so the caller's buffer may be dropped at once. The stream
+    /// borrows the model mutably until it is dropped, and a chunk is only
+    /// transcribed when the next segment is asked for: a consumer that stops
+    /// early skips the remaining chunks.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the audio cannot be resampled or `options` do not
+    /// suit this model. Errors transcribing a chunk are yielded by the stream.
+    pub fn transcribe_stream(
+        &mut self,
+        audio_data: &[f32],
+        sample_rate: u32,
+        plan: &ChunkPlan,
+        options: &TranscribeOptions,
+    ) -> Result<TranscriptStream<'_>> {
+        self.check_options(options)?;
+        let audio = resample_for_model(audio_data, sample_rate, self.sample_rate)?.into_owned();
+        let options = options.clone();
+        let sample_rate = self.sample_rate;
+        Ok(TranscriptStream::new(
+            audio,
+            sample_rate,
+            plan,
+            move |chunk| self.transcribe_audio_16k(chunk, &options, |_| {}),
+        ))
+    }
+
+    /// Pad mono audio at the model's rate to whole encoder windows and convert it into
+    /// the log-mel features the encoder consumes, of shape
+    /// `(windows, num_mel_bins, frames)` with 100 frames per second. For
+    /// Voxtral a window is 30 seconds, or 3000 frames.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the features cannot be computed.
+    pub fn mel_features(&self, audio: &[f32]) -> Result<Tensor> {
+        check_finite(audio)?;
+        let _span = profile::span(Stage::Mel);
+        // Pad audio to whole encoder windows before feature extraction
+        let padded_audio = audio::pad(
+            audio,
+            padded_len(
+                audio.len(),
+                mel::window_samples(self.max_source_positions, self.sample_rate),
+            ),
+            self.pad_mode,
+        );
+
+        let audio_features = mel::extract_features(
+            &padded_audio,
+            &self.mel_filters,
+            self.num_mel_bins,
+            mel::window_frames(self.max_source_positions),
+            self.sample_rate,
+            self.device(),
         )?;
+        validate_audio_features(&audio_features, self.num_mel_bins)?;
+        Ok(audio_features)
+    }
 
-        Ok(TranscriptionResult {
-            text: result,
-            tokens,
-        })
+    /// Samples of audio at the model's rate per pass when passes are capped at
+    /// `max_seconds`: whole encoder windows, and at least one, since shorter
+    /// audio is padded to a full window anyway.
+    pub fn pass_samples(&self, max_seconds: f32) -> usize {
+        let window = mel::window_samples(self.max_source_positions, self.sample_rate);
+        let windows = (max_seconds.max(0.0) * self.sample_rate as f32) as usize / window;
+        windows.max(1) * window
+    }
+
+    /// Rough activation memory in bytes of one pass over `samples` of audio
+    /// at the model's rate, see [`crate::memory`] for what it assumes.
+    pub fn estimated_pass_bytes(&self, samples: usize) -> u64 {
+        let window = mel::window_samples(self.max_source_positions, self.sample_rate);
+        self.pass_dims
+            .activation_bytes(padded_len(samples, window) / window)
     }
 
-    /// Return the `Device` the model is placed on (CPU or CUDA device).
+    /// Return the `Device` the model is placed on (CPU, CUDA or Metal device).
     pub fn device(&self) -> &Device {
         &self.device
     }
 }
 
-/// Run transcription with the Voxtral model given precomputed audio features.
+/// Transcribes one chunk of a [`TranscriptStream`].
+type ChunkTranscriber<'a> = Box<dyn FnMut(&[f32]) -> Result<TranscriptionResult> + 'a>;
+
+/// Iterator over the segments of chunked audio, returned by
+/// [`VoxtralModel::transcribe_stream`].
 ///
-/// This helper builds the token prompt (including the appropriate number of
-/// audio tokens), runs the model generation and decodes the newly generated
-/// tokens into a UTF-8 string. Returns the decoded string and the generated
-/// token ids.
-fn transcribe_with_voxtral(
-    model: &VoxtralForConditionalGeneration,
-    tokenizer: &Tekkenizer,
-    audio_features: &Tensor,
-    audio_token_id: &usize,
-    device: &Device,
-    cache: &VoxtralCache,
-) -> Result<(String, Vec<u32>)> {
-    // Validate audio features shape
-    let audio_dims = audio_features.dims();
-    if audio_dims.len() != 3 {
-        return Err(anyhow::anyhow!(
-            "Audio features must be 3D tensor (batch, mels, time), got shape: {:?}",
-            audio_dims
-        ));
+/// Each call to `next` transcribes one chunk and yields its segment, with
+/// the text, timing and confidence of the chunk, even when the text is
+/// empty. After an error the stream ends.
+pub struct TranscriptStream<'a> {
+    audio: Vec<f32>,
+    sample_rate: u32,
+    ranges: std::iter::Enumerate<std::vec::IntoIter<Range<usize>>>,
+    transcribe: ChunkTranscriber<'a>,
+    failed: bool,
+}
+
+impl<'a> TranscriptStream<'a> {
+    /// Stream the chunks of mono `audio` at `sample_rate` planned by
+    /// `plan`, each transcribed by `transcribe` when its segment is asked for.
+    pub fn new(
+        audio: Vec<f32>,
+        sample_rate: u32,
+        plan: &ChunkPlan,
+        transcribe: impl FnMut(&[f32]) -> Result<TranscriptionResult> + 'a,
+    ) -> Self {
+        Self {
+            ranges: plan.ranges(audio.len()).into_iter().enumerate(),
+            audio,
+            sample_rate,
+            transcribe: Box::new(transcribe),
+            failed: false,
+        }
     }
+}
 
-    if audio_dims[1] != 128 {
-        return Err(anyhow::anyhow!(
-            "Audio features must have 128 mel bins, got {}",
-            audio_dims[1]
-        ));
+impl Iterator for TranscriptStream<'_> {
+    type Item = Result<Segment>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        let (index, range) = self.ranges.next()?;
+        let result = match (self.transcribe)(&self.audio[range.clone()]) {
+            Ok(result) => result,
+            Err(e) => {
+                self.failed = true;
+                return Some(Err(
+                    e.context(format!("Failed to transcribe chunk {}", index + 1))
+                ));
+            }
+        };
+        Some(Ok(Segment {
+            index,
+            start_sec: range.start as f32 / self.sample_rate as f32,
+            end_sec: range.end as f32 / self.sample_rate as f32,
+            text: result.text.trim().to_string(),
+            avg_logprob: Some(result.avg_logprob),
+            no_speech_prob: Some(result.no_speech_prob),
+            speaker: None,
+            language: None,
+            failed: false,
+        }))
+    }
+}
+
+/// Check that every sample of `audio` is a finite number; a single NaN
+/// would spread through the mel features and leave the transcript empty.
+fn check_finite(audio: &[f32]) -> Result<()> {
+    let count = audio.iter().filter(|sample| !sample.is_finite()).count();
+    if count > 0 {
+        anyhow::bail!(
+            "Audio holds {count} NaN or infinite samples; replace them first, \
+             for example with audio::sanitize"
+        );
+    }
+    Ok(())
+}
+
+/// Length `len` samples are padded to: the next whole number of windows of
+/// `window_samples`, and at least one window.
+fn padded_len(len: usize, window_samples: usize) -> usize {
+    len.div_ceil(window_samples).max(1) * window_samples
+}
+
+/// Resample `audio_data` to the `model_rate`, borrowing it when it already is.
+fn resample_for_model(
+    audio_data: &[f32],
+    sample_rate: u32,
+    model_rate: u32,
+) -> Result<Cow<'_, [f32]>> {
+    if sample_rate == model_rate {
+        return Ok(Cow::Borrowed(audio_data));
     }
+    crate::audio::resample(audio_data, sample_rate, model_rate)
+        .map(Cow::Owned)
+        .context("Failed to resample audio")
+}
 
-    // Create the exact token sequence that HuggingFace processor generates
-    let mut input_tokens = Vec::new();
+/// GPUs of each kind probed by [`list_devices`], from ordinal 0 up.
+const MAX_PROBED_GPUS: usize = 16;
 
-    // Pattern: <s>[INST][BEGIN_AUDIO][AUDIO]*N[/INST]lang:en[TRANSCRIBE]
-    input_tokens.push(1u32); // BOS: <s>
-    input_tokens.push(3u32); // [INST]
-    input_tokens.push(25u32); // [BEGIN_AUDIO]
+/// Whether a compute device can be used, as found by [`list_devices`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeviceState {
+    Available,
+    /// Compiled in, but the device failed to initialise, with the reason.
+    Failed(String),
+    /// The build lacks the feature for this kind of device.
+    NotBuilt,
+}
 
-    // Calculate number of audio tokens to match Python exactly: 7 chunks × 375 tokens = 2625
-    let batch_size = audio_features.dim(0)?; // Number of chunks (should be 7)
+/// A compute device and whether this build can run on it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceReport {
+    /// Such as `cpu`, `cuda:0` or `metal:0`; just the kind when no device of
+    /// it could be created.
+    pub name: String,
+    pub state: DeviceState,
+}
 
-    // Python uses exactly 375 tokens per 3000-frame chunk
-    let tokens_per_chunk = 375; // Fixed value from Python analysis
-    let num_audio_tokens = batch_size * tokens_per_chunk;
+impl std::fmt::Display for DeviceReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:<8} ", self.name)?;
+        match &self.state {
+            DeviceState::Available if self.name == "cpu" => match utils::get_num_threads() {
+                1 => write!(f, "available, 1 thread"),
+                threads => write!(f, "available, {threads} threads"),
+            },
+            DeviceState::Available => write!(f, "available"),
+            DeviceState::Failed(reason) => write!(f, "unavailable: {reason}"),
+            DeviceState::NotBuilt => write!(
+                f,
+                "not in this build, rebuild with --features {}",
+                self.name
+            ),
+        }
+    }
+}
 
-    // Add AUDIO tokens
-    for _ in 0..num_audio_tokens {
-        input_tokens.push(*audio_token_id as u32); // [AUDIO] token (24)
+/// The compute devices this build can use: always the CPU, then each CUDA
+/// GPU and the Metal GPU that initialises, or why none does.
+///
+/// Every GPU is created once to find out, so this takes a moment on a
+/// machine with GPUs. A backend that panics while probing counts as failed.
+pub fn list_devices() -> Vec<DeviceReport> {
+    let mut devices = vec![DeviceReport {
+        name: "cpu".to_string(),
+        state: DeviceState::Available,
+    }];
+    devices.extend(probe_gpus(
+        "cuda",
+        utils::cuda_is_available(),
+        Device::new_cuda,
+    ));
+    devices.extend(probe_gpus(
+        "metal",
+        utils::metal_is_available(),
+        Device::new_metal,
+    ));
+    devices
+}
+
+/// Create GPUs of `kind` with `new` from ordinal 0 until one fails, and
+/// report those that initialised or, when none did, why.
+fn probe_gpus(
+    kind: &str,
+    built: bool,
+    new: impl Fn(usize) -> candle_core::Result<Device>,
+) -> Vec<DeviceReport> {
+    if !built {
+        return vec![DeviceReport {
+            name: kind.to_string(),
+            state: DeviceState::NotBuilt,
+        }];
     }
+    let mut devices = Vec::new();
+    for ordinal in 0..MAX_PROBED_GPUS {
+        let reason = match std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| new(ordinal))) {
+            Ok(Ok(_)) => {
+                devices.push(DeviceReport {
+                    name: format!("{kind}:{ordinal}"),
+                    state: DeviceState::Available,
+                });
+                continue;
+            }
+            Ok(Err(e)) => e.to_string(),
+            Err(_) => "the driver panicked while creating the device".to_string(),
+        };
+        if ordinal == 0 {
+            devices.push(DeviceReport {
+                name: kind.to_string(),
+                state: DeviceState::Failed(reason),
+            });
+        }
+        break;
+    }
+    devices
+}
 
-    input_tokens.push(4u32); // [/INST]
-    input_tokens.push(9909u32); // lang
-    input_tokens.push(1058u32); // :
-    input_tokens.push(1262u32); // en
-    input_tokens.push(34u32); // [TRANSCRIBE]
+/// Thread count applied by the first [`set_cpu_threads`] call.
+static CPU_THREADS: OnceLock<usize> = OnceLock::new();
 
-    let input_len = input_tokens.len();
-    let input_ids = Tensor::new(input_tokens, device)?.unsqueeze(0)?;
+/// Limit the CPU backend to `threads` threads for the rest of the process.
+///
+/// candle runs its CPU operations, matrix products included, on the global
+/// rayon pool, which is built only once, so the count is applied by the
+/// first call. Matrix products are still split into as many tasks as
+/// `RAYON_NUM_THREADS` or the core count says, but only `threads` of them
+/// run at a time. Later calls with the same
+/// count do nothing, which lets every `--parallel` worker pass it along.
+///
+/// # Errors
+///
+/// Returns an error if `threads` is 0, or if an earlier call applied a
+/// different count.
+pub fn set_cpu_threads(threads: usize) -> Result<()> {
+    if threads == 0 {
+        anyhow::bail!("The CPU thread count must be at least 1");
+    }
+    let applied = *CPU_THREADS.get_or_init(|| {
+        if let Err(e) = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build_global()
+        {
+            log::warn!(
+                "CPU thread pool already running, can not limit it to {threads} threads: {e}"
+            );
+        }
+        log::debug!("CPU backend limited to {threads} threads");
+        threads
+    });
+    if applied != threads {
+        anyhow::bail!(
+            "The CPU backend already runs on {applied} threads and can not switch to {threads}"
+        );
+    }
+    Ok(())
+}
 
-    // Generate response using the model (match Python parameters)
-    let generation_config = VoxtralGenerationConfig {
-        max_new_tokens: 1000, // max_new_tokens
-        temperature: 0.0,     // temperature=0 for deterministic generation
-        top_p: None,
-        device: device.clone(),
-        cache: Some(cache.clone()),
-    };
+/// Pick the compute device: CUDA when compiled in and available, then Metal,
+/// otherwise CPU.
+///
+/// A GPU that fails to initialise at runtime, such as a CUDA build running in
+/// a container without a GPU, falls back to CPU with a warning instead of
+/// aborting the run. With `require_gpu` any fallback to CPU is an error.
+/// `ordinal` picks the GPU when several are installed.
+fn select_device(use_cpu: bool, require_gpu: bool, ordinal: usize) -> Result<Device> {
+    if use_cpu {
+        if require_gpu {
+            anyhow::bail!(
+                "A GPU is required but CPU was selected or this build has no cuda/metal feature"
+            );
+        }
+        return Ok(Device::Cpu);
+    }
 
-    let generated_tokens = model
-        .generate(
-            &input_ids,
-            Some(audio_features), // Audio features will be processed and inserted at audio token position
-            generation_config,
-        )
-        .map_err(|e| {
-            println!("Generation error: {:?}", e);
-            println!("Error details: {:#}", e);
-            anyhow::anyhow!("Failed to generate tokens: {e}")
-        })?;
+    if utils::cuda_is_available() {
+        match Device::new_cuda(ordinal) {
+            Ok(device) => return Ok(device),
+            Err(e) if require_gpu => {
+                return Err(e).with_context(|| format!("Failed to create CUDA device {ordinal}"));
+            }
+            Err(e) => log::warn!("Failed to create CUDA device, falling back to CPU: {e}"),
+        }
+    }
 
-    // Decode only the newly generated tokens (skip input prompt)
-    let new_tokens = if generated_tokens.len() > input_len {
-        &generated_tokens[input_len..]
-    } else {
-        &generated_tokens
-    };
+    if utils::metal_is_available() {
+        match Device::new_metal(ordinal) {
+            Ok(device) => return Ok(device),
+            Err(e) if require_gpu => {
+                return Err(e).with_context(|| format!("Failed to create Metal device {ordinal}"));
+            }
+            Err(e) => log::warn!("Failed to create Metal device, falling back to CPU: {e}"),
+        }
+    }
+
+    if require_gpu {
+        anyhow::bail!("A GPU is required but none could be initialised");
+    }
+    Ok(Device::Cpu)
+}
+
+/// Resolve the compute dtype for `device`, validating an explicit request.
+///
+/// Without a request F16 is used on GPU and F32 on CPU, where F16 is
+/// emulated and slow. BF16 is refused on CPU.
+fn resolve_dtype(requested: Option<ModelDType>, device: &Device) -> Result<DType> {
+    match requested {
+        None if device.is_cpu() => Ok(DType::F32),
+        None => Ok(DType::F16),
+        Some(ModelDType::Bf16) if device.is_cpu() => Err(anyhow::anyhow!(
+            "BF16 is not supported on CPU, use --dtype f32 or f16 instead"
+        )),
+        Some(dtype) => Ok(dtype.to_dtype()),
+    }
+}
+
+/// Rank candidate languages by the softmax of their first-token logits.
+///
+/// Probabilities are normalised over the candidates only, so they sum to one
+/// even though the model also assigns mass to unrelated tokens.
+pub fn rank_languages(logits: &[f32], candidates: &[(&str, u32)]) -> Vec<LanguageProbability> {
+    let scores: Vec<f32> = candidates
+        .iter()
+        .map(|&(_, token)| {
+            logits
+                .get(token as usize)
+                .copied()
+                .unwrap_or(f32::NEG_INFINITY)
+        })
+        .collect();
+    let max = scores.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+    let exps: Vec<f32> = scores.iter().map(|&s| (s - max).exp()).collect();
+    let total: f32 = exps.iter().sum();
+
+    let mut ranked: Vec<LanguageProbability> = candidates
+        .iter()
+        .zip(exps)
+        .map(|(&(language, _), e)| LanguageProbability {
+            language: language.to_string(),
+            probability: if total > 0.0 { e / total } else { 0.0 },
+        })
+        .collect();
+    ranked.sort_by(|a, b| b.probability.total_cmp(&a.probability));
+    ranked
+}
+
+/// Encode a language code into the tokens following `lang:` in the prompt.
+fn encode_language(tokenizer: &Tekkenizer, language: &str) -> Result<Vec<u32>> {
+    let tokens = tokenizer
+        .encode(language, false, false)
+        .map_err(|e| anyhow::anyhow!("Failed to encode language '{language}': {e}"))?;
+    if tokens.is_empty() {
+        anyhow::bail!("Language code must not be empty");
+    }
+    Ok(tokens)
+}
+
+/// Encode the initial prompt, keeping at most [`MAX_PROMPT_TOKENS`] from its end.
+fn encode_prompt(tokenizer: &Tekkenizer, prompt: &str) -> Result<Vec<u32>> {
+    let tokens = tokenizer
+        .encode(prompt, false, false)
+        .map_err(|e| anyhow::anyhow!("Failed to encode prompt: {e}"))?;
+    Ok(tokens[tokens.len().saturating_sub(MAX_PROMPT_TOKENS)..].to_vec())
+}
+
+/// Whether `options` ask for [`VERBATIM_PROMPT`] in the decoder context:
+/// with `verbatim` set, for a transcript in [`VERBATIM_LANGUAGE`] only.
+fn verbatim_example(options: &TranscribeOptions) -> bool {
+    options.verbatim && options.language == VERBATIM_LANGUAGE
+}
+
+/// Text of the decoder context before the previous text: the
+/// [`VERBATIM_PROMPT`] when `verbatim`, then the `prompt`. A prompt that
+/// takes up most of [`MAX_PROMPT_TOKENS`] pushes the verbatim example out.
+fn context_text(prompt: Option<&str>, verbatim: bool) -> Option<Cow<'_, str>> {
+    match (prompt, verbatim) {
+        (Some(prompt), true) => Some(Cow::Owned(format!("{VERBATIM_PROMPT} {prompt}"))),
+        (None, true) => Some(Cow::Borrowed(VERBATIM_PROMPT)),
+        (prompt, false) => prompt.map(Cow::Borrowed),
+    }
+}
+
+/// Decoder context of a transcription: the prompt tokens followed by the
+/// `previous` text's, keeping at most [`MAX_PROMPT_TOKENS`] from the end.
+fn decoder_context(mut prompt_tokens: Vec<u32>, previous: &[u32]) -> Vec<u32> {
+    prompt_tokens.extend_from_slice(previous);
+    prompt_tokens.drain(..prompt_tokens.len().saturating_sub(MAX_PROMPT_TOKENS));
+    prompt_tokens
+}
+
+/// Token ids of [`NON_SPEECH_SYMBOLS`] and [`MUSIC_SYMBOLS`] under `encode`,
+/// sorted and without duplicates, as Whisper builds its `suppress_tokens`.
+///
+/// A symbol counts alone and after a space. Symbols that take several tokens
+/// are kept out, as their pieces also spell speech, except for music symbols
+/// whose first token is taken.
+pub fn non_speech_tokens(encode: impl Fn(&str) -> Option<Vec<u32>>) -> Vec<u32> {
+    let mut ids: Vec<u32> = [" -", " '"]
+        .iter()
+        .filter_map(|text| encode(text)?.first().copied())
+        .collect();
+    for (symbol, music) in NON_SPEECH_SYMBOLS
+        .iter()
+        .map(|symbol| (symbol, false))
+        .chain(MUSIC_SYMBOLS.iter().map(|symbol| (symbol, true)))
+    {
+        for text in [symbol.to_string(), format!(" {symbol}")] {
+            match encode(&text).as_deref() {
+                Some([id]) => ids.push(*id),
+                Some([id, ..]) if music => ids.push(*id),
+                _ => {}
+            }
+        }
+    }
+    ids.sort_unstable();
+    ids.dedup();
+    ids
+}
+
+/// Check that every id of `--suppress-tokens` is below `vocab_size`.
+///
+/// # Errors
+///
+/// Returns an error naming the ids outside the vocabulary.
+pub fn check_suppress_tokens(tokens: &[u32], vocab_size: usize) -> Result<()> {
+    let outside: Vec<String> = tokens
+        .iter()
+        .filter(|&&token| token as usize >= vocab_size)
+        .map(u32::to_string)
+        .collect();
+    if !outside.is_empty() {
+        anyhow::bail!(
+            "--suppress-tokens {} outside the vocabulary of {vocab_size} tokens (ids 0 to {})",
+            outside.join(","),
+            vocab_size.saturating_sub(1)
+        );
+    }
+    Ok(())
+}
+
+/// Resolve the [`SpecialTokens`] from `tokenizer` and check that they round
+/// trip, so a tokenizer of another model fails at load time instead of
+/// silently producing nonsense.
+///
+/// Tokens the tokenizer does not know fall back to the Voxtral Mini 3B ids
+/// with a warning.
+fn special_tokens(tokenizer: &Tekkenizer, audio_token_id: usize) -> Result<SpecialTokens> {
+    let (tokens, fallbacks) = SpecialTokens::resolve(
+        audio_token_id as u32,
+        |name| tokenizer.get_control_token(name).ok(),
+        tokenizer.encode("lang:", false, false).ok(),
+    );
+    if !fallbacks.is_empty() {
+        log::warn!(
+            "Tokenizer has no {}, using the Voxtral Mini 3B ids",
+            fallbacks.join(", ")
+        );
+    }
+    check_tokenizer(&tokens, &fallbacks, |id| {
+        tokenizer
+            .decode(&[id], tekken::SpecialTokenPolicy::Keep)
+            .ok()
+    })?;
+    Ok(tokens)
+}
+
+/// Check that every resolved control token decodes back to its name, listing
+/// every mismatch in the error. Tokens in `fallbacks` are not checked.
+fn check_tokenizer(
+    tokens: &SpecialTokens,
+    fallbacks: &[&str],
+    decode: impl Fn(u32) -> Option<String>,
+) -> Result<()> {
+    let problems: Vec<String> = tokens
+        .named()
+        .into_iter()
+        .filter(|(name, _)| !fallbacks.contains(name))
+        .filter_map(|(name, id)| {
+            let decoded = decode(id);
+            (decoded.as_deref() != Some(name))
+                .then(|| format!("id {id} decodes to {decoded:?} instead of {name}"))
+        })
+        .collect();
+
+    if !problems.is_empty() {
+        anyhow::bail!(
+            "the tokenizer does not match the model: {}",
+            problems.join("; ")
+        );
+    }
+    Ok(())
+}
+
+/// Build the full transcription prompt:
+/// `<s>[INST][BEGIN_AUDIO][AUDIO]*N[/INST]{prompt}lang:xx[TRANSCRIBE]`.
+///
+/// The optional prompt tokens go right after `[/INST]` so the language tag
+/// and `[TRANSCRIBE]` still directly precede the generated text.
+fn transcription_prompt(
+    num_audio_tokens: usize,
+    tokens: &SpecialTokens,
+    prompt_tokens: &[u32],
+    language_tokens: &[u32],
+) -> Vec<u32> {
+    let mut input_tokens = audio_prompt(num_audio_tokens, tokens);
+    input_tokens.extend_from_slice(prompt_tokens);
+    input_tokens.extend_from_slice(&tokens.lang_prefix);
+    input_tokens.extend_from_slice(language_tokens);
+    input_tokens.push(tokens.transcribe);
+    input_tokens
+}
+
+/// Number of `[AUDIO]` tokens for `windows` encoder windows of
+/// `max_source_positions` positions: the projector merges every
+/// [`POSITIONS_PER_AUDIO_TOKEN`] positions into one, 375 per window for
+/// Voxtral's 1500.
+fn audio_token_count(windows: usize, max_source_positions: usize) -> usize {
+    windows * (max_source_positions / POSITIONS_PER_AUDIO_TOKEN)
+}
+
+/// Build the prompt up to and including `[/INST]`:
+/// `<s>[INST][BEGIN_AUDIO][AUDIO]*N[/INST]` with `num_audio_tokens` audio
+/// tokens.
+fn audio_prompt(num_audio_tokens: usize, tokens: &SpecialTokens) -> Vec<u32> {
+    let mut input_tokens = Vec::with_capacity(num_audio_tokens + 4);
+    input_tokens.push(tokens.bos);
+    input_tokens.push(tokens.inst);
+    input_tokens.push(tokens.begin_audio);
+    input_tokens.extend(std::iter::repeat_n(tokens.audio, num_audio_tokens));
+    input_tokens.push(tokens.inst_end);
+    input_tokens
+}
+
+/// Check that `audio_features` has the `(batch, mels, time)` shape the
+/// encoder expects, with `num_mel_bins` mels.
+fn validate_audio_features(audio_features: &Tensor, num_mel_bins: usize) -> Result<()> {
+    let audio_dims = audio_features.dims();
+    if audio_dims.len() != 3 {
+        return Err(anyhow::anyhow!(
+            "Audio features must be 3D tensor (batch, mels, time), got shape: {:?}",
+            audio_dims
+        ));
+    }
 
-    let decoded_text = tokenizer
-        .decode(new_tokens, tekken::SpecialTokenPolicy::Ignore)
-        .map_err(|e| anyhow::anyhow!("Failed to decode tokens: {}", e))?;
+    if audio_dims[1] != num_mel_bins {
+        return Err(anyhow::anyhow!(
+            "Audio features must have {} mel bins, got {}",
+            num_mel_bins,
+            audio_dims[1]
+        ));
+    }
+    Ok(())
+}
 
-    // Return both transcription and tokens
-    Ok((decoded_text, new_tokens.to_vec()))
+/// Sample rate the model in `model_dir` expects its audio at: the
+/// `sampling_rate` of its `preprocessor_config.json`, else of its
+/// `config.json` or that file's `audio_config`, else 16000 Hz.
+///
+/// # Errors
+///
+/// Returns an error if one of the files cannot be read or parsed, or names
+/// a rate that is not a positive whole number.
+pub fn expected_sample_rate(model_dir: &Path) -> Result<u32> {
+    for name in [PREPROCESSOR_CONFIG_FILE, "config.json"] {
+        let path = model_dir.join(name);
+        if !path.is_file() {
+            continue;
+        }
+        let json: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&path)?)
+            .with_context(|| format!("Failed to parse {}", path.display()))?;
+        if let Some(rate) = config_sample_rate(&json)
+            .with_context(|| format!("Unusable sampling_rate in {}", path.display()))?
+        {
+            return Ok(rate);
+        }
+    }
+    Ok(SAMPLE_RATE)
 }
 
-/// Load model weights from safetensors files
-fn load_model_weights<'a>(model_files: &'a [PathBuf], device: &Device) -> Result<VarBuilder<'a>> {
-    let dtype = DType::F16; // F16 for memory efficiency
+/// The `sampling_rate` of a model configuration, at the top or in its
+/// `audio_config`, if it names one.
+fn config_sample_rate(json: &serde_json::Value) -> Result<Option<u32>> {
+    let Some(value) = json.get("sampling_rate").or_else(|| {
+        json.get("audio_config")
+            .and_then(|audio| audio.get("sampling_rate"))
+    }) else {
+        return Ok(None);
+    };
+    match value.as_u64().and_then(|rate| u32::try_from(rate).ok()) {
+        Some(rate) if rate > 0 => Ok(Some(rate)),
+        _ => anyhow::bail!("expected a positive number of Hz, got {value}"),
+    }
+}
 
+/// Load model weights in the given dtype from the safetensors files, any
+/// number of shards or a single merged file, memory-mapped
+fn load_model_weights<'a>(
+    model_files: &'a [PathBuf],
+    dtype: DType,
+    device: &Device,
+) -> Result<VarBuilder<'a>> {
     // MEMORY OPTIMIZATION: Force garbage collection before loading
-    if let Device::Cuda(_) = device {
+    if !device.is_cpu() {
         device.synchronize()?;
     }
 
@@ -256,13 +1609,40 @@ fn load_model_weights<'a>(model_files: &'a [PathBuf], device: &Device) -> Result
     let vb = unsafe { VarBuilder::from_mmaped_safetensors(model_files, dtype, device)? };
 
     // MEMORY OPTIMIZATION: Force garbage collection after loading
-    if let Device::Cuda(_) = device {
+    if !device.is_cpu() {
         device.synchronize()?;
     }
 
     Ok(vb)
 }
 
+/// Load model weights from a GGUF file, dequantizing them.
+///
+/// The tensors must use the same names as the safetensors checkpoint, which is
+/// what `candle`'s `tensor-tools quantize` produces. The Voxtral implementation
+/// in `candle-transformers` has no quantized forward pass, so every tensor is
+/// dequantized to `dtype` on `device` while loading. This saves downloading
+/// the safetensors shards, but not the memory used at inference time.
+fn load_gguf_weights(
+    gguf_path: &Path,
+    dtype: DType,
+    device: &Device,
+) -> Result<VarBuilder<'static>> {
+    let mut file = std::fs::File::open(gguf_path)
+        .with_context(|| format!("Failed to open {}", gguf_path.display()))?;
+    let content = gguf_file::Content::read(&mut file)
+        .with_context(|| format!("Failed to read GGUF file {}", gguf_path.display()))?;
+
+    let mut tensors = HashMap::with_capacity(content.tensor_infos.len());
+    for name in content.tensor_infos.keys() {
+        let qtensor = content.tensor(&mut file, name, device)?;
+        let tensor = qtensor.dequantize(device)?.to_dtype(dtype)?;
+        tensors.insert(name.clone(), tensor);
+    }
+
+    Ok(VarBuilder::from_tensors(tensors, dtype, device))
+}
+
 /// Load model configuration from JSON file
 fn load_model_config(config_file: &PathBuf) -> Result<VoxtralConfig> {
     let config_str = std::fs::read_to_string(config_file)?;
@@ -290,12 +1670,88 @@ fn load_model_config(config_file: &PathBuf) -> Result<VoxtralConfig> {
         .unwrap_or("gelu")
         .to_string();
 
-    Ok(VoxtralConfig {
+    let config = VoxtralConfig {
         audio_config,
         text_config,
         audio_token_id,
         projector_hidden_act,
-    })
+    };
+    validate_config(&config).context("Invalid config.json")?;
+    Ok(config)
+}
+
+/// Check the invariants the model relies on, so a wrong or mistyped config
+/// fails here with the offending field instead of as a shape mismatch deep
+/// inside the forward pass.
+fn validate_config(config: &VoxtralConfig) -> Result<()> {
+    let audio = &config.audio_config;
+    let text = &config.text_config;
+
+    let non_zero = [
+        ("audio_config.hidden_size", audio.hidden_size),
+        ("audio_config.num_hidden_layers", audio.num_hidden_layers),
+        (
+            "audio_config.num_attention_heads",
+            audio.num_attention_heads,
+        ),
+        ("audio_config.num_mel_bins", audio.num_mel_bins),
+        (
+            "audio_config.max_source_positions",
+            audio.max_source_positions,
+        ),
+        ("audio_config.head_dim", audio.head_dim),
+        ("text_config.vocab_size", text.vocab_size),
+        ("text_config.hidden_size", text.hidden_size),
+        ("text_config.num_hidden_layers", text.num_hidden_layers),
+        ("text_config.num_attention_heads", text.num_attention_heads),
+        ("text_config.num_key_value_heads", text.num_key_value_heads),
+    ];
+    for (field, value) in non_zero {
+        if value == 0 {
+            anyhow::bail!("{field} must be greater than 0");
+        }
+    }
+
+    if !audio.hidden_size.is_multiple_of(audio.num_attention_heads) {
+        anyhow::bail!(
+            "audio_config.hidden_size ({}) must be divisible by audio_config.num_attention_heads ({})",
+            audio.hidden_size,
+            audio.num_attention_heads
+        );
+    }
+    if audio.head_dim * audio.num_attention_heads != audio.hidden_size {
+        anyhow::bail!(
+            "audio_config.head_dim ({}) times audio_config.num_attention_heads ({}) must equal audio_config.hidden_size ({})",
+            audio.head_dim,
+            audio.num_attention_heads,
+            audio.hidden_size
+        );
+    }
+    if !text.hidden_size.is_multiple_of(text.num_attention_heads) {
+        anyhow::bail!(
+            "text_config.hidden_size ({}) must be divisible by text_config.num_attention_heads ({})",
+            text.hidden_size,
+            text.num_attention_heads
+        );
+    }
+    if !text
+        .num_attention_heads
+        .is_multiple_of(text.num_key_value_heads)
+    {
+        anyhow::bail!(
+            "text_config.num_attention_heads ({}) must be divisible by text_config.num_key_value_heads ({})",
+            text.num_attention_heads,
+            text.num_key_value_heads
+        );
+    }
+    if config.audio_token_id >= text.vocab_size {
+        anyhow::bail!(
+            "audio_token_id ({}) must be below text_config.vocab_size ({})",
+            config.audio_token_id,
+            text.vocab_size
+        );
+    }
+    Ok(())
 }
 
 /// Parse audio encoder config from JSON
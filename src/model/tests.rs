@@ -1,4 +1,5 @@
 use super::*;
+use candle_transformers::models::voxtral::VoxtralGenerationConfig;
 use std::fs;
 use tempfile::TempDir;
 
@@ -7,6 +8,9 @@ fn test_transcription_result_serialization() {
     let result = TranscriptionResult {
         text: "Hello, world!".to_string(),
         tokens: vec![1, 2, 3, 4],
+        avg_logprob: -0.25,
+        no_speech_prob: 0.125,
+        truncated: false,
     };
 
     // Test that the result can be serialized
@@ -17,6 +21,8 @@ fn test_transcription_result_serialization() {
     let deserialized: TranscriptionResult = serde_json::from_str(&serialized).unwrap();
     assert_eq!(deserialized.text, "Hello, world!");
     assert_eq!(deserialized.tokens, vec![1, 2, 3, 4]);
+    assert_eq!(deserialized.avg_logprob, -0.25);
+    assert_eq!(deserialized.no_speech_prob, 0.125);
 }
 
 #[test]
@@ -24,6 +30,9 @@ fn test_transcription_result_empty() {
     let result = TranscriptionResult {
         text: "".to_string(),
         tokens: vec![],
+        avg_logprob: 0.0,
+        no_speech_prob: 0.0,
+        truncated: false,
     };
 
     let serialized = serde_json::to_string(&result).unwrap();
@@ -32,6 +41,258 @@ fn test_transcription_result_empty() {
     assert_eq!(deserialized.tokens.len(), 0);
 }
 
+#[test]
+fn test_transcription_result_join() {
+    let joined = TranscriptionResult::join(vec![
+        TranscriptionResult {
+            text: " Hello".to_string(),
+            tokens: vec![1, 2, 3],
+            avg_logprob: -0.5,
+            no_speech_prob: 0.75,
+            truncated: false,
+        },
+        TranscriptionResult {
+            text: "".to_string(),
+            tokens: vec![],
+            avg_logprob: 0.0,
+            no_speech_prob: 0.9,
+            truncated: false,
+        },
+        TranscriptionResult {
+            text: "world ".to_string(),
+            tokens: vec![4],
+            avg_logprob: -0.25,
+            no_speech_prob: 0.125,
+            truncated: true,
+        },
+    ]);
+
+    assert_eq!(joined.text, "Hello world");
+    assert_eq!(joined.tokens, vec![1, 2, 3, 4]);
+    assert_eq!(joined.avg_logprob, -0.4375);
+    assert_eq!(joined.no_speech_prob, 0.125);
+    assert!(joined.truncated);
+}
+
+/// Stream `seconds` of 16 kHz audio in one-second chunks, transcribing
+/// each to its length in samples and counting the calls in `calls`.
+fn counting_stream(seconds: f32, calls: &mut usize) -> TranscriptStream<'_> {
+    let plan = ChunkPlan::new(1.0, 0.0, SAMPLE_RATE).unwrap();
+    let audio = vec![0.0; (seconds * SAMPLE_RATE as f32) as usize];
+    TranscriptStream::new(audio, SAMPLE_RATE, &plan, move |chunk| {
+        *calls += 1;
+        Ok(TranscriptionResult {
+            text: format!(" {} ", chunk.len()),
+            tokens: vec![1],
+            avg_logprob: -0.25,
+            no_speech_prob: 0.5,
+            truncated: false,
+        })
+    })
+}
+
+#[test]
+fn test_transcript_stream_yields_a_segment_per_chunk() {
+    let mut calls = 0;
+    let segments: Vec<Segment> = counting_stream(2.5, &mut calls)
+        .collect::<Result<_>>()
+        .unwrap();
+
+    assert_eq!(calls, 3);
+    assert_eq!(segments.len(), 3);
+    assert_eq!(
+        segments
+            .iter()
+            .map(|segment| (segment.index, segment.start_sec, segment.end_sec))
+            .collect::<Vec<_>>(),
+        [(0, 0.0, 1.0), (1, 1.0, 2.0), (2, 2.0, 2.5)]
+    );
+    assert_eq!(segments[2].text, "8000");
+    assert_eq!(segments[0].avg_logprob, Some(-0.25));
+}
+
+#[test]
+fn test_transcript_stream_is_lazy() {
+    let mut calls = 0;
+    let first = counting_stream(5.0, &mut calls).next().unwrap().unwrap();
+
+    assert_eq!(first.index, 0);
+    assert_eq!(calls, 1);
+}
+
+#[test]
+fn test_transcript_stream_ends_after_an_error() {
+    let plan = ChunkPlan::new(1.0, 0.0, SAMPLE_RATE).unwrap();
+    let mut stream = TranscriptStream::new(
+        vec![0.0; 3 * SAMPLE_RATE as usize],
+        SAMPLE_RATE,
+        &plan,
+        |_| anyhow::bail!("out of memory"),
+    );
+
+    let err = stream.next().unwrap().unwrap_err();
+    assert_eq!(
+        format!("{err:#}"),
+        "Failed to transcribe chunk 1: out of memory"
+    );
+    assert!(stream.next().is_none());
+}
+
+/// Decode attempt that records its temperature and scores it by `score`.
+fn scored_attempt<'a>(
+    temperatures: &'a mut Vec<f32>,
+    score: impl Fn(f32) -> f32 + 'a,
+) -> impl FnMut(f32) -> Result<TranscriptionResult> + 'a {
+    move |temperature| {
+        temperatures.push(temperature);
+        Ok(TranscriptionResult {
+            text: format!("at {temperature}"),
+            tokens: vec![1],
+            avg_logprob: score(temperature),
+            no_speech_prob: 0.0,
+            truncated: false,
+        })
+    }
+}
+
+#[test]
+fn test_decode_with_fallback_single_attempt() {
+    let mut temperatures = Vec::new();
+    let options = TranscribeOptions::default();
+    decode_with_fallback(&options, scored_attempt(&mut temperatures, |_| -5.0)).unwrap();
+    assert_eq!(temperatures, [0.0]);
+
+    let mut temperatures = Vec::new();
+    let options = TranscribeOptions {
+        decode_strategy: DecodeStrategy::Sample,
+        temperature: 0.7,
+        ..TranscribeOptions::default()
+    };
+    decode_with_fallback(&options, scored_attempt(&mut temperatures, |_| -5.0)).unwrap();
+    assert_eq!(temperatures, [0.7]);
+}
+
+#[test]
+fn test_decode_with_fallback_escalates_until_confident() {
+    let options = TranscribeOptions {
+        decode_strategy: DecodeStrategy::TemperatureFallback,
+        ..TranscribeOptions::default()
+    };
+
+    // Confident greedy output is kept without retrying
+    let mut temperatures = Vec::new();
+    let result =
+        decode_with_fallback(&options, scored_attempt(&mut temperatures, |_| -0.2)).unwrap();
+    assert_eq!(temperatures, [0.0]);
+    assert_eq!(result.text, "at 0");
+
+    // Retries stop at the first attempt reaching the threshold
+    let mut temperatures = Vec::new();
+    let result = decode_with_fallback(
+        &options,
+        scored_attempt(&mut temperatures, |t| if t >= 0.4 { -0.5 } else { -2.0 }),
+    )
+    .unwrap();
+    assert_eq!(temperatures, [0.0, 0.2, 0.4]);
+    assert_eq!(result.text, "at 0.4");
+}
+
+#[test]
+fn test_decode_with_fallback_keeps_the_best_attempt() {
+    let options = TranscribeOptions {
+        decode_strategy: DecodeStrategy::TemperatureFallback,
+        fallback_temperatures: vec![0.3, 0.9],
+        ..TranscribeOptions::default()
+    };
+    let mut temperatures = Vec::new();
+    let result = decode_with_fallback(
+        &options,
+        scored_attempt(&mut temperatures, |t| if t == 0.3 { -1.5 } else { -3.0 }),
+    )
+    .unwrap();
+
+    assert_eq!(temperatures, [0.0, 0.3, 0.9]);
+    assert_eq!(result.text, "at 0.3");
+    assert_eq!(result.avg_logprob, -1.5);
+}
+
+#[test]
+fn test_encode_then_decode_encodes_once() {
+    let options = TranscribeOptions {
+        decode_strategy: DecodeStrategy::TemperatureFallback,
+        fallback_temperatures: vec![0.5],
+        ..TranscribeOptions::default()
+    };
+    let mut encodes = 0;
+    let mut temperatures = Vec::new();
+    let mut decode = scored_attempt(&mut temperatures, |t| if t == 0.0 { -3.0 } else { -0.5 });
+
+    let result = encode_then_decode(
+        &options,
+        || {
+            encodes += 1;
+            Ok("features")
+        },
+        |features: &&str, temperature| {
+            assert_eq!(*features, "features");
+            decode(temperature)
+        },
+    )
+    .unwrap();
+    drop(decode);
+
+    assert_eq!(encodes, 1);
+    assert_eq!(temperatures, [0.0, 0.5]);
+    assert_eq!(result.text, "at 0.5");
+
+    // A failing encoder is not decoded
+    let err = encode_then_decode(
+        &options,
+        || -> Result<()> { anyhow::bail!("out of memory") },
+        |_, _| unreachable!(),
+    )
+    .unwrap_err();
+    assert_eq!(err.to_string(), "out of memory");
+}
+
+#[test]
+fn test_non_speech_tokens() {
+    // A toy tokenizer: single ASCII symbols and " -" are one token, music
+    // notes two, and everything else one token per character
+    let encode = |text: &str| -> Option<Vec<u32>> {
+        match text {
+            "(" => Some(vec![40]),
+            " (" => Some(vec![1040]),
+            " -" => Some(vec![1045]),
+            "♪" => Some(vec![9000, 9001]),
+            _ => Some(text.chars().map(|c| c as u32).collect()),
+        }
+    };
+
+    let ids = non_speech_tokens(encode);
+
+    assert!(ids.contains(&40) && ids.contains(&1040) && ids.contains(&1045));
+    // First token of a multi-token music symbol
+    assert!(ids.contains(&9000) && !ids.contains(&9001));
+    // Single-token symbols count, "((" spelled with two "(" tokens does not
+    assert!(ids.contains(&('#' as u32)) && !ids.contains(&('a' as u32)));
+    assert_eq!(ids.iter().filter(|&&id| id == 40).count(), 1);
+    assert!(ids.windows(2).all(|pair| pair[0] < pair[1]));
+    assert!(non_speech_tokens(|_| None).is_empty());
+}
+
+#[test]
+fn test_check_suppress_tokens() {
+    assert!(check_suppress_tokens(&[], 100).is_ok());
+    assert!(check_suppress_tokens(&[0, 99], 100).is_ok());
+    assert_eq!(
+        check_suppress_tokens(&[5, 100, 250], 100)
+            .unwrap_err()
+            .to_string(),
+        "--suppress-tokens 100,250 outside the vocabulary of 100 tokens (ids 0 to 99)"
+    );
+}
+
 #[test]
 fn test_voxtral_config_validation() {
     // Test that configuration parsing works with valid JSON
@@ -117,12 +378,22 @@ fn test_transcribe_with_voxtral_input_validation() {
     // Audio features must be 3D tensor
     // This test documents the expected input shape
 
-    let audio_dims = vec![1, 128, 1000]; // [batch, mels, time]
+    let audio_dims = [1, 128, 1000]; // [batch, mels, time]
 
     assert_eq!(audio_dims.len(), 3, "Audio features must be 3D");
     assert_eq!(audio_dims[1], 128, "Must have 128 mel bins");
 }
 
+#[test]
+fn test_check_finite_rejects_nan_audio() {
+    assert!(check_finite(&[0.0, -0.5, 1.0]).is_ok());
+
+    let err = check_finite(&[0.0, f32::NAN, f32::INFINITY]).unwrap_err();
+    assert!(err
+        .to_string()
+        .starts_with("Audio holds 2 NaN or infinite samples"));
+}
+
 #[test]
 fn test_pcm_resample_call() {
     // Test that transcribe_audio calls resample when needed
@@ -159,24 +430,34 @@ fn test_pcm_no_resample_needed() {
 }
 
 #[test]
-fn test_padded_audio_chunk_size() {
-    // Test that audio is padded to 30-second chunks (480000 samples)
-    let chunk_size = 480000; // 30 seconds * 16000 Hz
-    let audio = vec![0.5; 100000]; // 100k samples
+fn test_padded_len_whole_windows() {
+    let window = mel::window_samples(1500, 16_000);
+    assert_eq!(window, 480_000);
+
+    assert_eq!(padded_len(100_000, window), 480_000);
+    assert_eq!(padded_len(480_000, window), 480_000);
+    assert_eq!(padded_len(480_001, window), 960_000);
+    assert_eq!(padded_len(0, window), 480_000);
+}
 
-    let padded_audio = if audio.len() % chunk_size != 0 {
-        let target_samples = ((audio.len() / chunk_size) + 1) * chunk_size;
-        let mut padded = audio.clone();
-        padded.resize(target_samples, 0.0);
-        padded
-    } else {
-        audio
-    };
+#[test]
+fn test_padded_len_follows_config_source_positions() {
+    let temp_dir = TempDir::new().unwrap();
+    let config_path = temp_dir.path().join("config.json");
+    fs::write(
+        &config_path,
+        serde_json::json!({ "audio_config": { "max_source_positions": 750 }, "text_config": {} })
+            .to_string(),
+    )
+    .unwrap();
 
-    assert_eq!(padded_audio.len() % chunk_size, 0);
-    assert!(padded_audio.len() >= chunk_size);
-}
+    let config = load_model_config(&config_path).unwrap();
+    let window = mel::window_samples(config.audio_config.max_source_positions, 16_000);
 
+    assert_eq!(window, 240_000);
+    assert_eq!(padded_len(100_000, window), 240_000);
+    assert_eq!(padded_len(300_000, window), 480_000);
+}
 #[test]
 fn test_token_sequence_construction() {
     // Test that the expected token sequence is constructed correctly
@@ -212,6 +493,127 @@ fn test_token_sequence_construction() {
     assert_eq!(input_tokens[379], 9909); // lang
 }
 
+#[test]
+fn test_audio_tokens_follow_config_source_positions() {
+    assert_eq!(audio_token_count(2, 1500), 750);
+
+    let temp_dir = TempDir::new().unwrap();
+    let config_path = temp_dir.path().join("config.json");
+    fs::write(
+        &config_path,
+        serde_json::json!({ "audio_config": { "max_source_positions": 1000 }, "text_config": {} })
+            .to_string(),
+    )
+    .unwrap();
+    let config = load_model_config(&config_path).unwrap();
+    let positions = config.audio_config.max_source_positions;
+
+    // Two encoder positions per mel frame, four positions per audio token
+    assert_eq!(
+        audio_token_count(1, positions),
+        mel::window_frames(positions) / 2 / 4
+    );
+    let prompt = audio_prompt(audio_token_count(2, positions), &voxtral_tokens());
+    assert_eq!(prompt.iter().filter(|&&t| t == 24).count(), 500);
+}
+
+#[test]
+fn test_audio_prompt_layout() {
+    let prompt = audio_prompt(2 * 375, &voxtral_tokens());
+
+    assert_eq!(prompt.len(), 3 + 2 * 375 + 1);
+    assert_eq!(&prompt[..3], &[1, 3, 25]);
+    assert!(prompt[3..753].iter().all(|&t| t == 24));
+    assert_eq!(prompt[753], 4);
+}
+
+#[test]
+fn test_transcription_prompt_places_prompt_after_inst() {
+    let prompt = transcription_prompt(375, &voxtral_tokens(), &[500, 501], &[1262]);
+
+    let tail = &prompt[378..];
+    assert_eq!(prompt[378], 4); // [/INST]
+    assert_eq!(tail, &[4, 500, 501, 9909, 1058, 1262, 34]);
+}
+
+#[test]
+fn test_transcription_prompt_without_prompt_matches_reference() {
+    let prompt = transcription_prompt(375, &voxtral_tokens(), &[], &[1262]);
+
+    assert_eq!(prompt.len(), 383);
+    assert_eq!(&prompt[378..], &[4, 9909, 1058, 1262, 34]);
+}
+
+#[test]
+fn test_decoder_context_keeps_the_end() {
+    assert_eq!(decoder_context(vec![500, 501], &[]), [500, 501]);
+    assert_eq!(decoder_context(vec![500], &[600, 601]), [500, 600, 601]);
+
+    let previous: Vec<u32> = (0..MAX_PROMPT_TOKENS as u32).collect();
+    let context = decoder_context(vec![500, 501], &previous);
+    assert_eq!(context.len(), MAX_PROMPT_TOKENS);
+    assert_eq!(context, previous);
+}
+
+#[test]
+fn test_context_text_puts_the_verbatim_example_first() {
+    assert_eq!(context_text(None, false), None);
+    assert_eq!(
+        context_text(Some("Kitsune"), false).as_deref(),
+        Some("Kitsune")
+    );
+    assert_eq!(context_text(None, true).as_deref(), Some(VERBATIM_PROMPT));
+    assert_eq!(
+        context_text(Some("Kitsune"), true).as_deref(),
+        Some(format!("{VERBATIM_PROMPT} Kitsune").as_str())
+    );
+    assert!(VERBATIM_PROMPT.contains("uh") && VERBATIM_PROMPT.contains("we- we"));
+}
+
+#[test]
+fn test_verbatim_example_only_for_english() {
+    let options = TranscribeOptions {
+        verbatim: true,
+        ..TranscribeOptions::default()
+    };
+    assert_eq!(options.language, VERBATIM_LANGUAGE);
+    assert!(verbatim_example(&options));
+
+    let german = TranscribeOptions {
+        language: "de".to_string(),
+        ..options
+    };
+    assert!(!verbatim_example(&german));
+    assert!(!verbatim_example(&TranscribeOptions::default()));
+}
+
+#[test]
+fn test_rank_languages_orders_by_probability() {
+    let mut logits = vec![0.0f32; 10];
+    logits[3] = 2.0; // en
+    logits[5] = 4.0; // fr
+    logits[7] = 1.0; // de
+
+    let ranked = rank_languages(&logits, &[("en", 3), ("fr", 5), ("de", 7)]);
+
+    let order: Vec<&str> = ranked.iter().map(|l| l.language.as_str()).collect();
+    assert_eq!(order, ["fr", "en", "de"]);
+    let total: f32 = ranked.iter().map(|l| l.probability).sum();
+    assert!((total - 1.0).abs() < 1e-6);
+    assert!((ranked[0].probability - 0.8438).abs() < 1e-3);
+}
+
+#[test]
+fn test_rank_languages_out_of_vocab_candidate() {
+    let logits = [1.0f32, 1.0];
+
+    let ranked = rank_languages(&logits, &[("en", 0), ("xx", 99)]);
+
+    assert_eq!(ranked[0].language, "en");
+    assert_eq!(ranked[0].probability, 1.0);
+    assert_eq!(ranked[1].probability, 0.0);
+}
+
 #[test]
 fn test_generation_config_parameters() {
     // Test that generation config uses expected parameters
@@ -231,13 +633,78 @@ fn test_generation_config_parameters() {
 #[test]
 fn test_tensor_shape_validation() {
     // Test the validation logic for audio features
-    let dims = vec![2, 128, 500]; // Valid: [batch, mels, time]
+    let dims = [2, 128, 500]; // Valid: [batch, mels, time]
 
     assert!(
         dims.len() == 3,
         "Audio features must be 3D tensor (batch, mels, time)"
     );
     assert_eq!(dims[1], 128, "Audio features must have 128 mel bins");
+
+    let device = Device::Cpu;
+    let features = Tensor::zeros((2, 80, 500), DType::F32, &device).unwrap();
+    assert!(validate_audio_features(&features, 80).is_ok());
+    let err = validate_audio_features(&features, 128).unwrap_err();
+    assert!(err.to_string().contains("must have 128 mel bins, got 80"));
+    let flat = Tensor::zeros((80, 500), DType::F32, &device).unwrap();
+    assert!(validate_audio_features(&flat, 80).is_err());
+}
+
+#[test]
+fn test_probe_gpus_stops_at_first_failure() {
+    let devices = probe_gpus("cuda", true, |ordinal| {
+        if ordinal < 2 {
+            Ok(Device::Cpu)
+        } else {
+            Err(candle_core::Error::Msg(
+                "invalid device ordinal".to_string(),
+            ))
+        }
+    });
+
+    let names: Vec<&str> = devices.iter().map(|device| device.name.as_str()).collect();
+    assert_eq!(names, ["cuda:0", "cuda:1"]);
+    assert!(devices
+        .iter()
+        .all(|device| device.state == DeviceState::Available));
+}
+
+#[test]
+fn test_probe_gpus_reports_why_none_works() {
+    let devices = probe_gpus("cuda", true, |_| {
+        Err(candle_core::Error::Msg(
+            "no CUDA-capable device".to_string(),
+        ))
+    });
+    assert_eq!(
+        devices,
+        [DeviceReport {
+            name: "cuda".to_string(),
+            state: DeviceState::Failed("no CUDA-capable device".to_string()),
+        }]
+    );
+    assert_eq!(
+        devices[0].to_string(),
+        "cuda     unavailable: no CUDA-capable device"
+    );
+
+    let devices = probe_gpus("metal", true, |_| panic!("driver crashed"));
+    assert!(matches!(devices[0].state, DeviceState::Failed(_)));
+
+    let devices = probe_gpus("metal", false, |_| Ok(Device::Cpu));
+    assert_eq!(devices[0].state, DeviceState::NotBuilt);
+    assert_eq!(
+        devices[0].to_string(),
+        "metal    not in this build, rebuild with --features metal"
+    );
+}
+
+#[test]
+fn test_list_devices_starts_with_cpu() {
+    let devices = list_devices();
+
+    assert_eq!(devices[0].name, "cpu");
+    assert!(devices[0].to_string().starts_with("cpu      available, "));
 }
 
 #[test]
@@ -303,12 +770,12 @@ fn test_load_model_weights_memory_mapping() {
     // Test that the function signature accepts the expected parameters
     // Memory-mapped loading is used for efficiency
 
-    let model_files = vec![
+    let model_files = [
         std::path::PathBuf::from("model-00001-of-00002.safetensors"),
         std::path::PathBuf::from("model-00002-of-00002.safetensors"),
     ];
-    let device = &Device::Cpu;
-    let dtype = DType::F16;
+    let _device = &Device::Cpu;
+    let _dtype = DType::F16;
 
     // Verify we have the expected number of files
     assert_eq!(model_files.len(), 2);
@@ -395,28 +862,318 @@ fn test_parse_audio_config_defaults() {
 }
 
 #[test]
-fn test_slice_operations_on_files() {
-    // Test the slicing logic: files[1..files.len() - 1]
-    let files = vec![
-        std::path::PathBuf::from("config.json"),
-        std::path::PathBuf::from("model-00001.safetensors"),
-        std::path::PathBuf::from("model-00002.safetensors"),
-        std::path::PathBuf::from("tekken.json"),
+fn test_select_device_forced_cpu() {
+    let device = select_device(true, false, 0).unwrap();
+    assert!(device.is_cpu());
+}
+
+#[test]
+fn test_select_device_require_gpu_rejects_cpu() {
+    assert!(select_device(true, true, 0).is_err());
+}
+
+#[test]
+fn test_select_device_without_gpu_features() {
+    if !utils::cuda_is_available() && !utils::metal_is_available() {
+        let device = select_device(false, false, 0).unwrap();
+        assert!(device.is_cpu());
+        assert!(select_device(false, true, 0).is_err());
+    }
+}
+
+#[test]
+fn test_resolve_dtype_defaults_per_device() {
+    assert_eq!(resolve_dtype(None, &Device::Cpu).unwrap(), DType::F32);
+}
+
+#[test]
+fn test_resolve_dtype_explicit_on_cpu() {
+    assert_eq!(
+        resolve_dtype(Some(ModelDType::F16), &Device::Cpu).unwrap(),
+        DType::F16
+    );
+    assert_eq!(
+        resolve_dtype(Some(ModelDType::F32), &Device::Cpu).unwrap(),
+        DType::F32
+    );
+}
+
+#[test]
+fn test_resolve_dtype_rejects_bf16_on_cpu() {
+    let err = resolve_dtype(Some(ModelDType::Bf16), &Device::Cpu).unwrap_err();
+    assert!(err.to_string().contains("BF16"));
+}
+
+#[test]
+fn test_load_gguf_weights_dequantizes() {
+    use candle_core::quantized::{GgmlDType, QTensor};
+
+    let temp_dir = TempDir::new().unwrap();
+    let gguf_path = temp_dir.path().join("weights.gguf");
+
+    let weight = Tensor::arange(0f32, 64f32, &Device::Cpu)
+        .unwrap()
+        .reshape((2, 32))
+        .unwrap();
+    let qtensor = QTensor::quantize(&weight, GgmlDType::Q8_0).unwrap();
+    let mut file = fs::File::create(&gguf_path).unwrap();
+    gguf_file::write(&mut file, &[], &[("layer.weight", &qtensor)]).unwrap();
+    drop(file);
+
+    let vb = load_gguf_weights(&gguf_path, DType::F32, &Device::Cpu).unwrap();
+    let loaded = vb.get((2, 32), "layer.weight").unwrap();
+
+    assert_eq!(loaded.dtype(), DType::F32);
+    let values = loaded.flatten_all().unwrap().to_vec1::<f32>().unwrap();
+    assert!((values[63] - 63.0).abs() < 1.0);
+}
+
+#[test]
+fn test_load_gguf_weights_missing_file() {
+    let result = load_gguf_weights(
+        Path::new("nonexistent_weights_12345.gguf"),
+        DType::F32,
+        &Device::Cpu,
+    );
+    assert!(result.is_err());
+}
+
+fn write_config(dir: &TempDir, config: serde_json::Value) -> PathBuf {
+    let config_path = dir.path().join("config.json");
+    fs::write(&config_path, config.to_string()).unwrap();
+    config_path
+}
+
+#[test]
+fn test_load_model_config_accepts_defaults() {
+    let temp_dir = TempDir::new().unwrap();
+    let config_path = write_config(
+        &temp_dir,
+        serde_json::json!({ "audio_config": {}, "text_config": {} }),
+    );
+
+    let config = load_model_config(&config_path).unwrap();
+    assert_eq!(config.audio_config.num_mel_bins, 128);
+}
+
+#[test]
+fn test_load_model_config_names_invalid_field() {
+    let cases = [
+        (
+            serde_json::json!({ "audio_config": { "num_attention_heads": 7 }, "text_config": {} }),
+            "audio_config.hidden_size (1280) must be divisible by audio_config.num_attention_heads (7)",
+        ),
+        (
+            serde_json::json!({ "audio_config": { "num_hidden_layers": 0 }, "text_config": {} }),
+            "audio_config.num_hidden_layers must be greater than 0",
+        ),
+        (
+            serde_json::json!({ "audio_config": { "head_dim": 128 }, "text_config": {} }),
+            "audio_config.head_dim (128)",
+        ),
+        (
+            serde_json::json!({ "audio_config": {}, "text_config": { "num_key_value_heads": 5 } }),
+            "text_config.num_attention_heads (32) must be divisible by text_config.num_key_value_heads (5)",
+        ),
+        (
+            serde_json::json!({ "audio_config": {}, "text_config": { "hidden_size": 3000 } }),
+            "text_config.hidden_size (3000)",
+        ),
+        (
+            serde_json::json!({ "audio_token_id": 200000, "audio_config": {}, "text_config": {} }),
+            "audio_token_id (200000) must be below text_config.vocab_size (131072)",
+        ),
     ];
 
-    let safetensors_slice = &files[1..files.len() - 1];
+    for (config, expected) in cases {
+        let temp_dir = TempDir::new().unwrap();
+        let config_path = write_config(&temp_dir, config);
+        let err = format!("{:#}", load_model_config(&config_path).unwrap_err());
+        assert!(
+            err.contains(expected),
+            "{err:?} does not mention {expected:?}"
+        );
+    }
+}
+
+/// Control token names and ids of Voxtral Mini 3B.
+fn voxtral_vocab() -> HashMap<&'static str, u32> {
+    HashMap::from([
+        ("<s>", 1),
+        ("</s>", 2),
+        ("[INST]", 3),
+        ("[/INST]", 4),
+        ("[AUDIO]", 24),
+        ("[BEGIN_AUDIO]", 25),
+        ("[TRANSCRIBE]", 34),
+    ])
+}
+
+fn voxtral_tokens() -> SpecialTokens {
+    let vocab = voxtral_vocab();
+    let (tokens, fallbacks) =
+        SpecialTokens::resolve(24, |name| vocab.get(name).copied(), Some(vec![9909, 1058]));
+    assert!(fallbacks.is_empty());
+    tokens
+}
+
+fn decode_with<'a>(vocab: &'a HashMap<&'static str, u32>) -> impl Fn(u32) -> Option<String> + 'a {
+    move |id| {
+        vocab
+            .iter()
+            .find(|(_, &v)| v == id)
+            .map(|(name, _)| name.to_string())
+    }
+}
 
-    assert_eq!(safetensors_slice.len(), 2);
-    assert!(safetensors_slice[0]
-        .to_string_lossy()
-        .contains("model-00001"));
-    assert!(safetensors_slice[1]
-        .to_string_lossy()
-        .contains("model-00002"));
+#[test]
+fn test_special_tokens_resolve_from_tokenizer() {
+    // A revision where every control token moved
+    let vocab: HashMap<&str, u32> = voxtral_vocab()
+        .into_iter()
+        .map(|(name, id)| (name, id + 100))
+        .collect();
+
+    let (tokens, fallbacks) =
+        SpecialTokens::resolve(124, |name| vocab.get(name).copied(), Some(vec![7, 8]));
+
+    assert!(fallbacks.is_empty());
+    assert_eq!(tokens.bos, 101);
+    assert_eq!(tokens.transcribe, 134);
+    assert_eq!(tokens.lang_prefix, vec![7, 8]);
+    assert_eq!(tokens.eos_tokens(), vec![102, 128001, 128009, 128256]);
+    assert_eq!(
+        transcription_prompt(375, &tokens, &[], &[1262])[375 + 3..],
+        [104, 7, 8, 1262, 134]
+    );
+}
 
-    let config = files.first().unwrap();
-    assert!(config.to_string_lossy().ends_with("config.json"));
+#[test]
+fn test_special_tokens_fall_back_to_voxtral_ids() {
+    let (tokens, fallbacks) = SpecialTokens::resolve(24, |_| None, None);
+
+    assert_eq!(tokens, {
+        let vocab = voxtral_vocab();
+        SpecialTokens::resolve(24, |name| vocab.get(name).copied(), Some(vec![9909, 1058])).0
+    });
+    assert_eq!(
+        fallbacks,
+        [
+            "<s>",
+            "</s>",
+            "[INST]",
+            "[/INST]",
+            "[BEGIN_AUDIO]",
+            "[TRANSCRIBE]",
+            "lang:"
+        ]
+    );
+}
+
+#[test]
+fn test_check_tokenizer_accepts_voxtral_ids() {
+    let vocab = voxtral_vocab();
+    let result = check_tokenizer(&voxtral_tokens(), &[], decode_with(&vocab));
+    assert!(result.is_ok(), "{result:?}");
+}
 
-    let tokenizer = files.last().unwrap();
-    assert!(tokenizer.to_string_lossy().ends_with("tekken.json"));
+#[test]
+fn test_check_tokenizer_reports_mismatches() {
+    // A tokenizer where [INST] decodes as [/INST] and [AUDIO] is absent
+    let mut vocab = voxtral_vocab();
+    vocab.insert("[/INST]", 3);
+    vocab.remove("[INST]");
+    vocab.remove("[AUDIO]");
+
+    let err = check_tokenizer(&voxtral_tokens(), &[], decode_with(&vocab))
+        .unwrap_err()
+        .to_string();
+
+    assert!(
+        err.contains("id 3 decodes to Some(\"[/INST]\") instead of [INST]"),
+        "{err}"
+    );
+    assert!(
+        err.contains("id 24 decodes to None instead of [AUDIO]"),
+        "{err}"
+    );
+}
+
+#[test]
+fn test_check_tokenizer_skips_fallbacks() {
+    let mut vocab = voxtral_vocab();
+    vocab.remove("[TRANSCRIBE]");
+
+    let result = check_tokenizer(&voxtral_tokens(), &["[TRANSCRIBE]"], decode_with(&vocab));
+    assert!(result.is_ok(), "{result:?}");
+}
+
+#[test]
+fn test_cpu_threads_are_set_once() {
+    // The count candle already uses, so other tests keep their speed
+    let threads = utils::get_num_threads();
+
+    assert!(set_cpu_threads(0).is_err());
+    set_cpu_threads(threads).unwrap();
+    set_cpu_threads(threads).unwrap();
+    assert_eq!(rayon::current_num_threads(), threads);
+
+    let err = set_cpu_threads(threads + 1).unwrap_err().to_string();
+    assert!(
+        err.contains(&format!("already runs on {threads} threads")),
+        "{err}"
+    );
+}
+
+#[test]
+fn test_load_single_merged_safetensors() {
+    let temp_dir = TempDir::new().unwrap();
+    for name in ["config.json", "tekken.json"] {
+        fs::write(temp_dir.path().join(name), "{}").unwrap();
+    }
+    let weights = temp_dir.path().join("model.safetensors");
+    let tensors = HashMap::from([
+        (
+            "encoder.w".to_string(),
+            Tensor::new(&[1f32, 2.0], &Device::Cpu).unwrap(),
+        ),
+        (
+            "decoder.w".to_string(),
+            Tensor::new(&[3f32], &Device::Cpu).unwrap(),
+        ),
+    ]);
+    candle_core::safetensors::save(&tensors, &weights).unwrap();
+
+    let paths = download::model_files(temp_dir.path(), false).unwrap();
+    assert_eq!(paths.weights, [weights]);
+
+    let vb = load_model_weights(&paths.weights, DType::F32, &Device::Cpu).unwrap();
+    let encoder = vb.get(2, "encoder.w").unwrap();
+    assert_eq!(encoder.to_vec1::<f32>().unwrap(), [1.0, 2.0]);
+    assert!(vb.contains_tensor("decoder.w"));
+}
+
+#[test]
+fn test_expected_sample_rate_from_config() {
+    let temp_dir = TempDir::new().unwrap();
+    assert_eq!(expected_sample_rate(temp_dir.path()).unwrap(), SAMPLE_RATE);
+
+    let config = temp_dir.path().join("config.json");
+    fs::write(&config, r#"{"audio_token_id": 24}"#).unwrap();
+    assert_eq!(expected_sample_rate(temp_dir.path()).unwrap(), SAMPLE_RATE);
+    fs::write(&config, r#"{"audio_config": {"sampling_rate": 8000}}"#).unwrap();
+    assert_eq!(expected_sample_rate(temp_dir.path()).unwrap(), 8000);
+
+    // The feature extractor settings win over the model configuration
+    let preprocessor = temp_dir.path().join(PREPROCESSOR_CONFIG_FILE);
+    fs::write(&preprocessor, r#"{"sampling_rate": 24000}"#).unwrap();
+    assert_eq!(expected_sample_rate(temp_dir.path()).unwrap(), 24000);
+
+    fs::write(&preprocessor, r#"{"sampling_rate": 0}"#).unwrap();
+    let err = format!("{:#}", expected_sample_rate(temp_dir.path()).unwrap_err());
+    assert!(err.contains("Unusable sampling_rate"), "{err}");
+    assert!(
+        err.contains("expected a positive number of Hz, got 0"),
+        "{err}"
+    );
 }
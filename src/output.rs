@@ -0,0 +1,922 @@
+use anyhow::{Context, Result};
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Component, Path, PathBuf};
+
+use crate::timeline::WallClock;
+
+#[cfg(test)]
+mod tests;
+
+/// Segments shared by the tests of several modules and of the binary, which
+/// can only reach public items of the library.
+#[doc(hidden)]
+pub mod fixtures {
+    use super::Segment;
+
+    /// Segment `index` of 15-second chunks starting every 13.5 seconds, with
+    /// the `avg_logprob` of transcribed text or `None` for a placeholder.
+    pub fn segment(index: usize, text: &str, avg_logprob: Option<f32>) -> Segment {
+        Segment {
+            index,
+            start_sec: index as f32 * 13.5,
+            end_sec: index as f32 * 13.5 + 15.0,
+            text: text.to_string(),
+            avg_logprob,
+            no_speech_prob: avg_logprob.map(|_| 0.01),
+            speaker: None,
+            language: None,
+            failed: false,
+        }
+    }
+}
+
+/// File format of the written transcript.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum OutputFormat {
+    /// One line of plain text per chunk.
+    Txt,
+    /// A JSON array of segments with timings and confidence.
+    Json,
+    /// One JSON segment per line, written as each chunk completes.
+    Jsonl,
+    /// SubRip subtitles, one cue per chunk.
+    Srt,
+    /// A CSV table of `start_sec,end_sec,text`, one row per chunk.
+    Csv,
+    /// Markdown, one paragraph per chunk led by its `**[MM:SS]**` start time.
+    Md,
+    /// One `start_sec<TAB>end_sec<TAB>text` line per chunk, flushed as soon
+    /// as the chunk completes, for live captioning displays.
+    Live,
+}
+
+impl OutputFormat {
+    /// File extension used for this format.
+    pub fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::Txt => "txt",
+            OutputFormat::Json => "json",
+            OutputFormat::Jsonl => "jsonl",
+            OutputFormat::Srt => "srt",
+            OutputFormat::Csv => "csv",
+            OutputFormat::Md => "md",
+            OutputFormat::Live => "live.tsv",
+        }
+    }
+
+    /// Whether the format is written chunk by chunk rather than at the end.
+    pub fn is_streamed(self) -> bool {
+        matches!(
+            self,
+            OutputFormat::Txt | OutputFormat::Jsonl | OutputFormat::Live
+        )
+    }
+}
+
+/// Formats selected with `--format`: a comma-separated list such as
+/// `txt,srt,json`, or `all` for txt, srt and json.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FormatList(pub Vec<OutputFormat>);
+
+impl std::str::FromStr for FormatList {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        use clap::ValueEnum;
+
+        if s.trim().eq_ignore_ascii_case("all") {
+            return Ok(Self(vec![
+                OutputFormat::Txt,
+                OutputFormat::Srt,
+                OutputFormat::Json,
+            ]));
+        }
+        let mut formats = Vec::new();
+        for name in s.split(',').map(str::trim) {
+            let format = OutputFormat::from_str(name, true).map_err(|_| {
+                format!(
+                    "unknown format `{name}`, expected txt, json, jsonl, srt, csv, md, live or all"
+                )
+            })?;
+            if !formats.contains(&format) {
+                formats.push(format);
+            }
+        }
+        Ok(Self(formats))
+    }
+}
+
+/// Line ending written after each line of text output.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum LineEnding {
+    /// `\n`, as on Linux and macOS.
+    #[default]
+    Lf,
+    /// `\r\n`, as expected by many Windows tools.
+    Crlf,
+}
+
+impl LineEnding {
+    /// The bytes that end a line.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::Crlf => "\r\n",
+        }
+    }
+}
+
+/// UTF-8 byte order mark, written at the start of text output with `--bom`.
+pub const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+
+/// Writes text output line by line with a fixed line ending.
+pub struct TextWriter<W: Write> {
+    inner: W,
+    line_ending: LineEnding,
+}
+
+impl<W: Write> TextWriter<W> {
+    /// Wrap `inner`, first writing the UTF-8 byte order mark when `bom` is set.
+    ///
+    /// Leave `bom` unset when appending to a file that already has content.
+    pub fn new(mut inner: W, line_ending: LineEnding, bom: bool) -> io::Result<Self> {
+        if bom {
+            inner.write_all(UTF8_BOM)?;
+        }
+        Ok(Self { inner, line_ending })
+    }
+
+    /// Write `line` followed by the line ending. Line breaks inside `line`
+    /// are converted to the same line ending.
+    ///
+    /// The line is handed to `inner` in a single write, so a flushed file
+    /// never ends in the middle of a line unless the process dies during
+    /// that write.
+    pub fn write_line(&mut self, line: &str) -> io::Result<()> {
+        let ending = self.line_ending.as_str();
+        let mut buf = String::with_capacity(line.len() + ending.len());
+        for (i, part) in line.split('\n').enumerate() {
+            if i > 0 {
+                buf.push_str(ending);
+            }
+            buf.push_str(part.strip_suffix('\r').unwrap_or(part));
+        }
+        buf.push_str(ending);
+        self.inner.write_all(buf.as_bytes())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+}
+
+/// A transcribed span of audio.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Segment {
+    /// Position of the chunk in the input, counting skipped chunks.
+    pub index: usize,
+    pub start_sec: f32,
+    pub end_sec: f32,
+    pub text: String,
+    /// Mean token log-probability; absent when the model did not run.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub avg_logprob: Option<f32>,
+    /// Probability that the chunk holds no speech; absent when the model did not run.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub no_speech_prob: Option<f32>,
+    /// Who is speaking, such as `Speaker A` with `--diarize-by-channel`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub speaker: Option<String>,
+    /// Language the chunk was detected in and transcribed in, such as `fr`,
+    /// with `--language-per-chunk`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    /// Set when every attempt to transcribe the chunk failed; the text is a
+    /// placeholder then.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub failed: bool,
+}
+
+/// A segment as written to JSON, with the wall-clock times of its start
+/// and end when a [`WallClock`] is given.
+#[derive(serde::Serialize)]
+struct TimedSegment<'a> {
+    #[serde(flatten)]
+    segment: &'a Segment,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    start_time: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    end_time: Option<String>,
+}
+
+impl<'a> TimedSegment<'a> {
+    fn new(segment: &'a Segment, wall_clock: Option<&WallClock>) -> Self {
+        Self {
+            segment,
+            start_time: wall_clock.map(|clock| clock.at(segment.start_sec)),
+            end_time: wall_clock.map(|clock| clock.at(segment.end_sec)),
+        }
+    }
+}
+
+/// Write `segments` to `path` as a pretty-printed JSON array, adding
+/// `start_time` and `end_time` to each when `wall_clock` is given.
+pub fn write_json(path: &Path, segments: &[Segment], wall_clock: Option<&WallClock>) -> Result<()> {
+    let file = File::create(path).context("Failed to create output file for transcription")?;
+    let timed: Vec<TimedSegment> = segments
+        .iter()
+        .map(|segment| TimedSegment::new(segment, wall_clock))
+        .collect();
+    serde_json::to_writer_pretty(BufWriter::new(file), &timed)
+        .context("Failed to write JSON transcription")?;
+    Ok(())
+}
+
+/// Token ids the model generated for one chunk, as written by
+/// `--dump-tokens`.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct ChunkTokens {
+    /// Position of the chunk in the input, counting skipped chunks.
+    pub index: usize,
+    pub start_sec: f32,
+    pub end_sec: f32,
+    /// Generated tokens, excluding the prompt and the terminating EOS token.
+    pub tokens: Vec<u32>,
+}
+
+/// Write the tokens of `chunks` to `path` as a JSON array with one object
+/// per chunk.
+pub fn write_tokens(path: &Path, chunks: &[ChunkTokens]) -> Result<()> {
+    let file = File::create(path)
+        .with_context(|| format!("Failed to create token dump {}", path.display()))?;
+    let mut writer = BufWriter::new(file);
+    writer.write_all(b"[")?;
+    for (n, chunk) in chunks.iter().enumerate() {
+        // One chunk per line keeps long token lists readable and diffable
+        let separator = if n == 0 { "\n  " } else { ",\n  " };
+        writer.write_all(separator.as_bytes())?;
+        serde_json::to_writer(&mut writer, chunk).context("Failed to write token dump")?;
+    }
+    let end = if chunks.is_empty() { "]\n" } else { "\n]\n" };
+    writer.write_all(end.as_bytes())?;
+    writer.flush().context("Failed to write token dump")?;
+    Ok(())
+}
+
+/// Format `seconds` as an SRT timestamp, `HH:MM:SS,mmm`.
+pub fn srt_timestamp(seconds: f64) -> String {
+    let millis = (seconds.max(0.0) * 1000.0).round() as u64;
+    format!(
+        "{:02}:{:02}:{:02},{:03}",
+        millis / 3_600_000,
+        millis / 60_000 % 60,
+        millis / 1000 % 60,
+        millis % 1000
+    )
+}
+
+/// Write `segments` to `path` as SubRip subtitles, one cue per segment with
+/// text. A segment's speaker, if any, prefixes its cue text.
+///
+/// With `wall_clock` the cue times are times of day from its start instead
+/// of offsets into the file.
+pub fn write_srt(
+    path: &Path,
+    segments: &[Segment],
+    line_ending: LineEnding,
+    wall_clock: Option<&WallClock>,
+) -> Result<()> {
+    let cue_time = |seconds: f32| match wall_clock {
+        Some(clock) => srt_timestamp(clock.since_midnight(seconds)),
+        None => srt_timestamp(f64::from(seconds)),
+    };
+    let file = File::create(path).context("Failed to create output file for transcription")?;
+    let mut writer = TextWriter::new(BufWriter::new(file), line_ending, false)
+        .context("Failed to write subtitles to file")?;
+    let cues = segments.iter().filter(|s| !s.text.trim().is_empty());
+    for (number, segment) in cues.enumerate() {
+        let text = match &segment.speaker {
+            Some(speaker) => format!("{speaker}: {}", segment.text.trim()),
+            None => segment.text.trim().to_string(),
+        };
+        for line in [
+            (number + 1).to_string(),
+            format!(
+                "{} --> {}",
+                cue_time(segment.start_sec),
+                cue_time(segment.end_sec)
+            ),
+            text,
+            String::new(),
+        ] {
+            writer
+                .write_line(&line)
+                .context("Failed to write subtitles to file")?;
+        }
+    }
+    writer
+        .flush()
+        .context("Failed to write subtitles to file")?;
+    Ok(())
+}
+
+/// Quote a CSV field, as RFC 4180 requires, when it contains a separator,
+/// quote or line break.
+pub fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Write `segments` to `path` as CSV with a `start_sec,end_sec,text` header
+/// and one row per segment with text, times in seconds with three decimals.
+/// A segment's speaker, if any, prefixes its text.
+pub fn write_csv(path: &Path, segments: &[Segment], line_ending: LineEnding) -> Result<()> {
+    let file = File::create(path).context("Failed to create output file for transcription")?;
+    let mut writer = TextWriter::new(BufWriter::new(file), line_ending, false)
+        .context("Failed to write transcription to file")?;
+    writer
+        .write_line("start_sec,end_sec,text")
+        .context("Failed to write transcription to file")?;
+    for segment in segments.iter().filter(|s| !s.text.trim().is_empty()) {
+        let text = match &segment.speaker {
+            Some(speaker) => format!("{speaker}: {}", segment.text.trim()),
+            None => segment.text.trim().to_string(),
+        };
+        let row = format!(
+            "{:.3},{:.3},{}",
+            segment.start_sec,
+            segment.end_sec,
+            csv_field(&text)
+        );
+        writer
+            .write_line(&row)
+            .context("Failed to write transcription to file")?;
+    }
+    writer
+        .flush()
+        .context("Failed to write transcription to file")?;
+    Ok(())
+}
+
+/// Marks long pauses between segments in plain text and Markdown output,
+/// such as `[pause 12s]`, to break long monologues into paragraphs.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PauseMarker {
+    /// Shortest gap between segments, in seconds, that gets a marker.
+    pub threshold_sec: f32,
+    /// Marker text, in which `{seconds}` is replaced by the length of the
+    /// pause in whole seconds. An empty template makes an empty line.
+    pub template: String,
+}
+
+impl PauseMarker {
+    /// Marker for the gap between a segment ending at `previous_end_sec`
+    /// and the next one starting at `start_sec`, or `None` for a shorter gap
+    /// or without a previous segment.
+    pub fn between(&self, previous_end_sec: Option<f32>, start_sec: f32) -> Option<String> {
+        let gap = start_sec - previous_end_sec?;
+        (gap >= self.threshold_sec)
+            .then(|| self.template.replace("{seconds}", &format!("{gap:.0}")))
+    }
+}
+
+/// What happens to a segment below `--min-confidence`.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    clap::ValueEnum,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum LowConfidenceAction {
+    /// Leave the segment out of the transcripts.
+    #[default]
+    Drop,
+    /// Keep the segment with its text as `[unclear: ...]`.
+    Bracket,
+}
+
+/// Drops or brackets the segments whose average log-probability is below a
+/// cutoff, for clean captions.
+///
+/// Only segments with a confidence are filtered; silence and failure
+/// placeholders pass unchanged. So that a file the model is unsure about
+/// throughout does not end up empty, dropping starts once a segment meets
+/// the cutoff: segments before it are held back, and are all kept by
+/// [`ConfidenceFilter::finish`] when none does. The held segments are saved
+/// with the rest of the [`crate::progress::Progress`] of a `--resume` run.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct ConfidenceFilter {
+    min_logprob: f32,
+    action: LowConfidenceAction,
+    held: Vec<Segment>,
+    confident_seen: bool,
+    filtered: usize,
+}
+
+impl ConfidenceFilter {
+    pub fn new(min_logprob: f32, action: LowConfidenceAction) -> Self {
+        Self {
+            min_logprob,
+            action,
+            held: Vec::new(),
+            confident_seen: false,
+            filtered: 0,
+        }
+    }
+
+    /// Whether `segment` has a confidence below the cutoff.
+    pub fn is_low(&self, segment: &Segment) -> bool {
+        segment
+            .avg_logprob
+            .is_some_and(|logprob| logprob < self.min_logprob)
+    }
+
+    /// Segments to write, in order, now that `segment` has been transcribed.
+    pub fn admit(&mut self, mut segment: Segment) -> Vec<Segment> {
+        let low = self.is_low(&segment);
+        match self.action {
+            LowConfidenceAction::Bracket => {
+                if low {
+                    self.filtered += 1;
+                    segment.text = format!("[unclear: {}]", segment.text.trim());
+                }
+                vec![segment]
+            }
+            LowConfidenceAction::Drop if self.confident_seen => {
+                if low {
+                    self.filtered += 1;
+                    return Vec::new();
+                }
+                vec![segment]
+            }
+            LowConfidenceAction::Drop => {
+                if low || segment.avg_logprob.is_none() {
+                    self.held.push(segment);
+                    return Vec::new();
+                }
+                self.confident_seen = true;
+                let held = std::mem::take(&mut self.held);
+                let (low, mut kept): (Vec<Segment>, Vec<Segment>) =
+                    held.into_iter().partition(|held| self.is_low(held));
+                self.filtered += low.len();
+                kept.push(segment);
+                kept
+            }
+        }
+    }
+
+    /// Segments still held back at the end of the file: when no segment met
+    /// the cutoff, all of them, low confidence or not.
+    pub fn finish(&mut self) -> Vec<Segment> {
+        std::mem::take(&mut self.held)
+    }
+
+    /// Whether the segments so far all have a low confidence, so that
+    /// [`ConfidenceFilter::finish`] keeps them instead of dropping them.
+    pub fn all_low(&self) -> bool {
+        !self.confident_seen && self.held.iter().any(|held| self.is_low(held))
+    }
+
+    /// Number of segments dropped or bracketed so far.
+    pub fn filtered(&self) -> usize {
+        self.filtered
+    }
+}
+
+/// Refuse to write a transcript at `output` when it is the `input` file
+/// itself, which creating the transcript would truncate.
+///
+/// # Errors
+///
+/// Returns an error if both paths resolve to the same file.
+pub fn check_not_input(input: &Path, output: &Path) -> Result<()> {
+    let same = match (input.canonicalize(), output.canonicalize()) {
+        (Ok(input), Ok(output)) => input == output,
+        // An output that does not exist yet is not the input
+        _ => false,
+    };
+    if same {
+        anyhow::bail!(
+            "Refusing to write the transcript to {}, which is the input file",
+            output.display()
+        );
+    }
+    Ok(())
+}
+
+/// Header line written before the text of `input` when transcripts of
+/// several inputs are appended to one file: `=== talk.wav ===`.
+pub fn append_header(input: &Path) -> String {
+    let name = input
+        .file_name()
+        .map_or_else(|| input.to_string_lossy(), |name| name.to_string_lossy());
+    format!("=== {name} ===")
+}
+
+/// Where transcripts go when they are not written next to their inputs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutputLayout {
+    /// Directory the transcripts are written under.
+    pub dir: PathBuf,
+    /// Put every transcript directly in `dir` instead of mirroring the
+    /// directories of the input paths.
+    pub flatten: bool,
+}
+
+impl OutputLayout {
+    /// Paths the transcripts of `inputs` are based on, each format replacing
+    /// the extension: `dir` joined with the input path as given, without
+    /// its root and `..` components, or with only its file name when
+    /// flattened. Inputs that would share a transcript, such as `a.wav` and
+    /// `a.mp3`, get `-2`, `-3` and so on appended to the file stem in order.
+    pub fn bases(&self, inputs: &[PathBuf]) -> Vec<PathBuf> {
+        let mut taken = HashSet::new();
+        inputs
+            .iter()
+            .map(|input| {
+                let relative: PathBuf = if self.flatten {
+                    input.file_name().map(PathBuf::from).unwrap_or_default()
+                } else {
+                    input
+                        .components()
+                        .filter(|c| matches!(c, Component::Normal(_)))
+                        .collect()
+                };
+                let base = self.dir.join(relative);
+                let mut candidate = base.clone();
+                let mut n = 1;
+                while !taken.insert(candidate.with_extension("")) {
+                    n += 1;
+                    candidate = disambiguated(&base, n);
+                }
+                candidate
+            })
+            .collect()
+    }
+}
+
+/// Placeholders an [`OutputTemplate`] can hold, each between braces.
+const TEMPLATE_PLACEHOLDERS: [&str; 5] = ["stem", "ext", "lang", "date", "index"];
+
+/// File name pattern of the transcripts of an input, such as
+/// `transcript_{stem}_{lang}.txt`, replacing the input's own file name.
+///
+/// `{stem}` and `{ext}` are the input file name without and only its
+/// extension, `{lang}` the transcription language, `{date}` the day of the
+/// run as `YYYY-MM-DD` in UTC and `{index}` the position of the input among
+/// the files of the run, counting from 1. The pattern ends in an extension,
+/// which each format replaces with its own, and may name subdirectories.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutputTemplate(String);
+
+/// Values of the [`OutputTemplate`] placeholders for one input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TemplateFields<'a> {
+    pub stem: &'a str,
+    pub ext: &'a str,
+    pub lang: &'a str,
+    pub date: &'a str,
+    pub index: usize,
+}
+
+impl std::str::FromStr for OutputTemplate {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let mut literal = String::new();
+        let mut rest = s;
+        while let Some(open) = rest.find(['{', '}']) {
+            literal.push_str(&rest[..open]);
+            if rest[open..].starts_with('}') {
+                return Err(format!("unmatched `}}` in output template `{s}`"));
+            }
+            let close = rest[open..]
+                .find('}')
+                .ok_or_else(|| format!("unclosed `{{` in output template `{s}`"))?;
+            let name = &rest[open + 1..open + close];
+            if !TEMPLATE_PLACEHOLDERS.contains(&name) {
+                return Err(format!(
+                    "unknown placeholder `{{{name}}}` in output template `{s}`, expected {}",
+                    TEMPLATE_PLACEHOLDERS
+                        .map(|name| format!("{{{name}}}"))
+                        .join(", ")
+                ));
+            }
+            literal.push('\u{0}');
+            rest = &rest[open + close + 1..];
+        }
+        literal.push_str(rest);
+
+        let path = Path::new(s);
+        if path.is_absolute() || path.components().any(|c| c == Component::ParentDir) {
+            return Err(format!(
+                "output template `{s}` must be a relative path without `..`"
+            ));
+        }
+        // The extension has to be literal so that each format can replace it
+        let has_extension = literal
+            .rsplit(['/', '\\'])
+            .next()
+            .and_then(|name| name.rsplit_once('.'))
+            .is_some_and(|(stem, ext)| {
+                !stem.is_empty() && !ext.is_empty() && !ext.contains('\u{0}')
+            });
+        if !has_extension {
+            return Err(format!(
+                "output template `{s}` must end in an extension such as .txt, which each format replaces"
+            ));
+        }
+        Ok(Self(s.to_string()))
+    }
+}
+
+impl OutputTemplate {
+    /// The file name, or relative path, of the transcripts described by
+    /// `fields`.
+    pub fn expand(&self, fields: &TemplateFields) -> PathBuf {
+        let index = fields.index.to_string();
+        let mut expanded = String::with_capacity(self.0.len());
+        let mut rest = self.0.as_str();
+        while let Some(open) = rest.find('{') {
+            expanded.push_str(&rest[..open]);
+            let close = open + rest[open..].find('}').unwrap_or(rest.len() - open);
+            expanded.push_str(match &rest[open + 1..close] {
+                "stem" => fields.stem,
+                "ext" => fields.ext,
+                "lang" => fields.lang,
+                "date" => fields.date,
+                _ => &index,
+            });
+            rest = rest.get(close + 1..).unwrap_or_default();
+        }
+        expanded.push_str(rest);
+        PathBuf::from(expanded)
+    }
+}
+
+/// `base` with `-n` appended to its file stem: `dir/a.wav` becomes `dir/a-2.wav`.
+fn disambiguated(base: &Path, n: usize) -> PathBuf {
+    let stem = base.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match base.extension() {
+        Some(ext) => format!("{stem}-{n}.{}", ext.to_string_lossy()),
+        None => format!("{stem}-{n}"),
+    };
+    base.with_file_name(name)
+}
+
+/// Title and length of the audio, written as the YAML front matter of a
+/// Markdown transcript.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FrontMatter {
+    /// Title of the transcript, such as the input file name.
+    pub title: String,
+    /// Duration of the audio in seconds.
+    pub duration_sec: f32,
+}
+
+/// Format `seconds` as a Markdown timestamp, `MM:SS`, or `H:MM:SS` from an
+/// hour on.
+pub fn md_timestamp(seconds: f32) -> String {
+    let total = f64::from(seconds.max(0.0)).floor() as u64;
+    let (hours, minutes, seconds) = (total / 3600, total / 60 % 60, total % 60);
+    if hours > 0 {
+        format!("{hours}:{minutes:02}:{seconds:02}")
+    } else {
+        format!("{minutes:02}:{seconds:02}")
+    }
+}
+
+/// Escape the characters of `text` that Markdown would read as formatting,
+/// and join its lines so that none can start a heading or list.
+pub fn md_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .chars()
+    {
+        if matches!(
+            c,
+            '\\' | '`' | '*' | '_' | '[' | ']' | '<' | '>' | '|' | '~'
+        ) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Write `segments` to `path` as Markdown: the optional front matter, then
+/// one paragraph per segment with text, led by its start time in bold. A
+/// segment's speaker, if any, prefixes its text. A `pause_marker` becomes a
+/// paragraph of its own; an empty one adds nothing, as paragraphs are
+/// already apart.
+pub fn write_markdown(
+    path: &Path,
+    segments: &[Segment],
+    front_matter: Option<&FrontMatter>,
+    pause_marker: Option<&PauseMarker>,
+    line_ending: LineEnding,
+) -> Result<()> {
+    let file = File::create(path).context("Failed to create output file for transcription")?;
+    let mut writer = TextWriter::new(BufWriter::new(file), line_ending, false)
+        .context("Failed to write transcription to file")?;
+    let mut lines = Vec::new();
+    if let Some(front_matter) = front_matter {
+        let title = front_matter
+            .title
+            .replace('\\', "\\\\")
+            .replace('"', "\\\"");
+        lines.extend([
+            "---".to_string(),
+            format!("title: \"{title}\""),
+            format!("duration: \"{}\"", md_timestamp(front_matter.duration_sec)),
+            "---".to_string(),
+            String::new(),
+        ]);
+    }
+    let mut previous_end = None;
+    for segment in segments {
+        let pause = pause_marker.and_then(|marker| marker.between(previous_end, segment.start_sec));
+        previous_end = Some(segment.end_sec);
+        if segment.text.trim().is_empty() {
+            continue;
+        }
+        if let Some(pause) = pause.filter(|pause| !pause.is_empty()) {
+            lines.push(md_escape(&pause));
+            lines.push(String::new());
+        }
+        let text = match &segment.speaker {
+            Some(speaker) => format!("{speaker}: {}", segment.text.trim()),
+            None => segment.text.trim().to_string(),
+        };
+        lines.push(format!(
+            "**[{}]** {}",
+            md_timestamp(segment.start_sec),
+            md_escape(&text)
+        ));
+        lines.push(String::new());
+    }
+    for line in lines {
+        writer
+            .write_line(&line)
+            .context("Failed to write transcription to file")?;
+    }
+    writer
+        .flush()
+        .context("Failed to write transcription to file")?;
+    Ok(())
+}
+
+/// Serialize `segment` as a single line of JSON for `--format jsonl`, with
+/// wall-clock times as in [`write_json`].
+pub fn jsonl_line(segment: &Segment, wall_clock: Option<&WallClock>) -> Result<String> {
+    serde_json::to_string(&TimedSegment::new(segment, wall_clock))
+        .context("Failed to serialize segment as JSON")
+}
+
+/// Write `segments` to `path` as JSON Lines, one segment per line.
+pub fn write_jsonl(
+    path: &Path,
+    segments: &[Segment],
+    line_ending: LineEnding,
+    wall_clock: Option<&WallClock>,
+) -> Result<()> {
+    let file = File::create(path).context("Failed to create output file for transcription")?;
+    let mut writer = TextWriter::new(BufWriter::new(file), line_ending, false)
+        .context("Failed to write transcription to file")?;
+    for segment in segments {
+        writer
+            .write_line(&jsonl_line(segment, wall_clock)?)
+            .context("Failed to write transcription to file")?;
+    }
+    writer
+        .flush()
+        .context("Failed to write transcription to file")?;
+    Ok(())
+}
+
+/// Line of `segment` in the live format: its start and end in seconds and
+/// its text on one line, separated by tabs, such as `12.000\t27.000\tHello`.
+pub fn live_line(segment: &Segment) -> String {
+    let text = match &segment.speaker {
+        Some(speaker) => format!("{speaker}: {}", tagged_text(segment)),
+        None => tagged_text(segment).into_owned(),
+    };
+    format!(
+        "{:.3}\t{:.3}\t{}",
+        segment.start_sec,
+        segment.end_sec,
+        text.split_whitespace().collect::<Vec<_>>().join(" ")
+    )
+}
+
+/// Write `segments` in the live format to `path` at once, as [`live_line`]s.
+pub fn write_live(path: &Path, segments: &[Segment], line_ending: LineEnding) -> Result<()> {
+    let file = File::create(path).context("Failed to create output file for transcription")?;
+    let mut writer = TextWriter::new(BufWriter::new(file), line_ending, false)
+        .context("Failed to write transcription to file")?;
+    for segment in segments {
+        writer
+            .write_line(&live_line(segment))
+            .context("Failed to write transcription to file")?;
+    }
+    writer
+        .flush()
+        .context("Failed to write transcription to file")?;
+    Ok(())
+}
+
+/// Label for the speaker recorded on `channel`: `Speaker A`, `Speaker B`, ...
+pub fn channel_speaker(channel: usize) -> String {
+    match u8::try_from(channel).ok().filter(|&c| c < 26) {
+        Some(c) => format!("Speaker {}", char::from(b'A' + c)),
+        None => format!("Speaker {}", channel + 1),
+    }
+}
+
+/// Merge per-speaker segment lists into one list ordered by start time.
+///
+/// Segments starting at the same time keep the order of `tracks`.
+pub fn interleave_by_start(tracks: Vec<Vec<Segment>>) -> Vec<Segment> {
+    let mut segments: Vec<Segment> = tracks.into_iter().flatten().collect();
+    segments.sort_by(|a, b| a.start_sec.total_cmp(&b.start_sec));
+    segments
+}
+
+/// Plain-text lines for `segments`, one per speaker turn.
+///
+/// Consecutive segments of the same speaker are joined into one line that
+/// starts with the speaker label, e.g. `Speaker A: Hello there.`
+pub fn speaker_turns(segments: &[Segment]) -> Vec<String> {
+    let mut turns: Vec<(Option<&str>, String)> = Vec::new();
+    for segment in segments {
+        if segment.text.trim().is_empty() {
+            continue;
+        }
+        let tagged = tagged_text(segment);
+        let text = tagged.trim();
+        let speaker = segment.speaker.as_deref();
+        match turns.last_mut() {
+            Some((last, joined)) if *last == speaker => {
+                joined.push(' ');
+                joined.push_str(text);
+            }
+            _ => turns.push((speaker, text.to_string())),
+        }
+    }
+    turns
+        .into_iter()
+        .map(|(speaker, text)| match speaker {
+            Some(speaker) => format!("{speaker}: {text}"),
+            None => text,
+        })
+        .collect()
+}
+
+/// Text of `segment` for the plain-text transcript, preceded by its
+/// language in brackets, such as `[fr] Bonjour`, when it has one.
+pub fn tagged_text(segment: &Segment) -> Cow<'_, str> {
+    match &segment.language {
+        Some(language) => Cow::Owned(format!("[{language}] {}", segment.text.trim_start())),
+        None => Cow::Borrowed(&segment.text),
+    }
+}
+
+/// Write `segments` to `path` as plain text, one line per speaker turn.
+pub fn write_speaker_text(
+    path: &Path,
+    segments: &[Segment],
+    line_ending: LineEnding,
+    bom: bool,
+) -> Result<()> {
+    let file = File::create(path).context("Failed to create output file for transcription")?;
+    let mut writer = TextWriter::new(BufWriter::new(file), line_ending, bom)
+        .context("Failed to write transcription to file")?;
+    for line in speaker_turns(segments) {
+        writer
+            .write_line(&line)
+            .context("Failed to write transcription to file")?;
+    }
+    writer
+        .flush()
+        .context("Failed to write transcription to file")?;
+    Ok(())
+}
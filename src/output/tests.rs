@@ -0,0 +1,738 @@
+use super::fixtures::segment;
+use super::*;
+use tempfile::TempDir;
+
+#[test]
+fn test_output_format_extension() {
+    assert_eq!(OutputFormat::Txt.extension(), "txt");
+    assert_eq!(OutputFormat::Json.extension(), "json");
+    assert_eq!(OutputFormat::Jsonl.extension(), "jsonl");
+    assert_eq!(OutputFormat::Srt.extension(), "srt");
+}
+
+#[test]
+fn test_write_json_segments() {
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path().join("talk.json");
+    let segments = [
+        segment(0, "Hello", Some(-0.2)),
+        segment(1, "[silence]", None),
+    ];
+
+    write_json(&path, &segments, None).unwrap();
+
+    let value: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+    let array = value.as_array().unwrap();
+    assert_eq!(array.len(), 2);
+    assert_eq!(array[0]["text"], "Hello");
+    assert_eq!(array[0]["start_sec"], 0.0);
+    assert_eq!(array[0]["end_sec"], 15.0);
+    assert!((array[0]["avg_logprob"].as_f64().unwrap() + 0.2).abs() < 1e-6);
+    assert_eq!(array[1]["index"], 1);
+    assert!((array[0]["no_speech_prob"].as_f64().unwrap() - 0.01).abs() < 1e-6);
+    assert!(array[1].get("avg_logprob").is_none());
+    assert!(array[1].get("no_speech_prob").is_none());
+}
+
+#[test]
+fn test_failed_flag_only_serialized_when_set() {
+    let ok = serde_json::to_value(segment(0, "Hello", Some(-0.2))).unwrap();
+    let failed = serde_json::to_value(Segment {
+        failed: true,
+        ..segment(1, "[transcription failed]", None)
+    })
+    .unwrap();
+
+    assert!(ok.get("failed").is_none());
+    assert_eq!(failed["failed"], true);
+}
+
+fn spoken(speaker: &str, start_sec: f32, text: &str) -> Segment {
+    Segment {
+        start_sec,
+        end_sec: start_sec + 15.0,
+        speaker: Some(speaker.to_string()),
+        failed: false,
+        ..segment(0, text, Some(-0.1))
+    }
+}
+
+#[test]
+fn test_channel_speaker_labels() {
+    assert_eq!(channel_speaker(0), "Speaker A");
+    assert_eq!(channel_speaker(1), "Speaker B");
+    assert_eq!(channel_speaker(25), "Speaker Z");
+    assert_eq!(channel_speaker(26), "Speaker 27");
+}
+
+#[test]
+fn test_interleave_by_start() {
+    let a = vec![
+        spoken("Speaker A", 0.0, "a0"),
+        spoken("Speaker A", 27.0, "a2"),
+    ];
+    let b = vec![
+        spoken("Speaker B", 0.0, "b0"),
+        spoken("Speaker B", 13.5, "b1"),
+    ];
+
+    let texts: Vec<String> = interleave_by_start(vec![a, b])
+        .into_iter()
+        .map(|s| s.text)
+        .collect();
+
+    assert_eq!(texts, ["a0", "b0", "b1", "a2"]);
+}
+
+#[test]
+fn test_speaker_turns_join_consecutive_segments() {
+    let segments = [
+        spoken("Speaker A", 0.0, "Hello."),
+        spoken("Speaker A", 13.5, " How are you? "),
+        spoken("Speaker B", 27.0, ""),
+        spoken("Speaker B", 40.5, "Fine, thanks."),
+        spoken("Speaker A", 54.0, "Good."),
+    ];
+
+    assert_eq!(
+        speaker_turns(&segments),
+        [
+            "Speaker A: Hello. How are you?",
+            "Speaker B: Fine, thanks.",
+            "Speaker A: Good."
+        ]
+    );
+}
+
+#[test]
+fn test_write_speaker_text() {
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path().join("interview.txt");
+    let segments = [
+        spoken("Speaker A", 0.0, "Hi."),
+        spoken("Speaker B", 2.0, "Hello."),
+    ];
+
+    write_speaker_text(&path, &segments, LineEnding::Lf, false).unwrap();
+
+    assert_eq!(
+        std::fs::read_to_string(&path).unwrap(),
+        "Speaker A: Hi.\nSpeaker B: Hello.\n"
+    );
+}
+
+fn written(line_ending: LineEnding, bom: bool, lines: &[&str]) -> Vec<u8> {
+    let mut writer = TextWriter::new(Vec::new(), line_ending, bom).unwrap();
+    for line in lines {
+        writer.write_line(line).unwrap();
+    }
+    writer.get_ref().clone()
+}
+
+#[test]
+fn test_text_writer_default_is_plain_lf() {
+    assert_eq!(LineEnding::default(), LineEnding::Lf);
+    assert_eq!(
+        written(LineEnding::Lf, false, &["Hello", "world"]),
+        b"Hello\nworld\n"
+    );
+}
+
+#[test]
+fn test_text_writer_crlf_with_bom() {
+    assert_eq!(
+        written(LineEnding::Crlf, true, &["Hello", "world"]),
+        b"\xEF\xBB\xBFHello\r\nworld\r\n"
+    );
+}
+
+#[test]
+fn test_text_writer_converts_embedded_line_breaks() {
+    assert_eq!(
+        written(LineEnding::Crlf, false, &["one\ntwo", "three\r\nfour"]),
+        b"one\r\ntwo\r\nthree\r\nfour\r\n"
+    );
+    assert_eq!(
+        written(LineEnding::Lf, false, &["three\r\nfour"]),
+        b"three\nfour\n"
+    );
+}
+
+#[test]
+fn test_text_writer_flushes_on_drop() {
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path().join("talk.txt");
+    let file = File::create(&path).unwrap();
+    let mut writer = TextWriter::new(BufWriter::new(file), LineEnding::Lf, false).unwrap();
+    writer.write_line("Hello").unwrap();
+    writer.write_line("[interrupted]").unwrap();
+
+    // An interrupted run returns without flushing; dropping must not lose text
+    drop(writer);
+
+    assert_eq!(
+        std::fs::read_to_string(&path).unwrap(),
+        "Hello\n[interrupted]\n"
+    );
+}
+
+#[test]
+fn test_jsonl_line_is_single_line() {
+    let line = jsonl_line(&segment(2, "first\nsecond", Some(-0.3)), None).unwrap();
+
+    assert!(!line.contains('\n'));
+    let parsed: serde_json::Value = serde_json::from_str(&line).unwrap();
+    assert_eq!(parsed["index"], 2);
+    assert_eq!(parsed["start_sec"], 27.0);
+    assert_eq!(parsed["end_sec"], 42.0);
+    assert_eq!(parsed["text"], "first\nsecond");
+}
+
+#[test]
+fn test_write_tokens_one_chunk_per_line() {
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path().join("talk.tokens.json");
+    let chunks = [
+        ChunkTokens {
+            index: 0,
+            start_sec: 0.0,
+            end_sec: 15.0,
+            tokens: vec![1032, 7, 42],
+        },
+        ChunkTokens {
+            index: 2,
+            start_sec: 27.0,
+            end_sec: 42.0,
+            tokens: Vec::new(),
+        },
+    ];
+
+    write_tokens(&path, &chunks).unwrap();
+
+    let written = std::fs::read_to_string(&path).unwrap();
+    assert_eq!(
+        written,
+        "[\n  {\"index\":0,\"start_sec\":0.0,\"end_sec\":15.0,\"tokens\":[1032,7,42]},\n  \
+         {\"index\":2,\"start_sec\":27.0,\"end_sec\":42.0,\"tokens\":[]}\n]\n"
+    );
+    let parsed: Vec<ChunkTokens> = serde_json::from_str(&written).unwrap();
+    assert_eq!(parsed, chunks);
+
+    write_tokens(&path, &[]).unwrap();
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "[]\n");
+}
+
+#[test]
+fn test_write_jsonl_segments() {
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path().join("talk.jsonl");
+    let segments = [segment(0, "Hello", Some(-0.2)), segment(1, "world", None)];
+
+    write_jsonl(&path, &segments, LineEnding::Lf, None).unwrap();
+
+    let written = std::fs::read_to_string(&path).unwrap();
+    let parsed: Vec<Segment> = written
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(parsed, segments);
+}
+
+#[test]
+fn test_format_list_parsing() {
+    use OutputFormat::*;
+
+    let parse = |s: &str| s.parse::<FormatList>().map(|list| list.0);
+
+    assert_eq!(parse("txt").unwrap(), [Txt]);
+    assert_eq!(parse("txt,srt,json").unwrap(), [Txt, Srt, Json]);
+    assert_eq!(parse("json, jsonl,json").unwrap(), [Json, Jsonl]);
+    assert_eq!(parse("all").unwrap(), [Txt, Srt, Json]);
+    assert_eq!(parse("live,txt").unwrap(), [Live, Txt]);
+    assert!(parse("txt,vtt")
+        .unwrap_err()
+        .contains("unknown format `vtt`"));
+    assert!(parse("").is_err());
+}
+
+#[test]
+fn test_srt_timestamp() {
+    assert_eq!(srt_timestamp(0.0), "00:00:00,000");
+    assert_eq!(srt_timestamp(13.5), "00:00:13,500");
+    assert_eq!(srt_timestamp(3725.042), "01:02:05,042");
+    assert_eq!(srt_timestamp(-1.0), "00:00:00,000");
+}
+
+#[test]
+fn test_csv_field_quotes_when_needed() {
+    assert_eq!(csv_field("plain text"), "plain text");
+    assert_eq!(csv_field("one, two"), "\"one, two\"");
+    assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+    assert_eq!(csv_field("two\nlines"), "\"two\nlines\"");
+}
+
+/// Parse RFC 4180 CSV into rows of fields, with `\n` line endings.
+fn parse_csv(csv: &str) -> Vec<Vec<String>> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = csv.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, quoted) {
+            ('"', true) if chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            ('"', _) => quoted = !quoted,
+            (',', false) => row.push(std::mem::take(&mut field)),
+            ('\n', false) => {
+                row.push(std::mem::take(&mut field));
+                rows.push(std::mem::take(&mut row));
+            }
+            (c, _) => field.push(c),
+        }
+    }
+    rows
+}
+
+#[test]
+fn test_md_timestamp() {
+    assert_eq!(md_timestamp(0.0), "00:00");
+    assert_eq!(md_timestamp(-1.0), "00:00");
+    assert_eq!(md_timestamp(75.9), "01:15");
+    assert_eq!(md_timestamp(3599.0), "59:59");
+    assert_eq!(md_timestamp(3723.0), "1:02:03");
+}
+
+#[test]
+fn test_md_escape() {
+    assert_eq!(md_escape("plain words."), "plain words.");
+    assert_eq!(
+        md_escape("*really* [sic] 2 < 3 a_b `code` c\\d"),
+        "\\*really\\* \\[sic\\] 2 \\< 3 a\\_b \\`code\\` c\\\\d"
+    );
+    // A line break could otherwise start a heading
+    assert_eq!(md_escape("one\n# two"), "one # two");
+}
+
+#[test]
+fn test_write_markdown() {
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path().join("talk.md");
+    let segments = [
+        segment(0, "Hello *all*", Some(-0.2)),
+        segment(1, "", Some(-0.2)),
+        spoken("Speaker B", 75.0, "Bye"),
+    ];
+    let front_matter = FrontMatter {
+        title: "talk \"final\".wav".to_string(),
+        duration_sec: 90.0,
+    };
+
+    write_markdown(&path, &segments, Some(&front_matter), None, LineEnding::Lf).unwrap();
+
+    assert_eq!(
+        std::fs::read_to_string(&path).unwrap(),
+        "---\ntitle: \"talk \\\"final\\\".wav\"\nduration: \"01:30\"\n---\n\n\
+         **[00:00]** Hello \\*all\\*\n\n\
+         **[01:15]** Speaker B: Bye\n\n"
+    );
+}
+
+#[test]
+fn test_write_markdown_marks_pauses() {
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path().join("talk.md");
+    let segments = [
+        segment(0, "Hello", Some(-0.2)),
+        segment(1, "", Some(-0.2)),
+        spoken("Speaker B", 79.5, "Bye"),
+    ];
+    let mut marker = PauseMarker {
+        threshold_sec: 30.0,
+        template: "[pause {seconds}s]".to_string(),
+    };
+
+    write_markdown(&path, &segments, None, Some(&marker), LineEnding::Lf).unwrap();
+    assert_eq!(
+        std::fs::read_to_string(&path).unwrap(),
+        "**[00:00]** Hello\n\n\\[pause 51s\\]\n\n**[01:19]** Speaker B: Bye\n\n"
+    );
+
+    // Paragraphs are already apart, so an empty marker adds nothing
+    marker.template.clear();
+    write_markdown(&path, &segments, None, Some(&marker), LineEnding::Lf).unwrap();
+    assert_eq!(
+        std::fs::read_to_string(&path).unwrap(),
+        "**[00:00]** Hello\n\n**[01:19]** Speaker B: Bye\n\n"
+    );
+}
+
+#[test]
+fn test_pause_marker_between_segments() {
+    let marker = PauseMarker {
+        threshold_sec: 5.0,
+        template: "[pause {seconds}s]".to_string(),
+    };
+
+    assert_eq!(marker.between(None, 30.0), None);
+    assert_eq!(marker.between(Some(15.0), 13.5), None);
+    assert_eq!(marker.between(Some(15.0), 19.9), None);
+    assert_eq!(
+        marker.between(Some(15.0), 20.0).as_deref(),
+        Some("[pause 5s]")
+    );
+    assert_eq!(
+        marker.between(Some(28.5), 40.8).as_deref(),
+        Some("[pause 12s]")
+    );
+
+    let paragraph = PauseMarker {
+        template: String::new(),
+        ..marker
+    };
+    assert_eq!(paragraph.between(Some(0.0), 60.0).as_deref(), Some(""));
+}
+
+#[test]
+fn test_append_header_names_input_file() {
+    assert_eq!(
+        append_header(Path::new("recordings/day 1/talk.wav")),
+        "=== talk.wav ==="
+    );
+}
+
+#[test]
+fn test_write_csv_round_trip() {
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path().join("talk.csv");
+    let segments = [
+        segment(0, "Hello, \"world\"", Some(-0.2)),
+        segment(1, "two\nlines", None),
+        segment(2, " ", None),
+        spoken("Speaker B", 27.25, "Bye"),
+    ];
+
+    write_csv(&path, &segments, LineEnding::Lf).unwrap();
+
+    assert_eq!(
+        parse_csv(&std::fs::read_to_string(&path).unwrap()),
+        [
+            ["start_sec", "end_sec", "text"],
+            ["0.000", "15.000", "Hello, \"world\""],
+            ["13.500", "28.500", "two\nlines"],
+            ["27.250", "42.250", "Speaker B: Bye"],
+        ]
+    );
+}
+
+#[test]
+fn test_write_srt() {
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path().join("talk.srt");
+    let segments = [
+        segment(0, "Hello", Some(-0.2)),
+        segment(1, "", Some(-0.2)),
+        spoken("Speaker B", 27.0, "Bye"),
+    ];
+
+    write_srt(&path, &segments, LineEnding::Lf, None).unwrap();
+
+    assert_eq!(
+        std::fs::read_to_string(&path).unwrap(),
+        "1\n00:00:00,000 --> 00:00:15,000\nHello\n\n\
+         2\n00:00:27,000 --> 00:00:42,000\nSpeaker B: Bye\n\n"
+    );
+}
+
+#[test]
+fn test_output_layout_mirrors_input_tree() {
+    let layout = OutputLayout {
+        dir: PathBuf::from("out"),
+        flatten: false,
+    };
+    let inputs = [
+        "talks/2024/keynote.wav",
+        "talks/2025/keynote.wav",
+        "./intro.mp3",
+        "/data/../archive/old.flac",
+    ]
+    .map(PathBuf::from);
+
+    assert_eq!(
+        layout.bases(&inputs),
+        [
+            "out/talks/2024/keynote.wav",
+            "out/talks/2025/keynote.wav",
+            "out/intro.mp3",
+            "out/data/archive/old.flac",
+        ]
+        .map(PathBuf::from)
+    );
+}
+
+#[test]
+fn test_output_layout_flattened_disambiguates_collisions() {
+    let layout = OutputLayout {
+        dir: PathBuf::from("out"),
+        flatten: true,
+    };
+    let inputs = [
+        "talks/2024/keynote.wav",
+        "talks/2025/keynote.wav",
+        "keynote.mp3",
+        "talks/keynote-2.wav",
+        "notes",
+        "more/notes",
+    ]
+    .map(PathBuf::from);
+
+    let bases = layout.bases(&inputs);
+
+    assert_eq!(
+        bases,
+        [
+            "out/keynote.wav",
+            "out/keynote-2.wav",
+            "out/keynote-3.mp3",
+            "out/keynote-2-2.wav",
+            "out/notes",
+            "out/notes-2",
+        ]
+        .map(PathBuf::from)
+    );
+    // Every input gets a transcript of its own
+    let txt: HashSet<PathBuf> = bases.iter().map(|b| b.with_extension("txt")).collect();
+    assert_eq!(txt.len(), inputs.len());
+}
+
+#[test]
+fn test_output_layout_mirrored_disambiguates_shared_stems() {
+    let layout = OutputLayout {
+        dir: PathBuf::from("out"),
+        flatten: false,
+    };
+    let inputs = ["a/talk.wav", "a/talk.mp3"].map(PathBuf::from);
+
+    assert_eq!(
+        layout.bases(&inputs),
+        ["out/a/talk.wav", "out/a/talk-2.mp3"].map(PathBuf::from)
+    );
+}
+
+#[test]
+fn test_output_template_expands_placeholders() {
+    let fields = TemplateFields {
+        stem: "keynote.v2",
+        ext: "wav",
+        lang: "fr",
+        date: "2025-11-03",
+        index: 7,
+    };
+    let expand = |pattern: &str| pattern.parse::<OutputTemplate>().unwrap().expand(&fields);
+
+    assert_eq!(
+        expand("transcript_{stem}_{lang}.txt"),
+        PathBuf::from("transcript_keynote.v2_fr.txt")
+    );
+    assert_eq!(
+        expand("{date}/{index}-{stem}.{lang}.{ext}.txt"),
+        PathBuf::from("2025-11-03/7-keynote.v2.fr.wav.txt")
+    );
+    assert_eq!(
+        expand("{stem}{stem}.txt"),
+        PathBuf::from("keynote.v2keynote.v2.txt")
+    );
+    // The dots in the stem stay, each format replaces the template's extension
+    assert_eq!(
+        expand("{stem}.txt").with_extension("srt"),
+        PathBuf::from("keynote.v2.srt")
+    );
+}
+
+#[test]
+fn test_output_template_rejects_bad_patterns() {
+    let err = |pattern: &str| pattern.parse::<OutputTemplate>().unwrap_err();
+
+    assert_eq!(
+        err("{stem}_{language}.txt"),
+        "unknown placeholder `{language}` in output template `{stem}_{language}.txt`, \
+         expected {stem}, {ext}, {lang}, {date}, {index}"
+    );
+    assert!(err("{stem.txt").contains("unclosed `{`"));
+    assert!(err("stem}.txt").contains("unmatched `}`"));
+    assert!(err("{}.txt").contains("unknown placeholder `{}`"));
+    for pattern in ["{stem}", "{stem}.{ext}", "out.d/{stem}", ".txt"] {
+        assert!(
+            err(pattern).contains("must end in an extension"),
+            "{pattern}"
+        );
+    }
+    assert!(err("/tmp/{stem}.txt").contains("relative path"));
+    assert!(err("../{stem}.txt").contains("relative path"));
+    assert!("{lang}/{stem}.txt".parse::<OutputTemplate>().is_ok());
+}
+
+#[test]
+fn test_check_not_input() {
+    let temp_dir = TempDir::new().unwrap();
+    // Audio is probed by its content, so a WAV may well be named .txt
+    let input = temp_dir.path().join("talk.txt");
+    std::fs::write(&input, "RIFF").unwrap();
+
+    let err = check_not_input(&input, &input).unwrap_err().to_string();
+    assert!(err.contains("which is the input file"), "{err}");
+    let spelled_differently = temp_dir.path().join(".").join("talk.txt");
+    assert!(check_not_input(&input, &spelled_differently).is_err());
+
+    assert!(check_not_input(&input, &temp_dir.path().join("talk.srt")).is_ok());
+    std::fs::write(temp_dir.path().join("talk.json"), "[]").unwrap();
+    assert!(check_not_input(&input, &temp_dir.path().join("talk.json")).is_ok());
+}
+
+#[test]
+fn test_language_tags_text_and_json() {
+    let untagged = segment(0, " Hello", Some(-0.1));
+    assert_eq!(tagged_text(&untagged), " Hello");
+    assert!(serde_json::to_value(&untagged)
+        .unwrap()
+        .get("language")
+        .is_none());
+
+    let tagged = Segment {
+        language: Some("fr".to_string()),
+        ..segment(1, " Bonjour", Some(-0.2))
+    };
+    assert_eq!(tagged_text(&tagged), "[fr] Bonjour");
+    assert_eq!(serde_json::to_value(&tagged).unwrap()["language"], "fr");
+
+    let turns = speaker_turns(&[
+        Segment {
+            speaker: Some("Speaker A".to_string()),
+            ..tagged
+        },
+        spoken("Speaker A", 15.0, "Hello"),
+    ]);
+    assert_eq!(turns, ["Speaker A: [fr] Bonjour Hello"]);
+}
+
+#[test]
+fn test_wall_clock_times_in_json_and_srt() {
+    let clock: WallClock = "2024-01-02T10:00:00+02:00".parse().unwrap();
+    let first = Segment {
+        start_sec: 5.0,
+        end_sec: 7.5,
+        ..segment(0, "Hello", Some(-0.1))
+    };
+
+    let line = jsonl_line(&first, Some(&clock)).unwrap();
+    let value: serde_json::Value = serde_json::from_str(&line).unwrap();
+    assert_eq!(value["start_time"], "2024-01-02T10:00:05.000+02:00");
+    assert_eq!(value["end_time"], "2024-01-02T10:00:07.500+02:00");
+    assert_eq!(value["start_sec"], 5.0);
+    assert!(jsonl_line(&first, None)
+        .unwrap()
+        .find("start_time")
+        .is_none());
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("talk.srt");
+    write_srt(&path, &[first], LineEnding::Lf, Some(&clock)).unwrap();
+    let srt = std::fs::read_to_string(&path).unwrap();
+    assert!(srt.contains("10:00:05,000 --> 10:00:07,500"), "{srt}");
+}
+
+/// Texts of `segments`, to compare what a filter lets through.
+fn texts(segments: &[Segment]) -> Vec<&str> {
+    segments
+        .iter()
+        .map(|segment| segment.text.as_str())
+        .collect()
+}
+
+#[test]
+fn test_confidence_filter_brackets_low_segments() {
+    let mut filter = ConfidenceFilter::new(-1.0, LowConfidenceAction::Bracket);
+
+    let admitted: Vec<Segment> = [
+        segment(0, "clear", Some(-0.2)),
+        segment(1, " mumbled ", Some(-1.5)),
+        segment(2, "[silence]", None),
+        segment(3, "edge", Some(-1.0)),
+    ]
+    .into_iter()
+    .flat_map(|segment| filter.admit(segment))
+    .collect();
+
+    assert_eq!(
+        texts(&admitted),
+        ["clear", "[unclear: mumbled]", "[silence]", "edge"]
+    );
+    assert_eq!(filter.filtered(), 1);
+    assert!(filter.finish().is_empty());
+}
+
+#[test]
+fn test_confidence_filter_drops_low_segments() {
+    let mut filter = ConfidenceFilter::new(-1.0, LowConfidenceAction::Drop);
+
+    // Held back until a segment meets the cutoff
+    assert!(filter.admit(segment(0, "noise", Some(-2.0))).is_empty());
+    assert!(filter.admit(segment(1, "[silence]", None)).is_empty());
+    assert!(filter.all_low());
+    assert_eq!(
+        texts(&filter.admit(segment(2, "clear", Some(-0.5)))),
+        ["[silence]", "clear"]
+    );
+    assert!(filter.admit(segment(3, "mumbled", Some(-1.5))).is_empty());
+    assert_eq!(
+        texts(&filter.admit(segment(4, "again", Some(-0.1)))),
+        ["again"]
+    );
+
+    assert!(!filter.all_low());
+    assert!(filter.finish().is_empty());
+    assert_eq!(filter.filtered(), 2);
+}
+
+#[test]
+fn test_confidence_filter_keeps_a_file_that_is_all_low() {
+    let mut filter = ConfidenceFilter::new(-0.5, LowConfidenceAction::Drop);
+
+    assert!(filter.admit(segment(0, "first", Some(-0.9))).is_empty());
+    assert!(filter.admit(segment(1, "second", Some(-1.2))).is_empty());
+
+    assert!(filter.all_low());
+    assert_eq!(texts(&filter.finish()), ["first", "second"]);
+    assert_eq!(filter.filtered(), 0);
+}
+
+#[test]
+fn test_live_lines() {
+    assert_eq!(OutputFormat::Live.extension(), "live.tsv");
+    assert!(OutputFormat::Live.is_streamed());
+
+    let mut first = segment(0, " Hello\tthere,\n friend ", Some(-0.2));
+    assert_eq!(live_line(&first), "0.000\t15.000\tHello there, friend");
+    first.language = Some("fr".to_string());
+    first.speaker = Some("Speaker A".to_string());
+    assert_eq!(
+        live_line(&first),
+        "0.000\t15.000\tSpeaker A: [fr] Hello there, friend"
+    );
+
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path().join("talk.live.tsv");
+    write_live(
+        &path,
+        &[segment(0, "one", None), segment(1, "two", None)],
+        LineEnding::Crlf,
+    )
+    .unwrap();
+    assert_eq!(
+        std::fs::read_to_string(&path).unwrap(),
+        "0.000\t15.000\tone\r\n13.500\t28.500\ttwo\r\n"
+    );
+}
@@ -0,0 +1,71 @@
+use anyhow::Result;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+
+#[cfg(test)]
+mod tests;
+
+/// Run `work` over `jobs` on one thread per worker and hand the results to
+/// `handle` in job order.
+///
+/// Each worker, such as a model loaded on its own device, takes the next
+/// unclaimed job until none are left. Results that finish early are held
+/// back until every earlier job has been handled, so `handle` sees the same
+/// sequence as a sequential loop. The first error, from a worker or from
+/// `handle`, stops the remaining jobs from being started and is returned.
+pub fn run_ordered<W, T, R, F, H>(workers: Vec<W>, jobs: &[T], work: F, mut handle: H) -> Result<()>
+where
+    W: Send,
+    T: Sync,
+    R: Send,
+    F: Fn(&mut W, &T) -> Result<R> + Sync,
+    H: FnMut(usize, R) -> Result<()>,
+{
+    let next_job = AtomicUsize::new(0);
+
+    std::thread::scope(|scope| {
+        let (sender, receiver) = mpsc::channel::<(usize, Result<R>)>();
+        for mut worker in workers {
+            let sender = sender.clone();
+            let (next_job, work) = (&next_job, &work);
+            scope.spawn(move || loop {
+                let index = next_job.fetch_add(1, Ordering::SeqCst);
+                let Some(job) = jobs.get(index) else {
+                    break;
+                };
+                if sender.send((index, work(&mut worker, job))).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(sender);
+
+        let mut pending: BTreeMap<usize, R> = BTreeMap::new();
+        let mut next_to_handle = 0usize;
+        let mut outcome = Ok(());
+        for (index, result) in receiver {
+            if outcome.is_err() {
+                continue;
+            }
+            match result {
+                Ok(result) => {
+                    pending.insert(index, result);
+                }
+                Err(e) => outcome = Err(e),
+            }
+            while outcome.is_ok() {
+                let Some(result) = pending.remove(&next_to_handle) else {
+                    break;
+                };
+                outcome = handle(next_to_handle, result);
+                next_to_handle += 1;
+            }
+            if outcome.is_err() {
+                // Stop workers from claiming further jobs
+                next_job.store(jobs.len(), Ordering::SeqCst);
+            }
+        }
+        outcome
+    })
+}
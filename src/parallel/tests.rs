@@ -0,0 +1,97 @@
+use super::*;
+use std::time::Duration;
+
+#[test]
+fn test_run_ordered_handles_results_in_job_order() {
+    // Early jobs take longest, so they finish after later ones
+    let jobs: Vec<u64> = (0..8).rev().collect();
+    let mut handled = Vec::new();
+
+    run_ordered(
+        vec![(), (), ()],
+        &jobs,
+        |_, &delay| {
+            std::thread::sleep(Duration::from_millis(delay * 5));
+            Ok(delay * 10)
+        },
+        |index, result| {
+            handled.push((index, result));
+            Ok(())
+        },
+    )
+    .unwrap();
+
+    let expected: Vec<(usize, u64)> = jobs.iter().map(|&d| d * 10).enumerate().collect();
+    assert_eq!(handled, expected);
+}
+
+#[test]
+fn test_run_ordered_uses_every_worker() {
+    let jobs: Vec<usize> = (0..12).collect();
+    let mut used = std::collections::HashSet::new();
+
+    run_ordered(
+        vec![0usize, 1, 2],
+        &jobs,
+        |worker, _| {
+            std::thread::sleep(Duration::from_millis(5));
+            Ok(*worker)
+        },
+        |_, worker| {
+            used.insert(worker);
+            Ok(())
+        },
+    )
+    .unwrap();
+
+    assert_eq!(used.len(), 3);
+}
+
+#[test]
+fn test_run_ordered_propagates_worker_error() {
+    let jobs: Vec<usize> = (0..20).collect();
+    let mut handled = Vec::new();
+
+    let result = run_ordered(
+        vec![(), ()],
+        &jobs,
+        |_, &job| {
+            if job == 3 {
+                anyhow::bail!("job {job} failed");
+            }
+            Ok(job)
+        },
+        |index, _| {
+            handled.push(index);
+            Ok(())
+        },
+    );
+
+    assert_eq!(result.unwrap_err().to_string(), "job 3 failed");
+    assert!(handled.iter().all(|&index| index < 3));
+}
+
+#[test]
+fn test_run_ordered_propagates_handler_error() {
+    let jobs: Vec<usize> = (0..5).collect();
+
+    let result = run_ordered(
+        vec![()],
+        &jobs,
+        |_, &job| Ok(job),
+        |index, _| {
+            if index == 1 {
+                anyhow::bail!("write failed");
+            }
+            Ok(())
+        },
+    );
+
+    assert!(result.is_err());
+}
+
+#[test]
+fn test_run_ordered_without_jobs() {
+    let jobs: Vec<usize> = Vec::new();
+    run_ordered(vec![()], &jobs, |_, &job| Ok(job), |_, _| unreachable!()).unwrap();
+}
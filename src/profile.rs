@@ -0,0 +1,185 @@
+//! Time spent in each stage of a transcription, for `--profile`.
+//!
+//! The stages record themselves through [`span`] or [`record`] wherever
+//! they run, in the library as in the CLI, so that no timing has to be
+//! threaded through the call chain. Nothing is recorded until [`enable`] is
+//! called, and [`take`] returns what was recorded since the last call.
+//! Stages running on several model copies at once add up, so the total can
+//! exceed the wall-clock time.
+
+use std::cell::Cell;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+#[cfg(test)]
+mod tests;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+static PROFILE: Mutex<Profile> = Mutex::new(Profile::new());
+
+thread_local! {
+    /// Generation time of the chunk being transcribed on this thread.
+    static CHUNK_GENERATION: Cell<Duration> = const { Cell::new(Duration::ZERO) };
+}
+
+/// A timed stage of the pipeline.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    /// Reading and decoding the audio file.
+    Decode,
+    /// Resampling the decoded audio to the model's rate.
+    Resample,
+    /// Computing the log-mel features of each pass.
+    Mel,
+    /// The first forward pass of each pass through the model: the audio
+    /// encoder and the decoder over the prompt, shared by its attempts.
+    Encoder,
+    /// Generating tokens after the first forward pass.
+    Generation,
+}
+
+/// Seconds spent in each [`Stage`], summed over the recorded work.
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Profile {
+    pub decode_seconds: f64,
+    pub resample_seconds: f64,
+    pub mel_seconds: f64,
+    pub encoder_seconds: f64,
+    pub generation_seconds: f64,
+    /// Generation seconds of each transcribed chunk, in the order the
+    /// chunks completed.
+    pub chunk_generation_seconds: Vec<f64>,
+}
+
+impl Profile {
+    const fn new() -> Self {
+        Self {
+            decode_seconds: 0.0,
+            resample_seconds: 0.0,
+            mel_seconds: 0.0,
+            encoder_seconds: 0.0,
+            generation_seconds: 0.0,
+            chunk_generation_seconds: Vec::new(),
+        }
+    }
+
+    /// Add `elapsed` to the time of `stage`.
+    pub fn add(&mut self, stage: Stage, elapsed: Duration) {
+        let seconds = match stage {
+            Stage::Decode => &mut self.decode_seconds,
+            Stage::Resample => &mut self.resample_seconds,
+            Stage::Mel => &mut self.mel_seconds,
+            Stage::Encoder => &mut self.encoder_seconds,
+            Stage::Generation => &mut self.generation_seconds,
+        };
+        *seconds += elapsed.as_secs_f64();
+    }
+
+    /// Seconds spent in all stages together.
+    pub fn total_seconds(&self) -> f64 {
+        self.decode_seconds
+            + self.resample_seconds
+            + self.mel_seconds
+            + self.encoder_seconds
+            + self.generation_seconds
+    }
+}
+
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let total = self.total_seconds();
+        write!(f, "Time by stage:")?;
+        for (name, seconds) in [
+            ("decode", self.decode_seconds),
+            ("resample", self.resample_seconds),
+            ("mel", self.mel_seconds),
+            ("encoder", self.encoder_seconds),
+            ("generation", self.generation_seconds),
+        ] {
+            let share = if total > 0.0 { seconds / total } else { 0.0 };
+            write!(f, "\n  {name:<11} {seconds:>8.3} s {:>5.1}%", share * 100.0)?;
+        }
+        let chunks = &self.chunk_generation_seconds;
+        if !chunks.is_empty() {
+            let max = chunks.iter().copied().fold(0.0, f64::max);
+            let mean = chunks.iter().sum::<f64>() / chunks.len() as f64;
+            write!(
+                f,
+                "\nGeneration per chunk ({} chunks): mean {mean:.3} s, max {max:.3} s",
+                chunks.len()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Start recording stage times in this process.
+pub fn enable() {
+    ENABLED.store(true, Ordering::SeqCst);
+}
+
+/// Whether stage times are being recorded.
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Add `elapsed` to the time of `stage`, when recording.
+pub fn record(stage: Stage, elapsed: Duration) {
+    if !is_enabled() {
+        return;
+    }
+    if stage == Stage::Generation {
+        CHUNK_GENERATION.with(|chunk| chunk.set(chunk.get() + elapsed));
+    }
+    lock().add(stage, elapsed);
+}
+
+/// Timer of a stage that [`record`]s its time when dropped.
+#[must_use = "the stage is timed until the span is dropped"]
+pub struct Span {
+    stage: Stage,
+    started: Instant,
+}
+
+impl Drop for Span {
+    fn drop(&mut self) {
+        record(self.stage, self.started.elapsed());
+    }
+}
+
+/// Time `stage` until the returned span is dropped.
+pub fn span(stage: Stage) -> Span {
+    Span {
+        stage,
+        started: Instant::now(),
+    }
+}
+
+/// Mark the start of a chunk transcribed on this thread.
+pub fn start_chunk() {
+    CHUNK_GENERATION.with(|chunk| chunk.set(Duration::ZERO));
+}
+
+/// Mark the end of the chunk transcribed on this thread, keeping its
+/// generation time, when recording.
+pub fn finish_chunk() {
+    if !is_enabled() {
+        return;
+    }
+    let generation = CHUNK_GENERATION.with(|chunk| chunk.replace(Duration::ZERO));
+    lock()
+        .chunk_generation_seconds
+        .push(generation.as_secs_f64());
+}
+
+/// The times recorded since the last call, leaving an empty profile.
+pub fn take() -> Profile {
+    std::mem::take(&mut *lock())
+}
+
+fn lock() -> std::sync::MutexGuard<'static, Profile> {
+    PROFILE.lock().unwrap_or_else(|e| e.into_inner())
+}
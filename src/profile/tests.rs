@@ -0,0 +1,103 @@
+use super::*;
+use crate::audio::{self, Downmix, ResampleQuality};
+
+/// The profile is process wide, so tests that take it take turns.
+static PROFILE_LOCK: Mutex<()> = Mutex::new(());
+
+#[test]
+fn test_add_sums_by_stage() {
+    let mut profile = Profile::default();
+    profile.add(Stage::Mel, Duration::from_millis(250));
+    profile.add(Stage::Mel, Duration::from_millis(250));
+    profile.add(Stage::Generation, Duration::from_secs(2));
+
+    assert_eq!(profile.mel_seconds, 0.5);
+    assert_eq!(profile.generation_seconds, 2.0);
+    assert_eq!(profile.decode_seconds, 0.0);
+    assert_eq!(profile.total_seconds(), 2.5);
+}
+
+#[test]
+fn test_display_breakdown() {
+    let profile = Profile {
+        decode_seconds: 0.5,
+        resample_seconds: 0.25,
+        mel_seconds: 0.25,
+        encoder_seconds: 1.0,
+        generation_seconds: 3.0,
+        chunk_generation_seconds: vec![1.0, 2.0],
+    };
+
+    assert_eq!(
+        profile.to_string(),
+        "Time by stage:\n  \
+         decode         0.500 s  10.0%\n  \
+         resample       0.250 s   5.0%\n  \
+         mel            0.250 s   5.0%\n  \
+         encoder        1.000 s  20.0%\n  \
+         generation     3.000 s  60.0%\n\
+         Generation per chunk (2 chunks): mean 1.500 s, max 2.000 s"
+    );
+    assert!(Profile::default()
+        .to_string()
+        .contains("decode         0.000 s   0.0%"));
+}
+
+#[test]
+fn test_spans_and_chunks_are_recorded_once_enabled() {
+    let _guard = PROFILE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    enable();
+    take();
+
+    start_chunk();
+    record(Stage::Generation, Duration::from_millis(30));
+    {
+        let _span = span(Stage::Encoder);
+        std::thread::sleep(Duration::from_millis(5));
+    }
+    record(Stage::Generation, Duration::from_millis(20));
+    finish_chunk();
+
+    let profile = take();
+    assert!(profile.encoder_seconds >= 0.005, "{profile}");
+    assert!(profile.generation_seconds >= 0.05, "{profile}");
+    // Chunks transcribed by other tests may complete meanwhile
+    assert!(profile
+        .chunk_generation_seconds
+        .iter()
+        .any(|&seconds| (seconds - 0.05).abs() < 1e-9));
+}
+
+#[test]
+fn test_decode_and_prepare_is_profiled() {
+    let _guard = PROFILE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let path = temp_dir.path().join("tone.wav");
+    let tone: Vec<f32> = (0..8000)
+        .map(|i| 0.25 * (2.0 * std::f32::consts::PI * 300.0 * i as f32 / 8000.0).sin())
+        .collect();
+    audio::write_wav(&path, &tone, 8000).unwrap();
+    enable();
+    take();
+
+    let prepared = audio::decode_and_prepare(
+        &path,
+        16_000,
+        Some(ResampleQuality::Fast),
+        Downmix::Average,
+        true,
+    )
+    .unwrap();
+
+    assert_eq!(prepared.len(), 16_000);
+    let profile = take();
+    assert!(profile.decode_seconds > 0.0, "{profile}");
+    assert!(profile.resample_seconds > 0.0, "{profile}");
+    for seconds in [
+        profile.mel_seconds,
+        profile.encoder_seconds,
+        profile.generation_seconds,
+    ] {
+        assert!(seconds >= 0.0);
+    }
+}
@@ -0,0 +1,110 @@
+use anyhow::{Context, Result};
+use std::collections::BTreeMap;
+use std::fs::{File, OpenOptions};
+use std::path::{Path, PathBuf};
+
+use crate::align::WordTimestamp;
+use crate::hallucination::RollingContext;
+use crate::output::{ChunkTokens, ConfidenceFilter, Segment};
+
+#[cfg(test)]
+mod tests;
+
+/// State of a chunked transcription, saved after every chunk so an
+/// interrupted run can continue where it stopped.
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Progress {
+    /// Length of the prepared audio, used to detect a different input.
+    pub total_samples: usize,
+    /// Index of the next chunk to transcribe.
+    pub next_chunk: usize,
+    /// First sample of the next chunk.
+    pub next_sample: usize,
+    /// Bytes of each streamed output, by file extension, that belong to
+    /// completed chunks.
+    pub output_lens: BTreeMap<String, u64>,
+    pub skipped_chunks: usize,
+    pub suppressed_chunks: usize,
+    /// Chunks the model transcribed to no text.
+    #[serde(default)]
+    pub empty_chunks: usize,
+    /// Indexes of the chunks that failed every attempt.
+    #[serde(default)]
+    pub failed_chunks: Vec<usize>,
+    /// Segments so far, needed by outputs written at the end of the run.
+    pub segments: Vec<Segment>,
+    pub words: Vec<WordTimestamp>,
+    /// Tokens of every transcribed chunk so far, kept for `--dump-tokens`.
+    #[serde(default)]
+    pub chunk_tokens: Vec<ChunkTokens>,
+    /// State of `--min-confidence`, with the segments it holds back until a
+    /// segment meets its cutoff.
+    #[serde(default)]
+    pub confidence: Option<ConfidenceFilter>,
+    /// Tokens `--condition-on-previous` passes on to the next chunk.
+    #[serde(default)]
+    pub context: Option<RollingContext>,
+}
+
+impl Progress {
+    /// Sidecar path recording progress for `out_path`, e.g. `talk.txt.progress`.
+    pub fn path_for(out_path: &Path) -> PathBuf {
+        let mut path = out_path.as_os_str().to_owned();
+        path.push(".progress");
+        PathBuf::from(path)
+    }
+
+    /// Load progress from `path`, or `None` if there is no such file.
+    pub fn load(path: &Path) -> Result<Option<Self>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+        let file = File::open(path).context("Failed to open progress file")?;
+        let progress = serde_json::from_reader(std::io::BufReader::new(file))
+            .with_context(|| format!("Failed to parse progress file {}", path.display()))?;
+        Ok(Some(progress))
+    }
+
+    /// Save progress to `path`.
+    ///
+    /// The file is written next to the target and renamed over it, so a crash
+    /// while saving leaves the previous progress intact.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let mut tmp_path = path.as_os_str().to_owned();
+        tmp_path.push(".tmp");
+        let tmp_path = PathBuf::from(tmp_path);
+
+        let file = File::create(&tmp_path).context("Failed to create progress file")?;
+        serde_json::to_writer(std::io::BufWriter::new(file), self)
+            .context("Failed to write progress file")?;
+        std::fs::rename(&tmp_path, path).context("Failed to replace progress file")?;
+        Ok(())
+    }
+}
+
+/// Open the streamed output file for `--append`, creating it if needed and
+/// keeping what earlier runs wrote.
+pub fn append_output(path: &Path) -> Result<File> {
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("Failed to open {} to append to it", path.display()))
+}
+
+/// Open the streamed output file, continuing after `resume_len` bytes if given.
+///
+/// Anything past `resume_len`, such as a line written before a crash but not
+/// recorded in the progress file, is cut off before appending.
+pub fn open_output(path: &Path, resume_len: Option<u64>) -> Result<File> {
+    let Some(len) = resume_len else {
+        return File::create(path).context("Failed to create output file for transcription");
+    };
+    let file = OpenOptions::new()
+        .append(true)
+        .open(path)
+        .context("Failed to open output file to resume")?;
+    file.set_len(len)
+        .context("Failed to truncate output file to the last completed chunk")?;
+    Ok(file)
+}
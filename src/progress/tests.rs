@@ -0,0 +1,150 @@
+use super::*;
+use crate::output::fixtures::segment;
+use std::io::Write;
+use tempfile::TempDir;
+
+#[test]
+fn test_progress_path_for_appends_suffix() {
+    assert_eq!(
+        Progress::path_for(Path::new("dir/talk.txt")),
+        PathBuf::from("dir/talk.txt.progress")
+    );
+}
+
+#[test]
+fn test_progress_load_missing_file() {
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path().join("talk.txt.progress");
+    assert!(Progress::load(&path).unwrap().is_none());
+}
+
+#[test]
+fn test_progress_save_load_roundtrip() {
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path().join("talk.txt.progress");
+    let progress = Progress {
+        total_samples: 1_000_000,
+        next_chunk: 2,
+        next_sample: 432_000,
+        output_lens: BTreeMap::from([("txt".to_string(), 12)]),
+        skipped_chunks: 1,
+        suppressed_chunks: 0,
+        empty_chunks: 2,
+        failed_chunks: vec![3],
+        segments: vec![segment(0, "Hello", Some(-0.1))],
+        words: Vec::new(),
+        chunk_tokens: vec![ChunkTokens {
+            index: 0,
+            start_sec: 0.0,
+            end_sec: 15.0,
+            tokens: vec![1032, 7, 42],
+        }],
+        confidence: None,
+        context: None,
+    };
+
+    progress.save(&path).unwrap();
+
+    assert_eq!(Progress::load(&path).unwrap(), Some(progress));
+    assert!(!temp_dir.path().join("talk.txt.progress.tmp").exists());
+}
+
+#[test]
+fn test_progress_keeps_the_segments_held_by_min_confidence() {
+    use crate::output::LowConfidenceAction;
+
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path().join("talk.txt.progress");
+    let mut filter = ConfidenceFilter::new(-0.5, LowConfidenceAction::Drop);
+    let unsure = segment(0, "mumbled", Some(-2.0));
+    assert!(filter.admit(unsure.clone()).is_empty());
+    Progress {
+        next_chunk: 1,
+        confidence: Some(filter),
+        ..Progress::default()
+    }
+    .save(&path)
+    .unwrap();
+
+    // The run is resumed, and the file ends without a confident segment
+    let mut resumed = Progress::load(&path).unwrap().unwrap();
+    let filter = resumed.confidence.as_mut().unwrap();
+    assert!(filter.all_low());
+    assert_eq!(filter.finish(), [unsure]);
+
+    // Progress saved before the filter was kept still loads
+    std::fs::write(
+        &path,
+        r#"{"total_samples":0,"next_chunk":0,"next_sample":0,"output_lens":{},
+            "skipped_chunks":0,"suppressed_chunks":0,"segments":[],"words":[]}"#,
+    )
+    .unwrap();
+    assert_eq!(Progress::load(&path).unwrap().unwrap().confidence, None);
+}
+
+#[test]
+fn test_progress_keeps_the_condition_on_previous_context() {
+    use crate::hallucination::RollingContext;
+
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path().join("talk.txt.progress");
+    let mut context = RollingContext::new(4);
+    assert!(context.follow(&[5, 6, 7, 8, 9], false));
+    Progress {
+        next_chunk: 1,
+        context: Some(context),
+        ..Progress::default()
+    }
+    .save(&path)
+    .unwrap();
+
+    let resumed = Progress::load(&path).unwrap().unwrap();
+    assert_eq!(resumed.context.unwrap().tokens(), [6, 7, 8, 9]);
+}
+
+#[test]
+fn test_resumed_output_matches_uninterrupted_run() {
+    let temp_dir = TempDir::new().unwrap();
+    let lines = ["first chunk", "second chunk", "third chunk", "fourth"];
+
+    let full_path = temp_dir.path().join("full.txt");
+    let mut full = open_output(&full_path, None).unwrap();
+    for line in lines {
+        writeln!(full, "{line}").unwrap();
+    }
+
+    // Two chunks complete, then a crash in the middle of writing the third
+    let resumed_path = temp_dir.path().join("resumed.txt");
+    let mut first_run = open_output(&resumed_path, None).unwrap();
+    writeln!(first_run, "{}", lines[0]).unwrap();
+    writeln!(first_run, "{}", lines[1]).unwrap();
+    let output_len = first_run.metadata().unwrap().len();
+    write!(first_run, "third ch").unwrap();
+    drop(first_run);
+
+    let mut second_run = open_output(&resumed_path, Some(output_len)).unwrap();
+    for line in &lines[2..] {
+        writeln!(second_run, "{line}").unwrap();
+    }
+
+    assert_eq!(
+        std::fs::read(&full_path).unwrap(),
+        std::fs::read(&resumed_path).unwrap()
+    );
+}
+
+#[test]
+fn test_append_output_keeps_earlier_runs() {
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path().join("project.txt");
+
+    for line in ["=== one.wav ===", "=== two.wav ==="] {
+        let mut run = append_output(&path).unwrap();
+        writeln!(run, "{line}").unwrap();
+    }
+
+    assert_eq!(
+        std::fs::read_to_string(&path).unwrap(),
+        "=== one.wav ===\n=== two.wav ===\n"
+    );
+}
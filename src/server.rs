@@ -0,0 +1,538 @@
+//! Minimal HTTP server that keeps one model loaded and transcribes uploaded
+//! audio, behind the `server` feature.
+//!
+//! - `POST /transcribe` takes the audio as the raw request body or as the
+//!   first file of a `multipart/form-data` upload, and answers with the
+//!   segments as a JSON array, as written by `--format json`. A
+//!   `?language=de` query parameter, one of [`SUPPORTED_LANGUAGES`],
+//!   overrides the server's language.
+//! - `GET /health` answers `{"status":"ok"}` once the model is loaded.
+//!
+//! Every connection gets a thread of its own, up to
+//! [`ServerConfig::max_connections`] at once; the connections past the limit
+//! are answered 503 straight away, so that waiting uploads cannot pile up
+//! in memory. There is one model, so uploads are transcribed one at a time
+//! and the others wait for the model in turn. Each connection carries a
+//! single request, which must state its `Content-Length`; chunked uploads
+//! are refused.
+
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+
+use anyhow::Result;
+
+use crate::audio;
+use crate::chunk::ChunkPlan;
+use crate::model::{TranscribeOptions, VoxtralModel, SUPPORTED_LANGUAGES};
+use crate::output::Segment;
+use crate::timeline::Timeline;
+
+#[cfg(test)]
+mod tests;
+
+/// Largest request line and header block accepted.
+const MAX_HEAD_BYTES: usize = 16 * 1024;
+
+/// Most header fields accepted in a request.
+const MAX_HEADERS: usize = 64;
+
+/// How long a client may stall while sending its request.
+const READ_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How long a client may stall while the busy answer is sent to it.
+const BUSY_WRITE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How the server transcribes uploads.
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    /// Chunks each upload is split into, one segment per chunk.
+    pub plan: ChunkPlan,
+    /// Prompt language and decoding safeguards of every upload.
+    pub options: TranscribeOptions,
+    /// Detect the language of each upload instead of using the one in
+    /// `options`, unless the request names one.
+    pub detect_language: bool,
+    /// Largest upload accepted, in bytes.
+    pub max_upload_bytes: usize,
+    /// Most connections answered at once, each holding up to
+    /// `max_upload_bytes` of upload.
+    pub max_connections: usize,
+}
+
+/// Error answered to a request, with its HTTP status.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HttpError {
+    /// HTTP status code, such as 400 for audio that cannot be decoded.
+    pub status: u16,
+    /// What went wrong, sent as the `error` field of the body.
+    pub message: String,
+}
+
+impl HttpError {
+    fn new(status: u16, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            message: message.into(),
+        }
+    }
+
+    /// The error as the JSON body of a response: `{"error":"..."}`.
+    fn body(&self) -> String {
+        serde_json::json!({ "error": self.message }).to_string()
+    }
+}
+
+/// Request line and the headers the server acts on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Head {
+    method: String,
+    path: String,
+    query: String,
+    content_type: Option<String>,
+    content_length: Option<usize>,
+    chunked: bool,
+    expect_continue: bool,
+}
+
+/// Counts the connections being answered, up to a limit.
+#[derive(Debug)]
+struct ConnectionLimit {
+    active: AtomicUsize,
+    max: usize,
+}
+
+/// A connection counted against its [`ConnectionLimit`] until dropped.
+#[derive(Debug)]
+struct ConnectionSlot(Arc<ConnectionLimit>);
+
+impl ConnectionLimit {
+    fn new(max: usize) -> Self {
+        Self {
+            active: AtomicUsize::new(0),
+            max,
+        }
+    }
+
+    /// A slot for one more connection, or `None` when all are taken.
+    fn acquire(self: &Arc<Self>) -> Option<ConnectionSlot> {
+        self.active
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |active| {
+                (active < self.max).then_some(active + 1)
+            })
+            .ok()?;
+        Some(ConnectionSlot(Arc::clone(self)))
+    }
+}
+
+impl Drop for ConnectionSlot {
+    fn drop(&mut self) {
+        self.0.active.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// A model shared by the connections of a server.
+pub struct Server {
+    model: Mutex<VoxtralModel>,
+    /// Sample rate uploads are resampled to, the one the model expects.
+    sample_rate: u32,
+    connections: Arc<ConnectionLimit>,
+    config: ServerConfig,
+}
+
+impl Server {
+    /// A server transcribing uploads with `model` as `config` describes.
+    pub fn new(model: VoxtralModel, config: ServerConfig) -> Self {
+        Self {
+            sample_rate: model.sample_rate(),
+            model: Mutex::new(model),
+            connections: Arc::new(ConnectionLimit::new(config.max_connections)),
+            config,
+        }
+    }
+
+    /// Answer the connections of `listener`, each on a thread of its own,
+    /// until accepting fails. Connections past
+    /// [`ServerConfig::max_connections`] are answered 503 without reading
+    /// their request.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the listener stops accepting connections.
+    pub fn run(self: Arc<Self>, listener: TcpListener) -> Result<()> {
+        for stream in listener.incoming() {
+            let mut stream = stream?;
+            let Some(slot) = self.connections.acquire() else {
+                refuse_busy(&mut stream);
+                continue;
+            };
+            let server = Arc::clone(&self);
+            std::thread::spawn(move || {
+                server.handle(stream);
+                drop(slot);
+            });
+        }
+        Ok(())
+    }
+
+    /// Read one request from `stream`, answer it and close the connection.
+    fn handle(&self, mut stream: TcpStream) {
+        let peer = stream
+            .peer_addr()
+            .map_or_else(|_| "unknown".to_string(), |addr| addr.to_string());
+        if let Err(e) = stream.set_read_timeout(Some(READ_TIMEOUT)) {
+            log::warn!("{peer}: {e}");
+            return;
+        }
+        let (status, body) = match self.respond(&mut stream) {
+            Ok(body) => (200, body),
+            Err(e) => (e.status, e.body()),
+        };
+        log::info!("{peer}: {status}");
+        if let Err(e) = stream.write_all(&response(status, &body)) {
+            log::warn!("{peer}: failed to send response: {e}");
+        }
+    }
+
+    /// The body of a successful answer to the request on `stream`.
+    fn respond(&self, stream: &mut TcpStream) -> Result<String, HttpError> {
+        let (head, received) = read_head(stream)?;
+        log::info!("{} {}", head.method, head.path);
+        match (head.method.as_str(), head.path.as_str()) {
+            ("GET", "/health") => Ok(serde_json::json!({ "status": "ok" }).to_string()),
+            ("POST", "/transcribe") => {
+                check_upload(&head, self.config.max_upload_bytes)?;
+                if head.expect_continue {
+                    stream
+                        .write_all(b"HTTP/1.1 100 Continue\r\n\r\n")
+                        .map_err(|e| HttpError::new(400, e.to_string()))?;
+                }
+                let body = read_body(stream, &head, received)?;
+                let (audio, filename) = match head.content_type.as_deref().and_then(boundary) {
+                    Some(boundary) => multipart_file(&body, boundary)
+                        .ok_or_else(|| HttpError::new(400, "no file in the multipart upload"))?,
+                    None => (body.as_slice(), None),
+                };
+                let hint = format_hint(head.content_type.as_deref(), filename.as_deref());
+                let language = query_param(&head.query, "language");
+                if let Some(language) = &language {
+                    check_language(language)?;
+                }
+                let segments = self.transcribe(audio, hint.as_deref(), language.as_deref())?;
+                serde_json::to_string(&segments).map_err(|e| HttpError::new(500, e.to_string()))
+            }
+            (_, "/health" | "/transcribe") => Err(HttpError::new(405, "method not allowed")),
+            (_, path) => Err(HttpError::new(404, format!("no such endpoint {path}"))),
+        }
+    }
+
+    /// Transcribe encoded `audio` in the chunks of the server's plan, in
+    /// `language` or else the server's language, waiting for the model when
+    /// another upload holds it.
+    ///
+    /// # Errors
+    ///
+    /// Returns a 400 error if the audio cannot be decoded and a 500 error if
+    /// it cannot be transcribed.
+    pub fn transcribe(
+        &self,
+        audio: &[u8],
+        format_hint: Option<&str>,
+        language: Option<&str>,
+    ) -> Result<Vec<Segment>, HttpError> {
+        let (pcm, sample_rate) =
+            audio::pcm_decode_reader(std::io::Cursor::new(audio.to_vec()), format_hint)
+                .map_err(|e| HttpError::new(400, format!("Failed to decode audio: {e}")))?;
+        if pcm.is_empty() {
+            return Err(HttpError::new(400, "No audio samples after decoding"));
+        }
+        let pcm = if sample_rate == self.sample_rate {
+            pcm
+        } else {
+            audio::resample(&pcm, sample_rate, self.sample_rate)
+                .map_err(|e| HttpError::new(500, format!("Failed to resample audio: {e}")))?
+        };
+        let internal = |e: anyhow::Error| HttpError::new(500, format!("{e:#}"));
+
+        // The model keeps no state from one pass to the next, so a request
+        // that panicked while holding it leaves it usable
+        let mut model = self.model.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut options = self.config.options.clone();
+        match language {
+            Some(language) => options.language = language.to_string(),
+            None if self.config.detect_language => {
+                let candidates = model
+                    .detect_language(&pcm, self.sample_rate)
+                    .map_err(internal)?;
+                if let Some(best) = candidates.into_iter().next() {
+                    options.language = best.language;
+                }
+            }
+            None => {}
+        }
+        log::info!(
+            "Transcribing {:.1} s of audio in language {}",
+            pcm.len() as f32 / self.sample_rate as f32,
+            options.language
+        );
+
+        let timeline = Timeline::identity(pcm.len(), self.sample_rate);
+        let mut segments = Vec::new();
+        for (index, range) in self.config.plan.ranges(pcm.len()).into_iter().enumerate() {
+            let result = model
+                .transcribe_audio_16k(&pcm[range.clone()], &options, |_| {})
+                .map_err(internal)?;
+            // Chunks without text make no segment, as in the CLI transcripts
+            if result.text.trim().is_empty() {
+                continue;
+            }
+            let (start_sec, end_sec) = timeline.seconds(&range);
+            segments.push(Segment {
+                index,
+                start_sec,
+                end_sec,
+                text: result.text.trim().to_string(),
+                avg_logprob: Some(result.avg_logprob),
+                no_speech_prob: Some(result.no_speech_prob),
+                speaker: None,
+                language: None,
+                failed: false,
+            });
+        }
+        Ok(segments)
+    }
+}
+
+/// Read the request line and headers from `reader`, returning them with the
+/// bytes of the body that arrived along with them.
+fn read_head<R: Read>(reader: &mut R) -> Result<(Head, Vec<u8>), HttpError> {
+    let mut buf = Vec::new();
+    let mut chunk = [0; 4096];
+    loop {
+        let read = reader
+            .read(&mut chunk)
+            .map_err(|e| HttpError::new(400, e.to_string()))?;
+        if read == 0 {
+            return Err(HttpError::new(400, "connection closed before the request"));
+        }
+        buf.extend_from_slice(&chunk[..read]);
+        if let Some((head, len)) = parse_head(&buf)? {
+            return Ok((head, buf.split_off(len)));
+        }
+        if buf.len() > MAX_HEAD_BYTES {
+            return Err(HttpError::new(431, "request headers too large"));
+        }
+    }
+}
+
+/// Parse the request line and headers at the start of `buf`, with their
+/// length in bytes, or `None` when they are not complete yet.
+fn parse_head(buf: &[u8]) -> Result<Option<(Head, usize)>, HttpError> {
+    let mut headers = [httparse::EMPTY_HEADER; MAX_HEADERS];
+    let mut request = httparse::Request::new(&mut headers);
+    let len = match request.parse(buf) {
+        Ok(httparse::Status::Complete(len)) => len,
+        Ok(httparse::Status::Partial) => return Ok(None),
+        Err(e) => return Err(HttpError::new(400, format!("malformed request: {e}"))),
+    };
+    let target = request.path.unwrap_or("/");
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let mut head = Head {
+        method: request.method.unwrap_or_default().to_string(),
+        path: path.to_string(),
+        query: query.to_string(),
+        content_type: None,
+        content_length: None,
+        chunked: false,
+        expect_continue: false,
+    };
+    for header in request.headers.iter() {
+        let value = String::from_utf8_lossy(header.value).trim().to_string();
+        match header.name.to_ascii_lowercase().as_str() {
+            "content-type" => head.content_type = Some(value),
+            "content-length" => {
+                let length = value
+                    .parse()
+                    .map_err(|_| HttpError::new(400, "invalid Content-Length"))?;
+                head.content_length = Some(length);
+            }
+            "transfer-encoding" => head.chunked = value.to_ascii_lowercase().contains("chunked"),
+            "expect" => head.expect_continue = value.eq_ignore_ascii_case("100-continue"),
+            _ => {}
+        }
+    }
+    Ok(Some((head, len)))
+}
+
+/// Refuse a language the model was not trained on, such as a typo.
+fn check_language(language: &str) -> Result<(), HttpError> {
+    if SUPPORTED_LANGUAGES.contains(&language) {
+        return Ok(());
+    }
+    Err(HttpError::new(
+        400,
+        format!(
+            "unsupported language '{language}', expected one of {}",
+            SUPPORTED_LANGUAGES.join(", ")
+        ),
+    ))
+}
+
+/// Answer a connection past the limit with 503, without reading its request.
+fn refuse_busy(stream: &mut TcpStream) {
+    let error = HttpError::new(503, "too many connections, try again later");
+    log::warn!("{}", error.message);
+    let sent = stream
+        .set_write_timeout(Some(BUSY_WRITE_TIMEOUT))
+        .and_then(|()| stream.write_all(&response(error.status, &error.body())));
+    if let Err(e) = sent {
+        log::warn!("failed to send response: {e}");
+    }
+}
+
+/// Refuse an upload without a length or longer than `max_bytes`.
+fn check_upload(head: &Head, max_bytes: usize) -> Result<(), HttpError> {
+    match head.content_length {
+        _ if head.chunked => Err(HttpError::new(
+            411,
+            "chunked uploads are not supported, send a Content-Length",
+        )),
+        None => Err(HttpError::new(411, "Content-Length required")),
+        Some(0) => Err(HttpError::new(400, "empty upload")),
+        Some(length) if length > max_bytes => Err(HttpError::new(
+            413,
+            format!("upload of {length} bytes exceeds the limit of {max_bytes} bytes"),
+        )),
+        Some(_) => Ok(()),
+    }
+}
+
+/// The body of the request, of `head`'s length, after the `received` bytes
+/// that came with the head.
+fn read_body<R: Read>(
+    reader: &mut R,
+    head: &Head,
+    received: Vec<u8>,
+) -> Result<Vec<u8>, HttpError> {
+    let length = head.content_length.unwrap_or_default();
+    let mut body = received;
+    body.truncate(length);
+    let missing = length - body.len();
+    reader
+        .take(missing as u64)
+        .read_to_end(&mut body)
+        .map_err(|e| HttpError::new(400, e.to_string()))?;
+    if body.len() < length {
+        return Err(HttpError::new(
+            400,
+            format!("upload ended after {} of {length} bytes", body.len()),
+        ));
+    }
+    Ok(body)
+}
+
+/// The boundary of a `multipart/form-data` content type.
+fn boundary(content_type: &str) -> Option<&str> {
+    let (mime, params) = content_type.split_once(';')?;
+    if !mime.trim().eq_ignore_ascii_case("multipart/form-data") {
+        return None;
+    }
+    params.split(';').find_map(|param| {
+        let (name, value) = param.split_once('=')?;
+        name.trim()
+            .eq_ignore_ascii_case("boundary")
+            .then(|| value.trim().trim_matches('"'))
+    })
+}
+
+/// The data and file name of the first file in a `multipart/form-data`
+/// body, or of its first part when none is a file.
+fn multipart_file<'a>(body: &'a [u8], boundary: &str) -> Option<(&'a [u8], Option<String>)> {
+    let delimiter = format!("--{boundary}");
+    let mut parts = Vec::new();
+    let mut rest = &body[find(body, delimiter.as_bytes())? + delimiter.len()..];
+    // Each part runs up to the next delimiter, which closes the body when
+    // followed by `--`
+    while !rest.starts_with(b"--") {
+        let end = find(rest, delimiter.as_bytes())?;
+        let part = rest[..end].strip_prefix(b"\r\n")?;
+        let part = part.strip_suffix(b"\r\n").unwrap_or(part);
+        let header_end = find(part, b"\r\n\r\n")?;
+        let headers = String::from_utf8_lossy(&part[..header_end]);
+        parts.push((headers.into_owned(), &part[header_end + 4..]));
+        rest = &rest[end + delimiter.len()..];
+    }
+    let (headers, data) = parts
+        .iter()
+        .find(|(headers, _)| headers.contains("filename="))
+        .or(parts.first())?;
+    let filename = headers.split(';').find_map(|param| {
+        let value = param.trim().strip_prefix("filename=")?;
+        Some(value.lines().next()?.trim().trim_matches('"').to_string())
+    });
+    Some((data, filename))
+}
+
+/// Position of the first `needle` in `haystack`.
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+/// File extension to hint the decoder with: that of the uploaded file name,
+/// else one matching the content type.
+fn format_hint(content_type: Option<&str>, filename: Option<&str>) -> Option<String> {
+    let from_name = filename
+        .and_then(|name| std::path::Path::new(name).extension())
+        .map(|ext| ext.to_string_lossy().to_ascii_lowercase());
+    from_name.or_else(|| {
+        let mime = content_type?.split(';').next()?.trim().to_ascii_lowercase();
+        let ext = match mime.as_str() {
+            "audio/wav" | "audio/wave" | "audio/x-wav" | "audio/vnd.wave" => "wav",
+            "audio/mpeg" | "audio/mp3" => "mp3",
+            "audio/flac" | "audio/x-flac" => "flac",
+            "audio/ogg" => "ogg",
+            "audio/mp4" | "audio/m4a" | "audio/x-m4a" => "m4a",
+            "audio/aac" => "aac",
+            "audio/webm" | "video/webm" => "webm",
+            _ => return None,
+        };
+        Some(ext.to_string())
+    })
+}
+
+/// Value of the `name` parameter of a query string, such as `de` for
+/// `language` in `language=de&x=1`.
+fn query_param(query: &str, name: &str) -> Option<String> {
+    query.split('&').find_map(|pair| {
+        let (key, value) = pair.split_once('=')?;
+        (key == name && !value.is_empty()).then(|| value.to_string())
+    })
+}
+
+/// A complete HTTP response with a JSON `body`, closing the connection.
+fn response(status: u16, body: &str) -> Vec<u8> {
+    let reason = match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        411 => "Length Required",
+        413 => "Content Too Large",
+        431 => "Request Header Fields Too Large",
+        503 => "Service Unavailable",
+        _ => "Internal Server Error",
+    };
+    format!(
+        "HTTP/1.1 {status} {reason}\r\n\
+         Content-Type: application/json\r\n\
+         Content-Length: {}\r\n\
+         Connection: close\r\n\r\n\
+         {body}",
+        body.len()
+    )
+    .into_bytes()
+}
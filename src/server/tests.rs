@@ -0,0 +1,200 @@
+use super::*;
+use std::io::Cursor;
+
+fn head(request: &str) -> Head {
+    parse_head(request.as_bytes()).unwrap().unwrap().0
+}
+
+#[test]
+fn test_parse_head_reads_request_line_and_headers() {
+    let request = "POST /transcribe?language=de HTTP/1.1\r\n\
+                   Host: localhost\r\n\
+                   content-type: audio/wav\r\n\
+                   Content-Length: 4\r\n\
+                   Expect: 100-continue\r\n\r\nRIFF";
+
+    let (head, len) = parse_head(request.as_bytes()).unwrap().unwrap();
+
+    assert_eq!(head.method, "POST");
+    assert_eq!(head.path, "/transcribe");
+    assert_eq!(head.query, "language=de");
+    assert_eq!(head.content_type.as_deref(), Some("audio/wav"));
+    assert_eq!(head.content_length, Some(4));
+    assert!(head.expect_continue);
+    assert!(!head.chunked);
+    assert_eq!(&request.as_bytes()[len..], b"RIFF");
+}
+
+#[test]
+fn test_parse_head_partial_and_malformed() {
+    assert_eq!(
+        parse_head(b"GET /health HTTP/1.1\r\nHost: x\r\n").unwrap(),
+        None
+    );
+    let err = parse_head(b"GET /health HTTP/1.1\r\nHost\r\n\r\n").unwrap_err();
+    assert_eq!(err.status, 400);
+    let err = parse_head(b"POST / HTTP/1.1\r\nContent-Length: many\r\n\r\n").unwrap_err();
+    assert_eq!(err.message, "invalid Content-Length");
+}
+
+#[test]
+fn test_read_head_and_body() {
+    let request = b"POST /transcribe HTTP/1.1\r\nContent-Length: 5\r\n\r\nhello, extra";
+    let mut stream = Cursor::new(request.to_vec());
+
+    let (head, received) = read_head(&mut stream).unwrap();
+    let body = read_body(&mut stream, &head, received).unwrap();
+
+    assert_eq!(body, b"hello");
+
+    let request = b"POST /transcribe HTTP/1.1\r\nContent-Length: 9\r\n\r\nhello";
+    let mut stream = Cursor::new(request.to_vec());
+    let (head, received) = read_head(&mut stream).unwrap();
+    let err = read_body(&mut stream, &head, received).unwrap_err();
+    assert_eq!(err.message, "upload ended after 5 of 9 bytes");
+}
+
+#[test]
+fn test_read_head_rejects_endless_headers() {
+    let mut request = b"GET /health HTTP/1.1\r\n".to_vec();
+    request.resize(request.len() + MAX_HEAD_BYTES + 1, b'a');
+
+    let err = read_head(&mut Cursor::new(request)).unwrap_err();
+
+    assert_eq!(err.status, 431);
+}
+
+#[test]
+fn test_check_upload_limits() {
+    let limit = 1024;
+    let upload = |header: &str| head(&format!("POST /transcribe HTTP/1.1\r\n{header}\r\n\r\n"));
+    let status = |header: &str| check_upload(&upload(header), limit).unwrap_err().status;
+
+    assert!(check_upload(&upload("Content-Length: 1024"), limit).is_ok());
+    assert_eq!(status("Content-Length: 1025"), 413);
+    assert_eq!(status("Content-Length: 0"), 400);
+    assert_eq!(status("Host: x"), 411);
+    assert_eq!(status("Transfer-Encoding: chunked"), 411);
+}
+
+#[test]
+fn test_check_language() {
+    assert!(check_language("de").is_ok());
+    let err = check_language("xx").unwrap_err();
+    assert_eq!(err.status, 400);
+    assert_eq!(
+        err.message,
+        "unsupported language 'xx', expected one of en, es, fr, pt, hi, de, nl, it"
+    );
+    assert!(check_language("DE").is_err());
+}
+
+#[test]
+fn test_connection_limit() {
+    let limit = Arc::new(ConnectionLimit::new(2));
+
+    let first = limit.acquire().unwrap();
+    let second = limit.acquire().unwrap();
+    assert!(limit.acquire().is_none());
+
+    drop(first);
+    let third = limit.acquire().unwrap();
+    assert!(limit.acquire().is_none());
+    drop((second, third));
+    assert_eq!(limit.active.load(Ordering::SeqCst), 0);
+}
+
+#[test]
+fn test_multipart_boundary() {
+    assert_eq!(boundary("multipart/form-data; boundary=abc"), Some("abc"));
+    assert_eq!(
+        boundary("Multipart/Form-Data; charset=x; boundary=\"a b\""),
+        Some("a b")
+    );
+    assert_eq!(boundary("audio/wav"), None);
+    assert_eq!(boundary("multipart/mixed; boundary=abc"), None);
+}
+
+#[test]
+fn test_multipart_file_prefers_the_file_part() {
+    let body = b"--XyZ\r\n\
+                 Content-Disposition: form-data; name=\"note\"\r\n\r\n\
+                 hi\r\n\
+                 --XyZ\r\n\
+                 Content-Disposition: form-data; name=\"file\"; filename=\"talk.mp3\"\r\n\
+                 Content-Type: audio/mpeg\r\n\r\n\
+                 ID3\r\n--\r\n\
+                 --XyZ--\r\n";
+
+    let (data, filename) = multipart_file(body, "XyZ").unwrap();
+
+    assert_eq!(data, b"ID3\r\n--");
+    assert_eq!(filename.as_deref(), Some("talk.mp3"));
+}
+
+#[test]
+fn test_multipart_file_without_file_or_end() {
+    let body = b"--b\r\nContent-Disposition: form-data; name=\"audio\"\r\n\r\nRIFF\r\n--b--";
+    assert_eq!(multipart_file(body, "b"), Some((&b"RIFF"[..], None)));
+
+    assert_eq!(multipart_file(b"--b\r\nno end", "b"), None);
+    assert_eq!(multipart_file(b"no delimiter", "b"), None);
+}
+
+#[test]
+fn test_format_hint() {
+    assert_eq!(
+        format_hint(Some("audio/wav"), Some("Talk.FLAC")).as_deref(),
+        Some("flac")
+    );
+    assert_eq!(
+        format_hint(Some("audio/mpeg; x=1"), None).as_deref(),
+        Some("mp3")
+    );
+    assert_eq!(format_hint(Some("application/octet-stream"), None), None);
+    assert_eq!(format_hint(None, Some("noext")), None);
+}
+
+#[test]
+fn test_query_param() {
+    assert_eq!(
+        query_param("language=de", "language").as_deref(),
+        Some("de")
+    );
+    assert_eq!(
+        query_param("a=1&language=fi", "language").as_deref(),
+        Some("fi")
+    );
+    assert_eq!(query_param("language=", "language"), None);
+    assert_eq!(query_param("", "language"), None);
+}
+
+#[test]
+fn test_refuse_busy_answers_503() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    let (mut stream, _) = listener.accept().unwrap();
+
+    refuse_busy(&mut stream);
+    drop(stream);
+
+    let mut answer = String::new();
+    client.read_to_string(&mut answer).unwrap();
+    assert!(answer.starts_with("HTTP/1.1 503 Service Unavailable\r\n"));
+    assert!(answer.ends_with("{\"error\":\"too many connections, try again later\"}"));
+}
+
+#[test]
+fn test_response_and_error_body() {
+    let error = HttpError::new(413, "too \"big\"");
+    let response = String::from_utf8(response(error.status, &error.body())).unwrap();
+
+    assert_eq!(
+        response,
+        "HTTP/1.1 413 Content Too Large\r\n\
+         Content-Type: application/json\r\n\
+         Content-Length: 23\r\n\
+         Connection: close\r\n\r\n\
+         {\"error\":\"too \\\"big\\\"\"}"
+    );
+}
@@ -0,0 +1,62 @@
+use anyhow::{Context, Result};
+use std::path::PathBuf;
+
+use crate::profile::Profile;
+
+#[cfg(test)]
+mod tests;
+
+/// Machine-readable account of a transcription run, for automation that
+/// should not parse the log.
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct RunSummary {
+    /// The transcribed audio file.
+    pub input: PathBuf,
+    /// Duration of the audio in seconds.
+    pub audio_seconds: f64,
+    /// Sample rate of the input file in Hz, before resampling, when the
+    /// container states it.
+    pub sample_rate: Option<u32>,
+    /// Level of the loudest sample of the transcribed audio in dBFS.
+    #[serde(default)]
+    pub peak_dbfs: f32,
+    /// RMS level of the transcribed audio in dBFS.
+    #[serde(default)]
+    pub rms_dbfs: f32,
+    /// Device the model ran on, such as `Cpu` or `Cuda { gpu_id: 0 }`.
+    pub device: String,
+    /// Language code the audio was transcribed as.
+    pub language: String,
+    /// Chunks the audio was split into, over all channels.
+    pub chunks: usize,
+    /// Chunks skipped as silent without running the model.
+    pub skipped_chunks: usize,
+    /// Chunks whose text was suppressed because they held no speech.
+    pub suppressed_chunks: usize,
+    /// Chunks the model transcribed to no text, left out of the transcript.
+    #[serde(default)]
+    pub empty_chunks: usize,
+    /// Chunks that failed every attempt to transcribe them.
+    pub failed_chunks: usize,
+    /// Tokens generated over all chunks.
+    pub tokens: usize,
+    /// Time spent in the model over all chunks, summed over the models
+    /// of `--parallel`.
+    #[serde(default)]
+    pub model_seconds: f64,
+    /// Tokens generated per second of `model_seconds`.
+    #[serde(default)]
+    pub tokens_per_second: f64,
+    /// Wall-clock time spent transcribing, excluding model loading.
+    pub elapsed_seconds: f64,
+    /// Time spent in each stage, with `--profile`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<Profile>,
+}
+
+impl RunSummary {
+    /// Serialize the summary as pretty-printed JSON.
+    pub fn to_json(&self) -> Result<String> {
+        serde_json::to_string_pretty(self).context("Failed to serialize run summary")
+    }
+}
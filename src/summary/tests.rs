@@ -0,0 +1,80 @@
+use super::*;
+
+#[test]
+fn test_summary_json_round_trip() {
+    let summary = RunSummary {
+        input: PathBuf::from("talk.wav"),
+        audio_seconds: 62.5,
+        sample_rate: Some(44_100),
+        peak_dbfs: -1.5,
+        rms_dbfs: -23.25,
+        device: "Cpu".to_string(),
+        language: "en".to_string(),
+        chunks: 5,
+        skipped_chunks: 1,
+        suppressed_chunks: 0,
+        empty_chunks: 2,
+        failed_chunks: 1,
+        tokens: 321,
+        model_seconds: 10.5,
+        tokens_per_second: 30.5,
+        elapsed_seconds: 12.25,
+        profile: None,
+    };
+
+    let json = summary.to_json().unwrap();
+    let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+    assert_eq!(value["input"], "talk.wav");
+    assert_eq!(value["sample_rate"], 44_100);
+    assert_eq!(value["failed_chunks"], 1);
+    assert_eq!(value["rms_dbfs"], -23.25);
+    assert_eq!(value["tokens_per_second"], 30.5);
+
+    assert_eq!(serde_json::from_str::<RunSummary>(&json).unwrap(), summary);
+    assert!(value.get("profile").is_none());
+}
+
+#[test]
+fn test_summary_without_later_fields_still_parses() {
+    let json = r#"{
+        "input": "talk.wav",
+        "audio_seconds": 62.5,
+        "sample_rate": null,
+        "device": "Cpu",
+        "language": "en",
+        "chunks": 5,
+        "skipped_chunks": 1,
+        "suppressed_chunks": 0,
+        "failed_chunks": 0,
+        "tokens": 321,
+        "elapsed_seconds": 12.25
+    }"#;
+
+    let summary: RunSummary = serde_json::from_str(json).unwrap();
+
+    assert_eq!(summary.tokens, 321);
+    assert_eq!(summary.empty_chunks, 0);
+    assert_eq!(summary.peak_dbfs, 0.0);
+    assert_eq!(summary.rms_dbfs, 0.0);
+    assert_eq!(summary.model_seconds, 0.0);
+    assert_eq!(summary.tokens_per_second, 0.0);
+}
+
+#[test]
+fn test_summary_with_profile() {
+    let summary = RunSummary {
+        profile: Some(Profile {
+            mel_seconds: 0.5,
+            generation_seconds: 2.0,
+            chunk_generation_seconds: vec![1.25, 0.75],
+            ..Profile::default()
+        }),
+        ..RunSummary::default()
+    };
+
+    let json = summary.to_json().unwrap();
+    let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+    assert_eq!(value["profile"]["mel_seconds"], 0.5);
+    assert_eq!(value["profile"]["chunk_generation_seconds"][1], 0.75);
+    assert_eq!(serde_json::from_str::<RunSummary>(&json).unwrap(), summary);
+}
@@ -0,0 +1,201 @@
+use anyhow::{Context, Result};
+use icu_normalizer::ComposingNormalizerBorrowed;
+use regex::{Captures, Regex, RegexBuilder};
+use std::borrow::Cow;
+use std::path::Path;
+
+#[cfg(test)]
+mod tests;
+
+/// Normalize transcript text for stable diffs: Unicode NFC, every run of
+/// whitespace (including non-breaking spaces and line breaks) collapsed to a
+/// single space, and no leading or trailing whitespace.
+pub fn normalize(text: &str) -> String {
+    let composed = ComposingNormalizerBorrowed::new_nfc().normalize(text);
+    composed.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Prefix of a correction whose wrong spelling is a regular expression.
+pub const REGEX_RULE_PREFIX: &str = "re:";
+
+/// Find-and-replace rules of a `--corrections` file, fixing the spelling of
+/// product names, acronyms and other terms in transcript text.
+///
+/// Every rule matches regardless of case. A plain rule only matches whole
+/// words, so `ci` does not touch "circle"; a regular expression rule
+/// matches wherever it matches and may use `$1` in its replacement.
+#[derive(Debug, Clone, Default)]
+pub struct Corrections {
+    rules: Vec<Rule>,
+}
+
+/// One line of a corrections file.
+#[derive(Debug, Clone)]
+struct Rule {
+    pattern: Regex,
+    replacement: String,
+    /// Whether the replacement is taken as it is, without `$` expansion.
+    literal: bool,
+}
+
+impl Corrections {
+    /// Parse corrections: one `wrong<TAB>right` rule per line, where a
+    /// wrong spelling starting with [`REGEX_RULE_PREFIX`] is a regular
+    /// expression. Blank lines and lines starting with `#` are skipped, and
+    /// an empty right side deletes the match.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error listing every malformed line with its line number.
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut rules = Vec::new();
+        let mut problems = Vec::new();
+        for (index, raw) in text.lines().enumerate() {
+            let content = raw.strip_suffix('\r').unwrap_or(raw);
+            if content.trim().is_empty() || content.trim_start().starts_with('#') {
+                continue;
+            }
+            match Rule::parse(content) {
+                Ok(rule) => rules.push(rule),
+                Err(problem) => problems.push(format!("line {}: {problem}", index + 1)),
+            }
+        }
+        if !problems.is_empty() {
+            anyhow::bail!("Malformed corrections:\n  {}", problems.join("\n  "));
+        }
+        Ok(Self { rules })
+    }
+
+    /// Read and [`Corrections::parse`] the corrections file at `path`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or is malformed.
+    pub fn read(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read corrections {}", path.display()))?;
+        Self::parse(&text)
+            .with_context(|| format!("Failed to parse corrections {}", path.display()))
+    }
+
+    /// Number of rules.
+    pub fn len(&self) -> usize {
+        self.rules.len()
+    }
+
+    /// Whether there are no rules.
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// `text` with every rule applied in a single pass, so that replaced
+    /// text is never corrected again. Where the matches of several rules
+    /// overlap, the one starting first wins, then the longest, then the one
+    /// listed first.
+    pub fn apply<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        // (start, end, rule, replacement) of every match
+        let mut matches: Vec<(usize, usize, usize, String)> = self
+            .rules
+            .iter()
+            .enumerate()
+            .flat_map(|(index, rule)| {
+                rule.pattern
+                    .captures_iter(text)
+                    .filter_map(move |captures| {
+                        let found = captures.get(0)?;
+                        (!found.is_empty()).then(|| {
+                            (
+                                found.start(),
+                                found.end(),
+                                index,
+                                rule.replacement(&captures),
+                            )
+                        })
+                    })
+            })
+            .collect();
+        if matches.is_empty() {
+            return Cow::Borrowed(text);
+        }
+        matches.sort_by_key(|&(start, end, rule, _)| (start, std::cmp::Reverse(end), rule));
+
+        let mut corrected = String::with_capacity(text.len());
+        let mut copied = 0;
+        for (start, end, _, replacement) in matches {
+            if start < copied {
+                continue;
+            }
+            corrected.push_str(&text[copied..start]);
+            corrected.push_str(&replacement);
+            copied = end;
+        }
+        corrected.push_str(&text[copied..]);
+        Cow::Owned(corrected)
+    }
+}
+
+impl Rule {
+    /// The rule on a line of a corrections file.
+    fn parse(content: &str) -> std::result::Result<Self, String> {
+        let Some((wrong, right)) = content.split_once('\t') else {
+            return Err("expected a wrong and a right spelling, separated by a tab".to_string());
+        };
+        if right.contains('\t') {
+            return Err(
+                "expected a single tab between the wrong and the right spelling".to_string(),
+            );
+        }
+        let right = right.trim();
+        let (pattern, literal) = match wrong.trim().strip_prefix(REGEX_RULE_PREFIX) {
+            Some(expression) => (expression.to_string(), false),
+            None => (word_pattern(wrong.trim()), true),
+        };
+        if pattern.is_empty() {
+            return Err(format!("no wrong spelling before {right}"));
+        }
+        let pattern = RegexBuilder::new(&pattern)
+            .case_insensitive(true)
+            .build()
+            .map_err(|e| format!("invalid regular expression: {e}"))?;
+        Ok(Self {
+            pattern,
+            replacement: right.to_string(),
+            literal,
+        })
+    }
+
+    /// Text replacing a match of this rule with its `captures`.
+    fn replacement(&self, captures: &Captures) -> String {
+        if self.literal {
+            return self.replacement.clone();
+        }
+        let mut expanded = String::new();
+        captures.expand(&self.replacement, &mut expanded);
+        expanded
+    }
+}
+
+/// Regular expression matching `word` as a whole word: the text itself, not
+/// preceded or followed by another letter or digit where it starts or ends
+/// with one. Inner whitespace matches any run of whitespace.
+fn word_pattern(word: &str) -> String {
+    if word.is_empty() {
+        return String::new();
+    }
+    let is_word = |c: Option<char>| c.is_some_and(|c| c.is_alphanumeric() || c == '_');
+    let inner: Vec<String> = word.split_whitespace().map(regex::escape).collect();
+    format!(
+        "{}{}{}",
+        if is_word(word.chars().next()) {
+            r"\b"
+        } else {
+            ""
+        },
+        inner.join(r"\s+"),
+        if is_word(word.chars().last()) {
+            r"\b"
+        } else {
+            ""
+        }
+    )
+}
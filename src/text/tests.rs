@@ -0,0 +1,112 @@
+use super::*;
+
+#[test]
+fn test_normalize_composes_combining_characters() {
+    // "e" + COMBINING ACUTE ACCENT becomes the precomposed "é"
+    let text = normalize("caf\u{0065}\u{0301}");
+
+    assert_eq!(text, "caf\u{00e9}");
+    assert_eq!(text.chars().count(), 4);
+}
+
+#[test]
+fn test_normalize_keeps_precomposed_text() {
+    assert_eq!(normalize("Grüße aus Köln"), "Grüße aus Köln");
+}
+
+#[test]
+fn test_normalize_collapses_spaces() {
+    assert_eq!(normalize("Hello   world,  again"), "Hello world, again");
+}
+
+#[test]
+fn test_normalize_replaces_non_breaking_spaces() {
+    assert_eq!(normalize("10\u{00a0}km\u{202f}!"), "10 km !");
+}
+
+#[test]
+fn test_normalize_joins_lines() {
+    assert_eq!(
+        normalize("first\n\n\nsecond\r\nthird\t"),
+        "first second third"
+    );
+}
+
+#[test]
+fn test_normalize_trims() {
+    assert_eq!(normalize("  \u{00a0}Hello\n "), "Hello");
+    assert_eq!(normalize(" \n\t "), "");
+}
+
+#[test]
+fn test_corrections_match_whole_words_in_any_case() {
+    let corrections = Corrections::parse("kubernetes\tKubernetes\nci\tCI\n").unwrap();
+
+    assert_eq!(
+        corrections.apply("KUBERNETES runs the ci, not the circle"),
+        "Kubernetes runs the CI, not the circle"
+    );
+    assert_eq!(corrections.apply("ci/cd"), "CI/cd");
+    assert!(matches!(
+        corrections.apply("nothing here"),
+        Cow::Borrowed(_)
+    ));
+}
+
+#[test]
+fn test_corrections_boundaries_of_symbols_and_phrases() {
+    let corrections = Corrections::parse("c plus plus\tC++\nc++\tC++\n.net\t.NET\n").unwrap();
+
+    assert_eq!(
+        corrections.apply("c  plus\nplus and c++ on asp.net"),
+        "C++ and C++ on asp.NET"
+    );
+    assert_eq!(corrections.apply("c plus plush"), "c plus plush");
+}
+
+#[test]
+fn test_corrections_overlapping_rules() {
+    let corrections =
+        Corrections::parse("kitsune\tKitsune\nkitsune stt\tKitsune-STT\nstt\tSTT\n").unwrap();
+
+    // The longer match wins and its replacement is not corrected again
+    assert_eq!(
+        corrections.apply("kitsune stt beats plain kitsune and stt"),
+        "Kitsune-STT beats plain Kitsune and STT"
+    );
+
+    // At the same place and length, the rule listed first wins
+    let corrections = Corrections::parse("color\tcolour\nre:colou?r\tCOLOR\n").unwrap();
+    assert_eq!(corrections.apply("color colour"), "colour COLOR");
+}
+
+#[test]
+fn test_corrections_regex_rules() {
+    let corrections = Corrections::parse(
+        "# versions are spoken as words\n\
+         re:\\bv(\\d+)\\s+point\\s+(\\d+)\tv$1.$2\n\
+         re:\\b(um|uh)\\b\\s*\t\n",
+    )
+    .unwrap();
+    assert_eq!(corrections.len(), 2);
+
+    assert_eq!(
+        corrections.apply("Um we shipped V2 point 5 uh today"),
+        "we shipped v2.5 today"
+    );
+}
+
+#[test]
+fn test_corrections_report_every_malformed_line() {
+    let err = Corrections::parse("ok\tOK\nno tab\n\tEmpty\nre:(\tx\na\tb\tc\n")
+        .unwrap_err()
+        .to_string();
+
+    assert!(err.starts_with("Malformed corrections:"), "{err}");
+    assert!(err.contains("line 2: expected a wrong and a right spelling"));
+    assert!(err.contains("line 3: no wrong spelling before Empty"));
+    assert!(err.contains("line 4: invalid regular expression"));
+    assert!(err.contains("line 5: expected a single tab"));
+    assert!(!err.contains("line 1"));
+    assert!(Corrections::parse("# none\r\n\r\n").unwrap().is_empty());
+}
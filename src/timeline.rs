@@ -0,0 +1,188 @@
+use std::ops::Range;
+
+#[cfg(test)]
+mod tests;
+
+/// Maps sample positions in the prepared audio buffer back to time in the
+/// original file.
+///
+/// The prepared buffer is the concatenation of the spans of the source that
+/// were kept, so trimming silence or dropping regions only changes the span
+/// table and every timestamp is still reported in original-file time.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Timeline {
+    sample_rate: u32,
+    /// Kept source ranges in buffer order; buffer offsets are implied by the
+    /// lengths of the spans before.
+    spans: Vec<Range<usize>>,
+}
+
+impl Timeline {
+    /// Empty timeline for audio at `sample_rate`, to be filled with [`Self::push`].
+    pub fn new(sample_rate: u32) -> Self {
+        Self {
+            sample_rate,
+            spans: Vec::new(),
+        }
+    }
+
+    /// Timeline of a buffer that holds the source unchanged.
+    pub fn identity(total_samples: usize, sample_rate: u32) -> Self {
+        let mut timeline = Self::new(sample_rate);
+        timeline.push(0..total_samples);
+        timeline
+    }
+
+    /// Record that the next samples of the buffer are `source` samples of the
+    /// original file.
+    pub fn push(&mut self, source: Range<usize>) {
+        if !source.is_empty() {
+            self.spans.push(source);
+        }
+    }
+
+    /// Number of samples in the buffer.
+    pub fn len(&self) -> usize {
+        self.spans.iter().map(ExactSizeIterator::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.spans.is_empty()
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Position in the original file of buffer sample `sample`. Positions at
+    /// or past the end of the buffer map to the end of the last span.
+    pub fn source_sample(&self, sample: usize) -> usize {
+        let mut offset = 0;
+        for span in &self.spans {
+            if sample < offset + span.len() {
+                return span.start + (sample - offset);
+            }
+            offset += span.len();
+        }
+        self.spans.last().map_or(sample, |span| span.end)
+    }
+
+    /// Start and end in original-file seconds of the buffer samples `range`.
+    ///
+    /// The end is taken just after the last sample of the range, so a chunk
+    /// ending at a removed region does not stretch over it.
+    pub fn seconds(&self, range: &Range<usize>) -> (f32, f32) {
+        let start = self.source_sample(range.start);
+        let end = if range.is_empty() {
+            start
+        } else {
+            self.source_sample(range.end - 1) + 1
+        };
+        let rate = self.sample_rate as f32;
+        (start as f32 / rate, end as f32 / rate)
+    }
+}
+
+/// Samples from `start_sec` to `end_sec` of audio of `total_samples` at
+/// `sample_rate`, where a missing bound is the start or the end of the audio.
+///
+/// # Errors
+///
+/// Returns an error if a bound is negative or not a number, if the start is
+/// not before the end, or if a bound lies past the end of the audio.
+pub fn seconds_range(
+    start_sec: Option<f32>,
+    end_sec: Option<f32>,
+    total_samples: usize,
+    sample_rate: u32,
+) -> anyhow::Result<Range<usize>> {
+    let duration = total_samples as f32 / sample_rate as f32;
+    let to_sample = |name: &str, seconds: f32| -> anyhow::Result<usize> {
+        if !(seconds.is_finite() && seconds >= 0.0) {
+            anyhow::bail!("--{name} must be a non-negative number of seconds");
+        }
+        let sample = (f64::from(seconds) * f64::from(sample_rate)).round() as usize;
+        if sample > total_samples {
+            anyhow::bail!("--{name} {seconds} is past the end of the audio at {duration:.2} s");
+        }
+        Ok(sample)
+    };
+    let start = start_sec.map_or(Ok(0), |seconds| to_sample("start-sec", seconds))?;
+    let end = end_sec.map_or(Ok(total_samples), |seconds| to_sample("end-sec", seconds))?;
+    if start >= end {
+        anyhow::bail!(
+            "--start-sec must be before --end-sec and the end of the audio at {duration:.2} s"
+        );
+    }
+    Ok(start..end)
+}
+
+/// Wall-clock time at the start of the recording, from `--start-time`, so
+/// that timestamps can be given as absolute times instead of offsets into
+/// the file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WallClock {
+    start: jiff::Timestamp,
+    /// UTC offset the start was given in; absolute times are written in it.
+    offset: jiff::tz::Offset,
+}
+
+impl WallClock {
+    /// Time `seconds` into the recording in RFC 3339 with milliseconds and
+    /// the offset of the start, such as `2024-01-02T10:00:05.250+02:00`.
+    pub fn at(&self, seconds: f32) -> String {
+        self.zoned(seconds)
+            .strftime("%Y-%m-%dT%H:%M:%S%.3f%:z")
+            .to_string()
+    }
+
+    /// Seconds from midnight of the start day, in the offset of the start,
+    /// to `seconds` into the recording. Past midnight this goes beyond a
+    /// day, so that times of day keep increasing.
+    ///
+    /// The result is `f64`, as `f32` has too few digits for the
+    /// milliseconds of a time late in the day.
+    pub fn since_midnight(&self, seconds: f32) -> f64 {
+        let time = self.zoned(0.0).time();
+        let midnight = f64::from(time.hour()) * 3600.0
+            + f64::from(time.minute()) * 60.0
+            + f64::from(time.second())
+            + f64::from(time.subsec_nanosecond()) / 1e9;
+        midnight + f64::from(seconds.max(0.0))
+    }
+
+    fn zoned(&self, seconds: f32) -> jiff::Zoned {
+        let elapsed = jiff::SignedDuration::from_secs_f64(f64::from(seconds.max(0.0)));
+        self.start
+            .saturating_add(elapsed)
+            .expect("a signed duration is a valid timestamp span")
+            .to_zoned(jiff::tz::TimeZone::fixed(self.offset))
+    }
+}
+
+impl std::str::FromStr for WallClock {
+    type Err = String;
+
+    /// Parse an RFC 3339 timestamp such as `2024-01-02T10:00:00+02:00` or
+    /// `2024-01-02T08:00:00Z`. The offset is required, since a bare local
+    /// time would be ambiguous.
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        use jiff::fmt::temporal::{Pieces, PiecesOffset};
+
+        let invalid = |e: jiff::Error| {
+            format!("'{s}' is not an RFC 3339 time with an offset, such as 2024-01-02T10:00:00+02:00: {e}")
+        };
+        let pieces = Pieces::parse(s).map_err(invalid)?;
+        let offset = match pieces.offset() {
+            Some(PiecesOffset::Zulu) => jiff::tz::Offset::UTC,
+            Some(PiecesOffset::Numeric(offset)) => offset.offset(),
+            _ => {
+                return Err(format!(
+                    "'{s}' has no UTC offset; add one such as Z or +02:00"
+                ))
+            }
+        };
+        let start = s.parse::<jiff::Timestamp>().map_err(invalid)?;
+        Ok(Self { start, offset })
+    }
+}
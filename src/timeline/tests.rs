@@ -0,0 +1,141 @@
+use super::*;
+
+#[test]
+fn test_identity_timeline() {
+    let timeline = Timeline::identity(48_000, 16_000);
+
+    assert_eq!(timeline.len(), 48_000);
+    assert_eq!(timeline.source_sample(1_234), 1_234);
+    assert_eq!(timeline.seconds(&(16_000..32_000)), (1.0, 2.0));
+    assert_eq!(timeline.seconds(&(32_000..48_000)), (2.0, 3.0));
+}
+
+#[test]
+fn test_trimmed_prefix_keeps_original_time() {
+    // The first 1.5 seconds were trimmed as silence
+    let mut timeline = Timeline::new(16_000);
+    timeline.push(24_000..80_000);
+
+    assert_eq!(timeline.len(), 56_000);
+    let (start, end) = timeline.seconds(&(0..16_000));
+    assert_eq!(start, 1.5);
+    assert_eq!(end, 2.5);
+}
+
+#[test]
+fn test_removed_gap_between_spans() {
+    // 0-1 s kept, 1-3 s removed, 3-4 s kept
+    let mut timeline = Timeline::new(16_000);
+    timeline.push(0..16_000);
+    timeline.push(48_000..64_000);
+
+    assert_eq!(timeline.source_sample(15_999), 15_999);
+    assert_eq!(timeline.source_sample(16_000), 48_000);
+    assert_eq!(timeline.seconds(&(0..16_000)), (0.0, 1.0));
+    assert_eq!(timeline.seconds(&(16_000..32_000)), (3.0, 4.0));
+    // A chunk spanning the gap covers it in original time
+    assert_eq!(timeline.seconds(&(8_000..24_000)), (0.5, 3.5));
+}
+
+#[test]
+fn test_past_the_end_and_empty() {
+    let mut timeline = Timeline::new(16_000);
+    timeline.push(100..200);
+    timeline.push(300..300);
+
+    assert_eq!(timeline.len(), 100);
+    assert_eq!(timeline.source_sample(100), 200);
+    assert_eq!(timeline.seconds(&(100..100)), (0.0125, 0.0125));
+    assert!(Timeline::new(16_000).is_empty());
+}
+
+#[test]
+fn test_seconds_range_defaults_to_the_whole_audio() {
+    assert_eq!(
+        seconds_range(None, None, 48_000, 16_000).unwrap(),
+        0..48_000
+    );
+    assert_eq!(
+        seconds_range(Some(1.0), None, 48_000, 16_000).unwrap(),
+        16_000..48_000
+    );
+    assert_eq!(
+        seconds_range(None, Some(2.5), 48_000, 16_000).unwrap(),
+        0..40_000
+    );
+    assert_eq!(
+        seconds_range(Some(0.5), Some(3.0), 48_000, 16_000).unwrap(),
+        8_000..48_000
+    );
+}
+
+#[test]
+fn test_seconds_range_rejects_invalid_bounds() {
+    let err = |start, end| {
+        seconds_range(start, end, 48_000, 16_000)
+            .unwrap_err()
+            .to_string()
+    };
+
+    assert!(err(Some(2.0), Some(1.0)).contains("--start-sec must be before --end-sec"));
+    assert!(err(Some(1.0), Some(1.0)).contains("must be before"));
+    assert!(err(Some(3.0), None).contains("must be before"));
+    assert_eq!(
+        err(None, Some(3.5)),
+        "--end-sec 3.5 is past the end of the audio at 3.00 s"
+    );
+    assert!(err(Some(-1.0), None).contains("--start-sec must be a non-negative"));
+    assert!(err(None, Some(f32::NAN)).contains("--end-sec must be a non-negative"));
+}
+
+#[test]
+fn test_selected_range_keeps_file_time() {
+    // Minute 10 to minute 12 of a recording
+    let selection = seconds_range(Some(600.0), Some(720.0), 16_000 * 900, 16_000).unwrap();
+    let mut timeline = Timeline::new(16_000);
+    timeline.push(selection);
+
+    assert_eq!(timeline.len(), 16_000 * 120);
+    assert_eq!(timeline.seconds(&(0..16_000)), (600.0, 601.0));
+    assert_eq!(timeline.seconds(&(0..timeline.len())), (600.0, 720.0));
+}
+
+#[test]
+fn test_wall_clock_keeps_the_offset() {
+    let clock: WallClock = "2024-01-02T10:00:00+02:00".parse().unwrap();
+
+    assert_eq!(clock.at(0.0), "2024-01-02T10:00:00.000+02:00");
+    assert_eq!(clock.at(5.25), "2024-01-02T10:00:05.250+02:00");
+    assert_eq!(clock.since_midnight(5.25), 36_005.25);
+
+    let utc: WallClock = "2024-01-02T08:00:00Z".parse().unwrap();
+    assert_eq!(utc.at(65.0), "2024-01-02T08:01:05.000+00:00");
+}
+
+#[test]
+fn test_wall_clock_crosses_midnight() {
+    let clock: WallClock = "2024-12-31T23:59:30-05:00".parse().unwrap();
+
+    assert_eq!(clock.at(45.0), "2025-01-01T00:00:15.000-05:00");
+    assert_eq!(clock.since_midnight(45.0), 86_415.0);
+}
+
+#[test]
+fn test_wall_clock_needs_an_offset() {
+    let err = "2024-01-02T10:00:00".parse::<WallClock>().unwrap_err();
+    assert!(err.contains("has no UTC offset"), "{err}");
+
+    let err = "yesterday".parse::<WallClock>().unwrap_err();
+    assert!(err.contains("is not an RFC 3339 time"), "{err}");
+    assert!("2024-13-02T10:00:00Z".parse::<WallClock>().is_err());
+}
+
+#[test]
+fn test_wall_clock_keeps_milliseconds_late_in_the_day() {
+    let clock: WallClock = "2024-01-02T23:59:58.123Z".parse().unwrap();
+
+    assert_eq!(
+        crate::output::srt_timestamp(clock.since_midnight(1.5)),
+        "23:59:59,623"
+    );
+}
@@ -0,0 +1,271 @@
+//! End-to-end tests on the audio fixtures in `tests/fixtures`.
+//!
+//! Run with `cargo test --features integration`. The model test only runs
+//! when the model has already been downloaded to `Voxtral-Mini-3B-2507`.
+#![cfg(feature = "integration")]
+
+use kitsune_stt::audio::{self, Downmix, ResampleQuality};
+use kitsune_stt::chunk::ChunkPlan;
+use kitsune_stt::model::{ModelOptions, TranscribeOptions, VoxtralModel};
+use kitsune_stt::SAMPLE_RATE;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// 1.5 seconds of a synthetic voiced vowel, mono 16-bit at 22.05 kHz.
+fn fixture() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/vowel_22050.wav")
+}
+
+#[test]
+fn decode_fixture() {
+    let (pcm, sample_rate) = audio::pcm_decode(fixture()).unwrap();
+
+    assert_eq!(sample_rate, 22_050);
+    assert_eq!(pcm.len(), 33_075);
+    assert!(audio::rms(&pcm) > 0.05);
+}
+
+#[test]
+fn decode_fixture_from_memory() {
+    let bytes = std::fs::read(fixture()).unwrap();
+
+    let from_memory = audio::pcm_decode_reader(std::io::Cursor::new(bytes), Some("wav")).unwrap();
+
+    assert_eq!(from_memory, audio::pcm_decode(fixture()).unwrap());
+}
+
+#[test]
+fn decode_and_prepare_fixture() {
+    for quality in [
+        ResampleQuality::Fast,
+        ResampleQuality::Balanced,
+        ResampleQuality::High,
+    ] {
+        let prepared = audio::decode_and_prepare(
+            fixture(),
+            SAMPLE_RATE,
+            Some(quality),
+            Downmix::Average,
+            true,
+        )
+        .unwrap();
+
+        assert_eq!(prepared.len(), 24_000, "{quality:?}");
+        assert!(prepared.iter().all(|s| s.is_finite() && s.abs() <= 1.0));
+    }
+}
+
+#[test]
+fn decode_fixture_without_resampling() {
+    let err = audio::decode_and_prepare(fixture(), SAMPLE_RATE, None, Downmix::Average, true)
+        .unwrap_err();
+
+    assert!(err.to_string().contains("22050 Hz"), "{err}");
+}
+
+#[test]
+fn opus_fixture_is_detected_but_not_decoded() {
+    let opus = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/silence_48000.opus");
+
+    let info = audio::track_info(&opus).unwrap();
+    assert_eq!(info.codec, "opus");
+    assert_eq!(info.channels, Some(1));
+    assert_eq!(info.sample_rate, Some(48_000));
+
+    let err = audio::pcm_decode(&opus).unwrap_err();
+    assert!(err.to_string().contains("ffmpeg"), "{err}");
+}
+
+#[test]
+fn decode_m4a_fixture() {
+    let m4a = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/silence_44100.m4a");
+
+    let info = audio::track_info(&m4a).unwrap();
+    assert_eq!(info.codec, "aac");
+    assert_eq!(info.sample_rate, Some(44_100));
+
+    let (pcm, sample_rate) = audio::pcm_decode(&m4a).unwrap();
+    assert_eq!(sample_rate, 44_100);
+    assert_eq!(pcm.len(), 44 * 1024);
+    assert!(pcm.iter().all(|&s| s == 0.0));
+
+    let bytes = std::fs::read(&m4a).unwrap();
+    let from_memory = audio::pcm_decode_reader(std::io::Cursor::new(bytes), Some("m4a")).unwrap();
+    assert_eq!(from_memory, (pcm, sample_rate));
+}
+
+#[test]
+fn decode_flac_fixture() {
+    let flac = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/tones_44100.flac");
+
+    let info = audio::track_info(&flac).unwrap();
+    assert_eq!(info.codec, "flac");
+    assert_eq!(info.channels, Some(2));
+    assert_eq!(info.sample_rate, Some(44_100));
+    assert_eq!(info.bits_per_sample, Some(16));
+
+    let (channels, sample_rate) = audio::pcm_decode_channels(&flac).unwrap();
+    assert_eq!(sample_rate, 44_100);
+    assert_eq!(channels.len(), 2);
+    assert!(channels.iter().all(|channel| channel.len() == 8_820));
+    // Lossless: the peaks are exactly the encoded ones
+    let peak = |channel: &[f32]| channel.iter().fold(0f32, |peak, s| peak.max(s.abs()));
+    assert_eq!(peak(&channels[0]), 16_383.0 / 32_768.0);
+    assert_eq!(peak(&channels[1]), 8_191.0 / 32_768.0);
+
+    let (pcm, _) = audio::pcm_decode(&flac).unwrap();
+    assert_eq!(pcm.len(), 8_820);
+    let bytes = std::fs::read(&flac).unwrap();
+    let from_memory = audio::pcm_decode_reader(std::io::Cursor::new(bytes), Some("flac")).unwrap();
+    assert_eq!(from_memory, (pcm, sample_rate));
+}
+
+#[test]
+fn chunk_fixture() {
+    let prepared = audio::decode_and_prepare(
+        fixture(),
+        SAMPLE_RATE,
+        Some(ResampleQuality::High),
+        Downmix::Average,
+        true,
+    )
+    .unwrap();
+
+    let plan = ChunkPlan::new(1.0, 0.5, SAMPLE_RATE).unwrap();
+    let ranges = plan.ranges(prepared.len());
+
+    assert_eq!(ranges.first().unwrap().start, 0);
+    assert_eq!(ranges.last().unwrap().end, prepared.len());
+    assert!(ranges.windows(2).all(|pair| pair[1].start < pair[0].end));
+}
+
+#[test]
+fn quiet_prints_only_the_requested_output() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let dump = temp_dir.path().join("prepared.wav");
+    let run = |extra: &[&str]| {
+        let output = Command::new(env!("CARGO_BIN_EXE_kitsune-stt"))
+            .arg(fixture())
+            .args(["--dry-run", "--dump-audio"])
+            .arg(&dump)
+            .args(extra)
+            .env_remove("RUST_LOG")
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{output:?}");
+        output
+    };
+
+    let chatty = String::from_utf8(run(&[]).stdout).unwrap();
+    let quiet = run(&["--quiet"]);
+
+    let (note, plan) = chatty.split_once('\n').unwrap();
+    assert!(note.starts_with("Prepared audio written to"), "{chatty}");
+    assert_eq!(String::from_utf8(quiet.stdout).unwrap(), plan);
+    assert!(quiet.stderr.is_empty(), "{:?}", quiet.stderr);
+
+    // A report asked for is printed all the same
+    let profiled = String::from_utf8(run(&["--quiet", "--profile"]).stdout).unwrap();
+    assert!(profiled.starts_with(plan), "{profiled}");
+    assert!(profiled.contains("Time by stage:"), "{profiled}");
+}
+
+#[test]
+fn transcribe_fixture_with_model() {
+    let model_dir = Path::new("Voxtral-Mini-3B-2507");
+    if !model_dir.join("config.json").exists() {
+        eprintln!("Model not downloaded to {}, skipping", model_dir.display());
+        return;
+    }
+
+    let prepared = audio::decode_and_prepare(
+        fixture(),
+        SAMPLE_RATE,
+        Some(ResampleQuality::High),
+        Downmix::Average,
+        true,
+    )
+    .unwrap();
+    let mut model = VoxtralModel::new(&ModelOptions {
+        use_cpu: true,
+        ..ModelOptions::default()
+    })
+    .unwrap();
+    assert!(model.warm_up().unwrap() > std::time::Duration::ZERO);
+
+    let mut streamed = String::new();
+    let result = model
+        .transcribe_audio_16k(&prepared, &TranscribeOptions::default(), |text| {
+            streamed.push_str(text)
+        })
+        .unwrap();
+
+    assert_eq!(streamed, result.text);
+    assert!((0.0..=1.0).contains(&result.no_speech_prob));
+}
+
+#[test]
+fn stream_fixture_with_model() {
+    let model_dir = Path::new("Voxtral-Mini-3B-2507");
+    if !model_dir.join("config.json").exists() {
+        eprintln!("Model not downloaded to {}, skipping", model_dir.display());
+        return;
+    }
+
+    let (pcm, sample_rate) = audio::pcm_decode(fixture()).unwrap();
+    let mut model = VoxtralModel::new(&ModelOptions {
+        use_cpu: true,
+        ..ModelOptions::default()
+    })
+    .unwrap();
+    let plan = ChunkPlan::new(1.0, 0.0, SAMPLE_RATE).unwrap();
+
+    let segments = model
+        .transcribe_stream(&pcm, sample_rate, &plan, &TranscribeOptions::default())
+        .unwrap()
+        .collect::<anyhow::Result<Vec<_>>>()
+        .unwrap();
+
+    assert_eq!(segments.len(), 2);
+    assert!((segments[1].end_sec - 1.5).abs() < 0.01);
+}
+
+#[test]
+fn transcribe_short_clips_with_model() {
+    let model_dir = Path::new("Voxtral-Mini-3B-2507");
+    if !model_dir.join("config.json").exists() {
+        eprintln!("Model not downloaded to {}, skipping", model_dir.display());
+        return;
+    }
+
+    let mut model = VoxtralModel::new(&ModelOptions {
+        use_cpu: true,
+        ..ModelOptions::default()
+    })
+    .unwrap();
+
+    // Down to a single sample, shorter than one mel hop
+    for len in [1, 100, 16_000] {
+        let clip: Vec<f32> = (0..len)
+            .map(|i| 0.3 * (i as f32 * 440.0 * std::f32::consts::TAU / 16_000.0).sin())
+            .collect();
+        let mut streamed = String::new();
+        let result = model
+            .transcribe_audio_16k(&clip, &TranscribeOptions::default(), |text| {
+                streamed.push_str(text)
+            })
+            .unwrap();
+        assert_eq!(streamed, result.text, "{len} samples");
+        assert!((0.0..=1.0).contains(&result.no_speech_prob));
+    }
+
+    let result = model
+        .transcribe_audio(&[0.3], 44_100, &TranscribeOptions::default(), |_| {})
+        .unwrap();
+    assert!((0.0..=1.0).contains(&result.no_speech_prob));
+    let empty = model
+        .transcribe_audio_16k(&[], &TranscribeOptions::default(), |_| {})
+        .unwrap();
+    assert!(empty.text.is_empty() && empty.tokens.is_empty());
+    assert_eq!(empty.no_speech_prob, 1.0);
+}